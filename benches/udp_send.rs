@@ -0,0 +1,67 @@
+//! How long it takes to build, encode, and send a control packet
+//!
+//! This happens every 20ms for as long as the DS runs, so it should stay
+//! cheap.
+
+use std::hint::black_box;
+
+use bytes::BytesMut;
+use criterion::{Criterion, criterion_group, criterion_main};
+use robudst::{
+    Ds,
+    proto::{
+        outgoing::udp::UdpOutgoingPacket,
+        version::{Frc2015, ProtocolVersion},
+    },
+};
+use tokio::{net::UdpSocket, runtime::Runtime};
+
+/// A DS with every joystick slot in use, the worst case for packet size
+fn busy_ds() -> Ds {
+    let ds = Ds::builder(4533).build().unwrap();
+    for slot in 0..6 {
+        ds.set_joystick(
+            slot,
+            &[127, -128, 0, 64, -64, 1],
+            &[
+                true, false, true, true, false, false, true, false, true, true,
+            ],
+            &[90],
+        )
+        .unwrap();
+    }
+    ds
+}
+
+fn udp_send(c: &mut Criterion) {
+    let ds = busy_ds();
+
+    c.bench_function("build and encode control packet", |b| {
+        let mut buf = BytesMut::new();
+        b.iter(|| {
+            buf.clear();
+            Frc2015.write_control(UdpOutgoingPacket::build(black_box(&ds)), &mut buf);
+        })
+    });
+
+    let rt = Runtime::new().unwrap();
+    let (tx, _rx) = rt.block_on(async {
+        let rx = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let tx = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        tx.connect(rx.local_addr().unwrap()).await.unwrap();
+        (tx, rx)
+    });
+
+    c.bench_function("build, encode, and send control packet", |b| {
+        b.to_async(&rt).iter(|| async {
+            let mut buf = [0u8; 1024];
+            let len = UdpOutgoingPacket::build(black_box(&ds))
+                .encode_to_slice(&mut buf)
+                .unwrap();
+            tx.send(&buf[..len]).await.unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, udp_send);
+criterion_main!(benches);
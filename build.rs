@@ -0,0 +1,14 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        // Build scripts are single threaded, so nothing else is reading the
+        // environment
+        unsafe {
+            std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+        }
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["grpc/robudst.proto"], &["grpc"])
+            .unwrap();
+    }
+}
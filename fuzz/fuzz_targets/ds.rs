@@ -0,0 +1,14 @@
+#![no_main]
+
+//! Everything a roboRIO can send, handled by a real DS
+
+use std::sync::LazyLock;
+
+use libfuzzer_sys::fuzz_target;
+use robudst::Ds;
+
+static DS: LazyLock<Ds> = LazyLock::new(|| Ds::builder(4533).build().unwrap());
+
+fuzz_target!(|data: &[u8]| {
+    robudst::fuzz::ds_receive(&DS, data);
+});
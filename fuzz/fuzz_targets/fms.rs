@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use robudst::proto::fms::{DsStatusPacket, FmsControlPacket};
+
+fuzz_target!(|data: &[u8]| {
+    FmsControlPacket::parse(data);
+    DsStatusPacket::parse(data);
+    robudst::fuzz::fms_tcp_stream(data);
+});
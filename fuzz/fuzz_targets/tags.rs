@@ -0,0 +1,19 @@
+#![no_main]
+
+//! Each tag parser on its own, without the framing in front of it
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte picks the parser: the top bit for TCP or UDP, and the
+    // rest for the tag id
+    let Some((&selector, data)) = data.split_first() else {
+        return;
+    };
+    let id = selector & 0x7F;
+    if selector & 0x80 == 0 {
+        robudst::fuzz::tcp_tag(id, data);
+    } else {
+        robudst::fuzz::udp_tag(id, data);
+    }
+});
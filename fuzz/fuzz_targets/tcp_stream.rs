@@ -0,0 +1,21 @@
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use robudst::proto::{codec::TcpTagCodec, incoming::tcp::TcpTagStream};
+use tokio_util::codec::Decoder;
+
+fuzz_target!(|data: &[u8]| {
+    for tag in TcpTagStream::new(data) {
+        tag.to_owned();
+    }
+
+    // Split into frames the way a real connection would be, then decode each
+    let mut codec = TcpTagCodec::default();
+    let mut buf = BytesMut::from(data);
+    while let Ok(Some(frame)) = codec.decode(&mut buf) {
+        for tag in TcpTagStream::new(&frame) {
+            tag.to_owned();
+        }
+    }
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use robudst::proto::version::{Frc2015, ProtocolVersion};
+
+fuzz_target!(|data: &[u8]| {
+    robudst::fuzz::udp_stream(data);
+    Frc2015.read_status(data);
+});
@@ -0,0 +1,99 @@
+/*
+ * C API for robudst, an FRC driver station
+ *
+ * Build the shared library with `cargo build --release --features ffi`.
+ * Every function returns ROBUDST_OK or a negative ROBUDST_ERR_* code,
+ * unless it says otherwise, and can be called from any thread.
+ */
+
+#ifndef ROBUDST_H
+#define ROBUDST_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define ROBUDST_OK 0
+/* A pointer argument was null */
+#define ROBUDST_ERR_NULL -1
+#define ROBUDST_ERR_INVALID_JOYSTICK_SLOT -2
+#define ROBUDST_ERR_TOO_MANY_JOYSTICK_INPUTS -3
+#define ROBUDST_ERR_ESTOPPED -4
+#define ROBUDST_ERR_FMS_CONTROLLED -5
+#define ROBUDST_ERR_INVALID_MODE -6
+#define ROBUDST_ERR_OTHER -7
+
+#define ROBUDST_MODE_AUTONOMOUS 0
+#define ROBUDST_MODE_TELEOP 1
+#define ROBUDST_MODE_TEST 2
+
+/* Anything without its own kind, described in `message` */
+#define ROBUDST_EVENT_OTHER 0
+/* `code` is the new state: 0 disconnected, 2 connecting, 3 connected, or
+ * 4 lost (1 is unused) */
+#define ROBUDST_EVENT_CONNECTION_STATE_CHANGED 1
+#define ROBUDST_EVENT_COMM_LOST 2
+/* `code` is the level (0 info, 1 warning, 2 error), and `message` the text */
+#define ROBUDST_EVENT_CONSOLE_LINE 3
+/* `voltage` dropped below `threshold` */
+#define ROBUDST_EVENT_VOLTAGE_LOW 4
+/* `voltage` recovered above `threshold` */
+#define ROBUDST_EVENT_VOLTAGE_RECOVERED 5
+/* The robot browned out at `voltage` */
+#define ROBUDST_EVENT_BROWNOUT 6
+/* `code` is the fault: 0 comms, 1 12V, 2 6V rail, 3 5V rail, or 4 3.3V rail */
+#define ROBUDST_EVENT_FAULT 7
+/* `code` is the free memory, in bytes */
+#define ROBUDST_EVENT_LOW_MEMORY 8
+/* `code` is the free disk space, in bytes */
+#define ROBUDST_EVENT_LOW_DISK 9
+/* `message` is what the radio reported */
+#define ROBUDST_EVENT_RADIO 10
+
+typedef struct RobudstDs RobudstDs;
+
+typedef struct RobudstEvent {
+    uint32_t kind;
+    int64_t code;
+    float voltage;
+    float threshold;
+    /* Never null; only valid until the next robudst_poll_event or
+     * robudst_free */
+    const char *message;
+} RobudstEvent;
+
+/* Start a driver station for `team_number`, connecting to its robot, or
+ * return null if it couldn't be started */
+RobudstDs *robudst_init(uint16_t team_number);
+
+/* Disable the robot, stop the driver station, and free it */
+void robudst_free(RobudstDs *ds);
+
+int32_t robudst_enable(const RobudstDs *ds);
+int32_t robudst_disable(const RobudstDs *ds);
+int32_t robudst_estop(const RobudstDs *ds);
+
+/* Switch to one of the ROBUDST_MODE_* modes */
+int32_t robudst_set_mode(const RobudstDs *ds, int32_t mode);
+
+/* Set the joystick in `slot`, with axes from -128 to 127 and POVs in
+ * degrees (or -1 when not pressed). Arrays can be null if their count is
+ * zero. */
+int32_t robudst_set_joystick(const RobudstDs *ds, size_t slot,
+                             const int8_t *axes, size_t axis_count,
+                             const bool *buttons, size_t button_count,
+                             const int16_t *povs, size_t pov_count);
+
+/* Take the oldest event that hasn't been polled yet, returning 1 and
+ * filling in `event` if there was one, or 0 if there wasn't */
+int32_t robudst_poll_event(const RobudstDs *ds, RobudstEvent *event);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ROBUDST_H */
@@ -0,0 +1,94 @@
+//! Control packets, which the driver station sends the roboRIO every 20ms
+
+use bitflags::bitflags;
+
+/// Length of the fixed part of a control packet, before its tags
+pub const HEADER_LEN: usize = 6;
+/// The communication version every control packet has had since 2015
+pub const COMM_VERSION: u8 = 0x01;
+
+/// Tag with the seconds left in the match period
+pub const COUNTDOWN: u8 = 0x07;
+/// Tag with a joystick's state, one per slot, in slot order
+pub const JOYSTICK: u8 = 0x0C;
+/// Tag with the UTC date and time, sent when the roboRIO asks for it
+pub const DATE: u8 = 0x0F;
+/// Tag with the IANA timezone name, sent along with the date
+pub const TIMEZONE: u8 = 0x10;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Control: u8 {
+        const ESTOP         = 0b1000_0000;
+        const FMS_CONNECTED = 0b0000_1000;
+        const ENABLED       = 0b0000_0100;
+
+        const TELEOP = 0b00;
+        const AUTO   = 0b10;
+        const TEST   = 0b01;
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Request: u8 {
+        const REBOOT_RIO   = 0b0000_1000;
+        const RESTART_CODE = 0b0000_0100;
+    }
+}
+
+/// The fixed part of a control packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlHeader {
+    pub seqnum: u16,
+    pub comm_version: u8,
+    pub control: Control,
+    pub request: Request,
+    /// Alliance station, `0..=2` for red 1 to 3 and `3..=5` for blue 1 to 3
+    pub station: u8,
+}
+impl ControlHeader {
+    pub const fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let [seq_hi, seq_lo] = self.seqnum.to_be_bytes();
+        [
+            seq_hi,
+            seq_lo,
+            self.comm_version,
+            self.control.bits(),
+            self.request.bits(),
+            self.station,
+        ]
+    }
+
+    /// Decode the header at the start of `buf`, or `None` if it's too short
+    ///
+    /// Tags start at [`HEADER_LEN`].
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        let header: &[u8; HEADER_LEN] = buf.first_chunk()?;
+        Some(Self {
+            seqnum: u16::from_be_bytes([header[0], header[1]]),
+            comm_version: header[2],
+            control: Control::from_bits_retain(header[3]),
+            request: Request::from_bits_retain(header[4]),
+            station: header[5],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trips() {
+        let header = ControlHeader {
+            seqnum: 0x1234,
+            comm_version: COMM_VERSION,
+            control: Control::ENABLED | Control::AUTO,
+            request: Request::RESTART_CODE,
+            station: 4,
+        };
+        let bytes = header.to_bytes();
+        assert_eq!(bytes, [0x12, 0x34, 0x01, 0b0000_0110, 0b0000_0100, 4]);
+        assert_eq!(ControlHeader::parse(&bytes), Some(header));
+        assert_eq!(ControlHeader::parse(&bytes[..5]), None);
+    }
+}
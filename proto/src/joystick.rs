@@ -0,0 +1,73 @@
+//! Joystick tags, which carry a slot's axes, buttons, and POVs
+
+/// Number of joystick slots the driver station exposes to robot code
+pub const MAX_JOYSTICKS: usize = 6;
+/// Maximum number of axes per joystick
+pub const MAX_AXES: usize = 12;
+/// Maximum number of buttons per joystick
+pub const MAX_BUTTONS: usize = 32;
+/// Maximum number of POVs (hats) per joystick
+pub const MAX_POVS: usize = 12;
+
+/// How many bytes [`encode`] writes for a joystick with this many inputs
+pub const fn encoded_len(axes: usize, buttons: usize, povs: usize) -> usize {
+    3 + axes + buttons.div_ceil(8) + povs * 2
+}
+
+/// Write a joystick tag's data to the start of `buf`, returning its length
+///
+/// Axes are scaled to `-128..=127`, and POVs are in degrees (or `-1` when
+/// not pressed). Returns `None` if there are more inputs than the protocol
+/// allows, or `buf` is too short.
+pub fn encode(axes: &[i8], buttons: &[bool], povs: &[i16], buf: &mut [u8]) -> Option<usize> {
+    if axes.len() > MAX_AXES || buttons.len() > MAX_BUTTONS || povs.len() > MAX_POVS {
+        return None;
+    }
+    let len = encoded_len(axes.len(), buttons.len(), povs.len());
+    let buf = buf.get_mut(..len)?;
+
+    let (axis_count, rest) = buf.split_first_mut()?;
+    *axis_count = axes.len() as u8;
+    let (axis_bytes, rest) = rest.split_at_mut(axes.len());
+    for (byte, axis) in axis_bytes.iter_mut().zip(axes) {
+        *byte = *axis as u8;
+    }
+
+    // Each button's state is a single bit, packed into as few big endian
+    // bytes as possible, with the first button in the least significant bit
+    let packed = buttons
+        .iter()
+        .enumerate()
+        .fold(0u32, |packed, (i, button)| packed | (*button as u32) << i);
+    let (button_count, rest) = rest.split_first_mut()?;
+    *button_count = buttons.len() as u8;
+    let (button_bytes, rest) = rest.split_at_mut(buttons.len().div_ceil(8));
+    button_bytes.copy_from_slice(&packed.to_be_bytes()[4 - button_bytes.len()..]);
+
+    let (pov_count, rest) = rest.split_first_mut()?;
+    *pov_count = povs.len() as u8;
+    for (bytes, pov) in rest.chunks_exact_mut(2).zip(povs) {
+        bytes.copy_from_slice(&pov.to_be_bytes());
+    }
+
+    Some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_joystick() {
+        let mut buf = [0u8; 32];
+        let buttons = [true, false, false, false, false, false, false, false, true];
+        let len = encode(&[-128, 127], &buttons, &[90], &mut buf).unwrap();
+        assert_eq!(
+            buf[..len],
+            [2, 0x80, 0x7F, 9, 0b0000_0001, 0b0000_0001, 1, 0, 90]
+        );
+
+        assert_eq!(encode(&[0; MAX_AXES + 1], &[], &[], &mut buf), None);
+        assert_eq!(encode(&[0], &[], &[], &mut buf[..3]), None);
+    }
+}
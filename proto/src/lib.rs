@@ -0,0 +1,39 @@
+//! The FRC driver station wire format, without `std`
+//!
+//! This is only the layout of the packets: the fixed headers of control and
+//! status packets, their flags, the size-prefixed tags that follow them, and
+//! joystick tags. Nothing here allocates or does IO, so it works just as
+//! well on a microcontroller bridging an operator console as it does under
+//! [`robudst`](https://docs.rs/robudst), which builds its driver station on
+//! top of it.
+//!
+//! ```
+//! use robudst_proto::{
+//!     control::{self, Control, ControlHeader, Request},
+//!     tag,
+//! };
+//!
+//! let mut packet = [0u8; 64];
+//! let header = ControlHeader {
+//!     seqnum: 1,
+//!     comm_version: control::COMM_VERSION,
+//!     control: Control::ENABLED | Control::TELEOP,
+//!     request: Request::empty(),
+//!     station: 0,
+//! };
+//! packet[..control::HEADER_LEN].copy_from_slice(&header.to_bytes());
+//!
+//! let mut len = control::HEADER_LEN;
+//! len += tag::write_udp(&mut packet[len..], control::JOYSTICK, |buf| {
+//!     robudst_proto::joystick::encode(&[0, 127], &[true], &[-1], buf)
+//! })
+//! .unwrap();
+//! assert_eq!(len, 6 + 2 + 8);
+//! ```
+
+#![no_std]
+
+pub mod control;
+pub mod joystick;
+pub mod status;
+pub mod tag;
@@ -0,0 +1,164 @@
+//! Status packets, which the roboRIO sends back for every control packet
+
+use bitflags::bitflags;
+
+/// Length of the fixed part of a status packet, before its tags
+pub const HEADER_LEN: usize = 8;
+
+/// Tag with a joystick's outputs and rumble, one per slot, in slot order
+pub const JOYSTICK_OUTPUT: u8 = 0x01;
+/// Tag with the free disk space, in bytes
+pub const DISK_SPACE: u8 = 0x04;
+/// Tag with CPU usage
+pub const CPU_INFO: u8 = 0x05;
+/// Tag with RAM usage
+pub const RAM_INFO: u8 = 0x06;
+/// Tag with a PDP or PDH log, told apart by length
+pub const POWER: u8 = 0x08;
+/// Tag with CAN bus metrics
+pub const CAN_METRICS: u8 = 0x0E;
+
+bitflags! {
+    /// The status byte of a status packet, as the roboRIO sent it
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct Status: u8 {
+        const ESTOP = 0b1000_0000;
+        const BROWNOUT = 0b0001_0000;
+        const CODE_START = 0b0000_1000;
+        const ENABLED = 0b0000_0100;
+
+        // Mode flags
+        const TELEOP = 0b00;
+        const TEST = 0b01;
+        const AUTO = 0b10;
+    }
+}
+impl Status {
+    #[inline(always)]
+    pub const fn is_enabled(&self) -> bool {
+        self.contains(Status::ENABLED)
+    }
+
+    #[inline(always)]
+    pub const fn is_browned_out(self) -> bool {
+        self.contains(Status::BROWNOUT)
+    }
+
+    #[inline(always)]
+    pub const fn is_estopped(self) -> bool {
+        self.contains(Status::ESTOP)
+    }
+
+    // The mode is a two bit field rather than separate flags, and teleop is
+    // zero, so `contains` can't be used for these. Anything else is teleop.
+    #[inline(always)]
+    pub const fn is_in_auto(&self) -> bool {
+        self.bits() & 0b11 == Self::AUTO.bits()
+    }
+    #[inline(always)]
+    pub const fn is_in_test(&self) -> bool {
+        self.bits() & 0b11 == Self::TEST.bits()
+    }
+}
+
+bitflags! {
+    /// What the roboRIO reports about itself and the robot code, in every
+    /// status packet
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct Trace: u8 {
+        const ROBOT_CODE = 0b0010_0000;
+        const IS_ROBORIO = 0b0001_0000;
+        const TEST_MODE  = 0b0000_1000;
+        const AUTONOMOUS = 0b0000_0100;
+        const TELEOP     = 0b0000_0010;
+        const DISABLED   = 0b0000_0001;
+    }
+}
+impl Trace {
+    #[inline(always)]
+    pub const fn has_robot_code(&self) -> bool {
+        self.contains(Self::ROBOT_CODE)
+    }
+
+    /// Whether this is a real roboRIO, rather than a simulator
+    #[inline(always)]
+    pub const fn is_roborio(&self) -> bool {
+        self.contains(Self::IS_ROBORIO)
+    }
+}
+
+/// The fixed part of a status packet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatusHeader {
+    pub seqnum: u16,
+    pub comm_version: u8,
+    pub status: Status,
+    pub trace: Trace,
+    /// Battery voltage, to the nearest 256th of a volt
+    pub battery: f32,
+    /// Whether the roboRIO wants the date and timezone tags
+    pub need_date: bool,
+}
+impl StatusHeader {
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let [seq_hi, seq_lo] = self.seqnum.to_be_bytes();
+        // `f32::fract` needs `std`
+        let volts = self.battery as u8;
+        [
+            seq_hi,
+            seq_lo,
+            self.comm_version,
+            self.status.bits(),
+            self.trace.bits(),
+            // Whole volts, then 256ths of a volt
+            volts,
+            ((self.battery - volts as f32) * 256.0) as u8,
+            self.need_date as u8,
+        ]
+    }
+
+    /// Decode the header at the start of `buf`, or `None` if it's too short
+    ///
+    /// Tags start at [`HEADER_LEN`]. Status and trace bits this crate
+    /// doesn't know about are kept rather than rejecting the whole packet.
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        let header: &[u8; HEADER_LEN] = buf.first_chunk()?;
+        Some(Self {
+            seqnum: u16::from_be_bytes([header[0], header[1]]),
+            comm_version: header[2],
+            status: Status::from_bits_retain(header[3]),
+            trace: Trace::from_bits_retain(header[4]),
+            battery: header[5] as f32 + header[6] as f32 / 256.0,
+            need_date: header[7] == 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trips() {
+        let header = StatusHeader {
+            seqnum: 7,
+            comm_version: 0x01,
+            status: Status::ENABLED | Status::TEST,
+            trace: Trace::ROBOT_CODE | Trace::IS_ROBORIO,
+            battery: 12.5,
+            need_date: true,
+        };
+        let bytes = header.to_bytes();
+        assert_eq!(bytes, [0, 7, 0x01, 0b0000_0101, 0b0011_0000, 12, 128, 1]);
+        assert_eq!(StatusHeader::parse(&bytes), Some(header));
+        assert_eq!(StatusHeader::parse(&bytes[..7]), None);
+    }
+
+    #[test]
+    fn mode_is_a_two_bit_field() {
+        assert!(Status::AUTO.is_in_auto());
+        assert!(!Status::AUTO.is_in_test());
+        assert!(!Status::from_bits_retain(0b11).is_in_auto());
+        assert!(!Status::TELEOP.is_in_test());
+    }
+}
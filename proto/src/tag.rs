@@ -0,0 +1,176 @@
+//! The size-prefixed tags that follow a packet's header
+//!
+//! UDP tags have a one byte size and TCP tags a two byte one. Either way the
+//! size covers the tag's id and data, but not itself.
+
+/// A single tag, without its size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tag<'a> {
+    pub id: u8,
+    pub data: &'a [u8],
+}
+
+/// Splits the tags out of a datagram, after its header
+///
+/// Tags with a size of zero are skipped. A tag cut off by the end of the
+/// buffer ends the iteration, and is left in [`remaining`](Self::remaining).
+#[derive(Debug, Clone)]
+pub struct UdpTags<'a> {
+    buf: &'a [u8],
+}
+impl<'a> UdpTags<'a> {
+    #[inline(always)]
+    pub const fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// Whatever hasn't been split off yet
+    #[inline(always)]
+    pub const fn remaining(&self) -> &'a [u8] {
+        self.buf
+    }
+}
+impl<'a> Iterator for UdpTags<'a> {
+    type Item = Tag<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (&size, rest) = self.buf.split_first()?;
+            let (tag, rest) = rest.split_at_checked(size as usize)?;
+            self.buf = rest;
+
+            if let Some((&id, data)) = tag.split_first() {
+                return Some(Tag { id, data });
+            }
+        }
+    }
+}
+
+/// Splits the tags out of the TCP stream
+///
+/// Tags with a size of zero, which are sometimes used as keepalives, are
+/// skipped. A tag cut off by the end of the buffer ends the iteration, and
+/// is left in [`remaining`](Self::remaining) until the rest arrives.
+#[derive(Debug, Clone)]
+pub struct TcpTags<'a> {
+    buf: &'a [u8],
+}
+impl<'a> TcpTags<'a> {
+    #[inline(always)]
+    pub const fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// Whatever hasn't been split off yet
+    #[inline(always)]
+    pub const fn remaining(&self) -> &'a [u8] {
+        self.buf
+    }
+}
+impl<'a> Iterator for TcpTags<'a> {
+    type Item = Tag<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (size, rest) = self.buf.split_first_chunk::<2>()?;
+            let (tag, rest) = rest.split_at_checked(u16::from_be_bytes(*size) as usize)?;
+            self.buf = rest;
+
+            if let Some((&id, data)) = tag.split_first() {
+                return Some(Tag { id, data });
+            }
+        }
+    }
+}
+
+/// Write a UDP tag to the start of `buf`, returning its whole length
+///
+/// `data` writes the tag's data to the slice it's given, and returns how
+/// long it is. Returns `None` if the tag doesn't fit in `buf`, or is too
+/// long for its size.
+pub fn write_udp(
+    buf: &mut [u8],
+    id: u8,
+    data: impl FnOnce(&mut [u8]) -> Option<usize>,
+) -> Option<usize> {
+    let [size, tag_id, rest @ ..] = buf else {
+        return None;
+    };
+    let len = data(rest)?;
+    *size = u8::try_from(len + 1).ok()?;
+    *tag_id = id;
+    Some(2 + len)
+}
+
+/// Write a TCP tag to the start of `buf`, returning its whole length
+///
+/// Just like [`write_udp`], but with a two byte size.
+pub fn write_tcp(
+    buf: &mut [u8],
+    id: u8,
+    data: impl FnOnce(&mut [u8]) -> Option<usize>,
+) -> Option<usize> {
+    let [size_hi, size_lo, tag_id, rest @ ..] = buf else {
+        return None;
+    };
+    let len = data(rest)?;
+    [*size_hi, *size_lo] = u16::try_from(len + 1).ok()?.to_be_bytes();
+    *tag_id = id;
+    Some(3 + len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_udp_tags() {
+        let buf = [2, 0x04, 0xAA, 0, 1, 0x09, 3, 0x0E, 0xBB];
+        let mut tags = UdpTags::new(&buf);
+        assert_eq!(
+            tags.next(),
+            Some(Tag {
+                id: 0x04,
+                data: &[0xAA]
+            })
+        );
+        assert_eq!(
+            tags.next(),
+            Some(Tag {
+                id: 0x09,
+                data: &[]
+            })
+        );
+        assert_eq!(tags.next(), None);
+        assert_eq!(tags.remaining(), [3, 0x0E, 0xBB]);
+    }
+
+    #[test]
+    fn splits_tcp_tags() {
+        let buf = [0, 0, 0, 2, 0x0C, b'!', 0, 5, 0x0B];
+        let mut tags = TcpTags::new(&buf);
+        assert_eq!(
+            tags.next(),
+            Some(Tag {
+                id: 0x0C,
+                data: b"!"
+            })
+        );
+        assert_eq!(tags.next(), None);
+        assert_eq!(tags.remaining(), [0, 5, 0x0B]);
+    }
+
+    #[test]
+    fn writes_tags() {
+        let mut buf = [0u8; 8];
+        let write = |buf: &mut [u8]| {
+            buf.get_mut(..2)?.copy_from_slice(&[1, 2]);
+            Some(2)
+        };
+        assert_eq!(write_udp(&mut buf, 0x07, write), Some(4));
+        assert_eq!(buf[..4], [3, 0x07, 1, 2]);
+        assert_eq!(write_tcp(&mut buf, 0x0E, write), Some(5));
+        assert_eq!(buf[..5], [0, 3, 0x0E, 1, 2]);
+        assert_eq!(write_tcp(&mut buf[..4], 0x0E, write), None);
+    }
+}
@@ -0,0 +1,250 @@
+//! Recent battery voltage, for graphing it like the official DS does,
+//! alerts for when it gets low, and brownout tracking
+//!
+//! A sample is kept for every status packet from the roboRIO.
+
+use std::{collections::VecDeque, sync::atomic::Ordering, time::Instant};
+
+use crate::{Ds, event::DsEvent, trace::Level};
+
+/// How many battery samples are kept by default, a minute's worth at the
+/// roboRIO's usual 50Hz
+pub const DEFAULT_BATTERY_HISTORY_CAPACITY: usize = 3000;
+
+/// How far above its threshold the voltage has to recover before an alert
+/// can fire again, by default
+pub const DEFAULT_ALERT_HYSTERESIS: f32 = 0.5;
+
+/// Fires a [`DsEvent::VoltageLow`] when the battery drops below a threshold
+///
+/// Voltage sags hard while motors stall, so once an alert fires it won't
+/// fire again until the voltage is back above `threshold + hysteresis`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoltageAlert {
+    pub threshold: f32,
+    pub hysteresis: f32,
+}
+impl VoltageAlert {
+    pub const fn new(threshold: f32) -> Self {
+        Self {
+            threshold,
+            hysteresis: DEFAULT_ALERT_HYSTERESIS,
+        }
+    }
+
+    pub const fn hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+}
+
+/// A registered alert, and whether it's currently fired
+pub(crate) struct AlertState {
+    alert: VoltageAlert,
+    low: bool,
+}
+
+/// The battery voltage at a point in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatterySample {
+    /// When the status packet carrying this voltage arrived
+    pub at: Instant,
+    pub voltage: f32,
+}
+
+/// A bounded buffer of the most recent battery samples
+pub(crate) struct BatteryHistory {
+    samples: VecDeque<BatterySample>,
+    capacity: usize,
+}
+impl BatteryHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Add a sample, dropping the oldest one if full
+    pub fn push(&mut self, sample: BatterySample) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.samples.len() > capacity {
+            self.samples.pop_front();
+        }
+    }
+}
+
+impl Ds {
+    /// Get the most recent battery samples, oldest first
+    pub fn battery_history(&self) -> Vec<BatterySample> {
+        self.battery_history
+            .lock()
+            .unwrap()
+            .samples
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    /// Change how many battery samples are kept, dropping the oldest ones if
+    /// there are too many
+    pub fn set_battery_history_capacity(&self, capacity: usize) {
+        self.battery_history.lock().unwrap().set_capacity(capacity);
+    }
+
+    /// Forget all battery samples
+    pub fn clear_battery_history(&self) {
+        self.battery_history.lock().unwrap().samples.clear();
+    }
+
+    /// Get notified when the battery drops below `alert`'s threshold
+    ///
+    /// Alerts for several thresholds (like a warning and a critical level)
+    /// can be added.
+    pub fn add_voltage_alert(&self, alert: VoltageAlert) {
+        self.voltage_alerts
+            .lock()
+            .unwrap()
+            .push(AlertState { alert, low: false });
+    }
+
+    /// Remove every alert added with [`Ds::add_voltage_alert`]
+    pub fn clear_voltage_alerts(&self) {
+        self.voltage_alerts.lock().unwrap().clear();
+    }
+
+    /// How many times the robot has browned out since this DS was created,
+    /// or since [`Ds::reset_brownout_count`]
+    pub fn brownout_count(&self) -> u32 {
+        self.brownouts.load(Ordering::Acquire)
+    }
+
+    /// Start counting brownouts from zero, like at the start of a match
+    pub fn reset_brownout_count(&self) {
+        self.brownouts.store(0, Ordering::Release);
+    }
+
+    /// Count a brownout the roboRIO just reported
+    pub(crate) fn brownout_occurred(&self, voltage: f32) {
+        let count = self.brownouts.fetch_add(1, Ordering::AcqRel) + 1;
+        event!(Level::WARN, voltage, count, "Robot browned out");
+        self.emit(DsEvent::BrownoutOccurred { voltage });
+    }
+
+    /// Store the voltage from a status packet
+    pub(crate) fn record_battery(&self, voltage: f32) {
+        self.battery.store(voltage);
+        self.battery_history.lock().unwrap().push(BatterySample {
+            at: Instant::now(),
+            voltage,
+        });
+
+        for state in self.voltage_alerts.lock().unwrap().iter_mut() {
+            let VoltageAlert {
+                threshold,
+                hysteresis,
+            } = state.alert;
+
+            if !state.low && voltage < threshold {
+                state.low = true;
+                self.emit(DsEvent::VoltageLow { threshold, voltage });
+            } else if state.low && voltage > threshold + hysteresis {
+                state.low = false;
+                self.emit(DsEvent::VoltageRecovered { threshold, voltage });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MemoryTransport;
+
+    fn drain(events: &mut tokio::sync::broadcast::Receiver<DsEvent>) -> Vec<DsEvent> {
+        std::iter::from_fn(|| events.try_recv().ok()).collect()
+    }
+
+    #[test]
+    fn history_drops_the_oldest_samples() {
+        let mut history = BatteryHistory::new(3);
+        let at = Instant::now();
+        for voltage in [12.0, 11.5, 11.0, 10.5] {
+            history.push(BatterySample { at, voltage });
+        }
+        let voltages = |h: &BatteryHistory| h.samples.iter().map(|s| s.voltage).collect::<Vec<_>>();
+        assert_eq!(voltages(&history), [11.5, 11.0, 10.5]);
+
+        history.set_capacity(1);
+        assert_eq!(voltages(&history), [10.5]);
+
+        history.set_capacity(0);
+        history.push(BatterySample { at, voltage: 10.0 });
+        assert!(history.samples.is_empty());
+    }
+
+    #[tokio::test]
+    async fn alert_waits_for_recovery_before_firing_again() {
+        let ds = Ds::with_transport(4533, MemoryTransport::pair().0);
+        ds.add_voltage_alert(VoltageAlert::new(7.0));
+        let mut events = ds.subscribe();
+
+        ds.record_battery(12.0);
+        ds.record_battery(6.8);
+        // Still low, then bouncing around inside the hysteresis band
+        ds.record_battery(6.5);
+        ds.record_battery(7.2);
+        ds.record_battery(6.9);
+        ds.record_battery(7.6);
+        ds.record_battery(6.9);
+
+        assert_eq!(
+            drain(&mut events),
+            [
+                DsEvent::VoltageLow {
+                    threshold: 7.0,
+                    voltage: 6.8
+                },
+                DsEvent::VoltageRecovered {
+                    threshold: 7.0,
+                    voltage: 7.6
+                },
+                DsEvent::VoltageLow {
+                    threshold: 7.0,
+                    voltage: 6.9
+                },
+            ]
+        );
+        assert_eq!(ds.battery_history().len(), 7);
+    }
+
+    #[tokio::test]
+    async fn brownouts_are_counted_until_reset() {
+        let ds = Ds::with_transport(4533, MemoryTransport::pair().0);
+        let mut events = ds.subscribe();
+
+        ds.brownout_occurred(6.3);
+        ds.brownout_occurred(6.1);
+        assert_eq!(ds.brownout_count(), 2);
+        assert_eq!(
+            drain(&mut events),
+            [
+                DsEvent::BrownoutOccurred { voltage: 6.3 },
+                DsEvent::BrownoutOccurred { voltage: 6.1 },
+            ]
+        );
+
+        ds.reset_brownout_count();
+        assert_eq!(ds.brownout_count(), 0);
+    }
+}
@@ -0,0 +1,25 @@
+//! Runs a driver station from a config file, controlled over a local socket
+//!
+//! Usage: `robudstd <config.toml> [control-socket]`
+
+use std::{env, path::PathBuf, process::ExitCode};
+
+use robudst::daemon::{default_control_path, run_daemon};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut args = env::args_os().skip(1);
+    let Some(config_path) = args.next() else {
+        eprintln!("usage: robudstd <config.toml> [control-socket]");
+        return ExitCode::FAILURE;
+    };
+    let control_path = args.next().map_or_else(default_control_path, PathBuf::from);
+
+    match run_daemon(config_path, control_path).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("robudstd: {err:?}");
+            ExitCode::FAILURE
+        }
+    }
+}
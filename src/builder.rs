@@ -0,0 +1,186 @@
+//! Configuring a driver station before it starts
+
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    AlliancePos, DEFAULT_COMM_TIMEOUT, Ds, Error, PROTOCOL_YEAR, SEND_INTERVAL,
+    connection::Backoff,
+    discovery::{AddressConnector, ConnectionMode, RioAddress, RioConnector},
+    proto::version::{Frc2015, ProtocolVersion},
+    transport::Ports,
+    utils::gen_team_ip,
+};
+
+/// Builds a [`Ds`] with something other than the defaults
+///
+/// ```no_run
+/// # use robudst::Ds;
+/// let ds = Ds::builder(4533).address("127.0.0.1").build().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct DsBuilder {
+    team_number: u16,
+    address: Option<RioAddress>,
+    connection_mode: ConnectionMode,
+    ports: Ports,
+    send_interval: Duration,
+    alliance_pos: AlliancePos,
+    protocol_year: u16,
+    protocol: Arc<dyn ProtocolVersion>,
+    comm_timeout: Duration,
+    reconnect_backoff: Backoff,
+    joystick_interlock: Option<Duration>,
+}
+impl DsBuilder {
+    pub fn new(team_number: u16) -> Self {
+        Self {
+            team_number,
+            address: None,
+            connection_mode: ConnectionMode::Auto,
+            ports: Ports::default(),
+            send_interval: SEND_INTERVAL,
+            alliance_pos: AlliancePos::Red(1),
+            protocol_year: PROTOCOL_YEAR,
+            protocol: Arc::new(Frc2015),
+            comm_timeout: DEFAULT_COMM_TIMEOUT,
+            reconnect_backoff: Backoff::default(),
+            joystick_interlock: None,
+        }
+    }
+
+    /// Change the team number reported to the roboRIO and FMS
+    pub const fn team_number(mut self, team_number: u16) -> Self {
+        self.team_number = team_number;
+        self
+    }
+
+    /// Connect to `address` instead of looking for the roboRIO
+    ///
+    /// Handy for simulators, odd networks, or a roboRIO with a static IP.
+    /// The team number is still reported as usual.
+    pub fn address(mut self, address: impl Into<RioAddress>) -> Self {
+        self.address = Some(address.into());
+        self
+    }
+
+    /// Choose where to look for the roboRIO
+    ///
+    /// Ignored if an [`address`](Self::address) is set.
+    pub const fn connection_mode(mut self, mode: ConnectionMode) -> Self {
+        self.connection_mode = mode;
+        self
+    }
+
+    /// Drive a robot program in WPILib desktop simulation instead of a
+    /// real robot
+    ///
+    /// Shorthand for [`ConnectionMode::Sim`].
+    pub const fn sim(self) -> Self {
+        self.connection_mode(ConnectionMode::Sim)
+    }
+
+    /// Use non-standard ports
+    pub const fn ports(mut self, ports: Ports) -> Self {
+        self.ports = ports;
+        self
+    }
+
+    /// How often to send control packets, 20ms by default
+    pub const fn send_interval(mut self, interval: Duration) -> Self {
+        self.send_interval = interval;
+        self
+    }
+
+    /// Start out in `alliance_pos` instead of red 1
+    pub const fn alliance_pos(mut self, alliance_pos: AlliancePos) -> Self {
+        self.alliance_pos = alliance_pos;
+        self
+    }
+
+    /// Pin the FRC season whose protocol is spoken
+    pub const fn protocol_year(mut self, year: u16) -> Self {
+        self.protocol_year = year;
+        self
+    }
+
+    /// Speak a different wire format than the 2015+ one
+    ///
+    /// It has to support the [`protocol_year`](Self::protocol_year).
+    pub fn protocol(mut self, protocol: impl ProtocolVersion + 'static) -> Self {
+        self.protocol = Arc::new(protocol);
+        self
+    }
+
+    /// How long the roboRIO can go without sending status before it's
+    /// considered lost, 1 second by default
+    pub const fn comm_timeout(mut self, timeout: Duration) -> Self {
+        self.comm_timeout = timeout;
+        self
+    }
+
+    /// How long to wait between reconnection attempts
+    pub const fn reconnect_backoff(mut self, backoff: Backoff) -> Self {
+        self.reconnect_backoff = backoff;
+        self
+    }
+
+    /// Refuse to enable in teleop without joystick input from the last
+    /// `max_age`, see [`Ds::set_joystick_interlock`]
+    pub const fn joystick_interlock(mut self, max_age: Duration) -> Self {
+        self.joystick_interlock = Some(max_age);
+        self
+    }
+
+    /// Create the driver station, if the options make sense
+    ///
+    /// Nothing is connected until [`Ds::run`] is called.
+    pub fn build(self) -> Result<Ds, Error> {
+        if self.address.is_none()
+            && self.connection_mode == ConnectionMode::TeamIp
+            && gen_team_ip(self.team_number).is_none()
+        {
+            return Err(Error::InvalidTeamNumber);
+        }
+        self.alliance_pos.validate()?;
+        if !self.protocol.supports_year(self.protocol_year) {
+            return Err(Error::UnsupportedProtocolYear);
+        }
+        if self.send_interval.is_zero() || self.send_interval >= self.comm_timeout {
+            return Err(Error::InvalidSendInterval);
+        }
+
+        let mut ds = match self.address {
+            Some(address) => Ds::with_connector(
+                self.team_number,
+                AddressConnector {
+                    address,
+                    ports: self.ports,
+                },
+            ),
+            None => Ds::with_connector(
+                self.team_number,
+                RioConnector {
+                    ports: self.ports,
+                    ..RioConnector::new(self.connection_mode)
+                },
+            ),
+        };
+
+        ds.send_interval = self.send_interval;
+        ds.protocol_year = self.protocol_year;
+        ds.protocol = self.protocol;
+        ds.alliance_pos.store(self.alliance_pos);
+        ds.backoff.store(self.reconnect_backoff);
+        ds.comm_timeout.store(self.comm_timeout);
+        ds.joystick_interlock.store(self.joystick_interlock);
+
+        Ok(ds)
+    }
+}
+
+impl Ds {
+    /// Start configuring a driver station for `team_number`
+    pub fn builder(team_number: u16) -> DsBuilder {
+        DsBuilder::new(team_number)
+    }
+}
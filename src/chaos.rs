@@ -0,0 +1,431 @@
+//! Simulating a flaky field network, to test how a UI and the watchdogs
+//! cope with one
+//!
+//! A [`ChaosTransport`] wraps any other transport (usually one end of a
+//! [`MemoryTransport`](crate::transport::MemoryTransport) pair driven by a
+//! pretend roboRIO, or a real connection to a simulator) and delays, drops,
+//! duplicates, and reorders datagrams on their way through. Each direction
+//! gets its own [`LinkConditions`]:
+//!
+//! ```no_run
+//! # fn example() {
+//! use std::time::Duration;
+//! use robudst::{Ds, chaos::{ChaosTransport, LinkConditions}, transport::MemoryTransport};
+//!
+//! let (ds_end, rio_end) = MemoryTransport::pair();
+//! let field = LinkConditions::default()
+//!     .latency(Duration::from_millis(30))
+//!     .jitter(Duration::from_millis(15))
+//!     .drop_rate(0.05);
+//! let ds = Ds::with_transport(4533, ChaosTransport::new(ds_end, field, field));
+//! # }
+//! ```
+//!
+//! The byte stream is TCP, which hides loss and reordering itself, so it's
+//! passed through untouched.
+
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{
+    sync::{
+        Mutex as AsyncMutex,
+        mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+    },
+    task::JoinHandle,
+    time::sleep,
+};
+
+use crate::{
+    trace::Level,
+    transport::{Connect, Transport, TransportFuture},
+};
+
+/// How badly one direction of the network behaves
+///
+/// Rates are probabilities from `0.0` (never) to `1.0` (every datagram).
+/// The default is a perfect network.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LinkConditions {
+    /// How long every datagram takes to arrive
+    pub latency: Duration,
+    /// How far each datagram's delay can randomly stray from `latency`,
+    /// either way
+    ///
+    /// Datagrams can overtake each other when this is more than the time
+    /// between them.
+    pub jitter: Duration,
+    /// How often a datagram is lost
+    pub drop_rate: f64,
+    /// How often a datagram arrives twice
+    pub duplicate_rate: f64,
+    /// How often a datagram is held back long enough to arrive after the
+    /// next few
+    pub reorder_rate: f64,
+}
+impl LinkConditions {
+    pub const fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub const fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub const fn drop_rate(mut self, drop_rate: f64) -> Self {
+        self.drop_rate = drop_rate;
+        self
+    }
+
+    pub const fn duplicate_rate(mut self, duplicate_rate: f64) -> Self {
+        self.duplicate_rate = duplicate_rate;
+        self
+    }
+
+    pub const fn reorder_rate(mut self, reorder_rate: f64) -> Self {
+        self.reorder_rate = reorder_rate;
+        self
+    }
+}
+
+/// How long a reordered datagram is held back, on top of its usual delay
+///
+/// A few control packet intervals, so it lands behind the next ones.
+const REORDER_DELAY: Duration = Duration::from_millis(50);
+
+/// A small, seedable random number generator (SplitMix64)
+///
+/// Nothing here needs good randomness, just something repeatable so a bad
+/// run can be reproduced with the same seed.
+struct Rng(u64);
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0.0..1.0`
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, rate: f64) -> bool {
+        rate > 0.0 && self.next_f64() < rate
+    }
+}
+
+struct Chaos {
+    to_robot: LinkConditions,
+    from_robot: LinkConditions,
+    rng: Rng,
+}
+impl Chaos {
+    /// Decide what happens to a datagram: when each copy of it arrives, if
+    /// any do
+    fn plan(&mut self, to_robot: bool) -> Vec<Duration> {
+        let conditions = if to_robot {
+            self.to_robot
+        } else {
+            self.from_robot
+        };
+        if self.rng.chance(conditions.drop_rate) {
+            return Vec::new();
+        }
+
+        let copies = if self.rng.chance(conditions.duplicate_rate) {
+            2
+        } else {
+            1
+        };
+        (0..copies)
+            .map(|_| {
+                let jitter = conditions.jitter.as_secs_f64() * (self.rng.next_f64() * 2.0 - 1.0);
+                let mut delay =
+                    Duration::from_secs_f64((conditions.latency.as_secs_f64() + jitter).max(0.0));
+                if self.rng.chance(conditions.reorder_rate) {
+                    delay += REORDER_DELAY;
+                }
+                delay
+            })
+            .collect()
+    }
+}
+
+struct Shared<T> {
+    inner: T,
+    chaos: Mutex<Chaos>,
+}
+
+/// Wraps a [`Transport`], making its datagrams arrive late, out of order,
+/// twice, or not at all
+pub struct ChaosTransport<T> {
+    shared: Arc<Shared<T>>,
+    incoming: AsyncMutex<UnboundedReceiver<io::Result<Vec<u8>>>>,
+    /// Receives from `inner` as fast as it can, scheduling each datagram
+    reader: JoinHandle<()>,
+}
+impl<T: Transport + 'static> ChaosTransport<T> {
+    /// Wrap `inner`, with `to_robot` applied to control packets and
+    /// `from_robot` to status packets
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn new(inner: T, to_robot: LinkConditions, from_robot: LinkConditions) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let shared = Arc::new(Shared {
+            inner,
+            chaos: Mutex::new(Chaos {
+                to_robot,
+                from_robot,
+                rng: Rng(seed),
+            }),
+        });
+
+        let (tx, rx) = unbounded_channel();
+        let reader = tokio::spawn(receive(shared.clone(), tx));
+
+        Self {
+            shared,
+            incoming: AsyncMutex::new(rx),
+            reader,
+        }
+    }
+
+    /// Use a fixed seed, so the same datagrams get the same treatment every
+    /// run
+    pub fn seed(self, seed: u64) -> Self {
+        self.shared.chaos.lock().unwrap().rng = Rng(seed);
+        self
+    }
+
+    /// Change how the network behaves, from now on
+    pub fn set_conditions(&self, to_robot: LinkConditions, from_robot: LinkConditions) {
+        let mut chaos = self.shared.chaos.lock().unwrap();
+        chaos.to_robot = to_robot;
+        chaos.from_robot = from_robot;
+    }
+}
+impl<T> Drop for ChaosTransport<T> {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Pass datagrams from the roboRIO on to `tx`, each after its planned delay
+async fn receive<T: Transport + 'static>(
+    shared: Arc<Shared<T>>,
+    tx: UnboundedSender<io::Result<Vec<u8>>>,
+) {
+    let mut buf = [0; 1024];
+    loop {
+        let data = match shared.inner.recv_datagram(&mut buf).await {
+            Ok(len) => buf[..len].to_vec(),
+            Err(err) => {
+                let _ = tx.send(Err(err));
+                return;
+            }
+        };
+
+        let plan = shared.chaos.lock().unwrap().plan(false);
+        for delay in plan {
+            let tx = tx.clone();
+            let data = data.clone();
+            if delay.is_zero() {
+                // Nobody receiving means the transport was dropped
+                let _ = tx.send(Ok(data));
+            } else {
+                tokio::spawn(async move {
+                    sleep(delay).await;
+                    let _ = tx.send(Ok(data));
+                });
+            }
+        }
+    }
+}
+
+impl<T: Transport + 'static> Transport for ChaosTransport<T> {
+    fn send_datagram<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            let plan = self.shared.chaos.lock().unwrap().plan(true);
+            for delay in plan {
+                if delay.is_zero() {
+                    self.shared.inner.send_datagram(buf).await?;
+                    continue;
+                }
+
+                let shared = self.shared.clone();
+                let data = buf.to_vec();
+                tokio::spawn(async move {
+                    sleep(delay).await;
+                    if let Err(err) = shared.inner.send_datagram(&data).await {
+                        event!(Level::DEBUG, %err, "Delayed datagram couldn't be sent");
+                    }
+                });
+            }
+            Ok(())
+        })
+    }
+
+    fn recv_datagram<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        Box::pin(async move {
+            let datagram = self
+                .incoming
+                .lock()
+                .await
+                .recv()
+                .await
+                .ok_or(io::ErrorKind::ConnectionAborted)??;
+            let len = datagram.len().min(buf.len());
+            buf[..len].copy_from_slice(&datagram[..len]);
+            Ok(len)
+        })
+    }
+
+    fn write_stream<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        self.shared.inner.write_stream(buf)
+    }
+
+    fn read_stream<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        self.shared.inner.read_stream(buf)
+    }
+
+    fn close(&self) -> TransportFuture<'_, ()> {
+        self.shared.inner.close()
+    }
+}
+
+/// Wraps a [`Connect`], so every connection it makes is a
+/// [`ChaosTransport`]
+///
+/// Use this with [`Ds::with_connector`](crate::Ds::with_connector) to keep
+/// the bad network across reconnects.
+pub struct ChaosConnector<C> {
+    pub inner: C,
+    pub to_robot: LinkConditions,
+    pub from_robot: LinkConditions,
+}
+impl<C: Connect> Connect for ChaosConnector<C> {
+    fn connect(&self, team_number: u16) -> TransportFuture<'_, Box<dyn Transport>> {
+        Box::pin(async move {
+            let transport = self.inner.connect(team_number).await?;
+            Ok(Box::new(ChaosTransport::new(
+                transport,
+                self.to_robot,
+                self.from_robot,
+            )) as Box<dyn Transport>)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::transport::MemoryTransport;
+
+    #[test]
+    fn plans_follow_the_conditions() {
+        let conditions = LinkConditions::default()
+            .latency(Duration::from_millis(30))
+            .jitter(Duration::from_millis(10))
+            .drop_rate(0.2)
+            .duplicate_rate(0.1)
+            .reorder_rate(0.05);
+        let mut chaos = Chaos {
+            to_robot: conditions,
+            from_robot: LinkConditions::default(),
+            rng: Rng(4533),
+        };
+
+        const SENT: usize = 10_000;
+        let plans: Vec<_> = (0..SENT).map(|_| chaos.plan(true)).collect();
+        let rate = |count: usize, of: usize| count as f64 / of as f64;
+
+        let delivered = plans.iter().filter(|plan| !plan.is_empty()).count();
+        let dropped = rate(SENT - delivered, SENT);
+        assert!((0.18..0.22).contains(&dropped), "dropped {dropped}");
+
+        let duplicated = rate(
+            plans.iter().filter(|plan| plan.len() == 2).count(),
+            delivered,
+        );
+        assert!(
+            (0.08..0.12).contains(&duplicated),
+            "duplicated {duplicated}"
+        );
+
+        // Latency give or take jitter, plus the hold back for reordering
+        let (min, max) = (Duration::from_millis(20), Duration::from_millis(40));
+        let delays: Vec<_> = plans.iter().flatten().copied().collect();
+        let mut reordered = 0;
+        for mut delay in delays.iter().copied() {
+            if delay > max {
+                reordered += 1;
+                delay -= REORDER_DELAY;
+            }
+            assert!((min..=max).contains(&delay), "delayed {delay:?}");
+        }
+        let reordered = rate(reordered, delays.len());
+        assert!((0.04..0.06).contains(&reordered), "reordered {reordered}");
+
+        // The other direction is untouched
+        assert_eq!(chaos.plan(false), [Duration::ZERO]);
+    }
+
+    /// Send numbered datagrams through a seeded chaos transport, and get the
+    /// numbers that made it along with when the first one did
+    async fn send_through_chaos(seed: u64) -> (Vec<u16>, Duration) {
+        let to_robot = LinkConditions::default()
+            .latency(Duration::from_millis(20))
+            .drop_rate(0.25);
+        let (ds_end, rio) = MemoryTransport::pair();
+        let ds_end = ChaosTransport::new(ds_end, to_robot, LinkConditions::default()).seed(seed);
+
+        let start = Instant::now();
+        for n in 0..400u16 {
+            ds_end.send_datagram(&n.to_be_bytes()).await.unwrap();
+        }
+
+        let mut received = Vec::new();
+        let mut first = None;
+        let mut buf = [0u8; 2];
+        while let Ok(res) =
+            tokio::time::timeout(Duration::from_millis(200), rio.recv_datagram(&mut buf)).await
+        {
+            assert_eq!(res.unwrap(), 2);
+            first.get_or_insert_with(|| start.elapsed());
+            received.push(u16::from_be_bytes(buf));
+        }
+        received.sort_unstable();
+        (received, first.unwrap())
+    }
+
+    #[tokio::test]
+    async fn seeded_transport_drops_and_delays_datagrams() {
+        let (received, first) = send_through_chaos(4533).await;
+        assert!(
+            (260..340).contains(&received.len()),
+            "{} arrived",
+            received.len()
+        );
+        assert!(
+            first >= Duration::from_millis(20),
+            "first arrived after {first:?}"
+        );
+
+        // The same seed loses the same datagrams
+        let (again, _) = send_through_chaos(4533).await;
+        assert_eq!(received, again);
+    }
+}
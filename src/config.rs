@@ -0,0 +1,184 @@
+//! Loading driver station settings from a TOML file
+//!
+//! Meant for headless deployments that shouldn't need a recompile to change
+//! teams. Everything but the team number is optional:
+//!
+//! ```toml
+//! team-number = 4533
+//!
+//! [connection]
+//! mode = "usb"             # or "auto", "team-ip", "mdns", "sim"
+//! address = "10.45.33.2"   # overrides mode
+//! send-interval-ms = 20
+//!
+//! [safety]
+//! comm-timeout-ms = 1000
+//! reconnect-initial-ms = 100
+//! reconnect-max-ms = 5000
+//! joystick-interlock-ms = 500
+//!
+//! [[joysticks]]
+//! slot = 0
+//! deadband = 0.05
+//! inverted-axes = [1, 5]
+//! ```
+
+use std::{fs, io, path::Path, time::Duration};
+
+use serde::Deserialize;
+
+use crate::{
+    Ds, DsBuilder, Error, connection::Backoff, discovery::ConnectionMode,
+    joystick::JoystickMapping, transport::Ports,
+};
+
+/// Why a config file couldn't be used
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    /// The file parsed, but the settings don't make sense
+    Invalid(Error),
+}
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        Self::Parse(err)
+    }
+}
+impl From<Error> for ConfigError {
+    fn from(err: Error) -> Self {
+        Self::Invalid(err)
+    }
+}
+
+/// Everything a config file can set
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DsConfig {
+    pub team_number: u16,
+    #[serde(default)]
+    pub connection: ConnectionConfig,
+    #[serde(default)]
+    pub safety: SafetyConfig,
+    #[serde(default)]
+    pub joysticks: Vec<JoystickConfig>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ConnectionConfig {
+    pub mode: ConnectionMode,
+    /// IP address or hostname
+    pub address: Option<String>,
+    pub send_interval_ms: Option<u64>,
+    pub rio_tcp_port: Option<u16>,
+    pub rio_udp_port: Option<u16>,
+    pub ds_udp_port: Option<u16>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SafetyConfig {
+    pub comm_timeout_ms: Option<u64>,
+    pub reconnect_initial_ms: Option<u64>,
+    pub reconnect_max_ms: Option<u64>,
+    /// See [`Ds::set_joystick_interlock`]
+    pub joystick_interlock_ms: Option<u64>,
+}
+
+/// A [`JoystickMapping`] for one slot
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct JoystickConfig {
+    pub slot: usize,
+    #[serde(default)]
+    pub deadband: f32,
+    #[serde(default)]
+    pub inverted_axes: Vec<usize>,
+    #[serde(default)]
+    pub axis_order: Vec<usize>,
+    #[serde(default)]
+    pub button_order: Vec<usize>,
+}
+
+impl DsConfig {
+    /// Read and parse the config file at `path`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Get a builder with everything but the joysticks applied
+    pub fn builder(&self) -> DsBuilder {
+        let ConnectionConfig {
+            mode,
+            ref address,
+            send_interval_ms,
+            rio_tcp_port,
+            rio_udp_port,
+            ds_udp_port,
+        } = self.connection;
+        let ms = Duration::from_millis;
+
+        let defaults = Ports::default();
+        let mut builder = Ds::builder(self.team_number)
+            .connection_mode(mode)
+            .ports(Ports {
+                rio_tcp: rio_tcp_port.unwrap_or(defaults.rio_tcp),
+                rio_udp: rio_udp_port.unwrap_or(defaults.rio_udp),
+                ds_udp: ds_udp_port.unwrap_or(defaults.ds_udp),
+            });
+        if let Some(address) = address {
+            builder = builder.address(address.as_str());
+        }
+        if let Some(interval) = send_interval_ms {
+            builder = builder.send_interval(ms(interval));
+        }
+
+        if let Some(timeout) = self.safety.comm_timeout_ms {
+            builder = builder.comm_timeout(ms(timeout));
+        }
+        if let Some(max_age) = self.safety.joystick_interlock_ms {
+            builder = builder.joystick_interlock(ms(max_age));
+        }
+        let default_backoff = Backoff::default();
+        builder.reconnect_backoff(Backoff {
+            initial: self
+                .safety
+                .reconnect_initial_ms
+                .map_or(default_backoff.initial, ms),
+            max: self.safety.reconnect_max_ms.map_or(default_backoff.max, ms),
+            ..default_backoff
+        })
+    }
+}
+
+impl Ds {
+    /// Create a driver station from the TOML config file at `path`
+    ///
+    /// See [the module docs](crate::config) for what can be set.
+    pub fn from_config(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::from_ds_config(&DsConfig::load(path)?)
+    }
+
+    /// Create a driver station from an already loaded config
+    pub fn from_ds_config(config: &DsConfig) -> Result<Self, ConfigError> {
+        let ds = config.builder().build()?;
+
+        for joystick in &config.joysticks {
+            let mapping = JoystickMapping {
+                deadband: joystick.deadband,
+                inverted_axes: joystick.inverted_axes.clone(),
+                axis_order: joystick.axis_order.clone(),
+                button_order: joystick.button_order.clone(),
+            };
+            ds.set_joystick_mapping(joystick.slot, Some(mapping))?;
+        }
+
+        Ok(ds)
+    }
+}
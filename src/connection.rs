@@ -0,0 +1,385 @@
+//! The state of the driver station's connection to the roboRIO, and getting
+//! it back when it's lost
+
+use std::{
+    sync::Arc,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::watch;
+
+use crate::{
+    Ds, RobotStatus,
+    event::DsEvent,
+    proto::{
+        incoming::udp::{Status, Trace},
+        outgoing::udp::UdpOutgoingPacket,
+    },
+    timer::sleep,
+    trace::Level,
+    transport::{Connect, Transport},
+};
+
+/// How long to wait between connection attempts
+///
+/// The wait starts at `initial`, and is multiplied by `multiplier` after
+/// every failed attempt, up to `max`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: f32,
+}
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(5),
+            multiplier: 2.0,
+        }
+    }
+}
+
+/// Sequence number gaps bigger than this aren't counted as lost packets
+const MAX_SEQNUM_GAP: u16 = 100;
+
+/// How many control packets' send times are kept for measuring trip time
+///
+/// A second's worth at 50Hz, longer than any answer worth timing.
+pub(crate) const SENT_HISTORY: usize = 64;
+
+/// Where the driver station is in connecting to the roboRIO
+///
+/// This is what the "Communications" light on the official DS shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConnectionState {
+    /// Not connected, and not trying to be
+    Disconnected,
+    /// Looking for the roboRIO and waiting for it to accept a connection
+    Connecting,
+    Connected,
+    /// Was connected, but the roboRIO went away
+    Lost,
+}
+
+/// How the connection to the roboRIO is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectionStats {
+    pub state: ConnectionState,
+    /// Status packets received since this DS was created
+    pub packets_received: u64,
+    /// Status packets that never arrived, going by gaps in the control
+    /// packet sequence numbers the roboRIO echoes back
+    pub packets_lost: u64,
+    /// How long the roboRIO took to answer the last control packet it
+    /// answered
+    pub trip_time: Option<Duration>,
+}
+
+impl Ds {
+    /// Get the state of the connection to the roboRIO
+    #[inline(always)]
+    pub fn connection_state(&self) -> ConnectionState {
+        *self.connection.borrow()
+    }
+
+    /// Watch the state of the connection to the roboRIO
+    ///
+    /// Changes are also emitted as [`DsEvent::ConnectionStateChanged`].
+    pub fn watch_connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.connection.subscribe()
+    }
+
+    /// Get how the connection to the roboRIO is doing
+    pub fn connection_stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            state: self.connection_state(),
+            packets_received: self.packets_received.load(Ordering::Acquire),
+            packets_lost: self.packets_lost.load(Ordering::Acquire),
+            trip_time: self.trip_time.load(),
+        }
+    }
+
+    /// Build a control packet to send now, with the next sequence number
+    pub(crate) fn control_packet<'u>(&self) -> UdpOutgoingPacket<'u> {
+        let seqnum = self.control_seqnum.fetch_add(1, Ordering::AcqRel);
+        self.control_sent[seqnum as usize % SENT_HISTORY].store(Some((seqnum, Instant::now())));
+
+        let mut pkt = UdpOutgoingPacket::build(self);
+        pkt.set_seqnum(seqnum);
+        pkt
+    }
+
+    /// Count a status packet answering the control packet with sequence
+    /// number `seqnum`
+    pub(crate) fn count_status_packet(&self, seqnum: u16) {
+        self.packets_received.fetch_add(1, Ordering::AcqRel);
+        if let Some((sent, at)) = self.control_sent[seqnum as usize % SENT_HISTORY].load()
+            && sent == seqnum
+        {
+            self.trip_time.store(Some(at.elapsed()));
+        }
+
+        if let Some(last) = self.last_seqnum.swap(Some(seqnum)) {
+            let gap = seqnum.wrapping_sub(last);
+            // Anything bigger is more likely the roboRIO starting over, or a
+            // packet arriving late
+            if (2..=MAX_SEQNUM_GAP).contains(&gap) {
+                self.packets_lost
+                    .fetch_add(gap as u64 - 1, Ordering::AcqRel);
+            }
+        }
+    }
+
+    /// Change how long to wait between reconnection attempts
+    pub fn set_reconnect_backoff(&self, backoff: Backoff) {
+        self.backoff.store(backoff);
+    }
+
+    /// Change how long the roboRIO can go without sending status before the
+    /// robot is considered lost
+    ///
+    /// Defaults to 1 second.
+    pub fn set_comm_timeout(&self, timeout: Duration) {
+        self.comm_timeout.store(timeout);
+    }
+
+    /// Stop trusting the last status from the roboRIO after it went quiet
+    pub(crate) fn comm_lost(&self) {
+        event!(Level::WARN, "Lost communication with the robot");
+
+        self.store_status_with(|| self.forget_status());
+        self.emit(DsEvent::CommLost);
+    }
+
+    fn forget_status(&self) {
+        self.status.store(RobotStatus::NoCommunication);
+        self.status_flags.store(Status::empty());
+        self.trace.store(Trace::empty());
+        self.battery.store(0.0);
+        self.can_metrics.store(None);
+        self.power_stats.store(None);
+        self.cpu_info.store(None);
+        self.ram_info.store(None);
+        self.free_disk.store(None);
+        self.last_seqnum.store(None);
+        self.trip_time.store(None);
+    }
+
+    /// Get the current transport, if connected
+    pub(crate) fn transport(&self) -> Option<Arc<dyn Transport>> {
+        self.transport.read().unwrap().clone()
+    }
+
+    /// Connect with `connector`, retrying until it works
+    pub(crate) async fn connect(&self, connector: &dyn Connect) -> Arc<dyn Transport> {
+        let mut delay = self.backoff.load().initial;
+
+        loop {
+            self.set_connection_state(ConnectionState::Connecting);
+
+            match connector.connect(self.team_number()).await {
+                Ok(transport) => {
+                    let transport: Arc<dyn Transport> = transport.into();
+                    *self.transport.write().unwrap() = Some(transport.clone());
+
+                    // A fresh roboRIO doesn't know anything yet, reports its
+                    // versions and faults again, and isn't estopped anymore
+                    self.estop_latched.store(false, Ordering::Release);
+                    self.versions.lock().unwrap().clear();
+                    self.disable_faults.store(None);
+                    self.rail_faults.store(None);
+                    self.last_seqnum.store(None);
+                    self.descriptors_changed.store(true, Ordering::Release);
+                    self.tcp_state_stale.store(true, Ordering::Release);
+                    self.set_connection_state(ConnectionState::Connected);
+
+                    return transport;
+                }
+                Err(err) => {
+                    event!(Level::DEBUG, %err, ?delay, "Failed to connect to roboRIO");
+                    sleep(delay).await;
+
+                    let backoff = self.backoff.load();
+                    delay = delay.mul_f32(backoff.multiplier).min(backoff.max);
+                }
+            }
+        }
+    }
+
+    /// Forget the current transport after the roboRIO went away
+    pub(crate) fn connection_lost(&self) {
+        *self.transport.write().unwrap() = None;
+        self.status.store(RobotStatus::NoCommunication);
+        self.set_connection_state(ConnectionState::Lost);
+    }
+
+    pub(crate) fn set_connection_state(&self, state: ConnectionState) {
+        let changed = self.connection.send_if_modified(|current| {
+            let changed = *current != state;
+            *current = state;
+            changed
+        });
+
+        if changed {
+            event!(Level::INFO, ?state, "Connection state changed");
+            self.emit(DsEvent::ConnectionStateChanged(state));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io, sync::Mutex};
+
+    use crate::{
+        test_support::{ds::ControlPacket, rio},
+        transport::{MemoryTransport, TransportFuture},
+    };
+
+    fn status_packet(seqnum: u16) -> Vec<u8> {
+        rio::status_packet(seqnum, Status::empty(), Trace::DISABLED, 12.0, false, &[])
+    }
+
+    #[tokio::test]
+    async fn skipped_seqnum_counts_as_lost() {
+        let (ds_end, rio) = MemoryTransport::pair();
+        let ds = Ds::with_transport(4533, ds_end);
+
+        let test = async {
+            // The answer to control packet 2 never arrives
+            for seqnum in [0, 1, 3] {
+                rio.send_datagram(&status_packet(seqnum)).await.unwrap();
+            }
+            while ds.connection_stats().packets_received < 3 {
+                sleep(Duration::from_millis(5)).await;
+            }
+        };
+
+        tokio::select! {
+            _ = ds.run() => unreachable!(),
+            _ = test => {}
+        }
+        let stats = ds.connection_stats();
+        assert_eq!(stats.packets_received, 3);
+        assert_eq!(stats.packets_lost, 1);
+    }
+
+    #[tokio::test]
+    async fn echoed_seqnum_measures_trip_time() {
+        let (ds_end, rio) = MemoryTransport::pair();
+        let ds = Ds::with_transport(4533, ds_end);
+
+        let test = async {
+            let mut buf = [0u8; 1024];
+            let mut seqnums = Vec::new();
+            // Answer a few control packets like a roboRIO, a bit late
+            while seqnums.len() < 3 {
+                let len = rio.recv_datagram(&mut buf).await.unwrap();
+                let seqnum = ControlPacket::parse(&buf[..len]).unwrap().seqnum;
+                seqnums.push(seqnum);
+                sleep(Duration::from_millis(10)).await;
+                rio.send_datagram(&status_packet(seqnum)).await.unwrap();
+            }
+            while ds.connection_stats().packets_received < 3 {
+                sleep(Duration::from_millis(5)).await;
+            }
+            seqnums
+        };
+
+        let seqnums = tokio::select! {
+            _ = ds.run() => unreachable!(),
+            seqnums = test => seqnums,
+        };
+        // Every control packet gets the next sequence number
+        assert!(seqnums.windows(2).all(|pair| pair[1] > pair[0]));
+        let stats = ds.connection_stats();
+        assert!(stats.trip_time.unwrap() >= Duration::from_millis(10));
+    }
+
+    /// Fails the first attempt, then hands out `transport`
+    struct FlakyConnector {
+        attempts: Mutex<u32>,
+        transport: Mutex<Option<MemoryTransport>>,
+    }
+    impl Connect for FlakyConnector {
+        fn connect(&self, _team_number: u16) -> TransportFuture<'_, Box<dyn Transport>> {
+            Box::pin(async move {
+                let mut attempts = self.attempts.lock().unwrap();
+                *attempts += 1;
+                if *attempts == 1 {
+                    return Err(io::Error::from(io::ErrorKind::ConnectionRefused));
+                }
+
+                let transport = self.transport.lock().unwrap().take();
+                Ok(Box::new(transport.ok_or(io::ErrorKind::NotConnected)?) as Box<dyn Transport>)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn connection_state_follows_connect_attempts() {
+        let (ds_end, _rio) = MemoryTransport::pair();
+        let connector = FlakyConnector {
+            attempts: Mutex::new(0),
+            transport: Mutex::new(Some(ds_end)),
+        };
+        let ds = Ds::with_connector(4533, connector);
+        ds.set_reconnect_backoff(Backoff {
+            initial: Duration::from_millis(5),
+            ..Backoff::default()
+        });
+        assert_eq!(ds.connection_state(), ConnectionState::Disconnected);
+
+        let mut events = ds.subscribe();
+        let mut watcher = ds.watch_connection_state();
+        let test = async {
+            watcher
+                .wait_for(|state| *state == ConnectionState::Connected)
+                .await
+                .unwrap();
+        };
+
+        tokio::select! {
+            _ = ds.run() => unreachable!(),
+            _ = test => {}
+        }
+
+        let mut states = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let DsEvent::ConnectionStateChanged(state) = event {
+                states.push(state);
+            }
+        }
+        // The failed attempt doesn't leave Connecting, so it's only reported once
+        assert_eq!(
+            states,
+            [ConnectionState::Connecting, ConnectionState::Connected]
+        );
+        assert_eq!(ds.connection_stats().state, ConnectionState::Connected);
+    }
+
+    #[tokio::test]
+    async fn only_reconnecting_releases_an_estop() {
+        let ds = Ds::with_transport(4533, MemoryTransport::pair().0);
+        ds.estop().await;
+
+        // Quiet isn't a new roboRIO
+        ds.comm_lost();
+        assert!(ds.is_estopped());
+
+        let connector = FlakyConnector {
+            // The failed attempt is already used up
+            attempts: Mutex::new(1),
+            transport: Mutex::new(Some(MemoryTransport::pair().0)),
+        };
+        ds.connection_lost();
+        assert!(ds.is_estopped());
+        ds.connect(&connector).await;
+        assert!(!ds.is_estopped());
+    }
+}
@@ -0,0 +1,206 @@
+//! Recent console output from the robot, like the official DS console window
+//!
+//! Output arrives as TCP `Stdout` and error tags, and optionally over
+//! NetConsole (see [`Ds::run_netconsole`]). Both end up in the same history.
+
+use std::{collections::VecDeque, time::SystemTime};
+
+use crate::{Ds, event::DsEvent};
+#[cfg(all(feature = "runtime-tokio", not(target_arch = "wasm32")))]
+use {crate::trace::Level, tokio::net::UdpSocket};
+
+/// Port robot code sends NetConsole output to
+pub const NETCONSOLE_PORT: u16 = 6666;
+
+/// How many console lines are kept by default
+pub const DEFAULT_CONSOLE_CAPACITY: usize = 1000;
+
+/// How serious a console line is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConsoleLevel {
+    /// Plain robot output
+    Info,
+    Warning,
+    Error,
+}
+
+/// A single line of robot console output, or a reported error/warning
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConsoleLine {
+    /// Increases by one for every line the DS receives, so lines can be
+    /// told apart even after older ones fall out of the history
+    pub seqnum: u64,
+    /// When the DS received the line
+    pub received: SystemTime,
+    /// Seconds since robot code started, as reported by the robot
+    ///
+    /// NetConsole output doesn't include this.
+    pub robot_timestamp: Option<f32>,
+    pub level: ConsoleLevel,
+    /// The output itself, or the details of an error/warning
+    pub message: String,
+    /// Error code, for errors and warnings
+    pub error_code: Option<i32>,
+    /// Where an error/warning came from
+    pub location: String,
+    pub call_stack: String,
+}
+
+/// A bounded buffer of the most recent console lines
+pub(crate) struct ConsoleHistory {
+    lines: VecDeque<ConsoleLine>,
+    capacity: usize,
+    next_seqnum: u64,
+}
+impl ConsoleHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+            next_seqnum: 0,
+        }
+    }
+
+    /// Add a line, dropping the oldest one if full
+    ///
+    /// The line's `seqnum` is filled in here, and returned.
+    pub fn push(&mut self, mut line: ConsoleLine) -> u64 {
+        let seqnum = self.next_seqnum;
+        line.seqnum = seqnum;
+        self.next_seqnum += 1;
+
+        if self.capacity == 0 {
+            return seqnum;
+        }
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+
+        seqnum
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.lines.len() > capacity {
+            self.lines.pop_front();
+        }
+    }
+}
+
+impl Ds {
+    /// Get the most recent console lines, oldest first
+    pub fn console_history(&self) -> Vec<ConsoleLine> {
+        self.console.lock().unwrap().lines.iter().cloned().collect()
+    }
+
+    /// Get console lines newer than `seqnum`, oldest first
+    ///
+    /// Useful for following the console without seeing lines twice.
+    pub fn console_history_since(&self, seqnum: u64) -> Vec<ConsoleLine> {
+        self.console
+            .lock()
+            .unwrap()
+            .lines
+            .iter()
+            .filter(|line| line.seqnum > seqnum)
+            .cloned()
+            .collect()
+    }
+
+    /// Change how many console lines are kept, dropping the oldest ones if
+    /// there are too many
+    pub fn set_console_capacity(&self, capacity: usize) {
+        self.console.lock().unwrap().set_capacity(capacity);
+    }
+
+    /// Forget all console lines
+    pub fn clear_console(&self) {
+        self.console.lock().unwrap().lines.clear();
+    }
+
+    pub(crate) fn push_console_line(&self, mut line: ConsoleLine) {
+        line.seqnum = self.console.lock().unwrap().push(line.clone());
+        self.emit(DsEvent::ConsoleLine(line));
+    }
+
+    /// Listen for NetConsole output and add it to the console history until
+    /// an IO error occurs
+    ///
+    /// LabVIEW robot code and older roboRIO images print over NetConsole
+    /// instead of the DS TCP connection. Run this alongside [`Ds::run`] to
+    /// see that output too.
+    #[cfg(all(feature = "runtime-tokio", not(target_arch = "wasm32")))]
+    pub async fn run_netconsole(&self) -> std::io::Result<()> {
+        let socket = UdpSocket::bind(("0.0.0.0", NETCONSOLE_PORT)).await?;
+        let mut buf = [0u8; 4096];
+        // Output isn't split on line boundaries, so hold on to partial lines
+        let mut pending = String::new();
+
+        loop {
+            let len = socket.recv(&mut buf).await?;
+            pending.push_str(&String::from_utf8_lossy(&buf[..len]));
+
+            while let Some(end) = pending.find('\n') {
+                let line: String = pending.drain(..=end).collect();
+                let message = line.trim_end_matches(['\r', '\n']);
+
+                event!(Level::INFO, message, source = "netconsole");
+                self.push_console_line(ConsoleLine {
+                    seqnum: 0,
+                    received: SystemTime::now(),
+                    robot_timestamp: None,
+                    level: ConsoleLevel::Info,
+                    message: message.to_owned(),
+                    error_code: None,
+                    location: String::new(),
+                    call_stack: String::new(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::transport::{MemoryTransport, Transport};
+
+    #[tokio::test]
+    async fn stdout_split_across_reads_is_kept() {
+        let (ds_end, rio) = MemoryTransport::pair();
+        let ds = Ds::with_transport(4533, ds_end);
+
+        let message = b"split in two";
+        let mut tag = ((message.len() + 7) as u16).to_be_bytes().to_vec();
+        tag.push(0x0C);
+        tag.extend_from_slice(&1.5f32.to_be_bytes());
+        tag.extend_from_slice(&7u16.to_be_bytes());
+        tag.extend_from_slice(message);
+
+        let test = async {
+            // The size prefix and half the tag arrive first
+            rio.write_stream(&tag[..5]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert!(ds.console_history().is_empty());
+
+            rio.write_stream(&tag[5..]).await.unwrap();
+            while ds.console_history().is_empty() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        };
+
+        tokio::select! {
+            _ = ds.run() => unreachable!(),
+            _ = test => {}
+        }
+        let history = ds.console_history();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].message, "split in two");
+        assert_eq!(history[0].robot_timestamp, Some(1.5));
+    }
+}
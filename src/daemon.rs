@@ -0,0 +1,227 @@
+//! Running the driver station as a background service, controlled over a
+//! local socket
+//!
+//! [`run_daemon`] loads a [config file](crate::config), connects to the
+//! robot, and listens on a Unix domain socket (a named pipe on Windows), so
+//! the control surface can be a separate process: a UI, a script, or just
+//! `socat`. The `robudstd` binary does exactly this.
+//!
+//! Clients send one command per line, and get one line back for each:
+//! `ok`, `error <reason>`, or a JSON value.
+//!
+//! | Command            | Does                                          |
+//! |--------------------|-----------------------------------------------|
+//! | `enable`           | [`Ds::enable`]                                |
+//! | `disable`          | [`Ds::disable`]                               |
+//! | `estop`            | [`Ds::estop`]                                 |
+//! | `clear-estop`      | [`Ds::clear_estop`]                           |
+//! | `mode <mode>`      | [`Ds::set_mode`], with `auto`, `teleop`, or `test` |
+//! | `status`           | Replies with [`Ds::telemetry`] as JSON        |
+//! | `subscribe`        | Sends every [`DsEvent`] as a JSON line from now on |
+//! | `restart-code`     | [`Ds::restart_code`]                          |
+//! | `reboot`           | [`Ds::reboot_rio`]                            |
+//! | `shutdown`         | [`Ds::shutdown`], which also stops the daemon |
+//!
+//! ```sh
+//! $ echo status | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/robudst.sock
+//! ```
+//!
+//! Anything that can open the socket can enable the robot, so it should
+//! live somewhere only the DS's user can get to, like the default path.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    sync::broadcast::{Receiver, error::RecvError},
+};
+
+use crate::{
+    Ds, RobotCodeMode,
+    config::{ConfigError, DsConfig},
+    event::DsEvent,
+    handle::DsHandle,
+    trace::Level,
+};
+
+/// Where the control socket goes when not told otherwise
+///
+/// That's `robudst.sock` in `$XDG_RUNTIME_DIR` (falling back to the temp
+/// directory) on Unix, and the `\\.\pipe\robudst` named pipe on Windows.
+pub fn default_control_path() -> PathBuf {
+    #[cfg(windows)]
+    return PathBuf::from(r"\\.\pipe\robudst");
+
+    #[cfg(not(windows))]
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map_or_else(std::env::temp_dir, PathBuf::from)
+        .join("robudst.sock")
+}
+
+/// Load the config at `config_path`, run the driver station it describes,
+/// and serve the control socket at `control_path` until the DS is shut down
+pub async fn run_daemon(
+    config_path: impl AsRef<Path>,
+    control_path: impl AsRef<Path>,
+) -> Result<(), ConfigError> {
+    let config = DsConfig::load(config_path)?;
+    let (ds, task) = Ds::from_ds_config(&config)?.spawn();
+    event!(
+        Level::INFO,
+        team_number = config.team_number,
+        "Daemon started"
+    );
+
+    tokio::select! {
+        res = serve_control(ds.clone(), control_path.as_ref()) => {
+            // The robot mustn't keep running without a way to stop it
+            ds.shutdown().await;
+            res?;
+        }
+        _ = task => {}
+    }
+    Ok(())
+}
+
+/// Accept control connections for `ds` on the Unix socket at `path`, until
+/// the DS is shut down
+///
+/// A stale socket left by a previous run is replaced, and the socket is
+/// removed once done.
+#[cfg(unix)]
+pub async fn serve_control(ds: DsHandle, path: &Path) -> io::Result<()> {
+    use tokio::net::UnixListener;
+
+    // Connecting to a leftover socket fails, so it's safe to take over
+    if path.exists() && std::os::unix::net::UnixStream::connect(path).is_err() {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    event!(Level::INFO, path = %path.display(), "Listening for control connections");
+
+    let res = loop {
+        tokio::select! {
+            res = listener.accept() => match res {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_client(ds.clone(), stream));
+                }
+                Err(err) => break Err(err),
+            },
+            _ = ds.shutdown.cancelled() => break Ok(()),
+        }
+    };
+
+    let _ = std::fs::remove_file(path);
+    res
+}
+
+/// Accept control connections for `ds` on the named pipe at `path`, until
+/// the DS is shut down
+#[cfg(windows)]
+pub async fn serve_control(ds: DsHandle, path: &Path) -> io::Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(path)?;
+    event!(Level::INFO, path = %path.display(), "Listening for control connections");
+
+    loop {
+        tokio::select! {
+            res = server.connect() => {
+                res?;
+                // A new instance has to exist before the next client shows up
+                let client = std::mem::replace(&mut server, ServerOptions::new().create(path)?);
+                tokio::spawn(handle_client(ds.clone(), client));
+            }
+            _ = ds.shutdown.cancelled() => return Ok(()),
+        }
+    }
+}
+
+/// Run commands from one client until it disconnects
+async fn handle_client(ds: DsHandle, stream: impl AsyncRead + AsyncWrite) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    let mut events: Option<Receiver<DsEvent>> = None;
+
+    loop {
+        let reply = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => match run_command(&ds, line.trim(), &mut events).await {
+                    Ok(reply) => reply,
+                    Err(reason) => format!("error {reason}"),
+                },
+                Ok(None) => return,
+                Err(err) => {
+                    event!(Level::DEBUG, %err, "Control connection failed");
+                    return;
+                }
+            },
+            Some(event) = next_event(&mut events) => event,
+        };
+
+        if writer.write_all(reply.as_bytes()).await.is_err()
+            || writer.write_all(b"\n").await.is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Wait for the next event as a JSON line, or forever if not subscribed
+async fn next_event(events: &mut Option<Receiver<DsEvent>>) -> Option<String> {
+    let Some(rx) = events else {
+        return std::future::pending().await;
+    };
+    match rx.recv().await {
+        Ok(event) => serde_json::to_string(&event).ok(),
+        Err(RecvError::Lagged(missed)) => Some(format!("error missed {missed} events")),
+        Err(RecvError::Closed) => {
+            *events = None;
+            None
+        }
+    }
+}
+
+async fn run_command(
+    ds: &Ds,
+    line: &str,
+    events: &mut Option<Receiver<DsEvent>>,
+) -> Result<String, String> {
+    let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+    let arg = arg.trim();
+    event!(Level::DEBUG, command, "Control command");
+
+    match command {
+        "enable" => ds.enable().await.map_err(|e| format!("{e:?}"))?,
+        "disable" => ds.disable().await,
+        "estop" => {
+            event!(Level::WARN, "Emergency stop from control socket");
+            ds.estop().await;
+        }
+        "clear-estop" => ds.clear_estop().await,
+        "mode" => {
+            let mode = match arg {
+                "auto" | "autonomous" => RobotCodeMode::Autonomous,
+                "teleop" => RobotCodeMode::Teleop,
+                "test" => RobotCodeMode::Test,
+                _ => return Err(format!("unknown mode {arg:?}")),
+            };
+            ds.set_mode(mode).await;
+        }
+        "status" => {
+            return serde_json::to_string(&ds.telemetry()).map_err(|e| e.to_string());
+        }
+        "subscribe" => *events = Some(ds.subscribe()),
+        "restart-code" => ds.restart_code().await.map_err(|e| e.to_string())?,
+        "reboot" => ds.reboot_rio().await.map_err(|e| e.to_string())?,
+        "shutdown" => ds.shutdown().await,
+        "" => return Err("empty command".into()),
+        _ => return Err(format!("unknown command {command:?}")),
+    }
+    Ok("ok".into())
+}
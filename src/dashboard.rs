@@ -0,0 +1,53 @@
+//! Forwarding robot status to dashboards, like the official DS does on UDP
+//! port 1741
+//!
+//! The LabVIEW Dashboard (and third-party dashboards built to replace it)
+//! listen for the robot's status packets, which the official DS relays
+//! as-is after reading them. The format isn't documented beyond that, so
+//! every valid status packet is forwarded unchanged, and dashboards decode
+//! it the same way the DS does.
+
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+};
+
+use crate::{Ds, trace::Level};
+
+/// The port dashboards listen on for robot status
+pub const DASHBOARD_PORT: u16 = 1741;
+
+impl Ds {
+    /// Forward every status packet from the roboRIO to a dashboard at
+    /// `address`, or stop forwarding if `None`
+    pub fn forward_to_dashboard(&self, address: Option<SocketAddr>) -> io::Result<()> {
+        let socket = address
+            .map(|address| {
+                let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+                socket.connect(address)?;
+                // Sending happens on the receive loop, which mustn't stall
+                socket.set_nonblocking(true)?;
+                Ok::<_, io::Error>(socket)
+            })
+            .transpose()?;
+
+        event!(Level::INFO, ?address, "Dashboard forwarding changed");
+        *self.dashboard.lock().unwrap() = socket;
+        Ok(())
+    }
+
+    /// Forward status packets to a dashboard on this computer, where the
+    /// LabVIEW Dashboard runs
+    pub fn forward_to_local_dashboard(&self) -> io::Result<()> {
+        self.forward_to_dashboard(Some((Ipv4Addr::LOCALHOST, DASHBOARD_PORT).into()))
+    }
+
+    /// Pass a status packet on to the dashboard, if forwarding
+    pub(crate) fn forward_status(&self, packet: &[u8]) {
+        if let Some(socket) = &*self.dashboard.lock().unwrap() {
+            // Nothing listening, or a full buffer, just means a dashboard
+            // misses a packet
+            let _ = socket.send(packet);
+        }
+    }
+}
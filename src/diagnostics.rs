@@ -0,0 +1,611 @@
+//! Diagnostic data reported by the roboRIO
+//!
+//! Faults and versions come over TCP, and are published as a
+//! [`DsEvent`](crate::event::DsEvent) when they arrive. Usage stats come with
+//! status packets, and the latest ones are kept on [`Ds`].
+
+use crate::{Ds, event::DsEvent, trace::Level};
+
+/// The version of a piece of software or firmware on the robot
+///
+/// The roboRIO sends one of these for its image, the FRC libraries, and each
+/// CAN device it knows about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VersionEntry {
+    /// What kind of device this is, as reported by the roboRIO
+    pub device_type: u8,
+    /// Which device of that type, like a CAN ID
+    pub id: u8,
+    pub name: String,
+    pub version: String,
+}
+
+/// A single fault, for telling which counter went up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Fault {
+    /// The robot was disabled from losing communication
+    CommsDisable,
+    /// The robot was disabled from the 12V supply browning out
+    Power12vDisable,
+    Rail6v,
+    Rail5v,
+    Rail3v3,
+}
+
+/// Something the robot radio told the roboRIO about
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RadioEvent {
+    /// The radio connected to the DS side of the network
+    Connected,
+    /// The radio lost its connection to the DS side of the network
+    Disconnected,
+    /// The radio's configuration changed, with the radio's message
+    ConfigChanged(String),
+    /// Any message this crate doesn't recognize
+    Other(String),
+}
+/// How each radio message starts, and what it means
+///
+/// Checked in order, so longer prefixes go before shorter ones they start
+/// with.
+const RADIO_MESSAGES: &[(&str, RadioMessage)] = &[
+    ("Radio link restored", RadioMessage::Connected),
+    ("Radio connected", RadioMessage::Connected),
+    ("Radio link lost", RadioMessage::Disconnected),
+    ("Radio disconnected", RadioMessage::Disconnected),
+    ("Radio configuration changed", RadioMessage::ConfigChanged),
+    ("Configuration changed", RadioMessage::ConfigChanged),
+];
+
+#[derive(Debug, Clone, Copy)]
+enum RadioMessage {
+    Connected,
+    Disconnected,
+    ConfigChanged,
+}
+
+impl RadioEvent {
+    /// Work out what a radio event message means
+    ///
+    /// Only messages starting with one of the radio's known prefixes are
+    /// recognized, anything else is [`RadioEvent::Other`].
+    pub fn parse(message: &str) -> Self {
+        let known = RADIO_MESSAGES
+            .iter()
+            .find(|(prefix, _)| message.starts_with(prefix));
+
+        match known {
+            Some((_, RadioMessage::Connected)) => Self::Connected,
+            Some((_, RadioMessage::Disconnected)) => Self::Disconnected,
+            Some((_, RadioMessage::ConfigChanged)) => Self::ConfigChanged(message.to_owned()),
+            None => Self::Other(message.to_owned()),
+        }
+    }
+}
+
+/// What WPILib reported the robot program uses, from the roboRIO's usage
+/// report
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResourceUsage {
+    /// The team number the roboRIO is configured for
+    pub team: u16,
+    pub entries: Vec<UsageEntry>,
+}
+
+/// A single thing reported as used, like one motor controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UsageEntry {
+    /// WPILib's `tResourceType` for what's used
+    pub resource: u8,
+    /// Which instance of it, like a channel or CAN ID
+    pub instance: u8,
+}
+
+/// How many times the robot has been disabled by a fault since boot
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DisableFaults {
+    /// Disables from losing communication
+    pub comms: u16,
+    /// Disables from the 12V supply browning out
+    pub power_12v: u16,
+}
+
+/// How many times each of the roboRIO's user power rails has faulted since
+/// boot
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RailFaults {
+    pub rail_6v: u16,
+    pub rail_5v: u16,
+    pub rail_3v3: u16,
+}
+
+/// How busy the roboRIO's CPU is
+///
+/// Usage is split by the priority of what's running, each as a percentage.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuInfo {
+    pub num_cpus: f32,
+    pub time_critical: f32,
+    pub above_normal: f32,
+    pub normal: f32,
+    pub low: f32,
+}
+impl CpuInfo {
+    /// Total usage across every priority
+    pub fn total(&self) -> f32 {
+        self.time_critical + self.above_normal + self.normal + self.low
+    }
+}
+
+/// How much memory the roboRIO has free
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RamInfo {
+    /// The roboRIO's memory block size, in bytes
+    pub block: u32,
+    /// Free memory, in bytes
+    pub free_space: u32,
+}
+
+/// How healthy the robot's CAN bus is
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CanMetrics {
+    /// Bus utilization, from 0 to 1
+    pub utilization: f32,
+    /// How many times the roboRIO's CAN controller has gone bus-off
+    pub bus_off: u32,
+    /// How many times the transmit buffer was full
+    pub tx_full: u32,
+    pub rx_errors: u8,
+    pub tx_errors: u8,
+}
+
+/// How many channels a CTRE Power Distribution Panel has
+pub const PDP_CHANNELS: usize = 16;
+
+/// Readings from a CTRE Power Distribution Panel
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PdpStats {
+    /// Current through each channel, in amps
+    pub currents: [f32; PDP_CHANNELS],
+    /// Internal resistance, as the PDP reports it
+    pub resistance: u8,
+    /// Input voltage
+    pub voltage: f32,
+    /// Temperature, in degrees Celsius
+    pub temperature: f32,
+}
+
+/// How many channels a REV Power Distribution Hub has
+pub const PDH_CHANNELS: usize = 24;
+
+/// Readings from a REV Power Distribution Hub
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PdhStats {
+    /// Current through each channel, in amps
+    pub currents: [f32; PDH_CHANNELS],
+}
+impl Default for PdhStats {
+    fn default() -> Self {
+        Self {
+            currents: [0.0; PDH_CHANNELS],
+        }
+    }
+}
+
+/// Readings from whichever power distribution device the robot has
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PowerStats {
+    Pdp(PdpStats),
+    Pdh(PdhStats),
+}
+impl PowerStats {
+    /// Current through each channel, in amps
+    pub fn currents(&self) -> &[f32] {
+        match self {
+            Self::Pdp(stats) => &stats.currents,
+            Self::Pdh(stats) => &stats.currents,
+        }
+    }
+
+    /// Current through every channel together, in amps
+    pub fn total_current(&self) -> f32 {
+        self.currents().iter().sum()
+    }
+}
+
+/// Notices when a reported amount drops below a threshold, and only says so
+/// once until it's back above
+#[derive(Default)]
+pub(crate) struct LowWatermark {
+    threshold: Option<u32>,
+    low: bool,
+}
+impl LowWatermark {
+    pub fn set_threshold(&mut self, threshold: Option<u32>) {
+        self.threshold = threshold;
+        self.low = false;
+    }
+
+    /// Whether `value` just went below the threshold
+    pub fn crossed(&mut self, value: u32) -> bool {
+        let Some(threshold) = self.threshold else {
+            return false;
+        };
+
+        let was_low = self.low;
+        self.low = value < threshold;
+        self.low && !was_low
+    }
+}
+
+impl Ds {
+    /// Get how many times the robot has been disabled by each kind of fault
+    /// since the roboRIO booted
+    ///
+    /// These are all zero until the roboRIO reports them.
+    pub fn disable_faults(&self) -> DisableFaults {
+        self.disable_faults.load().unwrap_or_default()
+    }
+
+    /// Get how many times each of the roboRIO's user power rails has
+    /// faulted since it booted
+    ///
+    /// These are all zero until the roboRIO reports them.
+    pub fn rail_faults(&self) -> RailFaults {
+        self.rail_faults.load().unwrap_or_default()
+    }
+
+    pub(crate) fn record_disable_faults(&self, faults: DisableFaults) {
+        let previous = self.disable_faults.swap(Some(faults));
+        self.emit(DsEvent::DisableFaults(faults));
+
+        // The first report after connecting is counts from before then
+        if let Some(previous) = previous {
+            for (fault, now, before) in [
+                (Fault::CommsDisable, faults.comms, previous.comms),
+                (Fault::Power12vDisable, faults.power_12v, previous.power_12v),
+            ] {
+                self.fault_counted(fault, now, before);
+            }
+        }
+    }
+
+    pub(crate) fn record_rail_faults(&self, faults: RailFaults) {
+        let previous = self.rail_faults.swap(Some(faults));
+        self.emit(DsEvent::RailFaults(faults));
+
+        if let Some(previous) = previous {
+            for (fault, now, before) in [
+                (Fault::Rail6v, faults.rail_6v, previous.rail_6v),
+                (Fault::Rail5v, faults.rail_5v, previous.rail_5v),
+                (Fault::Rail3v3, faults.rail_3v3, previous.rail_3v3),
+            ] {
+                self.fault_counted(fault, now, before);
+            }
+        }
+    }
+
+    fn fault_counted(&self, fault: Fault, now: u16, before: u16) {
+        if now > before {
+            event!(Level::ERROR, ?fault, count = now, "A fault occurred");
+            self.emit(DsEvent::Fault(fault));
+        }
+    }
+
+    pub(crate) fn radio_event(&self, message: &str) {
+        event!(Level::INFO, message, "Radio event");
+        self.emit(DsEvent::RadioEvent(RadioEvent::parse(message)));
+    }
+
+    /// Get every version the roboRIO has reported since connecting, ordered
+    /// by device type then id
+    pub fn versions(&self) -> Vec<VersionEntry> {
+        let mut versions: Vec<_> = self.versions.lock().unwrap().values().cloned().collect();
+        versions.sort_by_key(|entry| (entry.device_type, entry.id));
+        versions
+    }
+
+    /// Get the version the roboRIO reported for one device, if it has
+    pub fn version(&self, device_type: u8, id: u8) -> Option<VersionEntry> {
+        self.versions
+            .lock()
+            .unwrap()
+            .get(&(device_type, id))
+            .cloned()
+    }
+
+    /// Warn with [`DsEvent::VersionMismatch`] whenever the roboRIO reports
+    /// something called `name` that isn't at `version`
+    ///
+    /// Like the official DS's version warnings, this catches an out of date
+    /// roboRIO image or library before it causes trouble. Versions already
+    /// reported are checked right away.
+    pub fn expect_version(&self, name: impl Into<String>, version: impl Into<String>) {
+        let (name, version) = (name.into(), version.into());
+
+        let mismatched: Vec<_> = self
+            .versions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|entry| entry.name == name && entry.version != version)
+            .cloned()
+            .collect();
+        self.expected_versions
+            .lock()
+            .unwrap()
+            .insert(name, version.clone());
+
+        for entry in mismatched {
+            self.version_mismatch(entry, version.clone());
+        }
+    }
+
+    /// Stop checking every version given to [`Ds::expect_version`]
+    pub fn clear_expected_versions(&self) {
+        self.expected_versions.lock().unwrap().clear();
+    }
+
+    pub(crate) fn record_version(&self, entry: VersionEntry) {
+        self.versions
+            .lock()
+            .unwrap()
+            .insert((entry.device_type, entry.id), entry.clone());
+        let expected = self
+            .expected_versions
+            .lock()
+            .unwrap()
+            .get(&entry.name)
+            .filter(|&expected| *expected != entry.version)
+            .cloned();
+
+        self.emit(DsEvent::VersionInfo(entry.clone()));
+        if let Some(expected) = expected {
+            self.version_mismatch(entry, expected);
+        }
+    }
+
+    fn version_mismatch(&self, entry: VersionEntry, expected: String) {
+        event!(
+            Level::WARN,
+            name = entry.name,
+            version = entry.version,
+            expected,
+            "Unexpected version"
+        );
+        self.emit(DsEvent::VersionMismatch { entry, expected });
+    }
+
+    /// Get the roboRIO's CPU usage from the last status packet that had it
+    ///
+    /// This is `None` until the roboRIO reports it, and after losing
+    /// communication.
+    pub fn cpu_info(&self) -> Option<CpuInfo> {
+        self.cpu_info.load()
+    }
+
+    /// Get the roboRIO's memory usage from the last status packet that had
+    /// it
+    ///
+    /// This is `None` until the roboRIO reports it, and after losing
+    /// communication.
+    pub fn ram_info(&self) -> Option<RamInfo> {
+        self.ram_info.load()
+    }
+
+    /// Emit [`DsEvent::LowMemory`] when the roboRIO's free memory drops
+    /// below `threshold` bytes, or stop if `None`
+    pub fn set_low_memory_threshold(&self, threshold: Option<u32>) {
+        self.low_memory.lock().unwrap().set_threshold(threshold);
+    }
+
+    /// Get the robot's CAN bus health from the last status packet that had
+    /// it
+    ///
+    /// This is `None` until the roboRIO reports it, and after losing
+    /// communication.
+    pub fn can_metrics(&self) -> Option<CanMetrics> {
+        self.can_metrics.load()
+    }
+
+    /// Get the latest power distribution readings, if the roboRIO has sent
+    /// any
+    ///
+    /// This is `None` after losing communication. Each new reading is also
+    /// sent as [`DsEvent::PowerStats`].
+    pub fn power_stats(&self) -> Option<PowerStats> {
+        self.power_stats.load()
+    }
+
+    pub(crate) fn store_power_stats(&self, stats: PowerStats) {
+        self.power_stats.store(Some(stats));
+        self.emit(DsEvent::PowerStats(stats));
+    }
+
+    pub(crate) fn store_ram_info(&self, info: RamInfo) {
+        self.ram_info.store(Some(info));
+
+        if self.low_memory.lock().unwrap().crossed(info.free_space) {
+            event!(
+                Level::WARN,
+                free = info.free_space,
+                "roboRIO is low on memory"
+            );
+            self.emit(DsEvent::LowMemory {
+                free_bytes: info.free_space,
+            });
+        }
+    }
+
+    /// Get the roboRIO's free disk space in bytes, from the last status
+    /// packet that had it
+    ///
+    /// This is `None` until the roboRIO reports it, and after losing
+    /// communication.
+    pub fn free_disk_bytes(&self) -> Option<u32> {
+        self.free_disk.load()
+    }
+
+    /// Emit [`DsEvent::LowDisk`] when the roboRIO's free disk space drops
+    /// below `threshold` bytes, or stop if `None`
+    ///
+    /// Deploys fail once the disk fills up, so this is worth keeping an eye
+    /// on.
+    pub fn set_low_disk_threshold(&self, threshold: Option<u32>) {
+        self.low_disk.lock().unwrap().set_threshold(threshold);
+    }
+
+    pub(crate) fn store_free_disk(&self, free_bytes: u32) {
+        self.free_disk.store(Some(free_bytes));
+
+        if self.low_disk.lock().unwrap().crossed(free_bytes) {
+            event!(
+                Level::WARN,
+                free = free_bytes,
+                "roboRIO is low on disk space"
+            );
+            self.emit(DsEvent::LowDisk { free_bytes });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        proto::incoming::udp::{Status, Trace},
+        test_support::rio,
+        timer::sleep,
+        transport::{MemoryTransport, Transport},
+    };
+
+    const MB: u32 = 1024 * 1024;
+
+    #[test]
+    fn low_watermark_says_so_once_per_drop() {
+        let mut watermark = LowWatermark::default();
+        // Nothing is low without a threshold
+        assert!(!watermark.crossed(0));
+
+        watermark.set_threshold(Some(100));
+        let crossings = [150, 99, 50, 100, 80, 80].map(|value| watermark.crossed(value));
+        assert_eq!(crossings, [false, true, false, false, true, false]);
+
+        // A new threshold starts over
+        watermark.set_threshold(Some(60));
+        assert!(watermark.crossed(50));
+        watermark.set_threshold(None);
+        assert!(!watermark.crossed(0));
+    }
+
+    #[tokio::test]
+    async fn usage_tags_are_kept() {
+        let (ds_end, rio) = MemoryTransport::pair();
+        let ds = Ds::with_transport(4533, ds_end);
+        ds.set_low_memory_threshold(Some(32 * MB));
+        ds.set_low_disk_threshold(Some(100 * MB));
+        let mut events = ds.subscribe();
+
+        let cpu = CpuInfo {
+            num_cpus: 2.0,
+            time_critical: 5.0,
+            above_normal: 10.0,
+            normal: 20.0,
+            low: 1.5,
+        };
+        let ram = RamInfo {
+            block: 4096,
+            free_space: 16 * MB,
+        };
+        let test = async {
+            let tags = [
+                rio::cpu_info(&cpu),
+                rio::ram_info(&ram),
+                rio::disk_space(64 * MB),
+            ];
+            let packet =
+                rio::status_packet(0, Status::empty(), Trace::DISABLED, 12.0, false, &tags);
+            rio.send_datagram(&packet).await.unwrap();
+            while ds.free_disk_bytes().is_none() {
+                sleep(Duration::from_millis(5)).await;
+            }
+        };
+
+        tokio::select! {
+            _ = ds.run() => unreachable!(),
+            _ = test => {}
+        }
+        assert_eq!(ds.cpu_info(), Some(cpu));
+        assert_eq!(ds.ram_info(), Some(ram));
+        assert_eq!(ds.free_disk_bytes(), Some(64 * MB));
+
+        let mut low = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            match event {
+                DsEvent::LowMemory { .. } | DsEvent::LowDisk { .. } => low.push(event),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            low,
+            [
+                DsEvent::LowMemory {
+                    free_bytes: 16 * MB
+                },
+                DsEvent::LowDisk {
+                    free_bytes: 64 * MB
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn radio_messages_go_by_prefix() {
+        let cases = [
+            ("Radio link restored", RadioEvent::Connected),
+            ("Radio connected to 10.45.33.5", RadioEvent::Connected),
+            ("Radio link lost", RadioEvent::Disconnected),
+            (
+                "Radio disconnected from 10.45.33.5",
+                RadioEvent::Disconnected,
+            ),
+            (
+                "Configuration changed, reconnecting",
+                RadioEvent::ConfigChanged("Configuration changed, reconnecting".to_owned()),
+            ),
+            (
+                "Radio configuration changed: channel 5",
+                RadioEvent::ConfigChanged("Radio configuration changed: channel 5".to_owned()),
+            ),
+            // Mentioning a keyword isn't enough
+            (
+                "Lost 3 beacons, still connected",
+                RadioEvent::Other("Lost 3 beacons, still connected".to_owned()),
+            ),
+            (
+                "radio link lost",
+                RadioEvent::Other("radio link lost".to_owned()),
+            ),
+            ("", RadioEvent::Other(String::new())),
+        ];
+
+        for (message, event) in cases {
+            assert_eq!(RadioEvent::parse(message), event, "{message:?}");
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Finding the roboRIO on the network
+//!
+//! The official DS doesn't know how the robot is connected, so it tries each
+//! place a roboRIO could be until one answers. [`RioConnector`] does the same.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+
+use crate::{
+    trace::Level,
+    transport::{Connect, Ports, Transport, TransportFuture},
+    utils::gen_team_ip,
+};
+
+/// Address the roboRIO always has over its USB device port
+pub const USB_RIO_ADDR: Ipv4Addr = Ipv4Addr::new(172, 22, 11, 2);
+/// Address of a robot program running in WPILib desktop simulation
+pub const SIM_RIO_ADDR: Ipv4Addr = Ipv4Addr::LOCALHOST;
+
+/// How long to wait on each address before trying the next
+#[cfg_attr(
+    any(not(feature = "runtime-tokio"), target_arch = "wasm32"),
+    allow(dead_code)
+)]
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where to look for the roboRIO
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "config",
+    derive(serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum ConnectionMode {
+    /// Try USB, then mDNS (with the `mdns` feature), then the team IP, like
+    /// the official DS
+    #[default]
+    Auto,
+    /// Only connect over USB, at [`USB_RIO_ADDR`]
+    Usb,
+    /// Only connect to whatever address `roboRIO-<team>-FRC.local` resolves
+    /// to
+    #[cfg(feature = "mdns")]
+    Mdns,
+    /// Only connect to the `10.TE.AM.2` team IP
+    TeamIp,
+    /// Only connect to a robot program in WPILib desktop simulation, at
+    /// [`SIM_RIO_ADDR`]
+    ///
+    /// The program needs the `halsim_ds_socket` extension, which listens on
+    /// the same ports as a real roboRIO.
+    Sim,
+}
+
+/// One place the roboRIO might be
+#[derive(Debug, Clone, Copy)]
+enum Candidate {
+    Addr(IpAddr),
+    /// A team's roboRIO, found with mDNS
+    #[cfg(feature = "mdns")]
+    Mdns(u16),
+}
+
+/// A fixed place to find the roboRIO, instead of looking for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RioAddress {
+    Ip(IpAddr),
+    /// Looked up with the system resolver on every connection attempt
+    Hostname(String),
+}
+impl From<IpAddr> for RioAddress {
+    fn from(addr: IpAddr) -> Self {
+        Self::Ip(addr)
+    }
+}
+impl From<Ipv4Addr> for RioAddress {
+    fn from(addr: Ipv4Addr) -> Self {
+        Self::Ip(addr.into())
+    }
+}
+impl From<Ipv6Addr> for RioAddress {
+    fn from(addr: Ipv6Addr) -> Self {
+        Self::Ip(addr.into())
+    }
+}
+impl From<&str> for RioAddress {
+    /// Parse an IP address (IPv6 may be in brackets), or use it as a
+    /// hostname
+    fn from(host: &str) -> Self {
+        let unbracketed = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+
+        match unbracketed.parse() {
+            Ok(addr) => Self::Ip(addr),
+            Err(_) => Self::Hostname(host.to_owned()),
+        }
+    }
+}
+impl From<String> for RioAddress {
+    fn from(host: String) -> Self {
+        host.as_str().into()
+    }
+}
+impl RioAddress {
+    /// Get every address this could be, IPv4 first
+    async fn resolve(&self) -> io::Result<Vec<IpAddr>> {
+        match self {
+            Self::Ip(addr) => Ok(vec![*addr]),
+            #[cfg(all(feature = "runtime-tokio", not(target_arch = "wasm32")))]
+            Self::Hostname(host) => {
+                let mut addrs: Vec<IpAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                    .await?
+                    .map(|addr| addr.ip())
+                    .collect();
+                addrs.sort_by_key(IpAddr::is_ipv6);
+                addrs.dedup();
+                Ok(addrs)
+            }
+            #[cfg(any(not(feature = "runtime-tokio"), target_arch = "wasm32"))]
+            Self::Hostname(_) => Err(io::ErrorKind::Unsupported.into()),
+        }
+    }
+}
+
+/// Connects to a roboRIO (or simulator) at a fixed [`RioAddress`]
+#[derive(Debug, Clone)]
+pub struct AddressConnector {
+    pub address: RioAddress,
+    pub ports: Ports,
+}
+impl AddressConnector {
+    pub fn new(address: impl Into<RioAddress>) -> Self {
+        Self {
+            address: address.into(),
+            ports: Ports::default(),
+        }
+    }
+}
+impl Connect for AddressConnector {
+    fn connect(&self, _team_number: u16) -> TransportFuture<'_, Box<dyn Transport>> {
+        Box::pin(async move {
+            let mut last_err = io::Error::from(io::ErrorKind::NotFound);
+
+            for addr in self.address.resolve().await? {
+                match connect_to(addr, self.ports).await {
+                    Ok(transport) => return Ok(transport),
+                    Err(err) => last_err = err,
+                }
+            }
+
+            Err(last_err)
+        })
+    }
+}
+
+/// Connects to a team's roboRIO wherever it can be found
+#[derive(Debug, Clone)]
+pub struct RioConnector {
+    pub mode: ConnectionMode,
+    pub ports: Ports,
+}
+impl RioConnector {
+    pub fn new(mode: ConnectionMode) -> Self {
+        Self {
+            mode,
+            ports: Ports::default(),
+        }
+    }
+
+    /// Places to try, in order
+    fn candidates(&self, team_number: u16) -> Vec<Candidate> {
+        let usb = Some(Candidate::Addr(IpAddr::V4(USB_RIO_ADDR)));
+        let team_ip = gen_team_ip(team_number).map(|ip| Candidate::Addr(IpAddr::V4(ip)));
+        #[cfg(feature = "mdns")]
+        let mdns = Some(Candidate::Mdns(team_number));
+        #[cfg(not(feature = "mdns"))]
+        let mdns = None;
+
+        match self.mode {
+            ConnectionMode::Auto => [usb, mdns, team_ip],
+            ConnectionMode::Usb => [usb, None, None],
+            #[cfg(feature = "mdns")]
+            ConnectionMode::Mdns => [mdns, None, None],
+            ConnectionMode::TeamIp => [team_ip, None, None],
+            ConnectionMode::Sim => [Some(Candidate::Addr(IpAddr::V4(SIM_RIO_ADDR))), None, None],
+        }
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    async fn try_connect(&self, candidate: Candidate) -> io::Result<Box<dyn Transport>> {
+        // Only infallible without the `mdns` feature
+        #[allow(clippy::infallible_destructuring_match)]
+        let addr = match candidate {
+            Candidate::Addr(addr) => addr,
+            #[cfg(feature = "mdns")]
+            Candidate::Mdns(team_number) => resolve_mdns(team_number, ATTEMPT_TIMEOUT).await?,
+        };
+
+        let transport = connect_to(addr, self.ports).await?;
+        event!(Level::INFO, %addr, "Found roboRIO");
+        Ok(transport)
+    }
+}
+impl Connect for RioConnector {
+    fn connect(&self, team_number: u16) -> TransportFuture<'_, Box<dyn Transport>> {
+        Box::pin(async move {
+            let mut last_err = io::Error::from(io::ErrorKind::AddrNotAvailable);
+
+            for candidate in self.candidates(team_number) {
+                match self.try_connect(candidate).await {
+                    Ok(transport) => return Ok(transport),
+                    Err(err) => last_err = err,
+                }
+            }
+
+            Err(last_err)
+        })
+    }
+}
+
+/// Connect to `addr`, giving up after [`ATTEMPT_TIMEOUT`]
+#[cfg(all(feature = "runtime-tokio", not(target_arch = "wasm32")))]
+async fn connect_to(addr: IpAddr, ports: Ports) -> io::Result<Box<dyn Transport>> {
+    let transport = tokio::time::timeout(
+        ATTEMPT_TIMEOUT,
+        crate::transport::SocketTransport::connect_with_ports(addr, ports),
+    )
+    .await
+    .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+    Ok(Box::new(transport))
+}
+
+/// Without tokio's sockets the roboRIO has to be reached through a
+/// [`Transport`] of the caller's own, like a
+/// [relay](crate::transport::relay) in a browser
+#[cfg(any(not(feature = "runtime-tokio"), target_arch = "wasm32"))]
+async fn connect_to(_addr: IpAddr, _ports: Ports) -> io::Result<Box<dyn Transport>> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Get the mDNS hostname of a team's roboRIO, like `roboRIO-4533-FRC.local.`
+pub fn mdns_hostname(team_number: u16) -> String {
+    format!("roboRIO-{team_number}-FRC.local.")
+}
+
+/// Look up a team's roboRIO with mDNS, waiting up to `wait` for an answer
+///
+/// Works even when the roboRIO got an address from DHCP that doesn't match
+/// the `10.TE.AM.2` convention. IPv4 addresses are preferred.
+#[cfg(feature = "mdns")]
+pub async fn resolve_mdns(team_number: u16, wait: Duration) -> io::Result<IpAddr> {
+    use mdns_sd::{HostnameResolutionEvent, ServiceDaemon};
+
+    let hostname = mdns_hostname(team_number);
+    let daemon = ServiceDaemon::new().map_err(io::Error::other)?;
+    let events = daemon
+        .resolve_hostname(&hostname, Some(wait.as_millis() as u64))
+        .map_err(io::Error::other)?;
+
+    let mut res = Err(io::ErrorKind::TimedOut.into());
+    while let Ok(event) = events.recv_async().await {
+        match event {
+            HostnameResolutionEvent::AddressesFound(_, addrs) => {
+                let addr = addrs
+                    .iter()
+                    .find(|addr| addr.is_ipv4())
+                    .or(addrs.iter().next());
+                if let Some(addr) = addr {
+                    res = Ok(*addr);
+                    break;
+                }
+            }
+            HostnameResolutionEvent::SearchTimeout(_) => break,
+            _ => {}
+        }
+    }
+
+    // The daemon runs its own thread, which doesn't stop on drop
+    let _ = daemon.shutdown();
+    event!(Level::DEBUG, %hostname, ?res, "mDNS lookup finished");
+    res
+}
@@ -0,0 +1,47 @@
+//! Emergency stop buttons that show up as a key, like most USB e-stops sold
+//! for PCs
+//!
+//! The device is read straight from `/dev/input`. Use a stable path from
+//! `/dev/input/by-id/` so it's found again after being replugged.
+
+use std::{io, path::PathBuf};
+
+use ::evdev::{Device, KeyCode};
+
+use super::EStopButton;
+
+/// An emergency stop button that presses `key` on an evdev device
+pub struct EvdevEStop {
+    path: PathBuf,
+    key: KeyCode,
+    device: Option<Device>,
+}
+impl EvdevEStop {
+    /// Open the device at `path`, with the button pressing `key`
+    pub fn open(path: impl Into<PathBuf>, key: KeyCode) -> io::Result<Self> {
+        let path = path.into();
+        let device = Device::open(&path)?;
+        Ok(Self {
+            path,
+            key,
+            device: Some(device),
+        })
+    }
+}
+impl EStopButton for EvdevEStop {
+    fn is_pressed(&mut self) -> io::Result<bool> {
+        let device = match &mut self.device {
+            Some(device) => device,
+            None => self.device.insert(Device::open(&self.path)?),
+        };
+
+        match device.get_key_state() {
+            Ok(keys) => Ok(keys.contains(self.key)),
+            Err(err) => {
+                // Opened again next time, in case it was replugged
+                self.device = None;
+                Err(err)
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Emergency stop buttons wired to a GPIO line, on Linux boards like a
+//! Raspberry Pi
+//!
+//! Lines are read through the GPIO character device (`/dev/gpiochip*`).
+
+use std::io;
+
+use gpio_cdev::{Chip, LineHandle, LineRequestFlags};
+
+use super::EStopButton;
+
+/// What the line shows up as to other programs
+const CONSUMER: &str = "robudst-estop";
+
+/// An emergency stop button on a GPIO line
+pub struct GpioEStop {
+    line: LineHandle,
+}
+impl GpioEStop {
+    /// Watch line `offset` on `chip`, like `/dev/gpiochip0`
+    ///
+    /// The button counts as pressed while the line is high, or while it's
+    /// low if `active_low` is set. Wire a normally closed button so that
+    /// pressing it or cutting its cable both read as pressed.
+    pub fn open(chip: &str, offset: u32, active_low: bool) -> io::Result<Self> {
+        let mut flags = LineRequestFlags::INPUT;
+        if active_low {
+            flags |= LineRequestFlags::ACTIVE_LOW;
+        }
+
+        let line = Chip::new(chip)
+            .and_then(|mut chip| chip.get_line(offset))
+            .and_then(|line| line.request(flags, 0, CONSUMER))
+            .map_err(io::Error::other)?;
+        Ok(Self { line })
+    }
+}
+impl EStopButton for GpioEStop {
+    fn is_pressed(&mut self) -> io::Result<bool> {
+        let value = self.line.get_value().map_err(io::Error::other)?;
+        Ok(value != 0)
+    }
+}
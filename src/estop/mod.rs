@@ -0,0 +1,160 @@
+//! Physical emergency stop buttons
+//!
+//! A button is read through an [`EStopButton`], and watched with
+//! [`Ds::watch_estop_button`] alongside [`Ds::run`]:
+//!
+//! ```no_run
+//! # #[cfg(feature = "estop-serial")]
+//! # async fn run(ds: &robudst::Ds) -> std::io::Result<()> {
+//! use robudst::estop::serial::SerialEStop;
+//!
+//! let button = SerialEStop::open("/dev/ttyUSB0")?;
+//! tokio::join!(ds.run(), ds.watch_estop_button(button));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The button is read once a packet period, so a press reaches the robot
+//! in the next control packet at the latest. The stop latches like any
+//! other [`Ds::estop`], and a button that's still held down stops the robot
+//! again if the stop is cleared.
+//!
+//! Losing the button is treated as losing the ability to stop the robot:
+//! it's disabled, [`DsEvent::EStopButtonLost`] is emitted, and it can't be
+//! enabled until the button is back.
+//!
+//! Backends:
+//!
+//! - [`serial`] reads a button wired across the control lines of a serial
+//!   port (`estop-serial` feature)
+//! - [`gpio`] reads a GPIO line on Linux (`estop-gpio` feature)
+//! - [`evdev`] reads a USB button that shows up as a key on Linux (`evdev`
+//!   feature)
+
+use std::{io, sync::atomic::Ordering};
+
+use crate::{Ds, event::DsEvent, timer::interval, trace::Level};
+
+#[cfg(all(feature = "evdev", target_os = "linux"))]
+pub mod evdev;
+#[cfg(all(feature = "estop-gpio", target_os = "linux"))]
+pub mod gpio;
+#[cfg(feature = "estop-serial")]
+pub mod serial;
+
+/// A physical emergency stop button
+pub trait EStopButton {
+    /// Read whether the button is pressed right now
+    ///
+    /// An error means the button can't be read, like when it's unplugged.
+    /// This is called again every packet period regardless, so a backend
+    /// that can reopen its device should try to here.
+    fn is_pressed(&mut self) -> io::Result<bool>;
+}
+
+/// Clears the lost button flag once nothing's watching it anymore, so the
+/// robot can still be enabled
+struct Watching<'a>(&'a Ds);
+impl Drop for Watching<'_> {
+    fn drop(&mut self) {
+        self.0.estop_button_lost.store(false, Ordering::Release);
+    }
+}
+
+impl Ds {
+    /// Stop the robot whenever `button` is pressed, until the returned
+    /// future is dropped
+    ///
+    /// See the [module docs](self) for what happens when the button is
+    /// lost.
+    pub async fn watch_estop_button(&self, mut button: impl EStopButton) {
+        let _watching = Watching(self);
+        let mut period = interval(self.send_interval);
+        let mut connected = true;
+
+        loop {
+            period.tick().await;
+
+            match button.is_pressed() {
+                Ok(pressed) => {
+                    if !connected {
+                        connected = true;
+                        self.estop_button_lost.store(false, Ordering::Release);
+                        event!(Level::INFO, "Emergency stop button is back");
+                        self.emit(DsEvent::EStopButtonFound);
+                    }
+                    if pressed && !self.is_estopped() {
+                        event!(Level::WARN, "Emergency stop button pressed");
+                        self.estop().await;
+                    }
+                }
+                Err(err) => {
+                    if connected {
+                        connected = false;
+                        self.estop_button_lost.store(true, Ordering::Release);
+                        event!(Level::ERROR, %err, "Lost the emergency stop button");
+                        self.emit(DsEvent::EStopButtonLost);
+                        self.disable_now().await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether a watched emergency stop button can't be read, which keeps
+    /// the robot from being enabled
+    #[inline(always)]
+    pub fn is_estop_button_lost(&self) -> bool {
+        self.estop_button_lost.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{Error, RobotStatus, transport::MemoryTransport};
+
+    /// A button the test presses and unplugs by hand
+    #[derive(Clone, Default)]
+    struct FakeButton(Arc<Mutex<Option<bool>>>);
+    impl EStopButton for FakeButton {
+        fn is_pressed(&mut self) -> io::Result<bool> {
+            self.0.lock().unwrap().ok_or(io::ErrorKind::NotFound.into())
+        }
+    }
+
+    #[tokio::test]
+    async fn button_stops_and_blocks_enabling() {
+        let ds = Ds::with_transport(4533, MemoryTransport::pair().0);
+        let button = FakeButton::default();
+        *button.0.lock().unwrap() = Some(false);
+        let mut events = ds.subscribe();
+
+        let test = async {
+            ds.enable().await.unwrap();
+
+            *button.0.lock().unwrap() = Some(true);
+            while !ds.is_estopped() {
+                tokio::task::yield_now().await;
+            }
+            assert_eq!(ds.status(), RobotStatus::EStopped);
+
+            *button.0.lock().unwrap() = None;
+            assert_eq!(events.recv().await.unwrap(), DsEvent::EStopButtonLost);
+            ds.clear_estop().await;
+            assert!(matches!(ds.enable().await, Err(Error::EStopButtonLost)));
+
+            *button.0.lock().unwrap() = Some(false);
+            assert_eq!(events.recv().await.unwrap(), DsEvent::EStopButtonFound);
+            ds.enable().await.unwrap();
+        };
+
+        tokio::select! {
+            _ = ds.watch_estop_button(button.clone()) => unreachable!(),
+            _ = test => {}
+        }
+        assert!(!ds.is_estop_button_lost());
+    }
+}
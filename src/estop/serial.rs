@@ -0,0 +1,55 @@
+//! Emergency stop buttons wired to a serial port's control lines
+//!
+//! No microcontroller needed: a normally closed button goes between DTR,
+//! which is held high, and CTS. Pressing the button breaks the circuit, and
+//! so does a cut cable, so either one stops the robot. Pulling out the USB
+//! adapter shows up as a lost button.
+
+use std::io;
+
+use serialport::SerialPort;
+
+use super::EStopButton;
+
+/// The port is never read from, so this doesn't matter
+const BAUD_RATE: u32 = 9600;
+
+/// An emergency stop button read through a serial port's CTS line
+pub struct SerialEStop {
+    path: String,
+    port: Option<Box<dyn SerialPort>>,
+}
+impl SerialEStop {
+    /// Open the serial port at `path`, like `/dev/ttyUSB0` or `COM3`
+    pub fn open(path: impl Into<String>) -> io::Result<Self> {
+        let path = path.into();
+        let port = Self::open_port(&path)?;
+        Ok(Self {
+            path,
+            port: Some(port),
+        })
+    }
+
+    fn open_port(path: &str) -> io::Result<Box<dyn SerialPort>> {
+        let mut port = serialport::new(path, BAUD_RATE).open()?;
+        port.write_data_terminal_ready(true)?;
+        Ok(port)
+    }
+}
+impl EStopButton for SerialEStop {
+    fn is_pressed(&mut self) -> io::Result<bool> {
+        let port = match &mut self.port {
+            Some(port) => port,
+            None => self.port.insert(Self::open_port(&self.path)?),
+        };
+
+        match port.read_clear_to_send() {
+            Ok(closed) => Ok(!closed),
+            Err(err) => {
+                // Opened again next time, in case it was replugged
+                self.port = None;
+                Err(err.into())
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Notifications about what a driver station is doing
+//!
+//! Anything that wants to react to changes (a UI, a logger, etc.) can
+//! [`subscribe`](Ds::subscribe) instead of polling.
+
+use futures_lite::Stream;
+use tokio::sync::broadcast;
+
+use crate::{
+    AlliancePos, Ds, RobotCodeMode, RobotStatus,
+    connection::ConnectionState,
+    console::ConsoleLine,
+    diagnostics::{
+        DisableFaults, Fault, PowerStats, RadioEvent, RailFaults, ResourceUsage, VersionEntry,
+    },
+    practice::MatchPhase,
+};
+
+/// How many events a slow subscriber can fall behind before missing some
+pub(crate) const EVENT_CAPACITY: usize = 64;
+
+/// Something that happened to a driver station
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum DsEvent {
+    /// The connection to the roboRIO changed state
+    ConnectionStateChanged(ConnectionState),
+    /// The roboRIO stopped sending status packets
+    ///
+    /// The robot's status is [`NoCommunication`](crate::RobotStatus) until
+    /// they start again.
+    CommLost,
+    /// A match sequence moved to a new phase
+    MatchPhaseChanged(MatchPhase),
+    /// The DS moved to a different alliance station
+    AllianceChanged(AlliancePos),
+    /// The robot printed something or reported an error/warning
+    ///
+    /// The line has also been added to the
+    /// [console history](Ds::console_history).
+    ConsoleLine(ConsoleLine),
+    /// The robot radio reported something through the roboRIO
+    RadioEvent(RadioEvent),
+    /// The roboRIO reported the version of something on the robot
+    VersionInfo(VersionEntry),
+    /// The roboRIO reported a different version of something than
+    /// [expected](Ds::expect_version)
+    VersionMismatch {
+        entry: VersionEntry,
+        expected: String,
+    },
+    /// The roboRIO reported what the robot program uses
+    UsageReport(ResourceUsage),
+    /// The roboRIO reported its disable fault counts
+    DisableFaults(DisableFaults),
+    /// The roboRIO reported its power rail fault counts
+    RailFaults(RailFaults),
+    /// One of the fault counts went up since the last report
+    Fault(Fault),
+    /// The battery dropped below a [`VoltageAlert`](crate::battery::VoltageAlert)'s
+    /// threshold
+    VoltageLow { threshold: f32, voltage: f32 },
+    /// The battery recovered from a [`DsEvent::VoltageLow`]
+    VoltageRecovered { threshold: f32, voltage: f32 },
+    /// The roboRIO started reporting a brownout, with the battery at
+    /// `voltage`
+    ///
+    /// These are counted by [`Ds::brownout_count`].
+    BrownoutOccurred { voltage: f32 },
+    /// The roboRIO's free memory dropped below the
+    /// [threshold](Ds::set_low_memory_threshold)
+    LowMemory { free_bytes: u32 },
+    /// The roboRIO's free disk space dropped below the
+    /// [threshold](Ds::set_low_disk_threshold)
+    LowDisk { free_bytes: u32 },
+    /// The roboRIO sent new power distribution readings
+    PowerStats(PowerStats),
+    /// The [emergency stop button](crate::estop) stopped responding, so the
+    /// robot was disabled
+    EStopButtonLost,
+    /// The [emergency stop button](crate::estop) is responding again
+    EStopButtonFound,
+}
+
+impl Ds {
+    /// Get notified of every [`DsEvent`] from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<DsEvent> {
+        self.events.subscribe()
+    }
+
+    /// Get each new [`RobotStatus`] from now on
+    ///
+    /// Only the latest status is kept for a slow reader, so a status that
+    /// only lasts until the next one can be missed, like enabling and
+    /// disabling in quick succession. The stream ends when the driver
+    /// station is dropped.
+    ///
+    /// ```no_run
+    /// # async fn watch(ds: &robudst::Ds) {
+    /// use futures_lite::StreamExt;
+    ///
+    /// let mut statuses = ds.status_stream();
+    /// while let Some(status) = statuses.next().await {
+    ///     println!("Robot is now {status:?}");
+    /// }
+    /// # }
+    /// ```
+    pub fn status_stream(&self) -> impl Stream<Item = RobotStatus> + Send + Unpin + 'static {
+        self.status.changes()
+    }
+
+    /// Get each new [`RobotCodeMode`] from now on
+    ///
+    /// Like [`Ds::status_stream`], only the latest mode is kept for a slow
+    /// reader.
+    pub fn mode_stream(&self) -> impl Stream<Item = RobotCodeMode> + Send + Unpin + 'static {
+        self.mode.changes()
+    }
+
+    pub(crate) fn emit(&self, event: DsEvent) {
+        // Nobody listening is fine
+        let _ = self.events.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::StreamExt;
+
+    use super::*;
+    use crate::transport::MemoryTransport;
+
+    #[tokio::test]
+    async fn streams_only_see_changes() {
+        let ds = Ds::with_transport(4533, MemoryTransport::pair().0);
+        let mut statuses = ds.status_stream();
+        let mut modes = ds.mode_stream();
+
+        ds.status.store(RobotStatus::NoCommunication);
+        ds.status.store(RobotStatus::Disabled);
+        assert_eq!(statuses.next().await, Some(RobotStatus::Disabled));
+
+        ds.mode.store(RobotCodeMode::Autonomous);
+        assert_eq!(modes.next().await, Some(RobotCodeMode::Autonomous));
+
+        // A slow reader only sees the latest
+        ds.status.store(RobotStatus::Enabled);
+        ds.status.store(RobotStatus::Disabled);
+        ds.status.store(RobotStatus::EStopped);
+        assert_eq!(statuses.next().await, Some(RobotStatus::EStopped));
+
+        drop(ds);
+        assert_eq!(statuses.next().await, None);
+    }
+}
@@ -0,0 +1,392 @@
+//! A C API, for embedding the driver station in a UI written in another
+//! language
+//!
+//! Build with the `ffi` feature to get a shared library exporting these,
+//! and include `include/robudst.h`:
+//!
+//! ```c
+//! RobudstDs *ds = robudst_init(4533);
+//! robudst_set_mode(ds, ROBUDST_MODE_TELEOP);
+//! robudst_enable(ds);
+//!
+//! RobudstEvent event;
+//! while (robudst_poll_event(ds, &event) == 1) {
+//!     if (event.kind == ROBUDST_EVENT_CONSOLE_LINE) {
+//!         printf("%s\n", event.message);
+//!     }
+//! }
+//!
+//! robudst_free(ds);
+//! ```
+//!
+//! Every function returns [`ROBUDST_OK`] or a negative `ROBUDST_ERR_*`
+//! code, unless it says otherwise. The DS runs on its own threads, so none
+//! of these block for long, and they can be called from any thread.
+
+use std::{
+    ffi::{CString, c_char},
+    ptr, slice,
+    sync::Mutex,
+};
+
+use tokio::{
+    runtime::Runtime,
+    sync::broadcast::{Receiver, error::TryRecvError},
+    task::JoinHandle,
+};
+
+use crate::{
+    Ds, DsHandle, Error, RobotCodeMode, connection::ConnectionState, console::ConsoleLevel,
+    diagnostics::Fault, event::DsEvent,
+};
+
+pub const ROBUDST_OK: i32 = 0;
+/// A pointer argument was null
+pub const ROBUDST_ERR_NULL: i32 = -1;
+pub const ROBUDST_ERR_INVALID_JOYSTICK_SLOT: i32 = -2;
+pub const ROBUDST_ERR_TOO_MANY_JOYSTICK_INPUTS: i32 = -3;
+pub const ROBUDST_ERR_ESTOPPED: i32 = -4;
+pub const ROBUDST_ERR_FMS_CONTROLLED: i32 = -5;
+/// A mode that isn't one of the `ROBUDST_MODE_*` values
+pub const ROBUDST_ERR_INVALID_MODE: i32 = -6;
+/// Any other [`Error`]
+pub const ROBUDST_ERR_OTHER: i32 = -7;
+
+pub const ROBUDST_MODE_AUTONOMOUS: i32 = 0;
+pub const ROBUDST_MODE_TELEOP: i32 = 1;
+pub const ROBUDST_MODE_TEST: i32 = 2;
+
+/// Anything without its own kind, described in `message`
+pub const ROBUDST_EVENT_OTHER: u32 = 0;
+/// `code` is the new state: 0 disconnected, 2 connecting, 3 connected, or
+/// 4 lost (1 is unused)
+pub const ROBUDST_EVENT_CONNECTION_STATE_CHANGED: u32 = 1;
+pub const ROBUDST_EVENT_COMM_LOST: u32 = 2;
+/// `code` is the level (0 info, 1 warning, 2 error), and `message` the text
+pub const ROBUDST_EVENT_CONSOLE_LINE: u32 = 3;
+/// `voltage` dropped below `threshold`
+pub const ROBUDST_EVENT_VOLTAGE_LOW: u32 = 4;
+/// `voltage` recovered above `threshold`
+pub const ROBUDST_EVENT_VOLTAGE_RECOVERED: u32 = 5;
+/// The robot browned out at `voltage`
+pub const ROBUDST_EVENT_BROWNOUT: u32 = 6;
+/// `code` is the fault: 0 comms, 1 12V, 2 6V rail, 3 5V rail, or 4 3.3V
+/// rail
+pub const ROBUDST_EVENT_FAULT: u32 = 7;
+/// `code` is the free memory, in bytes
+pub const ROBUDST_EVENT_LOW_MEMORY: u32 = 8;
+/// `code` is the free disk space, in bytes
+pub const ROBUDST_EVENT_LOW_DISK: u32 = 9;
+/// `message` is what the radio reported
+pub const ROBUDST_EVENT_RADIO: u32 = 10;
+
+/// A driver station, owned by C code
+///
+/// Made by [`robudst_init`], and freed with [`robudst_free`].
+pub struct RobudstDs {
+    runtime: Runtime,
+    ds: DsHandle,
+    task: JoinHandle<()>,
+    events: Mutex<Events>,
+}
+
+struct Events {
+    rx: Receiver<DsEvent>,
+    /// Text of the last event polled, kept alive for the C side
+    message: CString,
+}
+
+/// A [`DsEvent`], flattened for C
+///
+/// Which fields mean anything depends on `kind`; see the
+/// `ROBUDST_EVENT_*` constants.
+#[repr(C)]
+pub struct RobudstEvent {
+    pub kind: u32,
+    pub code: i64,
+    pub voltage: f32,
+    pub threshold: f32,
+    /// Always a valid string, empty if there's no text
+    ///
+    /// Only valid until the next [`robudst_poll_event`] or
+    /// [`robudst_free`].
+    pub message: *const c_char,
+}
+
+fn error_code(err: Error) -> i32 {
+    match err {
+        Error::InvalidJoystickSlot => ROBUDST_ERR_INVALID_JOYSTICK_SLOT,
+        Error::TooManyJoystickInputs => ROBUDST_ERR_TOO_MANY_JOYSTICK_INPUTS,
+        Error::EStopped => ROBUDST_ERR_ESTOPPED,
+        Error::FmsControlled => ROBUDST_ERR_FMS_CONTROLLED,
+        _ => ROBUDST_ERR_OTHER,
+    }
+}
+
+/// Start a driver station for `team_number`, connecting to its robot
+///
+/// Returns null if it couldn't be started.
+#[unsafe(no_mangle)]
+pub extern "C" fn robudst_init(team_number: u16) -> *mut RobudstDs {
+    let Ok(ds) = Ds::builder(team_number).build() else {
+        return ptr::null_mut();
+    };
+    let Ok(runtime) = Runtime::new() else {
+        return ptr::null_mut();
+    };
+
+    // Before spawning, so no events are missed
+    let events = Mutex::new(Events {
+        rx: ds.subscribe(),
+        message: CString::default(),
+    });
+    let (ds, task) = {
+        let _guard = runtime.enter();
+        ds.spawn()
+    };
+
+    Box::into_raw(Box::new(RobudstDs {
+        runtime,
+        ds,
+        task,
+        events,
+    }))
+}
+
+/// Disable the robot, stop the driver station, and free it
+///
+/// # Safety
+///
+/// `ds` must be null or from [`robudst_init`], and not used again after.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn robudst_free(ds: *mut RobudstDs) {
+    if ds.is_null() {
+        return;
+    }
+    let RobudstDs {
+        runtime, ds, task, ..
+    } = *unsafe { Box::from_raw(ds) };
+
+    runtime.block_on(async {
+        ds.shutdown().await;
+        let _ = task.await;
+    });
+}
+
+/// # Safety
+///
+/// `ds` must be null or a live pointer from [`robudst_init`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn robudst_enable(ds: *const RobudstDs) -> i32 {
+    let Some(ds) = (unsafe { ds.as_ref() }) else {
+        return ROBUDST_ERR_NULL;
+    };
+    match ds.runtime.block_on(ds.ds.enable()) {
+        Ok(()) => ROBUDST_OK,
+        Err(err) => error_code(err),
+    }
+}
+
+/// # Safety
+///
+/// `ds` must be null or a live pointer from [`robudst_init`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn robudst_disable(ds: *const RobudstDs) -> i32 {
+    let Some(ds) = (unsafe { ds.as_ref() }) else {
+        return ROBUDST_ERR_NULL;
+    };
+    ds.runtime.block_on(ds.ds.disable());
+    ROBUDST_OK
+}
+
+/// # Safety
+///
+/// `ds` must be null or a live pointer from [`robudst_init`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn robudst_estop(ds: *const RobudstDs) -> i32 {
+    let Some(ds) = (unsafe { ds.as_ref() }) else {
+        return ROBUDST_ERR_NULL;
+    };
+    ds.runtime.block_on(ds.ds.estop());
+    ROBUDST_OK
+}
+
+/// Switch to one of the `ROBUDST_MODE_*` modes
+///
+/// # Safety
+///
+/// `ds` must be null or a live pointer from [`robudst_init`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn robudst_set_mode(ds: *const RobudstDs, mode: i32) -> i32 {
+    let Some(ds) = (unsafe { ds.as_ref() }) else {
+        return ROBUDST_ERR_NULL;
+    };
+    let mode = match mode {
+        ROBUDST_MODE_AUTONOMOUS => RobotCodeMode::Autonomous,
+        ROBUDST_MODE_TELEOP => RobotCodeMode::Teleop,
+        ROBUDST_MODE_TEST => RobotCodeMode::Test,
+        _ => return ROBUDST_ERR_INVALID_MODE,
+    };
+    ds.runtime.block_on(ds.ds.set_mode(mode));
+    ROBUDST_OK
+}
+
+/// Set the joystick in `slot`, with axes from `-128` to `127` and POVs in
+/// degrees (or `-1` when not pressed)
+///
+/// # Safety
+///
+/// `ds` must be null or a live pointer from [`robudst_init`]. Each array
+/// must hold at least its count of elements, and can be null if its count
+/// is zero.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn robudst_set_joystick(
+    ds: *const RobudstDs,
+    slot: usize,
+    axes: *const i8,
+    axis_count: usize,
+    buttons: *const bool,
+    button_count: usize,
+    povs: *const i16,
+    pov_count: usize,
+) -> i32 {
+    let Some(ds) = (unsafe { ds.as_ref() }) else {
+        return ROBUDST_ERR_NULL;
+    };
+    let (Some(axes), Some(buttons), Some(povs)) = (unsafe {
+        (
+            array(axes, axis_count),
+            array(buttons, button_count),
+            array(povs, pov_count),
+        )
+    }) else {
+        return ROBUDST_ERR_NULL;
+    };
+
+    match ds.ds.set_joystick(slot, axes, buttons, povs) {
+        Ok(()) => ROBUDST_OK,
+        Err(err) => error_code(err),
+    }
+}
+
+/// Take the oldest event that hasn't been polled yet
+///
+/// Returns `1` and fills in `event` if there was one, or `0` if there
+/// wasn't. If events aren't polled often enough, the oldest are dropped.
+///
+/// # Safety
+///
+/// `ds` must be null or a live pointer from [`robudst_init`], and `event`
+/// must be null or valid to write a [`RobudstEvent`] to.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn robudst_poll_event(ds: *const RobudstDs, event: *mut RobudstEvent) -> i32 {
+    let (Some(ds), false) = (unsafe { ds.as_ref() }, event.is_null()) else {
+        return ROBUDST_ERR_NULL;
+    };
+    let mut events = ds.events.lock().unwrap();
+
+    let next = loop {
+        match events.rx.try_recv() {
+            Ok(next) => break next,
+            Err(TryRecvError::Lagged(_)) => continue,
+            Err(TryRecvError::Empty | TryRecvError::Closed) => return 0,
+        }
+    };
+
+    let mut flat = RobudstEvent {
+        kind: ROBUDST_EVENT_OTHER,
+        code: 0,
+        voltage: 0.0,
+        threshold: 0.0,
+        message: ptr::null(),
+    };
+    let message = match next {
+        DsEvent::ConnectionStateChanged(state) => {
+            flat.kind = ROBUDST_EVENT_CONNECTION_STATE_CHANGED;
+            flat.code = match state {
+                // 1 is left unused so existing callers keep working
+                ConnectionState::Disconnected => 0,
+                ConnectionState::Connecting => 2,
+                ConnectionState::Connected => 3,
+                ConnectionState::Lost => 4,
+            };
+            String::new()
+        }
+        DsEvent::CommLost => {
+            flat.kind = ROBUDST_EVENT_COMM_LOST;
+            String::new()
+        }
+        DsEvent::ConsoleLine(line) => {
+            flat.kind = ROBUDST_EVENT_CONSOLE_LINE;
+            flat.code = match line.level {
+                ConsoleLevel::Info => 0,
+                ConsoleLevel::Warning => 1,
+                ConsoleLevel::Error => 2,
+            };
+            line.message
+        }
+        DsEvent::VoltageLow { threshold, voltage } => {
+            flat.kind = ROBUDST_EVENT_VOLTAGE_LOW;
+            (flat.threshold, flat.voltage) = (threshold, voltage);
+            String::new()
+        }
+        DsEvent::VoltageRecovered { threshold, voltage } => {
+            flat.kind = ROBUDST_EVENT_VOLTAGE_RECOVERED;
+            (flat.threshold, flat.voltage) = (threshold, voltage);
+            String::new()
+        }
+        DsEvent::BrownoutOccurred { voltage } => {
+            flat.kind = ROBUDST_EVENT_BROWNOUT;
+            flat.voltage = voltage;
+            String::new()
+        }
+        DsEvent::Fault(fault) => {
+            flat.kind = ROBUDST_EVENT_FAULT;
+            flat.code = match fault {
+                Fault::CommsDisable => 0,
+                Fault::Power12vDisable => 1,
+                Fault::Rail6v => 2,
+                Fault::Rail5v => 3,
+                Fault::Rail3v3 => 4,
+            };
+            String::new()
+        }
+        DsEvent::LowMemory { free_bytes } => {
+            flat.kind = ROBUDST_EVENT_LOW_MEMORY;
+            flat.code = free_bytes.into();
+            String::new()
+        }
+        DsEvent::LowDisk { free_bytes } => {
+            flat.kind = ROBUDST_EVENT_LOW_DISK;
+            flat.code = free_bytes.into();
+            String::new()
+        }
+        DsEvent::RadioEvent(radio) => {
+            flat.kind = ROBUDST_EVENT_RADIO;
+            format!("{radio:?}")
+        }
+        other => format!("{other:?}"),
+    };
+
+    // C strings end at the first NUL, so drop any the robot sent
+    events.message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    flat.message = events.message.as_ptr();
+    unsafe { event.write(flat) };
+    1
+}
+
+/// A C array as a slice, or `None` if it's null but not empty
+///
+/// # Safety
+///
+/// `ptr` must be null or point to `len` elements.
+unsafe fn array<'a, T>(ptr: *const T, len: usize) -> Option<&'a [T]> {
+    if len == 0 {
+        Some(&[])
+    } else if ptr.is_null() {
+        None
+    } else {
+        Some(unsafe { slice::from_raw_parts(ptr, len) })
+    }
+}
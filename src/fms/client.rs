@@ -0,0 +1,313 @@
+//! Connecting to a real field, over the network
+
+use std::{io, net::IpAddr, sync::atomic::Ordering, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+    time::{Instant, interval, sleep_until},
+};
+
+use super::FmsInfo;
+use crate::{
+    Ds, MatchInfo, RobotCodeMode, RobotStatus,
+    proto::{
+        fms::{
+            DS_FMS_UDP_PORT, DsStatus, DsStatusPacket, DsTcpTag, FMS_ADDR, FMS_TCP_PORT,
+            FMS_UDP_PORT, FmsControlPacket, FmsDialect, FmsTcpTag, StationStatus, read_frame,
+        },
+        outgoing::udp::Control,
+    },
+    trace::Level,
+    utils::unspecified_for,
+};
+
+/// How long the field can go quiet before the robot gets disabled
+const FMS_TIMEOUT: Duration = Duration::from_secs(1);
+/// How often to remind Cheesy Arena that the DS is still connected
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+impl Ds {
+    /// Connect to the FMS at its standard field address and follow its
+    /// commands until the connection drops
+    pub async fn run_fms(&self) -> io::Result<()> {
+        self.run_fms_at(FMS_ADDR.into()).await
+    }
+
+    /// Connect to an FMS at `addr` and follow its commands until the
+    /// connection drops
+    ///
+    /// While connected, the FMS decides when the robot is enabled, its mode,
+    /// and the alliance station. The robot is always disabled once the FMS
+    /// goes away.
+    pub async fn run_fms_at(&self, addr: IpAddr) -> io::Result<()> {
+        self.run_fms_dialect(addr, FmsDialect::Official).await
+    }
+
+    /// Connect to a Cheesy Arena instance at `addr` and follow its commands
+    /// until the connection drops
+    ///
+    /// Behaves just like [`Ds::run_fms_at`]. Cheesy Arena assigns stations by
+    /// team number, so make sure the team number matches the one entered in
+    /// the match setup.
+    pub async fn run_cheesy_arena(&self, addr: IpAddr) -> io::Result<()> {
+        self.run_fms_dialect(addr, FmsDialect::CheesyArena).await
+    }
+
+    async fn run_fms_dialect(&self, addr: IpAddr, dialect: FmsDialect) -> io::Result<()> {
+        let unspecified = unspecified_for(addr);
+        let udp_rx = UdpSocket::bind((unspecified, DS_FMS_UDP_PORT)).await?;
+        let udp_tx = UdpSocket::bind((unspecified, 0)).await?;
+        udp_tx.connect((addr, FMS_UDP_PORT)).await?;
+
+        let mut tcp = TcpStream::connect((addr, FMS_TCP_PORT)).await?;
+        tcp.write_all(&DsTcpTag::TeamNumber(self.team_number()).write())
+            .await?;
+
+        event!(Level::INFO, %addr, ?dialect, "Connected to FMS");
+        self.fms_connected.store(true, Ordering::Release);
+
+        let res = self.fms_loop(&udp_rx, &udp_tx, &mut tcp, dialect).await;
+
+        event!(Level::WARN, ?res, "Disconnected from FMS");
+        self.fms_connected.store(false, Ordering::Release);
+        *self.fms_info.lock().unwrap() = None;
+        self.clear_match_time();
+        if self.status() == RobotStatus::Enabled {
+            self.disable_now().await;
+        }
+
+        res
+    }
+
+    async fn fms_loop(
+        &self,
+        udp_rx: &UdpSocket,
+        udp_tx: &UdpSocket,
+        tcp: &mut TcpStream,
+        dialect: FmsDialect,
+    ) -> io::Result<()> {
+        let mut keepalive_interval = interval(KEEPALIVE_INTERVAL);
+        let mut seqnum = 0u16;
+        let mut udp_buf = [0u8; 1024];
+        let mut tcp_buf = Vec::with_capacity(1024);
+        // Only control packets count, since the field stops sending them to
+        // disable everything while TCP carries on
+        let mut last_control = Instant::now();
+
+        loop {
+            tokio::select! {
+                res = udp_rx.recv(&mut udp_buf) => {
+                    let len = res?;
+                    let Some(pkt) = FmsControlPacket::parse(&udp_buf[..len]) else {
+                        continue;
+                    };
+                    last_control = Instant::now();
+
+                    self.apply_fms_control(&pkt).await;
+
+                    seqnum = seqnum.wrapping_add(1);
+                    let status = DsStatusPacket {
+                        seqnum,
+                        status: self.fms_status(),
+                        team_number: self.team_number(),
+                        battery: self.battery.load(),
+                    };
+                    udp_tx.send(&status.write()).await?;
+                }
+                res = tcp.read_buf(&mut tcp_buf) => {
+                    if res? == 0 {
+                        return Err(io::ErrorKind::ConnectionAborted.into());
+                    }
+
+                    while let Some((tag, len)) = read_frame(&tcp_buf) {
+                        if let Some(tag) = tag.and_then(|tag| FmsTcpTag::parse(tag.id, tag.data, dialect)) {
+                            self.handle_fms_tag(tag).await;
+                        }
+                        tcp_buf.drain(..len);
+                    }
+                }
+                _ = keepalive_interval.tick(), if dialect == FmsDialect::CheesyArena => {
+                    tcp.write_all(&DsTcpTag::Keepalive.write()).await?;
+                }
+                _ = sleep_until(last_control + FMS_TIMEOUT) => {
+                    if self.status() == RobotStatus::Enabled {
+                        event!(Level::WARN, "Lost FMS control packets, disabling");
+                        self.disable_now().await;
+                    }
+                    // Check again after another timeout, rather than right away
+                    last_control = Instant::now();
+                }
+            }
+        }
+    }
+
+    async fn apply_fms_control(&self, pkt: &FmsControlPacket) {
+        let previous = (self.status(), self.mode(), self.alliance());
+
+        let mode = match pkt.control.bits() & 0b11 {
+            0b01 => RobotCodeMode::Test,
+            0b10 => RobotCodeMode::Autonomous,
+            _ => RobotCodeMode::Teleop,
+        };
+        if pkt.control.contains(Control::ESTOP) {
+            self.estop_latched.store(true, Ordering::Release);
+        }
+        let status = if self.is_estopped() {
+            RobotStatus::EStopped
+        } else if pkt.control.contains(Control::ENABLED) {
+            RobotStatus::Enabled
+        } else {
+            RobotStatus::Disabled
+        };
+
+        self.status.store(status);
+        self.mode.store(mode);
+        self.store_alliance(pkt.station);
+        self.set_match_time(Duration::from_secs(pkt.remaining_time as u64));
+
+        let match_info = {
+            let mut info = self.fms_info.lock().unwrap();
+            let info = info.get_or_insert_with(|| FmsInfo {
+                event_code: String::new(),
+                station: pkt.station,
+                station_status: None,
+                match_type: pkt.match_type,
+                match_number: pkt.match_number,
+                play_number: pkt.play_number,
+                remaining_time: pkt.remaining_time,
+            });
+            info.station = pkt.station;
+            info.match_type = pkt.match_type;
+            info.match_number = pkt.match_number;
+            info.play_number = pkt.play_number;
+            info.remaining_time = pkt.remaining_time;
+
+            MatchInfo {
+                competition: info.event_code.clone(),
+                match_type: info.match_type,
+                match_number: info.match_number,
+                replay_number: info.play_number,
+            }
+        };
+        if match_info != self.match_info() {
+            self.set_match_info(match_info).await;
+        }
+
+        if previous != (self.status(), self.mode(), self.alliance()) {
+            self.send_udp().await;
+        }
+    }
+
+    async fn handle_fms_tag(&self, tag: FmsTcpTag<'_>) {
+        match tag {
+            FmsTcpTag::EventCode(code) => {
+                if let Some(info) = &mut *self.fms_info.lock().unwrap() {
+                    info.event_code = code.to_owned();
+                }
+
+                let mut match_info = self.match_info();
+                if match_info.competition != code {
+                    match_info.competition = code.to_owned();
+                    self.set_match_info(match_info).await;
+                }
+            }
+            FmsTcpTag::StationInfo { station, status } => {
+                if status == StationStatus::Bad {
+                    event!(
+                        Level::WARN,
+                        ?station,
+                        "FMS says this DS is in the wrong station"
+                    );
+                }
+                if let Some(info) = &mut *self.fms_info.lock().unwrap() {
+                    info.station_status = Some(status);
+                }
+            }
+            FmsTcpTag::GameData(game_data) => {
+                self.set_game_data(game_data).await;
+            }
+        }
+    }
+
+    fn fms_status(&self) -> DsStatus {
+        let mut status = match self.mode() {
+            RobotCodeMode::Teleop => DsStatus::TELEOP,
+            RobotCodeMode::Test => DsStatus::TEST,
+            RobotCodeMode::Autonomous => DsStatus::AUTO,
+        };
+
+        match self.status() {
+            RobotStatus::NoCommunication => {}
+            robot_status => {
+                status |= DsStatus::ROBOT_COMMS | DsStatus::RADIO_PING | DsStatus::RIO_PING;
+                match robot_status {
+                    RobotStatus::EStopped => status |= DsStatus::ESTOP,
+                    RobotStatus::Enabled => status |= DsStatus::ENABLED,
+                    _ => {}
+                }
+            }
+        }
+
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{net::TcpListener, time::sleep};
+
+    use super::*;
+    use crate::{AlliancePos, MatchType, transport::MemoryTransport};
+
+    #[tokio::test]
+    async fn tcp_traffic_doesnt_keep_the_robot_enabled() {
+        let ds = Ds::with_transport(4533, MemoryTransport::pair().0);
+
+        let fms_udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let udp_rx = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let udp_tx = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        udp_tx.connect(fms_udp.local_addr().unwrap()).await.unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut tcp = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut fms_tcp, _) = listener.accept().await.unwrap();
+
+        let control = FmsControlPacket {
+            seqnum: 1,
+            control: Control::ENABLED | Control::TELEOP,
+            station: AlliancePos::Red(1),
+            match_type: MatchType::Practice,
+            match_number: 1,
+            play_number: 1,
+            remaining_time: 135,
+        };
+        let event_code = FmsTcpTag::EventCode("TEST").write(FmsDialect::Official);
+
+        let fms = async {
+            let ds_addr = udp_rx.local_addr().unwrap();
+            fms_udp.send_to(&control.write(), ds_addr).await.unwrap();
+            while ds.status() != RobotStatus::Enabled {
+                sleep(Duration::from_millis(5)).await;
+            }
+
+            // Control packets stop, but TCP stays busy
+            let stopped = Instant::now();
+            while ds.status() == RobotStatus::Enabled {
+                assert!(stopped.elapsed() < FMS_TIMEOUT * 2, "robot stayed enabled");
+                fms_tcp.write_all(&event_code).await.unwrap();
+                sleep(Duration::from_millis(100)).await;
+            }
+            assert!(stopped.elapsed() >= FMS_TIMEOUT);
+        };
+
+        tokio::select! {
+            res = ds.fms_loop(&udp_rx, &udp_tx, &mut tcp, FmsDialect::Official) => {
+                panic!("FMS loop stopped: {res:?}")
+            }
+            _ = fms => {}
+        }
+        assert_eq!(ds.status(), RobotStatus::Disabled);
+    }
+}
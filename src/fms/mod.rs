@@ -0,0 +1,58 @@
+//! Running a driver station under the control of a field management system,
+//! or emulating one with [`FieldServer`]
+
+use std::sync::atomic::Ordering;
+
+use crate::{AlliancePos, Ds, MatchType, RobotStatus, proto::fms::StationStatus, trace::Level};
+
+#[cfg(all(feature = "runtime-tokio", not(target_arch = "wasm32")))]
+mod client;
+#[cfg(all(feature = "runtime-tokio", not(target_arch = "wasm32")))]
+mod server;
+#[cfg(all(feature = "runtime-tokio", not(target_arch = "wasm32")))]
+pub use server::{FieldServer, STATIONS};
+
+/// Match state reported by the FMS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FmsInfo {
+    pub event_code: String,
+    pub station: AlliancePos,
+    pub station_status: Option<StationStatus>,
+    pub match_type: MatchType,
+    pub match_number: u16,
+    pub play_number: u8,
+    /// Seconds left in the current match period
+    pub remaining_time: u16,
+}
+
+impl Ds {
+    /// Whether the DS is currently being controlled by an FMS
+    #[inline(always)]
+    pub fn is_fms_connected(&self) -> bool {
+        self.fms_connected.load(Ordering::Acquire)
+    }
+
+    /// Mark the DS as controlled by an FMS, or not, without connecting to one
+    ///
+    /// For when something else talks to the field. Robot code sees the FMS
+    /// as attached, and the robot can't be enabled or disabled locally, only
+    /// emergency stopped. The robot is disabled either way, so control
+    /// starts from a known state.
+    pub async fn set_fms_connected(&self, connected: bool) {
+        if self.fms_connected.swap(connected, Ordering::AcqRel) == connected {
+            return;
+        }
+
+        event!(Level::INFO, connected, "FMS control changed");
+        if self.status() == RobotStatus::Enabled {
+            self.disable_now().await;
+        } else {
+            self.send_udp().await;
+        }
+    }
+
+    /// Get the latest match state from the FMS, if connected
+    pub fn fms_info(&self) -> Option<FmsInfo> {
+        self.fms_info.lock().unwrap().clone()
+    }
+}
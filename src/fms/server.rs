@@ -0,0 +1,580 @@
+//! A minimal field management system, for off-season events and scrimmages
+
+use std::{
+    io, mem,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::mpsc::{UnboundedSender, unbounded_channel},
+    task::JoinSet,
+    time::interval,
+};
+
+use crate::{
+    AlliancePos, Error, MatchType,
+    practice::{MatchPhase, MatchTiming},
+    proto::{
+        fms::{
+            DS_FMS_UDP_PORT, DsStatusPacket, DsTcpTag, FMS_TCP_PORT, FMS_UDP_PORT,
+            FmsControlPacket, FmsDialect, FmsTcpTag, StationStatus, read_frame,
+        },
+        outgoing::udp::Control,
+    },
+    trace::Level,
+};
+
+/// Number of driver stations on a field
+pub const STATIONS: usize = 6;
+
+/// How often control packets are sent to each driver station
+const CONTROL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Default)]
+struct Station {
+    team: Option<u16>,
+    estopped: bool,
+    /// Where the assigned team's DS connected from
+    ds_addr: Option<IpAddr>,
+    /// Sends TCP frames to the assigned team's DS
+    ds_tx: Option<UnboundedSender<Vec<u8>>>,
+    /// What the assigned team's DS speaks, going by the tags it sends
+    dialect: FmsDialect,
+    last_status: Option<DsStatusPacket>,
+}
+
+struct Match {
+    match_type: MatchType,
+    match_number: u16,
+    play_number: u8,
+    started: Option<Instant>,
+    /// Ended early, either aborted or finished
+    ended: bool,
+}
+
+struct Inner {
+    timing: MatchTiming,
+    stations: Mutex<[Station; STATIONS]>,
+    current: Mutex<Match>,
+}
+
+/// Emulates the field management system for up to six driver stations
+///
+/// Teams get assigned to alliance stations ahead of time. Once their DS
+/// connects, the field controls it like a real FMS would: everything is
+/// disabled until a match starts, then autonomous, a short pause, and teleop
+/// run on the configured timing.
+#[derive(Clone)]
+pub struct FieldServer {
+    inner: Arc<Inner>,
+}
+impl FieldServer {
+    pub fn new(timing: MatchTiming) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                timing,
+                stations: Default::default(),
+                current: Mutex::new(Match {
+                    match_type: MatchType::Practice,
+                    match_number: 1,
+                    play_number: 1,
+                    started: None,
+                    ended: false,
+                }),
+            }),
+        }
+    }
+
+    /// Assign `team` to `station`, replacing whoever was there
+    ///
+    /// A team moved from another station keeps its DS connected, and is told
+    /// where it is now.
+    pub fn assign(&self, team: u16, station: AlliancePos) -> Result<(), Error> {
+        let index = index(station)?;
+        let mut stations = self.inner.stations.lock().unwrap();
+        if stations[index].team == Some(team) {
+            return Ok(());
+        }
+
+        // A team can only be in one place at a time
+        let moved = stations
+            .iter_mut()
+            .find(|station| station.team == Some(team))
+            .map(mem::take);
+        stations[index] = moved.unwrap_or(Station {
+            team: Some(team),
+            ..Default::default()
+        });
+
+        let moved = &stations[index];
+        if let Some(tx) = &moved.ds_tx {
+            let info = FmsTcpTag::StationInfo {
+                station,
+                status: StationStatus::Good,
+            };
+            let _ = tx.send(info.write(moved.dialect));
+        }
+        Ok(())
+    }
+
+    /// Remove whoever is assigned to `station`
+    pub fn unassign(&self, station: AlliancePos) -> Result<(), Error> {
+        self.inner.stations.lock().unwrap()[index(station)?] = Station::default();
+        Ok(())
+    }
+
+    /// Get the team assigned to `station`, and whether its DS is connected
+    pub fn station(&self, station: AlliancePos) -> Result<Option<(u16, bool)>, Error> {
+        let stations = self.inner.stations.lock().unwrap();
+        let station = &stations[index(station)?];
+        Ok(station.team.map(|team| (team, station.ds_addr.is_some())))
+    }
+
+    /// Get the latest status a station's DS reported
+    pub fn station_status(&self, station: AlliancePos) -> Result<Option<DsStatusPacket>, Error> {
+        Ok(self.inner.stations.lock().unwrap()[index(station)?].last_status)
+    }
+
+    /// Set up the next match, resetting the match timer
+    pub fn prepare_match(&self, match_type: MatchType, match_number: u16, play_number: u8) {
+        *self.inner.current.lock().unwrap() = Match {
+            match_type,
+            match_number,
+            play_number,
+            started: None,
+            ended: false,
+        };
+
+        for station in self.inner.stations.lock().unwrap().iter_mut() {
+            station.estopped = false;
+        }
+    }
+
+    /// Start the match timer
+    pub fn start_match(&self) {
+        let mut current = self.inner.current.lock().unwrap();
+        current.started = Some(Instant::now());
+        current.ended = false;
+        event!(
+            Level::INFO,
+            match_number = current.match_number,
+            "Match started"
+        );
+    }
+
+    /// End the match early, disabling every robot
+    pub fn abort_match(&self) {
+        self.inner.current.lock().unwrap().ended = true;
+        event!(Level::WARN, "Match aborted");
+    }
+
+    /// Emergency stop a single station until the next match
+    pub fn estop(&self, station: AlliancePos) -> Result<(), Error> {
+        self.inner.stations.lock().unwrap()[index(station)?].estopped = true;
+        Ok(())
+    }
+
+    /// Send game data to every connected driver station
+    pub fn set_game_data(&self, game_data: &str) {
+        for station in self.inner.stations.lock().unwrap().iter() {
+            if let Some(tx) = &station.ds_tx {
+                let _ = tx.send(FmsTcpTag::GameData(game_data).write(station.dialect));
+            }
+        }
+    }
+
+    /// Get the current match phase
+    pub fn phase(&self) -> MatchPhase {
+        self.phase_and_remaining().0
+    }
+
+    fn phase_and_remaining(&self) -> (MatchPhase, Duration) {
+        let current = self.inner.current.lock().unwrap();
+        let Some(started) = current.started else {
+            return (MatchPhase::PreMatch, self.inner.timing.autonomous);
+        };
+        if current.ended {
+            return (MatchPhase::PostMatch, Duration::ZERO);
+        }
+
+        self.inner.timing.phase_at(started.elapsed())
+    }
+
+    /// Accept driver stations and control them until an IO error occurs
+    pub async fn run(&self) -> io::Result<()> {
+        let listener = TcpListener::bind(("0.0.0.0", FMS_TCP_PORT)).await?;
+        let udp = UdpSocket::bind(("0.0.0.0", FMS_UDP_PORT)).await?;
+        let mut connections = JoinSet::new();
+        let mut control_interval = interval(CONTROL_INTERVAL);
+        let mut udp_buf = [0u8; 1024];
+        let mut seqnum = 0u16;
+        let mut last_phase = MatchPhase::PreMatch;
+
+        loop {
+            tokio::select! {
+                res = listener.accept() => {
+                    let (stream, addr) = res?;
+                    let server = self.clone();
+                    connections.spawn(async move {
+                        if let Err(err) = server.handle_ds(stream, addr).await {
+                            event!(Level::DEBUG, %addr, %err, "DS connection closed");
+                        }
+                    });
+                }
+                res = udp.recv_from(&mut udp_buf) => {
+                    let (len, addr) = res?;
+                    if let Some(status) = DsStatusPacket::parse(&udp_buf[..len]) {
+                        self.record_status(status, addr.ip());
+                    }
+                }
+                _ = control_interval.tick() => {
+                    let (phase, remaining) = self.phase_and_remaining();
+                    if phase != last_phase {
+                        event!(Level::INFO, ?phase, "Match phase changed");
+                        last_phase = phase;
+                    }
+
+                    seqnum = seqnum.wrapping_add(1);
+                    self.send_control_packets(&udp, DS_FMS_UDP_PORT, seqnum, phase, remaining)
+                        .await;
+                }
+                // Keep finished connections from piling up
+                Some(_) = connections.join_next() => {}
+            }
+        }
+    }
+
+    /// Store a status packet from the DS at `addr`
+    ///
+    /// Several driver stations can share an address, like behind NAT, so
+    /// the team in the packet has to match too.
+    fn record_status(&self, status: DsStatusPacket, addr: IpAddr) {
+        let mut stations = self.inner.stations.lock().unwrap();
+        let station = stations.iter_mut().find(|station| {
+            station.team == Some(status.team_number) && station.ds_addr == Some(addr)
+        });
+        if let Some(station) = station {
+            station.last_status = Some(status);
+        }
+    }
+
+    /// Send every connected DS its control packet, on `port`
+    ///
+    /// One unreachable DS shouldn't take the whole field down, so failures
+    /// are only logged.
+    async fn send_control_packets(
+        &self,
+        udp: &UdpSocket,
+        port: u16,
+        seqnum: u16,
+        phase: MatchPhase,
+        remaining: Duration,
+    ) {
+        for (addr, pkt) in self.control_packets(seqnum, phase, remaining) {
+            if let Err(err) = udp.send_to(&pkt.write(), (addr, port)).await {
+                event!(Level::WARN, %addr, %err, "Couldn't send a control packet");
+            }
+        }
+    }
+
+    fn control_packets(
+        &self,
+        seqnum: u16,
+        phase: MatchPhase,
+        remaining: Duration,
+    ) -> Vec<(IpAddr, FmsControlPacket)> {
+        let current = self.inner.current.lock().unwrap();
+        let stations = self.inner.stations.lock().unwrap();
+
+        let mut control = match phase {
+            MatchPhase::Autonomous | MatchPhase::Pause => Control::AUTO,
+            _ => Control::TELEOP,
+        };
+        if phase.is_enabled() {
+            control |= Control::ENABLED;
+        }
+
+        stations
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, station)| {
+                let addr = station.ds_addr?;
+                let control = if station.estopped {
+                    (control - Control::ENABLED) | Control::ESTOP
+                } else {
+                    control
+                };
+
+                Some((
+                    addr,
+                    FmsControlPacket {
+                        seqnum,
+                        control,
+                        station: AlliancePos::from_station(pos as u8)?,
+                        match_type: current.match_type,
+                        match_number: current.match_number,
+                        play_number: current.play_number,
+                        remaining_time: remaining.as_secs() as u16,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    async fn handle_ds(&self, mut stream: TcpStream, addr: SocketAddr) -> io::Result<()> {
+        let (tx, mut rx) = unbounded_channel();
+        let mut buf = Vec::with_capacity(256);
+        let mut team = None;
+
+        let res = loop {
+            tokio::select! {
+                res = stream.read_buf(&mut buf) => {
+                    if res? == 0 {
+                        break Ok(());
+                    }
+
+                    while let Some((tag, len)) = read_frame(&buf) {
+                        match tag.and_then(|tag| DsTcpTag::parse(tag.id, tag.data)) {
+                            Some(DsTcpTag::TeamNumber(number)) => {
+                                team = Some(number);
+                                let info = self.register_ds(number, addr.ip(), tx.clone());
+                                stream.write_all(&info.write(FmsDialect::Official)).await?;
+                            }
+                            // Only Cheesy Arena's driver stations send these
+                            Some(DsTcpTag::Keepalive) => {
+                                if let Some(team) = team {
+                                    self.set_dialect(team, FmsDialect::CheesyArena);
+                                }
+                            }
+                            None => {}
+                        }
+                        buf.drain(..len);
+                    }
+                }
+                Some(frame) = rx.recv() => {
+                    stream.write_all(&frame).await?;
+                }
+            }
+        };
+
+        if let Some(team) = team {
+            for station in self.inner.stations.lock().unwrap().iter_mut() {
+                if station.team == Some(team) {
+                    station.ds_addr = None;
+                    station.ds_tx = None;
+                    station.dialect = FmsDialect::Official;
+                    station.last_status = None;
+                }
+            }
+        }
+
+        res
+    }
+
+    fn set_dialect(&self, team: u16, dialect: FmsDialect) {
+        let mut stations = self.inner.stations.lock().unwrap();
+        for station in stations.iter_mut() {
+            if station.team == Some(team) && station.dialect != dialect {
+                event!(
+                    Level::DEBUG,
+                    team,
+                    ?dialect,
+                    "Driver station dialect detected"
+                );
+                station.dialect = dialect;
+            }
+        }
+    }
+
+    /// Attach a connecting DS to its team's station, returning the station
+    /// info to send back
+    fn register_ds(
+        &self,
+        team: u16,
+        addr: IpAddr,
+        tx: UnboundedSender<Vec<u8>>,
+    ) -> FmsTcpTag<'static> {
+        let mut stations = self.inner.stations.lock().unwrap();
+
+        let Some((pos, station)) = stations
+            .iter_mut()
+            .enumerate()
+            .find(|(_, station)| station.team == Some(team))
+        else {
+            event!(Level::WARN, team, "Unassigned team connected");
+            return FmsTcpTag::StationInfo {
+                station: AlliancePos::Red(1),
+                status: StationStatus::Waiting,
+            };
+        };
+
+        event!(Level::INFO, team, %addr, "Driver station connected");
+        station.ds_addr = Some(addr);
+        station.ds_tx = Some(tx);
+
+        FmsTcpTag::StationInfo {
+            // Always in range, there's only six stations
+            station: AlliancePos::from_station(pos as u8).unwrap(),
+            status: StationStatus::Good,
+        }
+    }
+}
+
+/// Index of a valid `station` in [`Inner::stations`]
+fn index(station: AlliancePos) -> Result<usize, Error> {
+    Ok(station.validate()?.to_pos() as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{practice::MatchTiming, proto::fms::DsStatus};
+
+    #[test]
+    fn invalid_stations_are_errors() {
+        let server = FieldServer::new(MatchTiming::default());
+
+        for station in [AlliancePos::Red(0), AlliancePos::Blue(9)] {
+            assert!(matches!(
+                server.assign(4533, station),
+                Err(Error::InvalidAlliancePos)
+            ));
+            assert!(server.unassign(station).is_err());
+            assert!(server.station(station).is_err());
+            assert!(server.station_status(station).is_err());
+            assert!(server.estop(station).is_err());
+        }
+
+        server.assign(4533, AlliancePos::Blue(3)).unwrap();
+        assert_eq!(
+            server.station(AlliancePos::Blue(3)).unwrap(),
+            Some((4533, false))
+        );
+        assert_eq!(server.station(AlliancePos::Red(1)).unwrap(), None);
+    }
+
+    /// Connect a pretend DS for `team`, returning its end of the connection
+    async fn connect(server: &FieldServer, team: u16, keepalive: bool) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut ds = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, addr) = listener.accept().await.unwrap();
+        let server = server.clone();
+        tokio::spawn(async move { server.handle_ds(stream, addr).await });
+
+        ds.write_all(&DsTcpTag::TeamNumber(team).write())
+            .await
+            .unwrap();
+        if keepalive {
+            ds.write_all(&DsTcpTag::Keepalive.write()).await.unwrap();
+        }
+
+        // Station info comes back once the team number is in
+        let mut info = [0u8; 5];
+        ds.read_exact(&mut info).await.unwrap();
+        ds
+    }
+
+    /// Read one whole frame, without its size
+    async fn read_tag(ds: &mut TcpStream) -> Vec<u8> {
+        let mut size = [0u8; 2];
+        ds.read_exact(&mut size).await.unwrap();
+        let mut frame = vec![0u8; u16::from_be_bytes(size) as usize];
+        ds.read_exact(&mut frame).await.unwrap();
+        frame
+    }
+
+    #[tokio::test]
+    async fn game_data_matches_each_stations_dialect() {
+        let server = FieldServer::new(MatchTiming::default());
+        server.assign(4533, AlliancePos::Red(1)).unwrap();
+        server.assign(254, AlliancePos::Blue(1)).unwrap();
+
+        let mut official = connect(&server, 4533, false).await;
+        let mut cheesy = connect(&server, 254, true).await;
+        while server.inner.stations.lock().unwrap()[3].dialect != FmsDialect::CheesyArena {
+            tokio::task::yield_now().await;
+        }
+
+        server.set_game_data("LRL");
+        assert_eq!(read_tag(&mut official).await, b"\x1CLRL");
+        assert_eq!(read_tag(&mut cheesy).await, b"\x1C\x03LRL");
+    }
+
+    #[tokio::test]
+    async fn moving_a_team_keeps_its_ds_connected() {
+        let server = FieldServer::new(MatchTiming::default());
+        server.assign(4533, AlliancePos::Red(1)).unwrap();
+        let mut ds = connect(&server, 4533, false).await;
+
+        // Assigning the same station again changes nothing
+        server.assign(4533, AlliancePos::Red(1)).unwrap();
+        assert_eq!(
+            server.station(AlliancePos::Red(1)).unwrap(),
+            Some((4533, true))
+        );
+
+        server.assign(4533, AlliancePos::Blue(2)).unwrap();
+        assert_eq!(server.station(AlliancePos::Red(1)).unwrap(), None);
+        assert_eq!(
+            server.station(AlliancePos::Blue(2)).unwrap(),
+            Some((4533, true))
+        );
+        // The DS hears where it is now
+        let info = read_tag(&mut ds).await;
+        assert_eq!(info, [0x19, AlliancePos::Blue(2).to_pos(), 0]);
+
+        // And game data still reaches it
+        server.set_game_data("RRL");
+        assert_eq!(read_tag(&mut ds).await, b"\x1CRRL");
+    }
+
+    #[tokio::test]
+    async fn status_needs_the_right_team_and_bad_addresses_are_skipped() {
+        let server = FieldServer::new(MatchTiming::default());
+        server.assign(4533, AlliancePos::Red(1)).unwrap();
+        server.assign(254, AlliancePos::Red(2)).unwrap();
+        let localhost = IpAddr::from([127, 0, 0, 1]);
+        // Sending to an IPv6 address from an IPv4 socket always fails
+        server.register_ds(4533, "::1".parse().unwrap(), unbounded_channel().0);
+        server.register_ds(254, localhost, unbounded_channel().0);
+
+        // Both are behind the same address as far as 254 is concerned
+        let status = |team_number| DsStatusPacket {
+            seqnum: 1,
+            status: DsStatus::empty(),
+            team_number,
+            battery: 12.5,
+        };
+        server.record_status(status(4533), localhost);
+        server.record_status(status(254), localhost);
+        assert!(
+            server
+                .station_status(AlliancePos::Red(1))
+                .unwrap()
+                .is_none()
+        );
+        let red2 = server.station_status(AlliancePos::Red(2)).unwrap();
+        assert_eq!(red2.unwrap().team_number, 254);
+
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let ds = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = ds.local_addr().unwrap().port();
+        server
+            .send_control_packets(&udp, port, 1, MatchPhase::PreMatch, Duration::ZERO)
+            .await;
+
+        // Red 1 failed first, and Red 2 still got its packet
+        let mut buf = [0u8; 64];
+        let len = ds.recv(&mut buf).await.unwrap();
+        let pkt = FmsControlPacket::parse(&buf[..len]).unwrap();
+        assert_eq!(pkt.station, AlliancePos::Red(2));
+    }
+}
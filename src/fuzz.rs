@@ -0,0 +1,72 @@
+//! Entry points for the fuzz targets in `fuzz/`
+//!
+//! Most of the decoders are crate-private, so this is the only way in for
+//! them. Only built under `cargo fuzz`, which sets `cfg(fuzzing)`:
+//!
+//! ```sh
+//! cargo +nightly fuzz run tcp_stream
+//! ```
+
+use crate::{
+    Ds,
+    diagnostics::{CanMetrics, CpuInfo, DisableFaults, PowerStats, RailFaults, RamInfo},
+    proto::{
+        fms::{DsTcpTag, FmsDialect, FmsTcpTag, read_frame},
+        incoming::{
+            read_array,
+            tcp::{ErrorMessage, Stdout, UsageReport, VersionInfo},
+            udp::{JoystickOutput, UdpIncomingStream},
+        },
+    },
+};
+
+/// Decode every status packet in `data`
+pub fn udp_stream(data: &[u8]) {
+    for _ in UdpIncomingStream::new(data) {}
+}
+
+/// Decode `data` as the TCP tag from the roboRIO with id `id`
+pub fn tcp_tag(id: u8, data: &[u8]) {
+    match id {
+        0x01 => drop(UsageReport::parse(data).map(|report| report.to_owned())),
+        0x04 => drop(DisableFaults::parse(data)),
+        0x05 => drop(RailFaults::parse(data)),
+        0x0A => drop(VersionInfo::parse(data).map(|info| info.to_owned())),
+        0x0B => drop(ErrorMessage::parse(data).map(|message| message.to_owned())),
+        0x0C => drop(Stdout::parse(data).map(|stdout| stdout.to_owned())),
+        _ => {}
+    }
+}
+
+/// Decode `data` as the status packet tag with id `id`
+pub fn udp_tag(id: u8, data: &[u8]) {
+    match id {
+        0x01 => drop(JoystickOutput::parse(data)),
+        0x04 => drop(read_array::<4>(data, 0)),
+        0x05 => drop(CpuInfo::parse(data)),
+        0x06 => drop(RamInfo::parse(data)),
+        // A PDP log or a PDH log, depending on the length
+        0x08 => drop(PowerStats::parse(data)),
+        0x0E => drop(CanMetrics::parse(data)),
+        _ => {}
+    }
+}
+
+/// Decode every FMS TCP tag in `data`, from either end, in both dialects
+pub fn fms_tcp_stream(mut data: &[u8]) {
+    while let Some((id, tag, len)) = read_frame(data) {
+        FmsTcpTag::parse(id, tag, FmsDialect::Official);
+        FmsTcpTag::parse(id, tag, FmsDialect::CheesyArena);
+        DsTcpTag::parse(id, tag);
+        data = &data[len..];
+    }
+}
+
+/// Hand `data` to `ds` as if the roboRIO had sent it, first as a TCP frame
+/// and then as a status packet
+pub fn ds_receive(ds: &Ds, data: &[u8]) {
+    ds.handle_tcp_frame(data);
+    if let Some(report) = ds.protocol.read_status(data) {
+        ds.store_status(report);
+    }
+}
@@ -0,0 +1,208 @@
+//! A gRPC service for driving a headless driver station from another
+//! process, in any language
+//!
+//! The service is defined in `grpc/robudst.proto`. It can enable, disable,
+//! and emergency stop the robot, and stream telemetry and console output.
+//! Anything that can reach it can enable the robot, so [`serve_grpc`] only
+//! listens on localhost.
+//!
+//! ```no_run
+//! # async fn run() {
+//! use robudst::{Ds, grpc::{DEFAULT_GRPC_PORT, serve_grpc}};
+//!
+//! let (ds, _) = Ds::init(4533).await.spawn();
+//! serve_grpc(ds, DEFAULT_GRPC_PORT).await.unwrap();
+//! # }
+//! ```
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use tokio::{
+    sync::{broadcast::error::RecvError, mpsc},
+    time::interval,
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, transport::Server};
+
+use crate::{
+    RobotCodeMode, RobotStatus,
+    console::{ConsoleLevel, ConsoleLine},
+    event::DsEvent,
+    handle::DsHandle,
+    telemetry::Telemetry,
+    trace::Level,
+};
+
+/// Types generated from `grpc/robudst.proto`
+pub mod pb {
+    tonic::include_proto!("robudst");
+}
+
+use pb::driver_station_server::{DriverStation, DriverStationServer};
+
+/// The port gRPC services conventionally listen on
+pub const DEFAULT_GRPC_PORT: u16 = 50051;
+
+/// How often telemetry is streamed when the client doesn't say
+const DEFAULT_TELEMETRY_PERIOD: Duration = Duration::from_millis(100);
+
+/// How many messages can wait for a slow client
+const STREAM_CAPACITY: usize = 16;
+
+/// Serve the gRPC service for `ds` on localhost, until an error occurs
+pub async fn serve_grpc(ds: DsHandle, port: u16) -> Result<(), tonic::transport::Error> {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    event!(Level::INFO, %addr, "Serving gRPC");
+
+    Server::builder()
+        .add_service(GrpcService::new(ds).into_server())
+        .serve(addr)
+        .await
+}
+
+/// The gRPC service itself, for adding to a [`Server`] alongside others
+pub struct GrpcService {
+    ds: DsHandle,
+}
+impl GrpcService {
+    pub const fn new(ds: DsHandle) -> Self {
+        Self { ds }
+    }
+
+    pub fn into_server(self) -> DriverStationServer<Self> {
+        DriverStationServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl DriverStation for GrpcService {
+    async fn enable(&self, _: Request<pb::Empty>) -> Result<Response<pb::Empty>, Status> {
+        self.ds
+            .enable()
+            .await
+            .map_err(|e| Status::failed_precondition(format!("{e:?}")))?;
+        Ok(Response::new(pb::Empty {}))
+    }
+
+    async fn disable(&self, _: Request<pb::Empty>) -> Result<Response<pb::Empty>, Status> {
+        self.ds.disable().await;
+        Ok(Response::new(pb::Empty {}))
+    }
+
+    async fn estop(&self, _: Request<pb::Empty>) -> Result<Response<pb::Empty>, Status> {
+        event!(Level::WARN, "Emergency stop over gRPC");
+        self.ds.estop().await;
+        Ok(Response::new(pb::Empty {}))
+    }
+
+    type StreamTelemetryStream = ReceiverStream<Result<pb::Telemetry, Status>>;
+
+    async fn stream_telemetry(
+        &self,
+        request: Request<pb::TelemetryRequest>,
+    ) -> Result<Response<Self::StreamTelemetryStream>, Status> {
+        let period = match request.into_inner().period_ms {
+            0 => DEFAULT_TELEMETRY_PERIOD,
+            ms => Duration::from_millis(ms.into()),
+        };
+
+        let (tx, rx) = mpsc::channel(STREAM_CAPACITY);
+        let ds = self.ds.clone();
+        tokio::spawn(async move {
+            let mut interval = interval(period);
+            loop {
+                interval.tick().await;
+                // The client hung up
+                if tx.send(Ok(ds.telemetry().into())).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    type StreamConsoleStream = ReceiverStream<Result<pb::ConsoleLine, Status>>;
+
+    async fn stream_console(
+        &self,
+        _: Request<pb::Empty>,
+    ) -> Result<Response<Self::StreamConsoleStream>, Status> {
+        let (tx, rx) = mpsc::channel(STREAM_CAPACITY);
+        let mut events = self.ds.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let line = match events.recv().await {
+                    Ok(DsEvent::ConsoleLine(line)) => Ok(line.into()),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => {
+                        Err(Status::data_loss(format!("Missed {missed} events")))
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                if tx.send(line).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+impl From<Telemetry> for pb::Telemetry {
+    fn from(telemetry: Telemetry) -> Self {
+        let status = match telemetry.status {
+            RobotStatus::NoCommunication => pb::RobotStatus::NoCommunication,
+            RobotStatus::NoRobotCode => pb::RobotStatus::NoRobotCode,
+            RobotStatus::EStopped => pb::RobotStatus::Estopped,
+            RobotStatus::BrownedOut => pb::RobotStatus::BrownedOut,
+            RobotStatus::Disabled => pb::RobotStatus::Disabled,
+            RobotStatus::Enabled => pb::RobotStatus::Enabled,
+        };
+        let mode = match telemetry.mode {
+            RobotCodeMode::Autonomous => pb::RobotCodeMode::Autonomous,
+            RobotCodeMode::Teleop => pb::RobotCodeMode::Teleop,
+            RobotCodeMode::Test => pb::RobotCodeMode::Test,
+        };
+
+        Self {
+            status: status.into(),
+            mode: mode.into(),
+            has_robot_code: telemetry.has_robot_code,
+            browned_out: telemetry.browned_out,
+            battery_voltage: telemetry.battery_voltage,
+            cpu_percent: telemetry.cpu.map(|cpu| cpu.total()),
+            can_utilization: telemetry.can.map(|can| can.utilization),
+            packets_received: telemetry.connection.packets_received,
+            packets_lost: telemetry.connection.packets_lost,
+            brownouts: telemetry.brownouts,
+        }
+    }
+}
+
+impl From<ConsoleLine> for pb::ConsoleLine {
+    fn from(line: ConsoleLine) -> Self {
+        let level = match line.level {
+            ConsoleLevel::Info => pb::ConsoleLevel::Info,
+            ConsoleLevel::Warning => pb::ConsoleLevel::Warning,
+            ConsoleLevel::Error => pb::ConsoleLevel::Error,
+        };
+
+        Self {
+            seqnum: line.seqnum,
+            received_ms: line
+                .received
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as i64,
+            level: level.into(),
+            message: line.message,
+            error_code: line.error_code,
+            location: line.location,
+        }
+    }
+}
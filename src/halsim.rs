@@ -0,0 +1,399 @@
+//! Controlling simulated robots over the WPILib HALSim WebSocket protocol
+//!
+//! Robot programs running with the `halsim_ws_server` extension don't open
+//! the usual DS ports, only a WebSocket. [`HalSimTransport`] translates
+//! between the two: control packets from [`Ds`](crate::Ds) become
+//! `DriverStation` and `Joystick` messages, and status is made up from what
+//! the DS asked for, since the simulation always does as it's told.
+//!
+//! ```no_run
+//! # async fn run() {
+//! use robudst::{Ds, halsim::HalSimConnector};
+//!
+//! let ds = Ds::with_connector(4533, HalSimConnector::new("ws://localhost:3300/wpilibws"));
+//! ds.run().await;
+//! # }
+//! ```
+//!
+//! [Romi](HalSimConnector::romi) and [XRP](HalSimConnector::xrp) robots are
+//! controlled the same way, through the simulated program driving them.
+//!
+//! Reference: <https://github.com/wpilibsuite/allwpilib/blob/main/simulation/halsim_ws_core/doc/hal-websocket-specification.md>
+
+use std::{
+    io,
+    sync::{Arc, Mutex as StdMutex},
+};
+
+use futures_util::{
+    SinkExt, StreamExt,
+    stream::{SplitSink, SplitStream},
+};
+use serde_json::{Value, json};
+use tokio::{
+    net::TcpStream,
+    sync::{
+        Mutex, Notify,
+        mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+    },
+    task::JoinHandle,
+};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async, tungstenite::Message};
+
+use crate::{
+    AlliancePos,
+    joystick::{JoystickOutput, MAX_JOYSTICKS},
+    trace::Level,
+    transport::{Connect, Transport, TransportFuture},
+};
+
+/// Where `halsim_ws_server` listens by default
+pub const DEFAULT_HALSIM_URL: &str = "ws://localhost:3300/wpilibws";
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// What the simulated robot has told us
+#[derive(Default)]
+struct SimState {
+    outputs: [JoystickOutput; MAX_JOYSTICKS],
+    closed: bool,
+}
+
+/// A connection to a HALSim WebSocket server, pretending to be a roboRIO
+pub struct HalSimTransport {
+    ws_tx: Mutex<SplitSink<WsStream, Message>>,
+    /// Status packets made up in reply to control packets
+    status_tx: UnboundedSender<Vec<u8>>,
+    status_rx: Mutex<UnboundedReceiver<Vec<u8>>>,
+    state: Arc<StdMutex<SimState>>,
+    closed: Arc<Notify>,
+    reader: JoinHandle<()>,
+}
+impl HalSimTransport {
+    /// Connect to the HALSim WebSocket server at `url`
+    pub async fn connect(url: &str) -> io::Result<Self> {
+        let (ws, _) = connect_async(url).await.map_err(io::Error::other)?;
+        let (ws_tx, ws_rx) = ws.split();
+        let (status_tx, status_rx) = unbounded_channel();
+
+        let state = Arc::new(StdMutex::new(SimState::default()));
+        let closed = Arc::new(Notify::new());
+        let reader = tokio::spawn(read_messages(ws_rx, state.clone(), closed.clone()));
+
+        Ok(Self {
+            ws_tx: Mutex::new(ws_tx),
+            status_tx,
+            status_rx: Mutex::new(status_rx),
+            state,
+            closed,
+            reader,
+        })
+    }
+
+    async fn send_json(&self, msg: Value) -> io::Result<()> {
+        self.ws_tx
+            .lock()
+            .await
+            .send(Message::text(msg.to_string()))
+            .await
+            .map_err(io::Error::other)
+    }
+}
+impl Drop for HalSimTransport {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+impl Transport for HalSimTransport {
+    fn send_datagram<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            let Some(control) = SimControl::parse(buf) else {
+                return Err(io::ErrorKind::InvalidData.into());
+            };
+
+            self.send_json(control.driver_station_message()).await?;
+            for (slot, joystick) in control.joysticks.iter().enumerate() {
+                self.send_json(joystick.message(slot)).await?;
+            }
+
+            let outputs = self.state.lock().unwrap().outputs;
+            // Nobody receiving is fine
+            let _ = self.status_tx.send(control.status_packet(&outputs));
+            Ok(())
+        })
+    }
+
+    fn recv_datagram<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        Box::pin(async move {
+            let status = self
+                .status_rx
+                .lock()
+                .await
+                .recv()
+                .await
+                .ok_or(io::ErrorKind::ConnectionAborted)?;
+
+            let len = status.len().min(buf.len());
+            buf[..len].copy_from_slice(&status[..len]);
+            Ok(len)
+        })
+    }
+
+    fn write_stream<'a>(&'a self, _buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        // Nothing on the TCP side (descriptors, match info) has a HALSim
+        // equivalent worth sending
+        Box::pin(async { Ok(()) })
+    }
+
+    fn read_stream<'a>(&'a self, _buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        // Nothing ever comes in, but the stream "closes" with the WebSocket,
+        // so the DS notices and reconnects
+        Box::pin(async move {
+            let closed = self.closed.notified();
+            tokio::pin!(closed);
+            // Registered before checking, so closing in between isn't missed
+            closed.as_mut().enable();
+            if !self.state.lock().unwrap().closed {
+                closed.await;
+            }
+            Ok(0)
+        })
+    }
+
+    fn close(&self) -> TransportFuture<'_, ()> {
+        Box::pin(async move {
+            self.ws_tx
+                .lock()
+                .await
+                .close()
+                .await
+                .map_err(io::Error::other)
+        })
+    }
+}
+
+/// Keep track of what the simulated robot sends until the WebSocket closes
+async fn read_messages(
+    mut ws_rx: SplitStream<WsStream>,
+    state: Arc<StdMutex<SimState>>,
+    closed: Arc<Notify>,
+) {
+    while let Some(Ok(msg)) = ws_rx.next().await {
+        let Message::Text(text) = msg else {
+            continue;
+        };
+        let Ok(msg) = serde_json::from_str::<Value>(&text) else {
+            event!(Level::DEBUG, %text, "Ignoring malformed HALSim message");
+            continue;
+        };
+
+        if msg["type"] == "Joystick"
+            && let Some(slot) = msg["device"].as_str().and_then(|slot| slot.parse().ok())
+            && let Some(output) = state.lock().unwrap().outputs.get_mut::<usize>(slot)
+        {
+            let data = &msg["data"];
+            if let Some(outputs) = data["<outputs"].as_u64() {
+                output.outputs = outputs as u32;
+            }
+            // Rumble is `0.0..=1.0` here, but the full `u16` range on the wire
+            if let Some(rumble) = data["<rumble_left"].as_f64() {
+                output.left_rumble = (rumble.clamp(0.0, 1.0) * u16::MAX as f64) as u16;
+            }
+            if let Some(rumble) = data["<rumble_right"].as_f64() {
+                output.right_rumble = (rumble.clamp(0.0, 1.0) * u16::MAX as f64) as u16;
+            }
+        }
+    }
+
+    event!(Level::INFO, "HALSim WebSocket closed");
+    state.lock().unwrap().closed = true;
+    closed.notify_waiters();
+}
+
+/// A control packet, decoded just enough to translate
+struct SimControl {
+    seqnum: u16,
+    control: u8,
+    station: AlliancePos,
+    match_time: Option<f32>,
+    joysticks: Vec<SimJoystick>,
+}
+impl SimControl {
+    const ESTOP: u8 = 0b1000_0000;
+    const FMS_CONNECTED: u8 = 0b0000_1000;
+    const ENABLED: u8 = 0b0000_0100;
+    const TEST: u8 = 0b01;
+    const AUTO: u8 = 0b10;
+
+    fn parse(buf: &[u8]) -> Option<Self> {
+        let header = buf.get(..6)?;
+        let mut control = Self {
+            seqnum: u16::from_be_bytes([header[0], header[1]]),
+            control: header[3],
+            station: AlliancePos::from_station(header[5])?,
+            match_time: None,
+            joysticks: Vec::new(),
+        };
+
+        let mut pos = 6;
+        while let Some(&[size, id]) = buf.get(pos..pos + 2) {
+            // The size includes the tag id
+            let data = buf.get(pos + 2..pos + 1 + size as usize)?;
+            pos += 1 + size as usize;
+
+            match id {
+                0x07 => control.match_time = Some(f32::from_be_bytes(data.try_into().ok()?)),
+                0x0C => control.joysticks.push(SimJoystick::parse(data)?),
+                _ => {}
+            }
+        }
+
+        Some(control)
+    }
+
+    fn driver_station_message(&self) -> Value {
+        let (alliance, pos) = match self.station {
+            AlliancePos::Red(pos) => ("red", pos),
+            AlliancePos::Blue(pos) => ("blue", pos),
+        };
+
+        json!({
+            "type": "DriverStation",
+            "device": "",
+            "data": {
+                ">new_data": true,
+                ">enabled": self.control & Self::ENABLED != 0,
+                ">autonomous": self.control & 0b11 == Self::AUTO,
+                ">test": self.control & 0b11 == Self::TEST,
+                ">estop": self.control & Self::ESTOP != 0,
+                ">fms": self.control & Self::FMS_CONNECTED != 0,
+                ">ds": true,
+                ">station": format!("{alliance}{pos}"),
+                ">match_time": self.match_time.unwrap_or(-1.0),
+            },
+        })
+    }
+
+    /// Make up the status a roboRIO would reply with, which is just whatever
+    /// was asked for
+    fn status_packet(&self, outputs: &[JoystickOutput]) -> Vec<u8> {
+        // Estop, enabled, and the mode are in the same bits as the control
+        let status = self.control & (Self::ESTOP | Self::ENABLED | 0b11);
+        let mut trace = 0b0011_0000; // Robot code, is a roboRIO
+        trace |= if self.control & Self::ENABLED == 0 {
+            0b0000_0001
+        } else {
+            match self.control & 0b11 {
+                Self::TEST => 0b0000_1000,
+                Self::AUTO => 0b0000_0100,
+                _ => 0b0000_0010,
+            }
+        };
+
+        let mut buf = Vec::with_capacity(8 + outputs.len() * 10);
+        buf.extend(self.seqnum.to_be_bytes());
+        // A simulated battery is always 12V
+        buf.extend([0x01, status, trace, 12, 0, 0]);
+
+        // Always at least one tag, since a bare header looks truncated
+        for output in outputs.iter().take(self.joysticks.len().max(1)) {
+            buf.extend([9, 0x01]);
+            buf.extend(output.outputs.to_le_bytes());
+            buf.extend(output.left_rumble.to_be_bytes());
+            buf.extend(output.right_rumble.to_be_bytes());
+        }
+
+        buf
+    }
+}
+
+struct SimJoystick {
+    axes: Vec<f64>,
+    buttons: Vec<bool>,
+    povs: Vec<i16>,
+}
+impl SimJoystick {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let axis_count = *data.first()? as usize;
+        let axes = data.get(1..1 + axis_count)?;
+        let mut pos = 1 + axis_count;
+
+        let button_count = *data.get(pos)? as usize;
+        let button_bytes = button_count.div_ceil(8);
+        let packed = data.get(pos + 1..pos + 1 + button_bytes)?;
+        let packed = packed
+            .iter()
+            .fold(0u32, |acc, byte| acc << 8 | *byte as u32);
+        pos += 1 + button_bytes;
+
+        let pov_count = *data.get(pos)? as usize;
+        let povs = data.get(pos + 1..pos + 1 + pov_count * 2)?;
+
+        Some(Self {
+            axes: axes.iter().map(|axis| *axis as i8 as f64 / 127.0).collect(),
+            buttons: (0..button_count).map(|i| packed & 1 << i != 0).collect(),
+            povs: povs
+                .chunks_exact(2)
+                .map(|pov| i16::from_be_bytes([pov[0], pov[1]]))
+                .collect(),
+        })
+    }
+
+    fn message(&self, slot: usize) -> Value {
+        json!({
+            "type": "Joystick",
+            "device": slot.to_string(),
+            "data": {
+                ">axes": self.axes,
+                ">buttons": self.buttons,
+                ">povs": self.povs,
+            },
+        })
+    }
+}
+
+/// Connects to a HALSim WebSocket server at a fixed URL
+#[derive(Debug, Clone)]
+pub struct HalSimConnector {
+    pub url: String,
+}
+impl HalSimConnector {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+
+    /// Connect to the robot program driving a Romi
+    ///
+    /// Romi robot code doesn't run on the Romi. It runs in desktop
+    /// simulation, and drives the Romi over the Romi's own WebSocket
+    /// (`ws://10.0.0.2:3300/wpilibws`). Enabling the robot and sending
+    /// joysticks happens through the simulated program, so add
+    /// `wpi.sim.addWebsocketsServer()` next to the Romi's
+    /// `wpi.sim.addWebsocketsClient()` in `build.gradle` and connect here.
+    pub fn romi() -> Self {
+        Self::default()
+    }
+
+    /// Connect to the robot program driving an XRP
+    ///
+    /// Like the Romi, XRP robot code runs in desktop simulation (talking to
+    /// the XRP itself over UDP port 3540), so the DS controls the simulated
+    /// program. Add `wpi.sim.addWebsocketsServer()` to `build.gradle` and
+    /// connect here.
+    pub fn xrp() -> Self {
+        Self::default()
+    }
+}
+impl Default for HalSimConnector {
+    fn default() -> Self {
+        Self::new(DEFAULT_HALSIM_URL)
+    }
+}
+impl Connect for HalSimConnector {
+    fn connect(&self, _team_number: u16) -> TransportFuture<'_, Box<dyn Transport>> {
+        Box::pin(async move {
+            let transport = HalSimTransport::connect(&self.url).await?;
+            Ok(Box::new(transport) as Box<dyn Transport>)
+        })
+    }
+}
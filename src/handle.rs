@@ -0,0 +1,38 @@
+//! Running a driver station in the background
+
+use std::{ops::Deref, sync::Arc};
+
+use tokio::task::JoinHandle;
+
+use crate::Ds;
+
+/// A cheap, cloneable handle to a driver station spawned with [`Ds::spawn`]
+///
+/// Derefs to [`Ds`], so everything (enabling, disabling, reading status) is
+/// available through it.
+#[derive(Clone)]
+pub struct DsHandle(Arc<Ds>);
+impl Deref for DsHandle {
+    type Target = Ds;
+
+    fn deref(&self) -> &Ds {
+        &self.0
+    }
+}
+
+impl Ds {
+    /// Run the driver station on the current tokio runtime
+    ///
+    /// The returned [`JoinHandle`] finishes once [`Ds::shutdown`] is called
+    /// through any handle.
+    pub fn spawn(self) -> (DsHandle, JoinHandle<()>) {
+        let ds = Arc::new(self);
+
+        let task = tokio::spawn({
+            let ds = ds.clone();
+            async move { ds.run().await }
+        });
+
+        (DsHandle(ds), task)
+    }
+}
@@ -0,0 +1,215 @@
+//! The official DS's safety hotkeys, whichever window has focus
+//!
+//! Just like the official DS, Enter disables the robot and Space triggers
+//! an emergency stop. Keys are only listened to, never grabbed, so they
+//! still reach whatever window they were typed into.
+//!
+//! **Every Space typed in any application emergency stops the robot**,
+//! including one typed into a chat window or code editor, and the robot
+//! stays stopped until [`Ds::clear_estop`] or a reconnect. Only watch
+//! hotkeys on a computer that's being used to drive.
+//!
+//! ```no_run
+//! # async fn run(ds: &robudst::Ds) {
+//! tokio::select! {
+//!     _ = ds.run() => {}
+//!     res = ds.watch_hotkeys() => eprintln!("Hotkeys stopped working: {res:?}"),
+//! }
+//! # }
+//! ```
+//!
+//! The keyboard is listened to by a thread that's started the first time
+//! it's needed, and shared by every driver station. On Linux it needs an X
+//! server (XWayland works).
+
+use std::{
+    io,
+    sync::{
+        OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+};
+
+use rdev::{EventType, Key};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{Ds, trace::Level};
+
+/// How many hotkeys a slow driver station can fall behind on before the
+/// oldest are dropped
+const HOTKEY_CAPACITY: usize = 16;
+
+/// Every emergency stop hotkey ever pressed, counted before it's sent so a
+/// driver station that fell behind can tell if it missed one
+static ESTOP_PRESSES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hotkey {
+    Disable,
+    EStop,
+}
+impl Hotkey {
+    fn from_key(key: Key) -> Option<Self> {
+        match key {
+            Key::Return | Key::KpReturn => Some(Self::Disable),
+            Key::Space => Some(Self::EStop),
+            _ => None,
+        }
+    }
+}
+
+/// Get every hotkey pressed from now on, starting the listener if it isn't
+/// already
+///
+/// Only the listener thread holds a sender, so the channel closes if it
+/// couldn't start.
+fn hotkeys() -> broadcast::Receiver<Hotkey> {
+    static HOTKEYS: OnceLock<broadcast::Receiver<Hotkey>> = OnceLock::new();
+
+    HOTKEYS
+        .get_or_init(|| {
+            let (tx, rx) = broadcast::channel(HOTKEY_CAPACITY);
+            let listener = thread::Builder::new()
+                .name("robudst-hotkeys".into())
+                .spawn(move || {
+                    let res = rdev::listen(move |event| {
+                        if let EventType::KeyPress(key) = event.event_type
+                            && let Some(hotkey) = Hotkey::from_key(key)
+                        {
+                            if hotkey == Hotkey::EStop {
+                                ESTOP_PRESSES.fetch_add(1, Ordering::AcqRel);
+                            }
+                            // Nobody listening is fine
+                            let _ = tx.send(hotkey);
+                        }
+                    });
+                    if let Err(err) = res {
+                        event!(Level::ERROR, ?err, "Couldn't listen for hotkeys");
+                    }
+                });
+            if let Err(err) = listener {
+                event!(Level::ERROR, %err, "Couldn't start the hotkey listener");
+            }
+            rx
+        })
+        .resubscribe()
+}
+
+impl Ds {
+    /// Disable the robot when Enter is pressed, and emergency stop it when
+    /// Space is, until the returned future is dropped
+    ///
+    /// These are pressed in any application, not just this one, so typing a
+    /// space anywhere emergency stops the robot.
+    ///
+    /// If this falls too far behind to keep up, the robot is disabled, and
+    /// emergency stopped if any of the missed presses was Space. An emergency
+    /// stop is never lost.
+    ///
+    /// Only returns if the keyboard can't be listened to, like when there's
+    /// no display to listen on.
+    pub async fn watch_hotkeys(&self) -> io::Result<()> {
+        // Counted before subscribing, so a press in between can only make
+        // this stop when it didn't need to, never the other way around
+        let estops_seen = ESTOP_PRESSES.load(Ordering::Acquire);
+        self.handle_hotkeys(hotkeys(), &ESTOP_PRESSES, estops_seen)
+            .await
+    }
+
+    /// Act on hotkeys from `hotkeys`, with `estop_presses` counting every
+    /// emergency stop sent on it, `estops_seen` of them from before
+    /// subscribing
+    async fn handle_hotkeys(
+        &self,
+        mut hotkeys: broadcast::Receiver<Hotkey>,
+        estop_presses: &AtomicU64,
+        mut estops_seen: u64,
+    ) -> io::Result<()> {
+        loop {
+            match hotkeys.recv().await {
+                Ok(Hotkey::Disable) => {
+                    event!(Level::INFO, "Disable hotkey pressed");
+                    self.disable().await;
+                }
+                Ok(Hotkey::EStop) => {
+                    event!(Level::WARN, "Emergency stop hotkey pressed");
+                    // Capped, since presses already handled after falling
+                    // behind still arrive
+                    estops_seen = (estops_seen + 1).min(estop_presses.load(Ordering::Acquire));
+                    self.estop().await;
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    let presses = estop_presses.load(Ordering::Acquire);
+                    if presses > estops_seen {
+                        event!(
+                            Level::WARN,
+                            missed,
+                            "Missed an emergency stop hotkey, stopping"
+                        );
+                        estops_seen = presses;
+                        self.estop().await;
+                    } else {
+                        // Only disables were missed, so do what they asked
+                        event!(Level::WARN, missed, "Missed disable hotkeys, disabling");
+                        self.disable().await;
+                    }
+                }
+                Err(RecvError::Closed) => {
+                    return Err(io::Error::other("couldn't listen to the keyboard"));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RobotStatus, transport::MemoryTransport};
+
+    #[test]
+    fn keys_match_the_official_ds() {
+        assert_eq!(Hotkey::from_key(Key::Return), Some(Hotkey::Disable));
+        assert_eq!(Hotkey::from_key(Key::KpReturn), Some(Hotkey::Disable));
+        assert_eq!(Hotkey::from_key(Key::Space), Some(Hotkey::EStop));
+        assert_eq!(Hotkey::from_key(Key::KeyA), None);
+    }
+
+    /// Send `pressed` to a DS that's too far behind to see them as they
+    /// happen, then let it catch up
+    async fn overflow(pressed: &[Hotkey]) -> Ds {
+        let ds = Ds::with_transport(4533, MemoryTransport::pair().0);
+        ds.status.store(RobotStatus::Enabled);
+
+        let estop_presses = AtomicU64::new(0);
+        let (tx, rx) = broadcast::channel(HOTKEY_CAPACITY);
+        for &hotkey in pressed {
+            if hotkey == Hotkey::EStop {
+                estop_presses.fetch_add(1, Ordering::AcqRel);
+            }
+            tx.send(hotkey).unwrap();
+        }
+        drop(tx);
+
+        assert!(ds.handle_hotkeys(rx, &estop_presses, 0).await.is_err());
+        ds
+    }
+
+    #[tokio::test]
+    async fn missed_estop_still_stops() {
+        // The e-stop is pushed out of the channel by the disables after it
+        let mut pressed = vec![Hotkey::EStop];
+        pressed.extend([Hotkey::Disable; HOTKEY_CAPACITY + 4]);
+
+        let ds = overflow(&pressed).await;
+        assert!(ds.is_estopped());
+    }
+
+    #[tokio::test]
+    async fn missed_disables_only_disable() {
+        let ds = overflow(&[Hotkey::Disable; HOTKEY_CAPACITY + 4]).await;
+        assert!(!ds.is_estopped());
+        assert_eq!(ds.status(), RobotStatus::Disabled);
+    }
+}
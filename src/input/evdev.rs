@@ -0,0 +1,229 @@
+//! Raw evdev joystick support for Linux
+//!
+//! Meant for headless driver stations, where there's no windowing system to
+//! get input from. Devices are read straight from `/dev/input/event*`, and
+//! hot-plugging is detected by watching `/dev/input` with inotify.
+
+use std::{
+    io,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use ::evdev::{AbsoluteAxisCode, Device, KeyCode};
+use inotify::{Inotify, WatchMask};
+
+use super::{JoystickProvider, axis_to_i8, dpad_to_pov};
+use crate::{
+    joystick::{Joystick, JoystickDescriptor, MAX_AXES, MAX_BUTTONS, MAX_JOYSTICKS, MAX_POVS},
+    proto::outgoing::tcp::JoystickKind,
+};
+
+const INPUT_DIR: &str = "/dev/input";
+
+/// Range of joystick and gamepad button codes, used to tell joysticks apart
+/// from keyboards and mice
+const BTN_JOYSTICK: RangeInclusive<u16> = KeyCode::BTN_TRIGGER.0..=KeyCode::BTN_THUMBR.0;
+/// Directional pad buttons, which get turned into a POV instead
+const BTN_DPAD: [KeyCode; 4] = [
+    KeyCode::BTN_DPAD_UP,
+    KeyCode::BTN_DPAD_RIGHT,
+    KeyCode::BTN_DPAD_DOWN,
+    KeyCode::BTN_DPAD_LEFT,
+];
+/// Hat axes come in X/Y pairs, from `ABS_HAT0X` to `ABS_HAT3Y`
+const ABS_HATS: RangeInclusive<u16> = AbsoluteAxisCode::ABS_HAT0X.0..=AbsoluteAxisCode::ABS_HAT3Y.0;
+
+/// Joystick provider that reads evdev devices directly
+///
+/// Devices are assigned to the lowest free slot when they show up, and keep
+/// that slot until they're removed.
+pub struct EvdevProvider {
+    inotify: Inotify,
+    slots: [Option<(PathBuf, Device)>; MAX_JOYSTICKS],
+}
+impl EvdevProvider {
+    /// Open every joystick that's currently connected, and start watching
+    /// for new ones
+    pub fn new() -> io::Result<Self> {
+        let inotify = Inotify::init()?;
+        inotify.watches().add(
+            INPUT_DIR,
+            WatchMask::CREATE | WatchMask::ATTRIB | WatchMask::DELETE,
+        )?;
+
+        let mut provider = Self {
+            inotify,
+            slots: Default::default(),
+        };
+
+        let mut devices: Vec<(PathBuf, Device)> = ::evdev::enumerate()
+            .filter(|(_, device)| is_joystick(device))
+            .collect();
+        // Keep the slot order stable between runs
+        devices.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (path, device) in devices {
+            provider.insert(path, device);
+        }
+
+        Ok(provider)
+    }
+
+    /// Get the device in `slot`, if there is one
+    pub fn device(&self, slot: usize) -> Option<&Device> {
+        self.slots.get(slot)?.as_ref().map(|(_, device)| device)
+    }
+
+    fn insert(&mut self, path: PathBuf, device: Device) {
+        if self.slots.iter().flatten().any(|(p, _)| *p == path) {
+            return;
+        }
+
+        if let Some(slot) = self.slots.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some((path, device));
+        } else {
+            warn!(?path, "No free joystick slot for device");
+        }
+    }
+
+    fn remove(&mut self, path: &Path) {
+        for slot in &mut self.slots {
+            if slot.as_ref().is_some_and(|(p, _)| p == path) {
+                *slot = None;
+            }
+        }
+    }
+
+    fn handle_hotplug(&mut self) {
+        let mut buf = [0u8; 4096];
+
+        loop {
+            let events = match self.inotify.read_events(&mut buf) {
+                Ok(events) => events,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return,
+                Err(err) => {
+                    warn!(%err, "Failed to read input device events");
+                    return;
+                }
+            };
+
+            let mut added = Vec::new();
+            let mut removed = Vec::new();
+            for event in events {
+                let Some(name) = event.name else { continue };
+                if !name.to_string_lossy().starts_with("event") {
+                    continue;
+                }
+
+                let path = Path::new(INPUT_DIR).join(name);
+                if event.mask.contains(inotify::EventMask::DELETE) {
+                    removed.push(path);
+                } else {
+                    // Permissions usually get fixed up by udev after the node
+                    // is created, so opening is retried on ATTRIB too
+                    added.push(path);
+                }
+            }
+
+            for path in removed {
+                self.remove(&path);
+            }
+            for path in added {
+                if let Ok(device) = Device::open(&path)
+                    && is_joystick(&device)
+                {
+                    self.insert(path, device);
+                }
+            }
+        }
+    }
+}
+impl JoystickProvider for EvdevProvider {
+    fn poll(&mut self) -> [Option<Joystick>; MAX_JOYSTICKS] {
+        self.handle_hotplug();
+
+        core::array::from_fn(|slot| {
+            let (path, device) = self.slots[slot].as_ref()?;
+            match to_joystick(device) {
+                Ok(joystick) => Some(joystick),
+                Err(err) => {
+                    warn!(?path, %err, "Lost joystick");
+                    self.slots[slot] = None;
+                    None
+                }
+            }
+        })
+    }
+
+    fn descriptor(&self, slot: usize) -> Option<JoystickDescriptor> {
+        let device = self.device(slot)?;
+        let joystick = to_joystick(device).ok()?;
+
+        Some(JoystickDescriptor {
+            name: device.name().unwrap_or_default().to_owned(),
+            kind: JoystickKind::HIDJoystick,
+            ..JoystickDescriptor::generic(&joystick)
+        })
+    }
+}
+
+fn is_joystick(device: &Device) -> bool {
+    let has_buttons = device
+        .supported_keys()
+        .is_some_and(|keys| keys.iter().any(|key| BTN_JOYSTICK.contains(&key.code())));
+
+    has_buttons && device.supported_absolute_axes().is_some()
+}
+
+fn to_joystick(device: &Device) -> io::Result<Joystick> {
+    let mut axes = Vec::new();
+    let mut hats = Vec::new();
+    for (axis, info) in device.get_absinfo()? {
+        if ABS_HATS.contains(&axis.0) {
+            hats.push((axis, info.value()));
+            continue;
+        }
+
+        // Scale from the device's range to -1.0..=1.0
+        let range = (info.maximum() - info.minimum()).max(1) as f32;
+        let value = (info.value() - info.minimum()) as f32 / range * 2.0 - 1.0;
+        axes.push(axis_to_i8(value));
+    }
+    axes.truncate(MAX_AXES);
+
+    let keys = device.get_key_state()?;
+    let mut buttons: Vec<bool> = device
+        .supported_keys()
+        .into_iter()
+        .flat_map(|supported| supported.iter())
+        .filter(|key| key.code() >= *BTN_JOYSTICK.start() && !BTN_DPAD.contains(key))
+        .map(|key| keys.contains(key))
+        .collect();
+    buttons.truncate(MAX_BUTTONS);
+
+    let mut povs: Vec<i16> = hats
+        .chunks(2)
+        .map(|hat| {
+            let x = hat
+                .iter()
+                .find(|(axis, _)| axis.0 % 2 == 0)
+                .map_or(0, |h| h.1);
+            let y = hat
+                .iter()
+                .find(|(axis, _)| axis.0 % 2 == 1)
+                .map_or(0, |h| h.1);
+            dpad_to_pov(y < 0, x > 0, y > 0, x < 0)
+        })
+        .collect();
+    let has_dpad = device
+        .supported_keys()
+        .is_some_and(|keys| BTN_DPAD.iter().all(|key| keys.contains(*key)));
+    if has_dpad {
+        let [up, right, down, left] = BTN_DPAD.map(|key| keys.contains(key));
+        povs.push(dpad_to_pov(up, right, down, left));
+    }
+    povs.truncate(MAX_POVS);
+
+    // Can't fail, everything was truncated to the protocol limits
+    Ok(Joystick::new(&axes, &buttons, &povs).unwrap())
+}
@@ -0,0 +1,181 @@
+//! Gamepad support using [gilrs](https://docs.rs/gilrs)
+
+use ::gilrs::{
+    Axis, Button, EventType, Gamepad, GamepadId, Gilrs,
+    ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Repeat},
+};
+
+use super::{JoystickProvider, axis_to_i8, dpad_to_pov};
+use crate::{
+    joystick::{Joystick, JoystickDescriptor, MAX_JOYSTICKS},
+    proto::outgoing::tcp::{AxisKind, JoystickKind},
+};
+
+/// Buttons in the order WPILib's `XboxController` expects them
+const BUTTONS: [Button; 10] = [
+    Button::South,
+    Button::East,
+    Button::West,
+    Button::North,
+    Button::LeftTrigger,
+    Button::RightTrigger,
+    Button::Select,
+    Button::Start,
+    Button::LeftThumb,
+    Button::RightThumb,
+];
+
+/// Joystick provider backed by gilrs
+///
+/// Gamepads are assigned to the lowest free slot when they're connected, and
+/// keep that slot until they're disconnected.
+pub struct GilrsProvider {
+    gilrs: Gilrs,
+    slots: [Option<GamepadId>; MAX_JOYSTICKS],
+    /// Rumble currently playing on each slot, which stops when dropped
+    rumble: [Option<Effect>; MAX_JOYSTICKS],
+}
+impl GilrsProvider {
+    pub fn new() -> Result<Self, Box<::gilrs::Error>> {
+        Ok(Self::from_gilrs(Gilrs::new().map_err(Box::new)?))
+    }
+
+    /// Use an already configured gilrs instance
+    pub fn from_gilrs(gilrs: Gilrs) -> Self {
+        let mut provider = Self {
+            gilrs,
+            slots: [None; MAX_JOYSTICKS],
+            rumble: Default::default(),
+        };
+
+        let connected: Vec<GamepadId> = provider.gilrs.gamepads().map(|(id, _)| id).collect();
+        for id in connected {
+            provider.connect(id);
+        }
+
+        provider
+    }
+
+    /// Get the gamepad in `slot`, if there is one
+    pub fn gamepad(&self, slot: usize) -> Option<Gamepad<'_>> {
+        self.gilrs.connected_gamepad((*self.slots.get(slot)?)?)
+    }
+
+    fn connect(&mut self, id: GamepadId) {
+        if self.slots.contains(&Some(id)) {
+            return;
+        }
+        if let Some(slot) = self.slots.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(id);
+        } else {
+            warn!(?id, "No free joystick slot for gamepad");
+        }
+    }
+
+    fn disconnect(&mut self, id: GamepadId) {
+        for (slot, rumble) in self.slots.iter_mut().zip(&mut self.rumble) {
+            if *slot == Some(id) {
+                *slot = None;
+                *rumble = None;
+            }
+        }
+    }
+
+    fn rumble(
+        &mut self,
+        id: GamepadId,
+        left: u16,
+        right: u16,
+    ) -> Result<Effect, ::gilrs::ff::Error> {
+        let effect = EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Strong { magnitude: left },
+                ..Default::default()
+            })
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Weak { magnitude: right },
+                ..Default::default()
+            })
+            .gamepads(&[id])
+            .repeat(Repeat::Infinitely)
+            .finish(&mut self.gilrs)?;
+        effect.play()?;
+
+        Ok(effect)
+    }
+}
+impl JoystickProvider for GilrsProvider {
+    fn poll(&mut self) -> [Option<Joystick>; MAX_JOYSTICKS] {
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::Connected => self.connect(event.id),
+                EventType::Disconnected => self.disconnect(event.id),
+                _ => {}
+            }
+        }
+
+        core::array::from_fn(|slot| self.gamepad(slot).map(|gamepad| to_joystick(&gamepad)))
+    }
+
+    fn descriptor(&self, slot: usize) -> Option<JoystickDescriptor> {
+        let gamepad = self.gamepad(slot)?;
+
+        Some(JoystickDescriptor {
+            name: gamepad.name().to_owned(),
+            kind: JoystickKind::HIDGamepad,
+            is_xbox: false,
+            axes: vec![
+                AxisKind::X,
+                AxisKind::Y,
+                AxisKind::Z,
+                AxisKind::Z,
+                AxisKind::X,
+                AxisKind::Y,
+            ],
+            button_count: BUTTONS.len() as u8,
+            pov_count: 1,
+        })
+    }
+
+    fn set_outputs(&mut self, slot: usize, _outputs: u32, left_rumble: u16, right_rumble: u16) {
+        let Some(Some(id)) = self.slots.get(slot).copied() else {
+            return;
+        };
+
+        // Dropping the old effect stops it
+        self.rumble[slot] = None;
+        if left_rumble == 0 && right_rumble == 0 {
+            return;
+        }
+
+        match self.rumble(id, left_rumble, right_rumble) {
+            Ok(effect) => self.rumble[slot] = Some(effect),
+            Err(err) => debug!(slot, %err, "Failed to set gamepad rumble"),
+        }
+    }
+}
+
+/// Map a gamepad onto the `XboxController` layout
+fn to_joystick(gamepad: &Gamepad<'_>) -> Joystick {
+    let trigger = |button| gamepad.button_data(button).map_or(0.0, |data| data.value());
+
+    // HID axes are positive down, gilrs' are positive up
+    let axes = [
+        axis_to_i8(gamepad.value(Axis::LeftStickX)),
+        axis_to_i8(-gamepad.value(Axis::LeftStickY)),
+        axis_to_i8(trigger(Button::LeftTrigger2)),
+        axis_to_i8(trigger(Button::RightTrigger2)),
+        axis_to_i8(gamepad.value(Axis::RightStickX)),
+        axis_to_i8(-gamepad.value(Axis::RightStickY)),
+    ];
+    let buttons = BUTTONS.map(|button| gamepad.is_pressed(button));
+    let pov = dpad_to_pov(
+        gamepad.is_pressed(Button::DPadUp),
+        gamepad.is_pressed(Button::DPadRight),
+        gamepad.is_pressed(Button::DPadDown),
+        gamepad.is_pressed(Button::DPadLeft),
+    );
+
+    // Can't fail, the layout is well within the protocol limits
+    Joystick::new(&axes, &buttons, &[pov]).unwrap()
+}
@@ -0,0 +1,59 @@
+//! Joystick input backends
+//!
+//! Each backend implements [`JoystickProvider`], and is polled with
+//! [`Ds::update_joysticks`](crate::Ds::update_joysticks) from whichever
+//! thread owns the underlying devices.
+
+use crate::joystick::{Joystick, JoystickDescriptor, MAX_JOYSTICKS};
+
+#[cfg(all(feature = "evdev", target_os = "linux"))]
+pub mod evdev;
+#[cfg(feature = "gilrs")]
+pub mod gilrs;
+pub mod recording;
+#[cfg(feature = "sdl2")]
+pub mod sdl2;
+#[cfg(all(feature = "xinput", windows))]
+pub mod xinput;
+
+/// A source of joystick state, such as a HID library
+pub trait JoystickProvider {
+    /// Refresh the state of every joystick, returning it in slot order
+    ///
+    /// Slots without a device are `None`.
+    fn poll(&mut self) -> [Option<Joystick>; MAX_JOYSTICKS];
+
+    /// Describe the joystick in `slot`, if the backend knows its layout
+    fn descriptor(&self, _slot: usize) -> Option<JoystickDescriptor> {
+        None
+    }
+
+    /// Apply outputs requested by robot code to the joystick in `slot`
+    ///
+    /// `outputs` is a bitfield of HID outputs (LEDs and such), and rumble
+    /// ranges from `0` (off) to `u16::MAX` (full strength).
+    fn set_outputs(&mut self, _slot: usize, _outputs: u32, _left_rumble: u16, _right_rumble: u16) {}
+}
+
+/// Convert an axis value in `-1.0..=1.0` to the wire format
+#[inline(always)]
+pub fn axis_to_i8(value: f32) -> i8 {
+    (value.clamp(-1.0, 1.0) * 127.0) as i8
+}
+
+/// Convert a directional pad to a POV angle in degrees
+///
+/// Returns `-1` when nothing (or an impossible combination) is pressed.
+pub const fn dpad_to_pov(up: bool, right: bool, down: bool, left: bool) -> i16 {
+    match (up, right, down, left) {
+        (true, false, false, false) => 0,
+        (true, true, false, false) => 45,
+        (false, true, false, false) => 90,
+        (false, true, true, false) => 135,
+        (false, false, true, false) => 180,
+        (false, false, true, true) => 225,
+        (false, false, false, true) => 270,
+        (true, false, false, true) => 315,
+        _ => -1,
+    }
+}
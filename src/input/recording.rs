@@ -0,0 +1,347 @@
+//! Recording joystick input and playing it back, so an autonomous routine
+//! can be made by driving it
+//!
+//! Wrap a backend in an [`InputRecorder`] while driving, then later use an
+//! [`InputPlayback`] in its place, in sim or on a test robot, to drive the
+//! same way again:
+//!
+//! ```no_run
+//! # fn example(ds: &robudst::Ds, gamepads: impl robudst::input::JoystickProvider) -> std::io::Result<()> {
+//! use std::fs::File;
+//! use robudst::input::recording::{InputPlayback, InputRecorder, InputRecording};
+//!
+//! let mut recorder = InputRecorder::new(gamepads, File::create("drive.rbin")?)?;
+//! // ...poll it with ds.update_joysticks(&mut recorder) while driving
+//! recorder.flush()?;
+//!
+//! let mut playback = InputPlayback::new(InputRecording::open("drive.rbin")?);
+//! while !playback.is_finished() {
+//!     ds.update_joysticks(&mut playback);
+//!     std::thread::sleep(std::time::Duration::from_millis(20));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A recording starts with [`MAGIC`] and a version byte, followed by one
+//! frame for every poll where the input changed:
+//!
+//! | Size | Field                                               |
+//! |------|-----------------------------------------------------|
+//! | 4    | Microseconds since the previous frame (LE)          |
+//! | 1    | Bit `n` set if slot `n` has a joystick              |
+//! |      | For each joystick:                                  |
+//! | 1    | Axis count, then one byte per axis                  |
+//! | 1    | Button count, then buttons as a bitfield (4, LE)    |
+//! | 1    | POV count, then two bytes per POV (LE)              |
+
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use super::JoystickProvider;
+use crate::{
+    joystick::{Joystick, JoystickDescriptor, MAX_BUTTONS, MAX_JOYSTICKS},
+    trace::Level,
+};
+
+/// Every input recording starts with this
+pub const MAGIC: &[u8; 8] = b"RBDSINP\0";
+const VERSION: u8 = 1;
+
+/// What every joystick was doing at one point in a recording
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputFrame {
+    /// Time since the recording started
+    pub at: Duration,
+    pub joysticks: [Option<Joystick>; MAX_JOYSTICKS],
+}
+
+/// Wraps a [`JoystickProvider`], writing everything polled from it to `W`
+///
+/// Recording starts with the first poll. Descriptors and outputs pass
+/// straight through to the wrapped backend.
+pub struct InputRecorder<P, W: Write> {
+    inner: P,
+    out: BufWriter<W>,
+    /// When the previous frame was written, and what it had
+    last: Option<(Instant, [Option<Joystick>; MAX_JOYSTICKS])>,
+}
+impl<P: JoystickProvider, W: Write> InputRecorder<P, W> {
+    pub fn new(inner: P, out: W) -> io::Result<Self> {
+        let mut out = BufWriter::new(out);
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+
+        Ok(Self {
+            inner,
+            out,
+            last: None,
+        })
+    }
+
+    /// Write out anything still buffered
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Stop recording and get the wrapped backend back
+    pub fn into_inner(mut self) -> io::Result<P> {
+        self.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write_frame(&mut self, delta: Duration, joysticks: &[Option<Joystick>]) -> io::Result<()> {
+        let delta = delta.as_micros().min(u32::MAX as u128) as u32;
+        let present = joysticks
+            .iter()
+            .enumerate()
+            .filter(|(_, joystick)| joystick.is_some())
+            .fold(0u8, |mask, (slot, _)| mask | 1 << slot);
+
+        let mut frame = Vec::with_capacity(64);
+        frame.extend_from_slice(&delta.to_le_bytes());
+        frame.push(present);
+        for joystick in joysticks.iter().flatten() {
+            frame.push(joystick.axes().len() as u8);
+            frame.extend(joystick.axes().iter().map(|&axis| axis as u8));
+
+            let buttons = joystick
+                .buttons()
+                .iter()
+                .enumerate()
+                .fold(0u32, |bits, (i, &pressed)| bits | (pressed as u32) << i);
+            frame.push(joystick.buttons().len() as u8);
+            frame.extend_from_slice(&buttons.to_le_bytes());
+
+            frame.push(joystick.povs().len() as u8);
+            for pov in joystick.povs() {
+                frame.extend_from_slice(&pov.to_le_bytes());
+            }
+        }
+        self.out.write_all(&frame)
+    }
+}
+impl<P: JoystickProvider, W: Write> JoystickProvider for InputRecorder<P, W> {
+    fn poll(&mut self) -> [Option<Joystick>; MAX_JOYSTICKS] {
+        let joysticks = self.inner.poll();
+        let now = Instant::now();
+
+        let delta = match self.last {
+            Some((_, last)) if last == joysticks => return joysticks,
+            Some((at, _)) => now.duration_since(at),
+            None => Duration::ZERO,
+        };
+        self.last = Some((now, joysticks));
+
+        // A broken recording shouldn't stop the robot from being driven
+        if let Err(err) = self.write_frame(delta, &joysticks) {
+            event!(Level::WARN, %err, "Failed to write input recording");
+        }
+        joysticks
+    }
+
+    fn descriptor(&self, slot: usize) -> Option<JoystickDescriptor> {
+        self.inner.descriptor(slot)
+    }
+
+    fn set_outputs(&mut self, slot: usize, outputs: u32, left_rumble: u16, right_rumble: u16) {
+        self.inner
+            .set_outputs(slot, outputs, left_rumble, right_rumble);
+    }
+}
+
+/// A recording made by an [`InputRecorder`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputRecording {
+    frames: Vec<InputFrame>,
+}
+impl InputRecording {
+    /// Read the recording at `path`
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Parse a recording
+    pub fn from_bytes(buf: &[u8]) -> io::Result<Self> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        if buf.get(..MAGIC.len()) != Some(MAGIC) {
+            return Err(invalid("not an input recording"));
+        }
+        if buf.get(MAGIC.len()) != Some(&VERSION) {
+            return Err(invalid("unsupported input recording version"));
+        }
+
+        let mut rest = &buf[MAGIC.len() + 1..];
+        let mut frames = Vec::new();
+        let mut at = Duration::ZERO;
+        while !rest.is_empty() {
+            let header = take(&mut rest, 5)?;
+            let delta = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+            let present = header[4];
+            at += Duration::from_micros(delta.into());
+
+            let mut joysticks = [None; MAX_JOYSTICKS];
+            for (slot, joystick) in joysticks.iter_mut().enumerate() {
+                if present & 1 << slot == 0 {
+                    continue;
+                }
+
+                let axis_count = take(&mut rest, 1)?[0] as usize;
+                let axes = take(&mut rest, axis_count)?
+                    .iter()
+                    .map(|&axis| axis as i8)
+                    .collect::<Vec<_>>();
+
+                let button_count = (take(&mut rest, 1)?[0] as usize).min(MAX_BUTTONS);
+                let bits = take(&mut rest, 4)?;
+                let bits = u32::from_le_bytes([bits[0], bits[1], bits[2], bits[3]]);
+                let buttons = (0..button_count)
+                    .map(|i| bits & 1 << i != 0)
+                    .collect::<Vec<_>>();
+
+                let pov_count = take(&mut rest, 1)?[0] as usize;
+                let povs = take(&mut rest, pov_count * 2)?
+                    .chunks_exact(2)
+                    .map(|pov| i16::from_le_bytes([pov[0], pov[1]]))
+                    .collect::<Vec<_>>();
+
+                *joystick = Some(
+                    Joystick::new(&axes, &buttons, &povs)
+                        .map_err(|_| invalid("too many joystick inputs"))?,
+                );
+            }
+
+            frames.push(InputFrame { at, joysticks });
+        }
+
+        Ok(Self { frames })
+    }
+
+    pub fn frames(&self) -> &[InputFrame] {
+        &self.frames
+    }
+
+    /// How long the recording runs for
+    pub fn duration(&self) -> Duration {
+        self.frames.last().map_or(Duration::ZERO, |frame| frame.at)
+    }
+}
+
+/// Plays an [`InputRecording`] back as a [`JoystickProvider`]
+///
+/// Playback starts with the first poll, and follows the recording's timing
+/// from there. Once it's over, every joystick is left centered with nothing
+/// pressed, so the robot doesn't keep doing whatever it was last told.
+pub struct InputPlayback {
+    recording: InputRecording,
+    started: Option<Instant>,
+    /// The frame being played
+    pos: usize,
+}
+impl InputPlayback {
+    pub fn new(recording: InputRecording) -> Self {
+        Self {
+            recording,
+            started: None,
+            pos: 0,
+        }
+    }
+
+    /// Start again from the beginning on the next poll
+    pub fn restart(&mut self) {
+        self.started = None;
+        self.pos = 0;
+    }
+
+    /// Whether the whole recording has been played
+    pub fn is_finished(&self) -> bool {
+        self.started
+            .is_some_and(|started| started.elapsed() > self.recording.duration())
+    }
+}
+impl JoystickProvider for InputPlayback {
+    fn poll(&mut self) -> [Option<Joystick>; MAX_JOYSTICKS] {
+        let frames = self.recording.frames();
+        let Some(last) = frames.last() else {
+            return [None; MAX_JOYSTICKS];
+        };
+
+        if self.is_finished() {
+            return last.joysticks.map(|joystick| joystick.map(neutral));
+        }
+
+        let elapsed = self.started.get_or_insert_with(Instant::now).elapsed();
+        while frames
+            .get(self.pos + 1)
+            .is_some_and(|next| next.at <= elapsed)
+        {
+            self.pos += 1;
+        }
+        frames[self.pos].joysticks
+    }
+}
+
+/// Split `len` bytes off the front of `rest`
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    let (taken, remaining) = rest
+        .split_at_checked(len)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "truncated input recording"))?;
+    *rest = remaining;
+    Ok(taken)
+}
+
+/// `joystick` with its sticks centered and nothing pressed
+fn neutral(joystick: Joystick) -> Joystick {
+    let axes = vec![0; joystick.axes().len()];
+    let buttons = vec![false; joystick.buttons().len()];
+    let povs = vec![-1; joystick.povs().len()];
+    // Same counts as an existing joystick, so they're in range
+    Joystick::new(&axes, &buttons, &povs).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays back a fixed sequence of states, one per poll
+    struct Scripted(Vec<[Option<Joystick>; MAX_JOYSTICKS]>);
+    impl JoystickProvider for Scripted {
+        fn poll(&mut self) -> [Option<Joystick>; MAX_JOYSTICKS] {
+            self.0.remove(0)
+        }
+    }
+
+    #[test]
+    fn recording_round_trips_changed_frames() {
+        let stick = Joystick::new(&[-128, 0, 127], &[true, false, true], &[90]).unwrap();
+        let moved = Joystick::new(&[5, 0, 127], &[false; 3], &[-1]).unwrap();
+        let mut states = [None; MAX_JOYSTICKS];
+        states[0] = Some(stick);
+        states[3] = Some(stick);
+        let mut changed = states;
+        changed[0] = Some(moved);
+
+        let mut out = Vec::new();
+        let mut recorder =
+            InputRecorder::new(Scripted(vec![states, states, changed]), &mut out).unwrap();
+        for _ in 0..3 {
+            recorder.poll();
+        }
+        recorder.into_inner().unwrap();
+
+        let recording = InputRecording::from_bytes(&out).unwrap();
+        let frames = recording
+            .frames()
+            .iter()
+            .map(|frame| frame.joysticks)
+            .collect::<Vec<_>>();
+        assert_eq!(frames, [states, changed]);
+
+        assert!(InputRecording::from_bytes(&out[..out.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Joystick support using [SDL2](https://docs.rs/sdl2)
+//!
+//! Unlike the gilrs backend, this uses SDL's raw joystick API rather than its
+//! game controller mappings, so flight sticks and button boards report every
+//! axis and button they have.
+
+use ::sdl2::{
+    JoystickSubsystem,
+    event::Event,
+    joystick::{HatState, Joystick as SdlJoystick},
+};
+
+use super::{JoystickProvider, dpad_to_pov};
+
+/// How long rumble lasts, since outputs are only passed along when they change
+///
+/// SDL overflows on `u32::MAX`, so this is just "long enough".
+const RUMBLE_DURATION_MS: u32 = 60 * 60 * 1000;
+use crate::{
+    joystick::{Joystick, JoystickDescriptor, MAX_AXES, MAX_BUTTONS, MAX_JOYSTICKS, MAX_POVS},
+    proto::outgoing::tcp::JoystickKind,
+};
+
+/// Joystick provider backed by SDL2
+///
+/// SDL only allows a single event pump, so hot-plugging relies on the
+/// application forwarding its events to [`Sdl2Provider::handle_event`].
+/// Joysticks that get unplugged are always dropped on the next poll.
+pub struct Sdl2Provider {
+    subsystem: JoystickSubsystem,
+    slots: [Option<SdlJoystick>; MAX_JOYSTICKS],
+}
+impl Sdl2Provider {
+    /// Open every joystick that's currently connected
+    pub fn new(subsystem: JoystickSubsystem) -> Result<Self, String> {
+        let mut provider = Self {
+            subsystem,
+            slots: Default::default(),
+        };
+
+        for index in 0..provider.subsystem.num_joysticks()? {
+            provider.open(index);
+        }
+
+        Ok(provider)
+    }
+
+    /// Get the joystick in `slot`, if there is one
+    pub fn joystick(&self, slot: usize) -> Option<&SdlJoystick> {
+        self.slots.get(slot)?.as_ref()
+    }
+
+    /// Handle joystick hot-plug events from the application's event pump
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::JoyDeviceAdded { which, .. } => self.open(which),
+            Event::JoyDeviceRemoved { which, .. } => {
+                for slot in &mut self.slots {
+                    if slot.as_ref().is_some_and(|js| js.instance_id() == which) {
+                        *slot = None;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn open(&mut self, index: u32) {
+        let joystick = match self.subsystem.open(index) {
+            Ok(joystick) => joystick,
+            Err(err) => {
+                warn!(index, %err, "Failed to open joystick");
+                return;
+            }
+        };
+
+        let id = joystick.instance_id();
+        if self.slots.iter().flatten().any(|js| js.instance_id() == id) {
+            return;
+        }
+
+        if let Some(slot) = self.slots.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(joystick);
+        } else {
+            warn!(name = joystick.name(), "No free joystick slot for joystick");
+        }
+    }
+}
+impl JoystickProvider for Sdl2Provider {
+    fn poll(&mut self) -> [Option<Joystick>; MAX_JOYSTICKS] {
+        self.subsystem.update();
+
+        for slot in &mut self.slots {
+            if slot.as_ref().is_some_and(|js| !js.attached()) {
+                *slot = None;
+            }
+        }
+
+        core::array::from_fn(|slot| self.slots[slot].as_ref().map(to_joystick))
+    }
+
+    fn descriptor(&self, slot: usize) -> Option<JoystickDescriptor> {
+        let joystick = self.joystick(slot)?;
+
+        Some(JoystickDescriptor {
+            name: joystick.name(),
+            kind: JoystickKind::HIDJoystick,
+            ..JoystickDescriptor::generic(&to_joystick(joystick))
+        })
+    }
+
+    fn set_outputs(&mut self, slot: usize, _outputs: u32, left_rumble: u16, right_rumble: u16) {
+        let Some(Some(joystick)) = self.slots.get_mut(slot) else {
+            return;
+        };
+
+        if let Err(err) = joystick.set_rumble(left_rumble, right_rumble, RUMBLE_DURATION_MS) {
+            debug!(slot, %err, "Failed to set joystick rumble");
+        }
+    }
+}
+
+fn to_joystick(joystick: &SdlJoystick) -> Joystick {
+    let axes: Vec<i8> = (0..joystick.num_axes().min(MAX_AXES as u32))
+        .map(|axis| (joystick.axis(axis).unwrap_or(0) >> 8) as i8)
+        .collect();
+    let buttons: Vec<bool> = (0..joystick.num_buttons().min(MAX_BUTTONS as u32))
+        .map(|button| joystick.button(button).unwrap_or(false))
+        .collect();
+    let povs: Vec<i16> = (0..joystick.num_hats().min(MAX_POVS as u32))
+        .map(|hat| {
+            let hat = joystick.hat(hat).unwrap_or(HatState::Centered) as u8;
+            dpad_to_pov(
+                hat & 0x01 != 0,
+                hat & 0x02 != 0,
+                hat & 0x04 != 0,
+                hat & 0x08 != 0,
+            )
+        })
+        .collect();
+
+    // Can't fail, everything was truncated to the protocol limits
+    Joystick::new(&axes, &buttons, &povs).unwrap()
+}
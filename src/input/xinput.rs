@@ -0,0 +1,158 @@
+//! Xbox controller support using XInput on Windows
+//!
+//! XInput only supports four controllers, which always map to the first four
+//! joystick slots by their XInput user index.
+
+use windows_sys::Win32::{
+    Foundation::ERROR_SUCCESS,
+    UI::Input::XboxController::{
+        XINPUT_CAPABILITIES, XINPUT_FLAG_GAMEPAD, XINPUT_GAMEPAD, XINPUT_GAMEPAD_A,
+        XINPUT_GAMEPAD_B, XINPUT_GAMEPAD_BACK, XINPUT_GAMEPAD_DPAD_DOWN, XINPUT_GAMEPAD_DPAD_LEFT,
+        XINPUT_GAMEPAD_DPAD_RIGHT, XINPUT_GAMEPAD_DPAD_UP, XINPUT_GAMEPAD_LEFT_SHOULDER,
+        XINPUT_GAMEPAD_LEFT_THUMB, XINPUT_GAMEPAD_RIGHT_SHOULDER, XINPUT_GAMEPAD_RIGHT_THUMB,
+        XINPUT_GAMEPAD_START, XINPUT_GAMEPAD_X, XINPUT_GAMEPAD_Y, XINPUT_STATE, XINPUT_VIBRATION,
+        XInputGetCapabilities, XInputGetState, XInputSetState, XUSER_MAX_COUNT,
+    },
+};
+
+use super::{JoystickProvider, dpad_to_pov};
+use crate::{
+    joystick::{Joystick, JoystickDescriptor, MAX_JOYSTICKS},
+    proto::outgoing::tcp::{AxisKind, JoystickKind},
+};
+
+const USERS: usize = XUSER_MAX_COUNT as usize;
+
+/// Buttons in the order WPILib's `XboxController` expects them
+const BUTTONS: [u16; 10] = [
+    XINPUT_GAMEPAD_A,
+    XINPUT_GAMEPAD_B,
+    XINPUT_GAMEPAD_X,
+    XINPUT_GAMEPAD_Y,
+    XINPUT_GAMEPAD_LEFT_SHOULDER,
+    XINPUT_GAMEPAD_RIGHT_SHOULDER,
+    XINPUT_GAMEPAD_BACK,
+    XINPUT_GAMEPAD_START,
+    XINPUT_GAMEPAD_LEFT_THUMB,
+    XINPUT_GAMEPAD_RIGHT_THUMB,
+];
+const AXES: [AxisKind; 6] = [
+    AxisKind::X,
+    AxisKind::Y,
+    AxisKind::Z,
+    AxisKind::Z,
+    AxisKind::X,
+    AxisKind::Y,
+];
+
+/// Joystick provider backed by XInput
+#[derive(Default)]
+pub struct XInputProvider {
+    capabilities: [Option<XINPUT_CAPABILITIES>; USERS],
+}
+impl XInputProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+impl JoystickProvider for XInputProvider {
+    fn poll(&mut self) -> [Option<Joystick>; MAX_JOYSTICKS] {
+        let mut joysticks = [None; MAX_JOYSTICKS];
+
+        for (user, capabilities) in self.capabilities.iter_mut().enumerate() {
+            // SAFETY: both structs are plain data, and only read if XInput
+            // reports success
+            let mut state: XINPUT_STATE = unsafe { core::mem::zeroed() };
+            if unsafe { XInputGetState(user as u32, &mut state) } != ERROR_SUCCESS {
+                *capabilities = None;
+                continue;
+            }
+
+            if capabilities.is_none() {
+                let mut caps: XINPUT_CAPABILITIES = unsafe { core::mem::zeroed() };
+                if unsafe { XInputGetCapabilities(user as u32, XINPUT_FLAG_GAMEPAD, &mut caps) }
+                    == ERROR_SUCCESS
+                {
+                    *capabilities = Some(caps);
+                }
+            }
+
+            joysticks[user] = Some(to_joystick(&state.Gamepad));
+        }
+
+        joysticks
+    }
+
+    fn descriptor(&self, slot: usize) -> Option<JoystickDescriptor> {
+        let capabilities = self.capabilities.get(slot)?.as_ref()?;
+
+        Some(JoystickDescriptor {
+            name: "Controller (Xbox One For Windows)".to_owned(),
+            kind: joystick_kind(capabilities.SubType),
+            is_xbox: true,
+            axes: AXES.to_vec(),
+            button_count: BUTTONS.len() as u8,
+            pov_count: 1,
+        })
+    }
+
+    fn set_outputs(&mut self, slot: usize, _outputs: u32, left_rumble: u16, right_rumble: u16) {
+        if self
+            .capabilities
+            .get(slot)
+            .is_none_or(|caps| caps.is_none())
+        {
+            return;
+        }
+
+        let vibration = XINPUT_VIBRATION {
+            wLeftMotorSpeed: left_rumble,
+            wRightMotorSpeed: right_rumble,
+        };
+        // SAFETY: vibration is a valid XINPUT_VIBRATION that outlives the call
+        unsafe { XInputSetState(slot as u32, &vibration) };
+    }
+}
+
+fn to_joystick(gamepad: &XINPUT_GAMEPAD) -> Joystick {
+    let stick = |value: i16| (value >> 8) as i8;
+    let trigger = |value: u8| (value >> 1) as i8;
+
+    // HID axes are positive down, XInput's are positive up
+    let axes = [
+        stick(gamepad.sThumbLX),
+        stick(gamepad.sThumbLY.saturating_neg()),
+        trigger(gamepad.bLeftTrigger),
+        trigger(gamepad.bRightTrigger),
+        stick(gamepad.sThumbRX),
+        stick(gamepad.sThumbRY.saturating_neg()),
+    ];
+    let pressed = |button: u16| gamepad.wButtons & button != 0;
+    let buttons = BUTTONS.map(pressed);
+    let pov = dpad_to_pov(
+        pressed(XINPUT_GAMEPAD_DPAD_UP),
+        pressed(XINPUT_GAMEPAD_DPAD_RIGHT),
+        pressed(XINPUT_GAMEPAD_DPAD_DOWN),
+        pressed(XINPUT_GAMEPAD_DPAD_LEFT),
+    );
+
+    // Can't fail, the layout is well within the protocol limits
+    Joystick::new(&axes, &buttons, &[pov]).unwrap()
+}
+
+/// XInput device subtypes share their values with [`JoystickKind`]
+const fn joystick_kind(subtype: u8) -> JoystickKind {
+    match subtype {
+        1 => JoystickKind::XInputGamepad,
+        2 => JoystickKind::XInputWheel,
+        3 => JoystickKind::XInputArcade,
+        4 => JoystickKind::XInputFlightStick,
+        5 => JoystickKind::XInputDancePad,
+        6 => JoystickKind::XInputGuitar,
+        7 => JoystickKind::XInputGuitar2,
+        8 => JoystickKind::XInputDrumKit,
+        11 => JoystickKind::XInputGuitar3,
+        19 => JoystickKind::XInputArcadePad,
+        _ => JoystickKind::XInputUnknown,
+    }
+}
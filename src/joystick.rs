@@ -0,0 +1,186 @@
+use crate::{
+    Error,
+    input::axis_to_i8,
+    proto::outgoing::{
+        tcp::TcpOutgoingTag,
+        tcp::{AxisKind, JoystickKind},
+        udp::UdpOutgoingTag,
+    },
+};
+
+pub use crate::proto::incoming::udp::JoystickOutput;
+
+pub use robudst_proto::joystick::{MAX_AXES, MAX_BUTTONS, MAX_JOYSTICKS, MAX_POVS};
+
+/// The state of a single joystick slot, as sent to the roboRIO
+///
+/// Axes are scaled to `-128..=127`, and POVs are in degrees (or `-1` when
+/// not pressed).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Joystick {
+    axes: [i8; MAX_AXES],
+    axis_count: u8,
+    buttons: [bool; MAX_BUTTONS],
+    button_count: u8,
+    povs: [i16; MAX_POVS],
+    pov_count: u8,
+}
+impl Joystick {
+    /// A joystick with no axes, buttons, or POVs
+    ///
+    /// This is what gets sent for empty slots below the highest used one.
+    pub const EMPTY: Self = Self {
+        axes: [0; MAX_AXES],
+        axis_count: 0,
+        buttons: [false; MAX_BUTTONS],
+        button_count: 0,
+        povs: [-1; MAX_POVS],
+        pov_count: 0,
+    };
+
+    pub fn new(axes: &[i8], buttons: &[bool], povs: &[i16]) -> Result<Self, Error> {
+        if axes.len() > MAX_AXES || buttons.len() > MAX_BUTTONS || povs.len() > MAX_POVS {
+            return Err(Error::TooManyJoystickInputs);
+        }
+
+        let mut joystick = Self::EMPTY;
+        joystick.axes[..axes.len()].copy_from_slice(axes);
+        joystick.axis_count = axes.len() as u8;
+        joystick.buttons[..buttons.len()].copy_from_slice(buttons);
+        joystick.button_count = buttons.len() as u8;
+        joystick.povs[..povs.len()].copy_from_slice(povs);
+        joystick.pov_count = povs.len() as u8;
+
+        Ok(joystick)
+    }
+
+    #[inline(always)]
+    pub fn axes(&self) -> &[i8] {
+        &self.axes[..self.axis_count as usize]
+    }
+
+    #[inline(always)]
+    pub fn buttons(&self) -> &[bool] {
+        &self.buttons[..self.button_count as usize]
+    }
+
+    #[inline(always)]
+    pub fn povs(&self) -> &[i16] {
+        &self.povs[..self.pov_count as usize]
+    }
+
+    #[inline(always)]
+    pub(crate) fn as_tag(&self) -> UdpOutgoingTag<'_> {
+        UdpOutgoingTag::Joystick {
+            axes: self.axes(),
+            buttons: self.buttons(),
+            povs: self.povs(),
+        }
+    }
+}
+
+/// Adjustments made to a joystick before it's sent to the roboRIO
+///
+/// Axes and buttons are reordered first, then inverted and deadbanded by
+/// their new index.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct JoystickMapping {
+    /// Axis values closer to zero than this (out of `1.0`) are sent as zero,
+    /// and the rest are rescaled to still cover the full range
+    pub deadband: f32,
+    pub inverted_axes: Vec<usize>,
+    /// Which device axis each sent axis comes from, or empty to keep them
+    /// all in order
+    pub axis_order: Vec<usize>,
+    /// Which device button each sent button comes from, or empty to keep
+    /// them all in order
+    pub button_order: Vec<usize>,
+}
+impl JoystickMapping {
+    pub fn apply(&self, joystick: &Joystick) -> Joystick {
+        let mut axes: Vec<i8> = reorder(joystick.axes(), &self.axis_order);
+        let buttons: Vec<bool> = reorder(joystick.buttons(), &self.button_order);
+
+        for (i, axis) in axes.iter_mut().enumerate() {
+            if self.inverted_axes.contains(&i) {
+                *axis = axis.saturating_neg();
+            }
+            *axis = self.apply_deadband(*axis);
+        }
+
+        // Reordering can only drop inputs, so this can't be too big
+        Joystick::new(&axes, &buttons, joystick.povs()).unwrap_or(*joystick)
+    }
+
+    fn apply_deadband(&self, axis: i8) -> i8 {
+        let deadband = self.deadband.clamp(0.0, 0.99);
+        let value = axis as f32 / 127.0;
+
+        if value.abs() <= deadband {
+            0
+        } else {
+            axis_to_i8(value.signum() * (value.abs() - deadband) / (1.0 - deadband))
+        }
+    }
+}
+
+/// Pick out `order` from `inputs`, skipping indices that don't exist
+fn reorder<T: Copy>(inputs: &[T], order: &[usize]) -> Vec<T> {
+    if order.is_empty() {
+        inputs.to_vec()
+    } else {
+        order
+            .iter()
+            .filter_map(|&i| inputs.get(i).copied())
+            .collect()
+    }
+}
+
+/// Describes the layout of a joystick to the roboRIO
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JoystickDescriptor {
+    pub name: String,
+    pub kind: JoystickKind,
+    pub is_xbox: bool,
+    pub axes: Vec<AxisKind>,
+    pub button_count: u8,
+    pub pov_count: u8,
+}
+impl JoystickDescriptor {
+    /// Descriptor sent for empty slots
+    pub const EMPTY: Self = Self {
+        name: String::new(),
+        kind: JoystickKind::Unknown,
+        is_xbox: false,
+        axes: Vec::new(),
+        button_count: 0,
+        pov_count: 0,
+    };
+
+    /// Describe a joystick with nothing but its input counts
+    ///
+    /// Used for joysticks whose backend doesn't know anything else about them.
+    pub fn generic(joystick: &Joystick) -> Self {
+        Self {
+            name: String::new(),
+            kind: JoystickKind::Unknown,
+            is_xbox: false,
+            axes: vec![AxisKind::X; joystick.axes().len()],
+            button_count: joystick.buttons().len() as u8,
+            pov_count: joystick.povs().len() as u8,
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn as_tag(&self, index: u8) -> TcpOutgoingTag<'_> {
+        TcpOutgoingTag::JoystickDescriptor {
+            index,
+            is_xbox: self.is_xbox,
+            kind: self.kind,
+            name: &self.name,
+            axes: &self.axes,
+            button_count: self.button_count,
+            pov_count: self.pov_count,
+        }
+    }
+}
@@ -1,27 +1,36 @@
-#![feature(array_chunks)]
-
-use std::{net::Ipv4Addr, sync::Arc};
+use std::{
+    collections::HashMap,
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
+use bytes::{Bytes, BytesMut};
+use connection::ConnectionState;
 use crossbeam_utils::atomic::AtomicCell;
-use futures_lite::{Stream, StreamExt};
+use input::JoystickProvider;
+use joystick::{Joystick, JoystickDescriptor, JoystickMapping, JoystickOutput, MAX_JOYSTICKS};
 use proto::{
-    incoming::{
-        IncomingTagHandler,
-        tcp::{TcpIncomingTag, TcpTagStream},
-        udp::{Status, UdpIncomingPacket, UdpIncomingStream},
-    },
-    outgoing::{tcp::TcpOutgoingTag, udp::UdpOutgoingPacket},
+    incoming::udp::{Status, Trace},
+    outgoing::{tcp::TcpOutgoingTag, udp::UdpOutgoingTag},
+    version::{Frc2015, ProtocolVersion},
 };
-use tokio::{
-    net::{
-        TcpStream, UdpSocket,
-        tcp::{OwnedReadHalf, OwnedWriteHalf},
-        unix::SocketAddr,
-    },
-    sync::Mutex,
-};
-use utils::{find_status, gen_team_ip};
+use timer::timeout;
+use trace::Level;
+use transport::{Connect, Transport};
+
+pub use builder::DsBuilder;
+#[cfg(feature = "runtime-tokio")]
+pub use handle::DsHandle;
+/// Re-exported for [`Ds::run_until_cancelled`]
+pub use tokio_util::sync::CancellationToken;
+#[cfg(feature = "timezone")]
+pub use utils::system_timezone;
 
+#[cfg(feature = "tracing")]
 #[macro_use]
 extern crate tracing;
 #[macro_use]
@@ -30,12 +39,110 @@ extern crate crossbeam_utils;
 extern crate futures_lite;
 extern crate tokio;
 
+// Must come first so the fallback macros are visible everywhere
+#[macro_use]
+mod trace;
+
+pub mod battery;
+mod builder;
+#[cfg(feature = "runtime-tokio")]
+pub mod chaos;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod connection;
+pub mod console;
+#[cfg(feature = "daemon")]
+pub mod daemon;
+pub mod dashboard;
+pub mod diagnostics;
+pub mod discovery;
+pub mod estop;
+pub mod event;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fms;
+#[cfg(fuzzing)]
+#[doc(hidden)]
+pub mod fuzz;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "halsim")]
+pub mod halsim;
+#[cfg(feature = "runtime-tokio")]
+mod handle;
+#[cfg(feature = "hotkeys")]
+pub mod hotkeys;
+pub mod input;
+pub mod joystick;
+mod lifecycle;
+pub mod log;
+mod match_timer;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "nt4")]
+pub mod nt4;
+#[cfg(feature = "runtime-tokio")]
+pub mod observer;
+#[cfg(feature = "runtime-tokio")]
+pub mod pool;
+pub mod practice;
 pub mod proto;
+#[cfg(feature = "radio")]
+pub mod radio;
+pub mod replay;
+#[cfg(feature = "scripting")]
+pub mod script;
+mod tasks;
+#[cfg(feature = "tba")]
+pub mod tba;
+pub mod telemetry;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+mod timer;
+pub mod transport;
 mod utils;
 
-pub enum Error {}
+/// How often control packets are sent to the roboRIO
+const SEND_INTERVAL: Duration = Duration::from_millis(20);
+/// The newest FRC season this crate knows the protocol for
+const PROTOCOL_YEAR: u16 = 2025;
+/// How long the roboRIO can go without sending status before it's
+/// considered gone
+const DEFAULT_COMM_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum Error {
+    /// Joystick slot is outside of `0..MAX_JOYSTICKS`
+    InvalidJoystickSlot,
+    /// Joystick has more axes, buttons, or POVs than the protocol allows
+    TooManyJoystickInputs,
+    /// Team number has no team IP (it's above `25599`)
+    InvalidTeamNumber,
+    /// Alliance station position isn't `1`, `2`, or `3`
+    InvalidAlliancePos,
+    /// Control packets would be sent never, or too rarely to keep the robot
+    /// enabled
+    InvalidSendInterval,
+    /// Protocol year isn't one the chosen protocol version speaks
+    UnsupportedProtocolYear,
+    /// Robot can't be enabled until the emergency stop is cleared
+    EStopped,
+    /// Robot can't be enabled while the emergency stop button being watched
+    /// is disconnected
+    EStopButtonLost,
+    /// Robot can't be enabled in teleop without recent joystick input, see
+    /// [`Ds::set_joystick_interlock`]
+    NoJoystickInput,
+    /// A packet from the robot was too short or otherwise didn't make sense
+    MalformedPacket,
+    /// Autonomous option isn't one the robot offers
+    UnknownAutoOption,
+    /// Robot is being controlled by an FMS, which decides when it's enabled
+    FmsControlled,
+}
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RobotStatus {
     NoCommunication,
     NoRobotCode,
@@ -45,7 +152,8 @@ pub enum RobotStatus {
     Enabled,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RobotCodeMode {
     Autonomous,
     Teleop,
@@ -55,193 +163,1004 @@ pub enum RobotCodeMode {
 /// The position and alliance of the driver station
 ///
 /// Position can be `1`, `2`, or `3`
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AlliancePos {
     Red(u8),
     Blue(u8),
 }
 impl AlliancePos {
-    const fn to_pos(self) -> u8 {
+    /// Check the position is `1`, `2`, or `3`
+    pub const fn validate(self) -> Result<Self, Error> {
         match self {
-            Self::Red(pos) => {
-                assert!(pos != 0 && pos <= 3);
-                pos.saturating_sub(1)
-            }
-            Self::Blue(pos) => {
-                assert!(pos != 0 && pos <= 3);
-                pos.saturating_sub(4)
-            }
+            Self::Red(1..=3) | Self::Blue(1..=3) => Ok(self),
+            _ => Err(Error::InvalidAlliancePos),
+        }
+    }
+
+    /// Get the station index used on the wire (`0..=2` for red, `3..=5` for
+    /// blue)
+    ///
+    /// Only meaningful for a [valid](Self::validate) position.
+    pub(crate) const fn to_pos(self) -> u8 {
+        match self {
+            Self::Red(pos) => pos.wrapping_sub(1),
+            Self::Blue(pos) => pos.wrapping_add(2),
+        }
+    }
+
+    /// Parse a station index from the wire
+    pub(crate) const fn from_station(station: u8) -> Option<Self> {
+        match station {
+            0..=2 => Some(Self::Red(station + 1)),
+            3..=5 => Some(Self::Blue(station - 2)),
+            _ => None,
+        }
+    }
+}
+
+/// The kind of match being played
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MatchType {
+    #[default]
+    None = 0,
+    Practice = 1,
+    Qualification = 2,
+    Elimination = 3,
+}
+impl MatchType {
+    pub(crate) const fn from_u8(match_type: u8) -> Self {
+        match match_type {
+            1 => Self::Practice,
+            2 => Self::Qualification,
+            3 => Self::Elimination,
+            _ => Self::None,
         }
     }
 }
 
+/// Match details sent to robot code
+///
+/// This is what robot code sees through `DriverStation.getEventName()`,
+/// `getMatchType()`, `getMatchNumber()`, and `getReplayNumber()`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MatchInfo {
+    /// Event name, like `CAMP`
+    pub competition: String,
+    pub match_type: MatchType,
+    pub match_number: u16,
+    pub replay_number: u8,
+}
+impl MatchInfo {
+    #[inline(always)]
+    pub(crate) fn as_tag(&self) -> TcpOutgoingTag<'_> {
+        TcpOutgoingTag::MatchInfo {
+            competition: &self.competition,
+            match_type: self.match_type,
+            match_number: self.match_number,
+            replay_number: self.replay_number,
+        }
+    }
+}
+
+/// Called with the joystick slot and its new outputs whenever robot code
+/// changes them
+pub type JoystickOutputCallback = Box<dyn Fn(usize, JoystickOutput) + Send + Sync>;
+
 /// A driver station instance
 pub struct Ds {
-    status: AtomicCell<RobotStatus>,
-    mode: AtomicCell<RobotCodeMode>,
-    can_bus_util: AtomicCell<f32>,
+    team_number: AtomicCell<u16>,
+    status: utils::Watched<RobotStatus>,
+    mode: utils::Watched<RobotCodeMode>,
+    /// Raw flags from the last status packet, for what [`RobotStatus`]
+    /// doesn't capture
+    status_flags: AtomicCell<Status>,
+    trace: AtomicCell<Trace>,
+    can_metrics: AtomicCell<Option<diagnostics::CanMetrics>>,
+    power_stats: AtomicCell<Option<diagnostics::PowerStats>>,
+    disable_faults: AtomicCell<Option<diagnostics::DisableFaults>>,
+    /// Odd while a status packet is being stored, see [`Ds::telemetry`]
+    status_seq: AtomicU64,
+    packets_received: AtomicU64,
+    packets_lost: AtomicU64,
+    last_seqnum: AtomicCell<Option<u16>>,
+    /// Sequence number for the next control packet, which the roboRIO
+    /// echoes back in its status packets
+    control_seqnum: AtomicU16,
+    /// When recent control packets were sent, indexed by sequence number
+    control_sent: [AtomicCell<Option<(u16, Instant)>>; connection::SENT_HISTORY],
+    trip_time: AtomicCell<Option<Duration>>,
+    rail_faults: AtomicCell<Option<diagnostics::RailFaults>>,
+    cpu_info: AtomicCell<Option<diagnostics::CpuInfo>>,
+    ram_info: AtomicCell<Option<diagnostics::RamInfo>>,
+    low_memory: std::sync::Mutex<diagnostics::LowWatermark>,
+    free_disk: AtomicCell<Option<u32>>,
+    low_disk: std::sync::Mutex<diagnostics::LowWatermark>,
     battery: AtomicCell<f32>,
+    battery_history: std::sync::Mutex<battery::BatteryHistory>,
+    voltage_alerts: std::sync::Mutex<Vec<battery::AlertState>>,
+    brownouts: AtomicU32,
     alliance_pos: AtomicCell<AlliancePos>,
+    joysticks: [AtomicCell<Option<Joystick>>; MAX_JOYSTICKS],
+    /// When any joystick was last given a state
+    joystick_updated: AtomicCell<Option<Instant>>,
+    /// How recent joystick input has to be to enable in teleop, if it has to
+    /// be at all
+    joystick_interlock: AtomicCell<Option<Duration>>,
+    joystick_descriptors: std::sync::Mutex<[Option<JoystickDescriptor>; MAX_JOYSTICKS]>,
+    joystick_mappings: std::sync::Mutex<[Option<JoystickMapping>; MAX_JOYSTICKS]>,
+    /// Whether the roboRIO needs to be sent new joystick descriptors
+    descriptors_changed: AtomicBool,
+    joystick_outputs: [AtomicCell<JoystickOutput>; MAX_JOYSTICKS],
+    /// Whether each joystick's outputs changed since the provider last saw them
+    joystick_outputs_changed: [AtomicBool; MAX_JOYSTICKS],
+    joystick_output_callback: std::sync::Mutex<Option<JoystickOutputCallback>>,
+    /// Whether an emergency stop is holding the robot stopped
+    estop_latched: AtomicBool,
+    /// Set while a watched emergency stop button can't be read
+    estop_button_lost: AtomicBool,
+    fms_connected: AtomicBool,
+    fms_info: std::sync::Mutex<Option<fms::FmsInfo>>,
+    /// Where to forward status packets, if anywhere
+    dashboard: std::sync::Mutex<Option<std::net::UdpSocket>>,
+    /// Versions reported by the roboRIO, by device type and id
+    versions: std::sync::Mutex<HashMap<(u8, u8), diagnostics::VersionEntry>>,
+    /// Versions to warn about not matching, by name
+    expected_versions: std::sync::Mutex<HashMap<String, String>>,
+    console: std::sync::Mutex<console::ConsoleHistory>,
+    /// Whether the roboRIO asked for the date and time
+    date_requested: AtomicBool,
+    timezone: std::sync::Mutex<String>,
+    match_info: std::sync::Mutex<MatchInfo>,
+    game_data: std::sync::Mutex<String>,
+    /// Whether match info and game data need to be sent again, like after
+    /// connecting
+    tcp_state_stale: AtomicBool,
+    /// Where to queue TCP tags for the current connection's writer
+    tcp_outgoing: std::sync::Mutex<Option<tokio::sync::mpsc::UnboundedSender<Bytes>>>,
+    /// When the match countdown runs out
+    match_deadline: AtomicCell<Option<Instant>>,
+    match_phase: AtomicCell<practice::MatchPhase>,
+    events: tokio::sync::broadcast::Sender<event::DsEvent>,
+    connection: tokio::sync::watch::Sender<connection::ConnectionState>,
+    backoff: AtomicCell<connection::Backoff>,
+    comm_timeout: AtomicCell<Duration>,
+    send_interval: Duration,
+    protocol_year: u16,
+    protocol: Arc<dyn ProtocolVersion>,
+    /// Cancelled once [`Ds::shutdown`] is called, to stop [`Ds::run`]
+    shutdown: CancellationToken,
+    /// Notified when the team number changes, to connect to the new robot
+    retarget: tokio::sync::Notify,
+    /// Woken for every status packet from the roboRIO
+    status_received: tokio::sync::Notify,
     //
-    rio_tcp_rx: Arc<Mutex<OwnedReadHalf>>,
-    rio_tcp_tx: Arc<Mutex<OwnedWriteHalf>>,
-    rio_incoming_udp: Arc<Mutex<UdpSocket>>,
-    rio_outgoing_udp: Arc<Mutex<UdpSocket>>,
+    transport: std::sync::RwLock<Option<Arc<dyn Transport>>>,
+    /// Makes new transports when the current one is lost, if possible
+    connector: Option<Box<dyn Connect>>,
+    lifecycle_callbacks: lifecycle::Callbacks,
 }
 impl Ds {
+    /// Create a driver station for `team_number`'s roboRIO
+    ///
+    /// Nothing is connected until [`Ds::run`] is called, and the connection
+    /// is made again whenever it's lost.
+    ///
+    /// The roboRIO is looked for over USB first, then at the team IP. Use
+    /// [`Ds::builder`] to connect somewhere else.
     pub async fn init(team_number: u16) -> Self {
-        let rio_ip = gen_team_ip(team_number).unwrap();
-
-        let (rio_tcp_rx, rio_tcp_tx) = TcpStream::connect(format!("{rio_ip}:1150"))
-            .await
-            .unwrap()
-            .into_split();
-        let rio_incoming_udp = UdpSocket::bind("0.0.0.0:1150").await.unwrap();
-        let rio_outgoing_udp = UdpSocket::bind("0.0.0.0:0").await.unwrap();
-        rio_outgoing_udp
-            .connect(format!("{rio_ip}:1110"))
-            .await
-            .unwrap();
+        // The defaults are always valid
+        Self::builder(team_number).build().unwrap()
+    }
+
+    /// Create a driver station that makes its connections with `connector`
+    pub fn with_connector(team_number: u16, connector: impl Connect + 'static) -> Self {
+        Self::new(team_number, None, Some(Box::new(connector)))
+    }
+
+    /// Create a driver station that talks to the roboRIO over `transport`
+    /// instead of the network
+    ///
+    /// There's no way to reconnect if `transport` is lost.
+    pub fn with_transport(team_number: u16, transport: impl Transport + 'static) -> Self {
+        Self::new(team_number, Some(Arc::new(transport)), None)
+    }
+
+    fn new(
+        team_number: u16,
+        transport: Option<Arc<dyn Transport>>,
+        connector: Option<Box<dyn Connect>>,
+    ) -> Self {
+        let connection = if transport.is_some() {
+            // Whoever made the transport already connected it
+            ConnectionState::Connected
+        } else {
+            ConnectionState::Disconnected
+        };
 
         Ds {
-            status: AtomicCell::new(RobotStatus::NoCommunication),
-            mode: AtomicCell::new(RobotCodeMode::Teleop),
-            can_bus_util: AtomicCell::new(0.0),
+            team_number: AtomicCell::new(team_number),
+            status: utils::Watched::new(RobotStatus::NoCommunication),
+            mode: utils::Watched::new(RobotCodeMode::Teleop),
+            status_flags: AtomicCell::new(Status::empty()),
+            trace: AtomicCell::new(Trace::empty()),
+            can_metrics: AtomicCell::new(None),
+            power_stats: AtomicCell::new(None),
+            disable_faults: AtomicCell::new(None),
+            status_seq: AtomicU64::new(0),
+            packets_received: AtomicU64::new(0),
+            packets_lost: AtomicU64::new(0),
+            last_seqnum: AtomicCell::new(None),
+            control_seqnum: AtomicU16::new(0),
+            control_sent: std::array::from_fn(|_| AtomicCell::new(None)),
+            trip_time: AtomicCell::new(None),
+            rail_faults: AtomicCell::new(None),
+            cpu_info: AtomicCell::new(None),
+            ram_info: AtomicCell::new(None),
+            low_memory: Default::default(),
+            free_disk: AtomicCell::new(None),
+            low_disk: Default::default(),
             battery: AtomicCell::new(0.0),
+            battery_history: std::sync::Mutex::new(battery::BatteryHistory::new(
+                battery::DEFAULT_BATTERY_HISTORY_CAPACITY,
+            )),
+            voltage_alerts: Default::default(),
+            brownouts: AtomicU32::new(0),
             alliance_pos: AtomicCell::new(AlliancePos::Red(1)),
+            joysticks: Default::default(),
+            joystick_updated: AtomicCell::new(None),
+            joystick_interlock: AtomicCell::new(None),
+            joystick_descriptors: Default::default(),
+            joystick_mappings: Default::default(),
+            // Descriptors always get sent once connected
+            descriptors_changed: AtomicBool::new(true),
+            joystick_outputs: Default::default(),
+            joystick_outputs_changed: Default::default(),
+            joystick_output_callback: Default::default(),
+            estop_latched: AtomicBool::new(false),
+            estop_button_lost: AtomicBool::new(false),
+            fms_connected: AtomicBool::new(false),
+            fms_info: Default::default(),
+            dashboard: Default::default(),
+            versions: Default::default(),
+            expected_versions: Default::default(),
+            console: std::sync::Mutex::new(console::ConsoleHistory::new(
+                console::DEFAULT_CONSOLE_CAPACITY,
+            )),
+            date_requested: AtomicBool::new(false),
+            timezone: std::sync::Mutex::new(utils::default_timezone()),
+            match_info: Default::default(),
+            game_data: Default::default(),
+            tcp_state_stale: AtomicBool::new(true),
+            tcp_outgoing: std::sync::Mutex::new(None),
+            match_deadline: AtomicCell::new(None),
+            match_phase: AtomicCell::new(practice::MatchPhase::PreMatch),
+            events: tokio::sync::broadcast::Sender::new(event::EVENT_CAPACITY),
+            connection: tokio::sync::watch::Sender::new(connection),
+            backoff: AtomicCell::new(Default::default()),
+            comm_timeout: AtomicCell::new(DEFAULT_COMM_TIMEOUT),
+            send_interval: SEND_INTERVAL,
+            protocol_year: PROTOCOL_YEAR,
+            protocol: Arc::new(Frc2015),
+            shutdown: CancellationToken::new(),
+            retarget: tokio::sync::Notify::new(),
+            status_received: tokio::sync::Notify::new(),
 
-            rio_tcp_rx: Arc::new(Mutex::new(rio_tcp_rx)),
-            rio_tcp_tx: Arc::new(Mutex::new(rio_tcp_tx)),
-            rio_incoming_udp: Arc::new(Mutex::new(rio_incoming_udp)),
-            rio_outgoing_udp: Arc::new(Mutex::new(rio_outgoing_udp)),
+            transport: std::sync::RwLock::new(transport),
+            connector,
+            lifecycle_callbacks: Default::default(),
+        }
+    }
+
+    /// Get the team number
+    #[inline(always)]
+    pub fn team_number(&self) -> u16 {
+        self.team_number.load()
+    }
+
+    /// Switch to a different team's robot
+    ///
+    /// The current robot is disabled and disconnected, and [`Ds::run`]
+    /// connects to the new team's roboRIO. Without a way to reconnect (like
+    /// with [`Ds::with_transport`]), only the reported team number changes.
+    pub async fn set_team_number(&self, team_number: u16) {
+        if self.team_number.swap(team_number) == team_number {
+            return;
+        }
+        event!(Level::INFO, team_number, "Switching teams");
+
+        if self.connector.is_some() {
+            self.disable_now().await;
+            self.retarget.notify_one();
         }
     }
 
     /// Get robot status
+    ///
+    /// To wait for it to change, use [`Ds::status_stream`].
     #[inline(always)]
     pub fn status(&self) -> RobotStatus {
         self.status.load()
     }
 
     /// Get robot code mode
+    ///
+    /// To wait for it to change, use [`Ds::mode_stream`].
     #[inline(always)]
     pub fn mode(&self) -> RobotCodeMode {
         self.mode.load()
     }
 
-    /// Get CAN bus utilization (as percentage)
+    /// Get the battery voltage from the last status packet
+    ///
+    /// This is 0 while there's no communication with the robot.
+    #[inline(always)]
+    pub fn battery_voltage(&self) -> f32 {
+        self.battery.load()
+    }
+
+    /// Whether the roboRIO reported robot code running in its last status
+    /// packet
+    #[inline(always)]
+    pub fn has_robot_code(&self) -> bool {
+        self.trace.load().has_robot_code()
+    }
+
+    /// Whether the robot is a real roboRIO, rather than a simulator
+    #[inline(always)]
+    pub fn is_real_roborio(&self) -> bool {
+        self.trace.load().is_roborio()
+    }
+
+    /// Whether the roboRIO reported a brownout in its last status packet
+    ///
+    /// Unlike [`RobotStatus::BrownedOut`], this is still reported while the
+    /// robot is estopped.
+    #[inline(always)]
+    pub fn is_browned_out(&self) -> bool {
+        self.status_flags.load().is_browned_out()
+    }
+
+    /// Get the trace flags from the last status packet
+    #[inline(always)]
+    pub fn trace(&self) -> Trace {
+        self.trace.load()
+    }
+
+    /// Get the status flags from the last status packet
+    #[inline(always)]
+    pub fn status_flags(&self) -> Status {
+        self.status_flags.load()
+    }
+
+    /// Get the FRC season whose protocol is being spoken
+    #[inline(always)]
+    pub fn protocol_year(&self) -> u16 {
+        self.protocol_year
+    }
+
+    /// Get CAN bus utilization, from 0 to 1
     #[inline(always)]
     pub fn can_bus_util(&self) -> f32 {
-        self.can_bus_util.load()
+        self.can_metrics
+            .load()
+            .map_or(0.0, |metrics| metrics.utilization)
+    }
+
+    /// Switch the robot code to `mode`
+    ///
+    /// Like the official DS, an enabled robot is disabled first, and the mode
+    /// only changes once the roboRIO confirms it's disabled (or stops
+    /// answering). The robot is left disabled.
+    ///
+    /// Does nothing while an FMS is in control, since it picks the mode.
+    pub async fn set_mode(&self, mode: RobotCodeMode) {
+        if mode == self.mode() || self.is_fms_connected() {
+            return;
+        }
+
+        if self.status() == RobotStatus::Enabled {
+            let confirmed = async {
+                loop {
+                    self.status_received.notified().await;
+                    if self.status() != RobotStatus::Enabled {
+                        break;
+                    }
+                }
+            };
+
+            self.disable().await;
+            if timeout(self.comm_timeout.load(), confirmed).await.is_err() {
+                event!(
+                    Level::WARN,
+                    ?mode,
+                    "roboRIO never confirmed disable, changing mode anyway"
+                );
+            }
+        }
+
+        self.set_mode_unchecked(mode).await;
+    }
+
+    /// Switch the robot code to `mode` right away, even while enabled
+    ///
+    /// Robot code might not expect this, so prefer [`Ds::set_mode`].
+    pub async fn set_mode_unchecked(&self, mode: RobotCodeMode) {
+        self.mode.store(mode);
+        self.send_udp().await;
     }
 
     /// Enable the robot code
-    pub async fn enable(&self) {
+    ///
+    /// Fails while an emergency stop is latched or its button is lost, while
+    /// an FMS is in control, or without recent joystick input when the
+    /// [interlock](Ds::set_joystick_interlock) is on.
+    pub async fn enable(&self) -> Result<(), Error> {
+        if self.is_estopped() {
+            return Err(Error::EStopped);
+        }
+        if self.is_estop_button_lost() {
+            return Err(Error::EStopButtonLost);
+        }
+        if self.is_fms_connected() {
+            return Err(Error::FmsControlled);
+        }
+        if !self.has_fresh_joystick_input() {
+            return Err(Error::NoJoystickInput);
+        }
+
         self.status.store(RobotStatus::Enabled);
         self.send_udp().await;
+        Ok(())
+    }
+
+    /// Refuse to enable in teleop unless a joystick was given a state within
+    /// the last `max_age`, or stop refusing with `None`
+    ///
+    /// Keeps a robot from being enabled with nothing to drive it, like when
+    /// a [`JoystickProvider`] was never hooked up or stopped being polled.
+    /// Other modes don't need an operator, so they're not affected. Off by
+    /// default.
+    pub fn set_joystick_interlock(&self, max_age: Option<Duration>) {
+        self.joystick_interlock.store(max_age);
+    }
+
+    /// Whether joystick input is recent enough for the interlock to allow
+    /// enabling
+    fn has_fresh_joystick_input(&self) -> bool {
+        let Some(max_age) = self.joystick_interlock.load() else {
+            return true;
+        };
+        if self.mode() != RobotCodeMode::Teleop {
+            return true;
+        }
+        self.joystick_updated
+            .load()
+            .is_some_and(|at| at.elapsed() <= max_age)
     }
 
     /// Disable the robot code
+    ///
+    /// Does nothing while an emergency stop is latched, since the robot is
+    /// already stopped, or while an FMS is in control. Use [`Ds::estop`] to
+    /// stop a robot on the field.
     pub async fn disable(&self) {
-        self.status.store(RobotStatus::Disabled);
+        if self.is_fms_connected() {
+            event!(Level::DEBUG, "Ignoring disable while FMS is in control");
+            return;
+        }
+        self.disable_now().await;
+    }
+
+    /// Disable the robot code, even while an FMS is in control
+    pub(crate) async fn disable_now(&self) {
+        if !self.is_estopped() {
+            self.status.store(RobotStatus::Disabled);
+        }
         self.send_udp().await;
     }
 
     /// Trigger an emergency stop
+    ///
+    /// Just like the official DS, the stop is sticky: disabling doesn't
+    /// release it, and the robot can't be enabled again until
+    /// [`Ds::clear_estop`] is called.
+    ///
+    /// It's also released whenever a new connection to the roboRIO is made,
+    /// like after the roboRIO reboots, since the roboRIO only stays stopped
+    /// until then. Going without status packets for a while doesn't release
+    /// it, and a DS made with [`Ds::with_transport`] never reconnects.
     pub async fn estop(&self) {
+        self.estop_latched.store(true, Ordering::Release);
         self.status.store(RobotStatus::EStopped);
         self.send_udp().await;
     }
 
+    /// Whether an emergency stop is latched
+    #[inline(always)]
+    pub fn is_estopped(&self) -> bool {
+        self.estop_latched.load(Ordering::Acquire)
+    }
+
+    /// Release a latched emergency stop, leaving the robot disabled
+    pub async fn clear_estop(&self) {
+        if self.estop_latched.swap(false, Ordering::AcqRel) {
+            event!(Level::INFO, "Emergency stop cleared");
+            self.status.store(RobotStatus::Disabled);
+            self.send_udp().await;
+        }
+    }
+
+    /// Set the state of the joystick in `slot`
+    ///
+    /// The state is sent with every control packet until it's replaced or
+    /// the slot is cleared.
+    pub fn set_joystick(
+        &self,
+        slot: usize,
+        axes: &[i8],
+        buttons: &[bool],
+        povs: &[i16],
+    ) -> Result<(), Error> {
+        let joystick = Joystick::new(axes, buttons, povs)?;
+        self.store_joystick(slot, Some(joystick))
+    }
+
+    /// Remove the joystick in `slot`
+    pub fn clear_joystick(&self, slot: usize) -> Result<(), Error> {
+        self.store_joystick(slot, None)?;
+        self.set_joystick_descriptor(slot, None)
+    }
+
+    /// Describe the layout of the joystick in `slot` to the roboRIO
+    ///
+    /// Joysticks without a descriptor are described with only their input
+    /// counts. Descriptors are re-sent whenever they change.
+    pub fn set_joystick_descriptor(
+        &self,
+        slot: usize,
+        descriptor: Option<JoystickDescriptor>,
+    ) -> Result<(), Error> {
+        let mut descriptors = self.joystick_descriptors.lock().unwrap();
+        let current = descriptors
+            .get_mut(slot)
+            .ok_or(Error::InvalidJoystickSlot)?;
+
+        if *current != descriptor {
+            *current = descriptor;
+            self.descriptors_changed.store(true, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    /// Adjust the joystick in `slot` with `mapping` from now on, or stop
+    /// adjusting it with `None`
+    pub fn set_joystick_mapping(
+        &self,
+        slot: usize,
+        mapping: Option<JoystickMapping>,
+    ) -> Result<(), Error> {
+        let mut mappings = self.joystick_mappings.lock().unwrap();
+        *mappings.get_mut(slot).ok_or(Error::InvalidJoystickSlot)? = mapping;
+        Ok(())
+    }
+
+    fn store_joystick(&self, slot: usize, joystick: Option<Joystick>) -> Result<(), Error> {
+        let joystick = match (&joystick, self.joystick_mappings.lock().unwrap().get(slot)) {
+            (Some(js), Some(Some(mapping))) => Some(mapping.apply(js)),
+            _ => joystick,
+        };
+
+        let previous = self
+            .joysticks
+            .get(slot)
+            .ok_or(Error::InvalidJoystickSlot)?
+            .swap(joystick);
+        if joystick.is_some() {
+            self.joystick_updated.store(Some(Instant::now()));
+        }
+
+        // Generic descriptors depend on the input counts, so those changing
+        // (or the joystick being added or removed) needs a re-send
+        let layout = |joystick: Option<Joystick>| {
+            joystick.map(|js| (js.axes().len(), js.buttons().len(), js.povs().len()))
+        };
+        if layout(previous) != layout(joystick) {
+            self.descriptors_changed.store(true, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    /// Replace every joystick slot with the latest state from `provider`
+    ///
+    /// Call this at least as often as control packets are sent (every 20ms)
+    /// so the robot sees fresh input.
+    pub fn update_joysticks(&self, provider: &mut impl JoystickProvider) {
+        for (slot, joystick) in provider.poll().into_iter().enumerate() {
+            // Slots are always in range here
+            let _ = self.store_joystick(slot, joystick);
+            let _ = self.set_joystick_descriptor(slot, provider.descriptor(slot));
+
+            if self.joystick_outputs_changed[slot].swap(false, Ordering::AcqRel) {
+                let JoystickOutput {
+                    outputs,
+                    left_rumble,
+                    right_rumble,
+                } = self.joystick_outputs[slot].load();
+                provider.set_outputs(slot, outputs, left_rumble, right_rumble);
+            }
+        }
+    }
+
+    /// Get the outputs (rumble, LEDs) robot code requested for the joystick
+    /// in `slot`
+    pub fn joystick_output(&self, slot: usize) -> Option<JoystickOutput> {
+        Some(self.joystick_outputs.get(slot)?.load())
+    }
+
+    /// Register a callback for joystick output changes
+    ///
+    /// Meant for consumers that manage their own HID devices instead of
+    /// using a [`JoystickProvider`]. The callback runs on the receive loop, so
+    /// it should return quickly.
+    pub fn on_joystick_output(
+        &self,
+        callback: impl Fn(usize, JoystickOutput) + Send + Sync + 'static,
+    ) {
+        *self.joystick_output_callback.lock().unwrap() = Some(Box::new(callback));
+    }
+
+    fn store_joystick_outputs(&self, outputs: &[JoystickOutput]) {
+        // Joysticks robot code didn't mention have no outputs
+        let outputs = outputs
+            .iter()
+            .copied()
+            .chain(core::iter::repeat(JoystickOutput::default()));
+
+        for (slot, output) in outputs.take(MAX_JOYSTICKS).enumerate() {
+            if self.joystick_outputs[slot].swap(output) == output {
+                continue;
+            }
+            self.joystick_outputs_changed[slot].store(true, Ordering::Release);
+
+            if let Some(callback) = &*self.joystick_output_callback.lock().unwrap() {
+                callback(slot, output);
+            }
+        }
+    }
+
+    /// Get the state of the joystick in `slot`, if there is one
+    pub fn joystick(&self, slot: usize) -> Option<Joystick> {
+        self.joysticks.get(slot)?.load()
+    }
+
     /// Issue a command to restart the roboRIO
-    pub async fn reboot_rio(&self) {
-        let mut pkt = UdpOutgoingPacket::build(self);
+    ///
+    /// Fails if there's no connection to the roboRIO, or the command couldn't
+    /// be sent.
+    pub async fn reboot_rio(&self) -> io::Result<()> {
+        let mut pkt = self.control_packet();
         pkt.reboot_rio();
-        self.rio_outgoing_udp
-            .lock()
-            .await
-            .send(&pkt.write())
-            .await
-            .unwrap();
+        let transport = self.transport().ok_or(io::ErrorKind::NotConnected)?;
+        let mut buf = BytesMut::new();
+        self.protocol.write_control(pkt, &mut buf);
+        transport.send_datagram(&buf).await
     }
 
     /// Issue a command to restart the robot code
-    pub async fn restart_code(&self) {
-        let mut pkt = UdpOutgoingPacket::build(self);
+    ///
+    /// Fails if there's no connection to the roboRIO, or the command couldn't
+    /// be sent.
+    pub async fn restart_code(&self) -> io::Result<()> {
+        let mut pkt = self.control_packet();
         pkt.restart_code();
-        self.rio_outgoing_udp
-            .lock()
-            .await
-            .send(&pkt.write())
-            .await
-            .unwrap();
+        let transport = self.transport().ok_or(io::ErrorKind::NotConnected)?;
+        let mut buf = BytesMut::new();
+        self.protocol.write_control(pkt, &mut buf);
+        transport.send_datagram(&buf).await
+    }
+
+    /// Set the timezone sent to the roboRIO along with the date
+    ///
+    /// Defaults to the host's timezone with the `timezone` feature, or `UTC`
+    /// without it.
+    pub fn set_timezone(&self, timezone: impl Into<String>) {
+        *self.timezone.lock().unwrap() = timezone.into();
+    }
+
+    /// Get the timezone sent to the roboRIO along with the date
+    pub fn timezone(&self) -> String {
+        self.timezone.lock().unwrap().clone()
+    }
+
+    /// Get the alliance station reported to robot code
+    pub fn alliance(&self) -> AlliancePos {
+        self.alliance_pos.load()
+    }
+
+    /// Move to another alliance station, starting with the next control
+    /// packet
+    pub fn set_alliance(&self, alliance_pos: AlliancePos) -> Result<(), Error> {
+        self.store_alliance(alliance_pos.validate()?);
+        Ok(())
+    }
+
+    /// Store a valid alliance station, telling subscribers if it changed
+    pub(crate) fn store_alliance(&self, alliance_pos: AlliancePos) {
+        if self.alliance_pos.swap(alliance_pos) != alliance_pos {
+            event!(Level::INFO, ?alliance_pos, "Alliance station changed");
+            self.emit(event::DsEvent::AllianceChanged(alliance_pos));
+        }
+    }
+
+    /// Get the match details sent to robot code
+    pub fn match_info(&self) -> MatchInfo {
+        self.match_info.lock().unwrap().clone()
+    }
+
+    /// Set the match details robot code sees, sending them right away
+    pub async fn set_match_info(&self, match_info: MatchInfo) {
+        *self.match_info.lock().unwrap() = match_info.clone();
+        self.send_tcp(match_info.as_tag());
+    }
+
+    /// Get the game-specific message sent to robot code
+    pub fn game_data(&self) -> String {
+        self.game_data.lock().unwrap().clone()
+    }
+
+    /// Set the game-specific message robot code sees through
+    /// `DriverStation.getGameSpecificMessage()`, sending it right away
+    ///
+    /// It gets sent again whenever the roboRIO reconnects.
+    pub async fn set_game_data(&self, game_data: &str) {
+        *self.game_data.lock().unwrap() = game_data.to_owned();
+        self.send_tcp(TcpOutgoingTag::GameData { game_data });
+    }
+
+    /// Send the match info and game data again
+    fn send_tcp_state(&self) {
+        let match_info = self.match_info();
+        if match_info != MatchInfo::default() {
+            self.send_tcp(match_info.as_tag());
+        }
+
+        let game_data = self.game_data();
+        if !game_data.is_empty() {
+            self.send_tcp(TcpOutgoingTag::GameData {
+                game_data: &game_data,
+            });
+        }
     }
 
     async fn send_udp(&self) {
-        self.rio_outgoing_udp
-            .lock()
-            .await
-            .send(&UdpOutgoingPacket::build(self).write())
-            .await
-            .unwrap();
+        if let Some(transport) = self.transport() {
+            self.send_udp_on(&*transport, &mut BytesMut::new()).await;
+        }
+    }
+
+    /// Send a control packet on a transport the caller already holds,
+    /// encoding it into `buf`
+    ///
+    /// The send loop uses this with the same buffer every time, so the 50Hz
+    /// path doesn't allocate or touch the transport lock.
+    async fn send_udp_on(&self, transport: &dyn Transport, buf: &mut BytesMut) {
+        let timezone;
+        // At most a countdown, date, and timezone, so no need for a Vec
+        let mut tags = [UdpOutgoingTag::Countdown { countdown: 0.0 }; 3];
+        let mut tag_count = 0;
+
+        if let Some(remaining) = self.match_time() {
+            tags[tag_count] = UdpOutgoingTag::Countdown {
+                countdown: remaining.as_secs_f32(),
+            };
+            tag_count += 1;
+        }
+        // The roboRIO keeps asking until it gets an answer
+        if self.date_requested.swap(false, Ordering::AcqRel) {
+            timezone = self.timezone();
+            tags[tag_count] = UdpOutgoingTag::date(SystemTime::now());
+            tags[tag_count + 1] = UdpOutgoingTag::Timezone {
+                timezone: &timezone,
+            };
+            tag_count += 2;
+        }
+
+        let mut pkt = self.control_packet();
+        pkt.set_tags(&tags[..tag_count]);
+        buf.clear();
+        self.protocol.write_control(pkt, buf);
+        // A rebooting roboRIO refuses packets for a while, which is fine
+        if let Err(err) = transport.send_datagram(buf).await {
+            event!(Level::TRACE, %err, "Failed to send UDP packet");
+        }
     }
 
-    async fn send_tcp(&self, tag: TcpOutgoingTag<'_>) {
-        let tcp_tx = self.rio_tcp_tx.lock().await;
-        tcp_tx.writable().await.unwrap();
-        tcp_tx.try_write(&tag.write()).unwrap();
+    /// Queue a TCP tag to be sent
+    ///
+    /// Tags are dropped while disconnected, since everything gets sent again
+    /// after connecting.
+    fn send_tcp(&self, tag: TcpOutgoingTag<'_>) {
+        if let Some(tags) = &*self.tcp_outgoing.lock().unwrap() {
+            let mut buf = BytesMut::new();
+            self.protocol.write_tcp(tag, &mut buf);
+            // Closed means the connection is going away anyway
+            let _ = tags.send(buf.freeze());
+        }
     }
 
+    /// Send a descriptor for every joystick slot
+    fn send_joystick_descriptors(&self) {
+        let descriptors: Vec<JoystickDescriptor> = {
+            let descriptors = self.joystick_descriptors.lock().unwrap();
+            descriptors
+                .iter()
+                .zip(&self.joysticks)
+                .map(
+                    |(descriptor, joystick)| match (descriptor, joystick.load()) {
+                        (Some(descriptor), _) => descriptor.clone(),
+                        (None, Some(joystick)) => JoystickDescriptor::generic(&joystick),
+                        (None, None) => JoystickDescriptor::EMPTY,
+                    },
+                )
+                .collect()
+        };
+
+        for (index, descriptor) in descriptors.iter().enumerate() {
+            self.send_tcp(descriptor.as_tag(index as u8));
+        }
+    }
+
+    /// Talk to the roboRIO, connecting and reconnecting as needed, until
+    /// [`Ds::shutdown`] is called
+    ///
+    /// With the `runtime-tokio` feature this has to run inside a tokio
+    /// runtime. Without it, any executor can drive it.
     pub async fn run(&self) {
-        let udp_rx = self.rio_incoming_udp.lock().await;
-        let tcp_rx = self.rio_tcp_rx.lock().await;
+        self.run_until_cancelled(CancellationToken::new()).await;
+    }
 
+    /// Like [`Ds::run`], but also stops once `cancel` is cancelled
+    ///
+    /// Prefer this over aborting the task running [`Ds::run`], which can
+    /// stop it halfway through writing a TCP tag. Stopping doesn't disable
+    /// the robot; the roboRIO does that itself once packets stop, or use
+    /// [`Ds::shutdown`].
+    pub async fn run_until_cancelled(&self, cancel: CancellationToken) {
+        tokio::select! {
+            _ = self.run_connections(&cancel) => {}
+            _ = self.run_lifecycle_callbacks() => {}
+        }
+    }
+
+    /// Connect to the roboRIO and talk to it until told to stop
+    async fn run_connections(&self, cancel: &CancellationToken) {
         loop {
-            tokio::select! {
-                res = udp_rx.readable() => {
-                    res.unwrap();
+            let transport = match (self.transport(), &self.connector) {
+                (Some(transport), _) => transport,
+                (None, Some(connector)) => tokio::select! {
+                    transport = self.connect(connector.as_ref()) => transport,
+                    _ = self.stopped(cancel) => return,
+                },
+                // Nothing to connect with, so just wait
+                (None, None) => return self.stopped(cancel).await,
+            };
 
-                    let mut buf = Vec::new();
-                    buf.clear();
+            if self.run_connection(&*transport, cancel).await.is_break() {
+                return;
+            }
+            // Might still be connected, if switching teams
+            let _ = transport.close().await;
+            drop(transport);
+            self.connection_lost();
+        }
+    }
 
-                    if let Err(err) = udp_rx.try_recv(&mut buf) {
-                        panic!("{err:?}");
-                    }
+    /// Wait until either `cancel` or [`Ds::shutdown`] says to stop
+    async fn stopped(&self, cancel: &CancellationToken) {
+        tokio::select! {
+            _ = cancel.cancelled() => {}
+            _ = self.shutdown.cancelled() => {}
+        }
+    }
 
-                    for pkt in UdpIncomingStream::new(&buf) {
-                        let UdpIncomingPacket { status, trace, battery, .. } = pkt;
+    /// Disable the robot and disconnect from it
+    ///
+    /// A final disabled control packet is sent, the TCP connection is
+    /// flushed and closed, and [`Ds::run`] returns. Nothing is sent after
+    /// this, so always call it before dropping a `Ds` that might have an
+    /// enabled robot.
+    pub async fn shutdown(&self) {
+        if self.shutdown.is_cancelled() {
+            return;
+        }
+        self.shutdown.cancel();
+        event!(Level::INFO, "Shutting down");
 
-                        let (status, mode) = find_status(status, trace);
+        self.disable_now().await;
 
-                        self.status.store(status);
-                        self.mode.store(mode);
-                        self.battery.store(battery);
-                    }
-                }
-                res = tcp_rx.readable() => {
-                    res.unwrap();
+        let transport = self.transport.write().unwrap().take();
+        if let Some(transport) = transport
+            && let Err(err) = transport.close().await
+        {
+            event!(Level::DEBUG, %err, "Failed to close connection cleanly");
+        }
 
-                    let mut buf = Vec::new();
-                    buf.clear();
+        self.status.store(RobotStatus::NoCommunication);
+        self.set_connection_state(ConnectionState::Disconnected);
+    }
+}
+impl Drop for Ds {
+    /// Last-ditch effort to disable the robot if [`Ds::shutdown`] wasn't
+    /// called
+    ///
+    /// Drop can't wait on anything, so the disabled packet is sent from a
+    /// new task, and only when dropped inside a tokio runtime. Without
+    /// `runtime-tokio` there's nowhere to send it from.
+    fn drop(&mut self) {
+        if self.shutdown.is_cancelled() || self.status.load() != RobotStatus::Enabled {
+            return;
+        }
+        #[cfg(feature = "runtime-tokio")]
+        if let (Ok(runtime), Some(transport)) =
+            (tokio::runtime::Handle::try_current(), self.transport())
+        {
+            self.status.store(RobotStatus::Disabled);
+            let mut pkt = BytesMut::new();
+            self.protocol.write_control(self.control_packet(), &mut pkt);
+            runtime.spawn(async move {
+                let _ = transport.send_datagram(&pkt).await;
+            });
+            return;
+        }
+        event!(Level::WARN, "Dropped while enabled, robot may stay enabled");
+    }
+}
 
-                    if let Err(err) = tcp_rx.try_read_buf(&mut buf) {
-                        panic!("{err:?}");
-                    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MemoryTransport;
 
-                    for tag in TcpTagStream::new(&buf) {
-                        match tag {
-                            TcpIncomingTag::RadioEvent(tag) => {},
-                            TcpIncomingTag::UsageReport => {},
-                            TcpIncomingTag::DisableFaults(tag) => tag.handle(self),
-                            TcpIncomingTag::RailFaults(tag) => tag.handle(self),
-                            TcpIncomingTag::VersionInfo(tag) => tag.handle(self),
-                            TcpIncomingTag::ErrorMessage(tag) => tag.handle(self),
-                            TcpIncomingTag::Stdout(tag) => tag.handle(self),
-                            TcpIncomingTag::Dummy => {},
-                        }
-                    }
-                }
-            }
-        }
+    #[tokio::test]
+    async fn interlock_needs_fresh_joysticks_in_teleop() {
+        let ds = Ds::with_transport(4533, MemoryTransport::pair().0);
+        ds.set_joystick_interlock(Some(Duration::from_millis(100)));
+        assert!(matches!(ds.enable().await, Err(Error::NoJoystickInput)));
+
+        // Nobody needs to drive in autonomous
+        ds.set_mode_unchecked(RobotCodeMode::Autonomous).await;
+        ds.enable().await.unwrap();
+        ds.disable().await;
+        ds.set_mode_unchecked(RobotCodeMode::Teleop).await;
+
+        ds.set_joystick(0, &[0], &[false], &[-1]).unwrap();
+        ds.enable().await.unwrap();
+        ds.disable().await;
+
+        ds.joystick_updated
+            .store(Some(Instant::now() - Duration::from_millis(200)));
+        assert!(matches!(ds.enable().await, Err(Error::NoJoystickInput)));
+
+        ds.set_joystick_interlock(None);
+        ds.enable().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn estop_stays_until_cleared() {
+        let ds = Ds::with_transport(4533, MemoryTransport::pair().0);
+        ds.enable().await.unwrap();
+        ds.estop().await;
+
+        ds.disable().await;
+        assert!(matches!(ds.enable().await, Err(Error::EStopped)));
+        assert!(ds.is_estopped());
+        assert_eq!(ds.status(), RobotStatus::EStopped);
+
+        ds.clear_estop().await;
+        assert!(!ds.is_estopped());
+        assert_eq!(ds.status(), RobotStatus::Disabled);
+        ds.enable().await.unwrap();
     }
 }
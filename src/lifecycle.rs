@@ -0,0 +1,208 @@
+//! Callbacks for when the robot is enabled, disabled, estopped, or lost
+//!
+//! An alternative to reading [`Ds::status_stream`] for consumers that would
+//! rather be called. The callbacks run one at a time alongside [`Ds::run`],
+//! so a slow one holds up the rest, and only sees the latest status when
+//! it's done (just like a slow stream reader). A panicking callback is
+//! logged instead of taking the driver station down with it.
+
+use std::{
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures_lite::StreamExt;
+
+use crate::{Ds, RobotStatus, trace::Level};
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+type Callback = Arc<dyn Fn() -> BoxFuture + Send + Sync>;
+
+/// Something a callback can be registered for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lifecycle {
+    Enable,
+    Disable,
+    EStop,
+    CommLost,
+}
+impl Lifecycle {
+    /// What going from `from` to `to` means, if anything
+    fn of(from: RobotStatus, to: RobotStatus) -> Option<Self> {
+        match (from, to) {
+            (_, RobotStatus::Enabled) => Some(Self::Enable),
+            (RobotStatus::Enabled, RobotStatus::Disabled) => Some(Self::Disable),
+            (_, RobotStatus::EStopped) => Some(Self::EStop),
+            (_, RobotStatus::NoCommunication) => Some(Self::CommLost),
+            _ => None,
+        }
+    }
+}
+
+/// The registered callbacks, one for each [`Lifecycle`]
+#[derive(Default)]
+pub(crate) struct Callbacks([Mutex<Option<Callback>>; 4]);
+impl Callbacks {
+    fn set<F, Fut>(&self, lifecycle: Lifecycle, callback: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let callback: Callback = Arc::new(move || Box::pin(callback()));
+        *self.0[lifecycle as usize].lock().unwrap() = Some(callback);
+    }
+
+    fn get(&self, lifecycle: Lifecycle) -> Option<Callback> {
+        self.0[lifecycle as usize].lock().unwrap().clone()
+    }
+}
+
+impl Ds {
+    /// Call `callback` whenever the robot reports being enabled
+    ///
+    /// Replaces any callback already registered for this.
+    ///
+    /// ```no_run
+    /// # fn register(ds: &robudst::Ds) {
+    /// ds.on_enable(|| async { println!("Enabled") });
+    /// # }
+    /// ```
+    pub fn on_enable<F, Fut>(&self, callback: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.lifecycle_callbacks.set(Lifecycle::Enable, callback);
+    }
+
+    /// Call `callback` whenever the robot goes from enabled to disabled
+    ///
+    /// Replaces any callback already registered for this.
+    pub fn on_disable<F, Fut>(&self, callback: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.lifecycle_callbacks.set(Lifecycle::Disable, callback);
+    }
+
+    /// Call `callback` whenever the robot reports being emergency stopped
+    ///
+    /// Replaces any callback already registered for this.
+    pub fn on_estop<F, Fut>(&self, callback: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.lifecycle_callbacks.set(Lifecycle::EStop, callback);
+    }
+
+    /// Call `callback` whenever communication with the robot is lost, either
+    /// because status packets stopped or the connection dropped
+    ///
+    /// Replaces any callback already registered for this.
+    pub fn on_comm_lost<F, Fut>(&self, callback: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.lifecycle_callbacks.set(Lifecycle::CommLost, callback);
+    }
+
+    /// Run the registered callbacks for every status change, forever
+    pub(crate) async fn run_lifecycle_callbacks(&self) {
+        let mut statuses = self.status.changes();
+        let mut last = self.status();
+
+        while let Some(status) = statuses.next().await {
+            let from = std::mem::replace(&mut last, status);
+            let Some(lifecycle) = Lifecycle::of(from, status) else {
+                continue;
+            };
+            let Some(callback) = self.lifecycle_callbacks.get(lifecycle) else {
+                continue;
+            };
+
+            let finished = match panic::catch_unwind(AssertUnwindSafe(|| callback())) {
+                Ok(future) => CatchUnwind(future).await,
+                Err(_) => false,
+            };
+            if !finished {
+                event!(Level::ERROR, ?lifecycle, "Lifecycle callback panicked");
+            }
+        }
+    }
+}
+
+/// Runs a callback's future, finishing early with `false` if it panics
+struct CatchUnwind(BoxFuture);
+impl Future for CatchUnwind {
+    type Output = bool;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<bool> {
+        let future = &mut self.0;
+        match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Ready(())) => Poll::Ready(true),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(_) => Poll::Ready(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+    use crate::transport::MemoryTransport;
+
+    #[tokio::test]
+    async fn callbacks_survive_panics() {
+        let ds = Ds::with_transport(4533, MemoryTransport::pair().0);
+        let (tx, mut rx) = unbounded_channel();
+
+        // Every callback reports in, and the one for enabling panics after
+        for lifecycle in [Lifecycle::Enable, Lifecycle::Disable, Lifecycle::CommLost] {
+            let tx = tx.clone();
+            let callback = move || {
+                let _ = tx.send(lifecycle);
+                async move {
+                    if lifecycle == Lifecycle::Enable {
+                        panic!("robot code is fine, it's the callback");
+                    }
+                }
+            };
+            match lifecycle {
+                Lifecycle::Enable => ds.on_enable(callback),
+                Lifecycle::Disable => ds.on_disable(callback),
+                _ => ds.on_comm_lost(callback),
+            }
+        }
+
+        let changes = async {
+            let mut called = Vec::new();
+            for status in [
+                RobotStatus::Enabled,
+                RobotStatus::Disabled,
+                RobotStatus::NoCommunication,
+            ] {
+                ds.status.store(status);
+                called.push(rx.recv().await.unwrap());
+            }
+            called
+        };
+
+        // Watching has to start before anything changes
+        tokio::select! {
+            biased;
+            _ = ds.run_lifecycle_callbacks() => unreachable!(),
+            called = changes => assert_eq!(
+                called,
+                [Lifecycle::Enable, Lifecycle::Disable, Lifecycle::CommLost]
+            ),
+        }
+    }
+}
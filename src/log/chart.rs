@@ -0,0 +1,174 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use super::status_name;
+use crate::{Ds, timer::interval};
+
+/// Writes the data the official DS's chart tab plots to a CSV file
+///
+/// Each row is a snapshot of `ds`, with the seconds since the export
+/// started. Columns for things the roboRIO hasn't reported yet are left
+/// empty.
+pub struct ChartExporter<W: Write> {
+    out: BufWriter<W>,
+    start: Instant,
+}
+impl ChartExporter<File> {
+    /// Create a CSV file at `path`, replacing it if it exists
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(File::create(path)?)
+    }
+
+    /// Add to the CSV file at `path`, creating it if it doesn't exist
+    ///
+    /// The header is only written to a new or empty file.
+    pub fn append(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            Self::new(file)
+        } else {
+            Ok(Self {
+                out: BufWriter::new(file),
+                start: Instant::now(),
+            })
+        }
+    }
+}
+impl<W: Write> ChartExporter<W> {
+    const HEADER: &str =
+        "time,voltage,trip_time_ms,lost_packets,cpu_percent,can_util_percent,status";
+
+    /// Start an export, writing the header to `out`
+    pub fn new(out: W) -> io::Result<Self> {
+        let mut out = BufWriter::new(out);
+        writeln!(out, "{}", Self::HEADER)?;
+
+        Ok(Self {
+            out,
+            start: Instant::now(),
+        })
+    }
+
+    /// Write a single row with the current state of `ds`
+    pub fn write_row(&mut self, ds: &Ds) -> io::Result<()> {
+        self.write_row_at(ds, self.start.elapsed())
+    }
+
+    fn write_row_at(&mut self, ds: &Ds, elapsed: Duration) -> io::Result<()> {
+        let telemetry = ds.telemetry();
+        let trip_time = telemetry
+            .connection
+            .trip_time
+            .map(|trip_time| format!("{:.1}", trip_time.as_secs_f64() * 1000.0))
+            .unwrap_or_default();
+        let cpu = telemetry
+            .cpu
+            .map(|cpu| format!("{:.1}", cpu.total()))
+            .unwrap_or_default();
+        let can_util = telemetry
+            .can
+            .map(|can| format!("{:.1}", can.utilization * 100.0))
+            .unwrap_or_default();
+
+        writeln!(
+            self.out,
+            "{:.3},{:.2},{},{},{},{},{}",
+            elapsed.as_secs_f64(),
+            telemetry.battery_voltage,
+            trip_time,
+            telemetry.connection.packets_lost,
+            cpu,
+            can_util,
+            status_name(telemetry.status),
+        )
+    }
+
+    /// Write out anything still buffered
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Write a row of `ds` every `period` until an IO error occurs
+    ///
+    /// Rows are flushed as they're written, so the file is usable even if
+    /// the program is killed.
+    pub async fn run(&mut self, ds: &Ds, period: Duration) -> io::Result<()> {
+        let mut interval = interval(period);
+
+        loop {
+            interval.tick().await;
+            self.write_row(ds)?;
+            self.flush()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        diagnostics::{CanMetrics, CpuInfo},
+        proto::incoming::udp::{Status, Trace},
+        test_support::rio,
+        transport::{MemoryTransport, Transport},
+    };
+
+    #[tokio::test]
+    async fn rows_follow_the_status_packets() {
+        let (ds_end, rio) = MemoryTransport::pair();
+        let ds = Ds::with_transport(4533, ds_end);
+        let mut csv = Vec::new();
+        let mut chart = ChartExporter::new(&mut csv).unwrap();
+
+        let usage = [
+            rio::cpu_info(&CpuInfo {
+                num_cpus: 2.0,
+                normal: 20.0,
+                low: 5.5,
+                ..Default::default()
+            }),
+            rio::can_metrics(&CanMetrics {
+                utilization: 0.25,
+                ..Default::default()
+            }),
+        ];
+        let packets = [
+            rio::status_packet(0, Status::empty(), Trace::ROBOT_CODE, 12.5, false, &usage),
+            // The answer to control packet 1 was lost
+            rio::status_packet(2, Status::ENABLED, Trace::ROBOT_CODE, 11.75, false, &[]),
+        ];
+
+        for (row, packet) in packets.iter().enumerate() {
+            let received = async {
+                rio.send_datagram(packet).await.unwrap();
+                while ds.connection_stats().packets_received <= row as u64 {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            };
+            tokio::select! {
+                _ = ds.run() => unreachable!(),
+                _ = received => {}
+            }
+
+            // Real trip times depend on how fast the test runs
+            ds.trip_time
+                .store(Some(Duration::from_micros(4500 * (row as u64 + 1))));
+            chart
+                .write_row_at(&ds, Duration::from_millis(500 * row as u64))
+                .unwrap();
+        }
+        chart.flush().unwrap();
+        drop(chart);
+
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "time,voltage,trip_time_ms,lost_packets,cpu_percent,can_util_percent,status\n\
+             0.000,12.50,4.5,0,25.5,25.0,disabled\n\
+             0.500,11.75,9.0,1,25.5,25.0,enabled\n"
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+    time::SystemTime,
+};
+
+use super::{read_header, read_timestamp};
+
+/// A single message from a `.dsevents` file
+///
+/// The text is kept as written. The official DS wraps it in markup like
+/// `<TagVersion>1 <time> 12.345 <message> ...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DseventsRecord {
+    pub time: SystemTime,
+    pub text: String,
+}
+
+/// Reads records from a `.dsevents` file
+///
+/// Iterating yields every record in order, stopping at the end of the file
+/// or after the first error.
+pub struct DseventsReader<R: Read> {
+    input: BufReader<R>,
+    start: SystemTime,
+    failed: bool,
+}
+impl DseventsReader<File> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(File::open(path)?)
+    }
+}
+impl<R: Read> DseventsReader<R> {
+    /// Start reading a log, checking its header
+    pub fn new(input: R) -> io::Result<Self> {
+        let mut input = BufReader::new(input);
+        let start = read_header(&mut input)?;
+
+        Ok(Self {
+            input,
+            start,
+            failed: false,
+        })
+    }
+
+    /// When the log was started
+    #[inline(always)]
+    pub fn start_time(&self) -> SystemTime {
+        self.start
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<DseventsRecord>> {
+        // A clean end of file can only happen between records
+        if self.input.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+
+        let time = read_timestamp(&mut self.input)?;
+        let mut len = [0u8; 4];
+        self.input.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as u64;
+
+        // Don't trust the length enough to allocate it all up front
+        let mut text = Vec::new();
+        (&mut self.input).take(len).read_to_end(&mut text)?;
+        if (text.len() as u64) < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        Ok(Some(DseventsRecord {
+            time,
+            text: String::from_utf8_lossy(&text).into_owned(),
+        }))
+    }
+}
+impl<R: Read> Iterator for DseventsReader<R> {
+    type Item = io::Result<DseventsRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let res = self.read_record().transpose();
+        self.failed = matches!(res, Some(Err(_)));
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::log::{dslog::VERSION, labview_timestamp};
+
+    fn timestamp(time: SystemTime) -> Vec<u8> {
+        let (secs, frac) = labview_timestamp(time);
+        let mut buf = secs.to_be_bytes().to_vec();
+        buf.extend(frac.to_be_bytes());
+        buf
+    }
+
+    fn record(time: SystemTime, text: &str) -> Vec<u8> {
+        let mut buf = timestamp(time);
+        buf.extend((text.len() as u32).to_be_bytes());
+        buf.extend_from_slice(text.as_bytes());
+        buf
+    }
+
+    #[test]
+    fn reads_records_until_a_truncated_one() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let first = "<TagVersion>1 <time> 00.000 <message> Robot code started";
+        let second = "<TagVersion>1 <time> 01.500 <message> Brownout";
+
+        let mut log = VERSION.to_be_bytes().to_vec();
+        log.extend(timestamp(start));
+        log.extend(record(start, first));
+        log.extend(record(start + Duration::from_millis(1500), second));
+        let truncated = record(start, "never finished");
+        log.extend(&truncated[..truncated.len() - 4]);
+
+        let mut reader = DseventsReader::new(&log[..]).unwrap();
+        assert_eq!(reader.start_time(), start);
+        assert_eq!(
+            reader.next().unwrap().unwrap(),
+            DseventsRecord {
+                time: start,
+                text: first.to_owned(),
+            }
+        );
+        let record = reader.next().unwrap().unwrap();
+        assert_eq!(record.text, second);
+        // LabVIEW fractions only round trip to within a nanosecond
+        let offset = record.time.duration_since(start).unwrap();
+        assert!(offset.abs_diff(Duration::from_millis(1500)) < Duration::from_micros(1));
+
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let mut log = 3i32.to_be_bytes().to_vec();
+        log.extend(timestamp(UNIX_EPOCH));
+        assert!(DseventsReader::new(&log[..]).is_err());
+    }
+}
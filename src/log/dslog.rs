@@ -0,0 +1,403 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use super::{labview_timestamp, read_header};
+use crate::{Ds, RobotCodeMode, RobotStatus, timer::interval};
+
+/// How often the official DS writes a `.dslog` record
+pub const DSLOG_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The only `.dslog` version this reads and writes
+pub(super) const VERSION: i32 = 4;
+
+/// Power distribution type bytes
+const PD_NONE: u8 = 0;
+const PD_CTRE: u8 = 25;
+const PD_REV: u8 = 33;
+
+/// Size of the power distribution data after its header, by type
+const PD_CTRE_SIZE: usize = 25;
+const PD_REV_SIZE: usize = 33;
+
+bitflags! {
+    /// Status flags in a record
+    ///
+    /// On disk these are stored inverted, so a cleared bit means the flag is
+    /// set.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct DslogStatus: u8 {
+        const BROWNOUT       = 0b1000_0000;
+        const WATCHDOG       = 0b0100_0000;
+        const DS_TELEOP      = 0b0010_0000;
+        const DS_AUTO        = 0b0001_0000;
+        const DS_DISABLED    = 0b0000_1000;
+        const ROBOT_TELEOP   = 0b0000_0100;
+        const ROBOT_AUTO     = 0b0000_0010;
+        const ROBOT_DISABLED = 0b0000_0001;
+    }
+}
+
+/// Writes `.dslog` files the DS Log Viewer can open
+///
+/// Each record holds the trip time, packet loss, battery voltage, CPU and
+/// CAN utilization, and robot status. Packet loss is for the time since the
+/// last record, like the official DS logs it. Fields this crate doesn't
+/// track (WiFi and power distribution) are written as zero.
+pub struct DslogWriter<W: Write> {
+    out: BufWriter<W>,
+    /// Status packets received and lost as of the last record
+    last_counts: (u64, u64),
+}
+impl DslogWriter<File> {
+    /// Create a `.dslog` file at `path`, replacing it if it exists
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(File::create(path)?)
+    }
+}
+impl<W: Write> DslogWriter<W> {
+    /// Start a log, writing the header to `out`
+    pub fn new(out: W) -> io::Result<Self> {
+        let mut out = BufWriter::new(out);
+
+        let (secs, frac) = labview_timestamp(SystemTime::now());
+        out.write_all(&VERSION.to_be_bytes())?;
+        out.write_all(&secs.to_be_bytes())?;
+        out.write_all(&frac.to_be_bytes())?;
+
+        Ok(Self {
+            out,
+            last_counts: (0, 0),
+        })
+    }
+
+    /// Write a single record with the current state of `ds`
+    pub fn write_record(&mut self, ds: &Ds) -> io::Result<()> {
+        let status = ds.status();
+        let mode = ds.mode();
+
+        let mut flags = DslogStatus::empty();
+        if status == RobotStatus::BrownedOut {
+            flags |= DslogStatus::BROWNOUT;
+        }
+        // The DS and robot agree on the mode, since the robot reports back
+        // whatever it was told
+        match (status, mode) {
+            (RobotStatus::Enabled, RobotCodeMode::Autonomous) => {
+                flags |= DslogStatus::DS_AUTO | DslogStatus::ROBOT_AUTO
+            }
+            (RobotStatus::Enabled, _) => {
+                flags |= DslogStatus::DS_TELEOP | DslogStatus::ROBOT_TELEOP
+            }
+            _ => flags |= DslogStatus::DS_DISABLED | DslogStatus::ROBOT_DISABLED,
+        }
+
+        let stats = ds.connection_stats();
+        let (received, lost) = (
+            stats.packets_received.saturating_sub(self.last_counts.0),
+            stats.packets_lost.saturating_sub(self.last_counts.1),
+        );
+        self.last_counts = (stats.packets_received, stats.packets_lost);
+        let loss = if received + lost == 0 {
+            0.0
+        } else {
+            lost as f32 / (received + lost) as f32
+        };
+
+        let trip_ms = stats.trip_time.unwrap_or_default().as_secs_f32() * 1000.0;
+        let trip_time = (trip_ms * 2.0).clamp(0.0, 255.0) as u8;
+        let loss = (loss * 25.0).round() as u8;
+        let voltage = (ds.battery.load().clamp(0.0, 255.0) * 256.0) as u16;
+        let cpu = ds.cpu_info().map_or(0.0, |cpu| cpu.total());
+        let cpu = (cpu.clamp(0.0, 100.0) * 2.0) as u8;
+        let can_util = (ds.can_bus_util().clamp(0.0, 1.0) * 200.0) as u8;
+
+        // Trip time (0.5ms units) and packet loss (4% units)
+        self.out.write_all(&[trip_time, loss])?;
+        self.out.write_all(&voltage.to_be_bytes())?;
+        // CPU (0.5% units)
+        self.out.write_all(&[cpu])?;
+        self.out.write_all(&[!flags.bits()])?;
+        self.out.write_all(&[can_util])?;
+        // WiFi signal (0.5dB units) and bandwidth (Mb/s, 8.8 fixed point)
+        self.out.write_all(&[0, 0, 0])?;
+        // Power distribution: 3 unknown bytes, then the type
+        self.out.write_all(&[0, 0, 0, PD_NONE])?;
+
+        Ok(())
+    }
+
+    /// Write out anything still buffered
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Write a record of `ds` every [`DSLOG_INTERVAL`] until an IO error
+    /// occurs
+    pub async fn run(&mut self, ds: &Ds) -> io::Result<()> {
+        let mut interval = interval(DSLOG_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            self.write_record(ds)?;
+        }
+    }
+}
+
+/// Power distribution data attached to a record
+///
+/// Only the kind of device is decoded; the per-channel data is left as it
+/// was in the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PowerDistribution {
+    None,
+    /// CTRE Power Distribution Panel
+    Ctre(Vec<u8>),
+    /// REV Power Distribution Hub
+    Rev(Vec<u8>),
+}
+
+/// A single record from a `.dslog` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct DslogRecord {
+    pub time: SystemTime,
+    pub trip_time: Duration,
+    /// Fraction of packets lost, `0.0..=1.0`
+    pub packet_loss: f32,
+    pub voltage: f32,
+    /// roboRIO CPU usage, `0.0..=1.0`
+    pub cpu: f32,
+    pub status: DslogStatus,
+    /// CAN bus utilization, `0.0..=1.0`
+    pub can_util: f32,
+    /// Radio signal strength, in dB
+    pub wifi_db: f32,
+    /// Radio bandwidth, in Mb/s
+    pub bandwidth: f32,
+    pub power_distribution: PowerDistribution,
+}
+
+/// Reads records from a `.dslog` file
+///
+/// Iterating yields every record in order, stopping at the end of the file
+/// or after the first error.
+pub struct DslogReader<R: Read> {
+    input: BufReader<R>,
+    start: SystemTime,
+    index: u32,
+    failed: bool,
+}
+impl DslogReader<File> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(File::open(path)?)
+    }
+}
+impl<R: Read> DslogReader<R> {
+    /// Start reading a log, checking its header
+    pub fn new(input: R) -> io::Result<Self> {
+        let mut input = BufReader::new(input);
+        let start = read_header(&mut input)?;
+
+        Ok(Self {
+            input,
+            start,
+            index: 0,
+            failed: false,
+        })
+    }
+
+    /// When the log was started
+    #[inline(always)]
+    pub fn start_time(&self) -> SystemTime {
+        self.start
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<DslogRecord>> {
+        // A clean end of file can only happen between records
+        if self.input.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let mut buf = [0u8; 14];
+        self.input.read_exact(&mut buf)?;
+
+        let power_distribution = match buf[13] {
+            PD_CTRE => {
+                let mut data = vec![0; PD_CTRE_SIZE];
+                self.input.read_exact(&mut data)?;
+                PowerDistribution::Ctre(data)
+            }
+            PD_REV => {
+                let mut data = vec![0; PD_REV_SIZE];
+                self.input.read_exact(&mut data)?;
+                PowerDistribution::Rev(data)
+            }
+            _ => PowerDistribution::None,
+        };
+
+        let record = DslogRecord {
+            time: self.start + DSLOG_INTERVAL * self.index,
+            trip_time: Duration::from_micros(buf[0] as u64 * 500),
+            packet_loss: (buf[1] as i8 as f32 * 0.04).clamp(0.0, 1.0),
+            voltage: u16::from_be_bytes([buf[2], buf[3]]) as f32 / 256.0,
+            cpu: buf[4] as f32 * 0.005,
+            status: DslogStatus::from_bits_retain(!buf[5]),
+            can_util: buf[6] as f32 * 0.005,
+            wifi_db: buf[7] as f32 * 0.5,
+            bandwidth: u16::from_be_bytes([buf[8], buf[9]]) as f32 / 256.0,
+            power_distribution,
+        };
+        self.index += 1;
+
+        Ok(Some(record))
+    }
+}
+impl<R: Read> Iterator for DslogReader<R> {
+    type Item = io::Result<DslogRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let res = self.read_record().transpose();
+        self.failed = matches!(res, Some(Err(_)));
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{diagnostics::CpuInfo, transport::MemoryTransport};
+
+    /// A record with everything zero but the voltage and power distribution
+    /// type
+    fn raw_record(voltage: u16, pd_type: u8) -> Vec<u8> {
+        let mut record = vec![0, 0];
+        record.extend(voltage.to_be_bytes());
+        record.extend([0, 0xFF, 0, 0, 0, 0, 0, 0, 0, pd_type]);
+        record
+    }
+
+    #[test]
+    fn written_records_read_back() {
+        let ds = Ds::with_transport(4533, MemoryTransport::pair().0);
+        let mut log = Vec::new();
+        {
+            let mut writer = DslogWriter::new(&mut log).unwrap();
+
+            ds.status.store(RobotStatus::Enabled);
+            ds.mode.store(RobotCodeMode::Autonomous);
+            ds.battery.store(12.25);
+            writer.write_record(&ds).unwrap();
+
+            ds.status.store(RobotStatus::Disabled);
+            ds.battery.store(7.5);
+            writer.write_record(&ds).unwrap();
+            writer.flush().unwrap();
+        }
+
+        // Stored inverted, right after the 20 byte header and 5 bytes into
+        // the record
+        let auto = DslogStatus::DS_AUTO | DslogStatus::ROBOT_AUTO;
+        assert_eq!(log[20 + 5], !auto.bits());
+
+        let reader = DslogReader::new(&log[..]).unwrap();
+        let start = reader.start_time();
+        let records: Vec<_> = reader.collect::<io::Result<_>>().unwrap();
+        assert_eq!(records.len(), 2);
+
+        assert_eq!(records[0].time, start);
+        assert_eq!(records[0].status, auto);
+        assert_eq!(records[0].voltage, 12.25);
+        assert_eq!(records[0].power_distribution, PowerDistribution::None);
+
+        assert_eq!(records[1].time, start + DSLOG_INTERVAL);
+        assert_eq!(
+            records[1].status,
+            DslogStatus::DS_DISABLED | DslogStatus::ROBOT_DISABLED
+        );
+        assert_eq!(records[1].voltage, 7.5);
+    }
+
+    #[test]
+    fn reader_skips_power_distribution_data() {
+        let mut log = Vec::new();
+        DslogWriter::new(&mut log).unwrap().flush().unwrap();
+
+        log.extend(raw_record(12 * 256, PD_CTRE));
+        log.extend([1; PD_CTRE_SIZE]);
+        log.extend(raw_record(11 * 256, PD_REV));
+        log.extend([2; PD_REV_SIZE]);
+        log.extend(raw_record(10 * 256, PD_NONE));
+        // Cut off partway through a record
+        log.extend(&raw_record(9 * 256, PD_NONE)[..6]);
+
+        let mut reader = DslogReader::new(&log[..]).unwrap();
+        let ctre = reader.next().unwrap().unwrap();
+        assert_eq!(ctre.voltage, 12.0);
+        assert_eq!(
+            ctre.power_distribution,
+            PowerDistribution::Ctre(vec![1; PD_CTRE_SIZE])
+        );
+        assert_eq!(ctre.status, DslogStatus::empty());
+
+        let rev = reader.next().unwrap().unwrap();
+        assert_eq!(rev.voltage, 11.0);
+        assert_eq!(
+            rev.power_distribution,
+            PowerDistribution::Rev(vec![2; PD_REV_SIZE])
+        );
+
+        let none = reader.next().unwrap().unwrap();
+        assert_eq!(none.voltage, 10.0);
+        assert_eq!(none.power_distribution, PowerDistribution::None);
+
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn connection_and_cpu_are_logged() {
+        let ds = Ds::with_transport(4533, MemoryTransport::pair().0);
+        let mut log = Vec::new();
+        {
+            let mut writer = DslogWriter::new(&mut log).unwrap();
+
+            // 1 of 4 status packets lost
+            for seqnum in [0, 1, 3] {
+                ds.count_status_packet(seqnum);
+            }
+            ds.trip_time.store(Some(Duration::from_millis(12)));
+            ds.cpu_info.store(Some(CpuInfo {
+                num_cpus: 2.0,
+                normal: 30.0,
+                low: 10.0,
+                ..Default::default()
+            }));
+            writer.write_record(&ds).unwrap();
+
+            // Nothing lost since the last record
+            ds.count_status_packet(4);
+            writer.write_record(&ds).unwrap();
+            writer.flush().unwrap();
+        }
+
+        let records: Vec<_> = DslogReader::new(&log[..])
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+
+        assert_eq!(records[0].trip_time, Duration::from_millis(12));
+        // Rounded to the nearest 4%
+        assert!(close(records[0].packet_loss, 0.24));
+        assert!(close(records[0].cpu, 0.4));
+
+        assert!(close(records[1].packet_loss, 0.0));
+        assert!(close(records[1].cpu, 0.4));
+    }
+}
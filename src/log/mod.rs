@@ -0,0 +1,89 @@
+//! Logs compatible with the official driver station's log files
+//!
+//! The official DS writes a `.dslog` file with robot telemetry sampled every
+//! 20ms, which the DS Log Viewer (and tools like AdvantageScope) can open,
+//! and a `.dsevents` file with timestamped messages. Both start with the same
+//! header: a version number and the time the log was started.
+//!
+//! [`ChartExporter`] writes what the DS chart tab shows as CSV instead, for
+//! opening in a spreadsheet. With the `sqlite` feature, `TelemetryDb`
+//! keeps telemetry and console output from many matches in one database.
+
+mod chart;
+mod dsevents;
+mod dslog;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use chart::ChartExporter;
+pub use dsevents::{DseventsReader, DseventsRecord};
+pub use dslog::{
+    DSLOG_INTERVAL, DslogReader, DslogRecord, DslogStatus, DslogWriter, PowerDistribution,
+};
+#[cfg(feature = "sqlite")]
+pub use sqlite::{Session, StoredConsoleLine, StoredSample, TelemetryDb};
+
+use std::{
+    io::{self, Read},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::RobotStatus;
+
+/// Seconds from the LabVIEW epoch (1904-01-01) to the Unix epoch
+const LABVIEW_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// Encode a time as a LabVIEW timestamp: whole seconds since 1904, then the
+/// fraction of a second in units of 2^-64
+fn labview_timestamp(time: SystemTime) -> (i64, u64) {
+    // Clocks set before 1970 aren't worth handling
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (secs, nanos) = (since.as_secs() as i64, since.subsec_nanos());
+
+    let frac = ((nanos as u128) << 64) / 1_000_000_000;
+    (secs + LABVIEW_EPOCH_OFFSET, frac as u64)
+}
+
+/// Decode a LabVIEW timestamp
+fn from_labview_timestamp(secs: i64, frac: u64) -> SystemTime {
+    let nanos = ((frac as u128 * 1_000_000_000) >> 64) as u64;
+    let since = Duration::from_secs((secs - LABVIEW_EPOCH_OFFSET).max(0) as u64)
+        + Duration::from_nanos(nanos);
+    UNIX_EPOCH + since
+}
+
+/// Read a LabVIEW timestamp (16 bytes, big endian)
+fn read_timestamp(input: &mut impl Read) -> io::Result<SystemTime> {
+    let mut buf = [0u8; 16];
+    input.read_exact(&mut buf)?;
+
+    let secs = i64::from_be_bytes(buf[..8].try_into().unwrap());
+    let frac = u64::from_be_bytes(buf[8..].try_into().unwrap());
+    Ok(from_labview_timestamp(secs, frac))
+}
+
+/// Read a log header, returning when the log was started
+fn read_header(input: &mut impl Read) -> io::Result<SystemTime> {
+    let mut version = [0u8; 4];
+    input.read_exact(&mut version)?;
+    if i32::from_be_bytes(version) != dslog::VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unsupported log version",
+        ));
+    }
+
+    read_timestamp(input)
+}
+
+/// How a status is written in exported logs
+const fn status_name(status: RobotStatus) -> &'static str {
+    match status {
+        RobotStatus::NoCommunication => "no-communication",
+        RobotStatus::NoRobotCode => "no-robot-code",
+        RobotStatus::EStopped => "estopped",
+        RobotStatus::BrownedOut => "browned-out",
+        RobotStatus::Disabled => "disabled",
+        RobotStatus::Enabled => "enabled",
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{Connection, params};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    Ds, MatchType,
+    console::{ConsoleLevel, ConsoleLine},
+    event::DsEvent,
+    telemetry::Telemetry,
+    timer::interval,
+};
+
+use super::status_name;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY,
+    started_ms INTEGER NOT NULL,
+    team INTEGER NOT NULL,
+    competition TEXT NOT NULL,
+    match_type INTEGER NOT NULL,
+    match_number INTEGER NOT NULL,
+    replay_number INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS samples (
+    session_id INTEGER NOT NULL REFERENCES sessions(id),
+    time_ms INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    voltage REAL NOT NULL,
+    cpu_percent REAL,
+    can_util REAL,
+    packets_lost INTEGER NOT NULL,
+    brownouts INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS console (
+    session_id INTEGER NOT NULL REFERENCES sessions(id),
+    time_ms INTEGER NOT NULL,
+    level TEXT NOT NULL,
+    message TEXT NOT NULL,
+    error_code INTEGER
+);
+CREATE INDEX IF NOT EXISTS samples_by_session ON samples(session_id, time_ms);
+CREATE INDEX IF NOT EXISTS console_by_session ON console(session_id, time_ms);
+";
+
+/// A stretch of time recorded with one team, in one match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub id: i64,
+    pub started: SystemTime,
+    pub team: u16,
+    pub competition: String,
+    pub match_type: MatchType,
+    pub match_number: u16,
+    pub replay_number: u8,
+}
+
+/// A telemetry sample, as stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredSample {
+    pub time: SystemTime,
+    pub status: String,
+    pub voltage: f32,
+    pub cpu_percent: Option<f32>,
+    /// CAN bus utilization, from 0 to 1
+    pub can_util: Option<f32>,
+    pub packets_lost: u64,
+    pub brownouts: u32,
+}
+
+/// A console line or error, as stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredConsoleLine {
+    pub time: SystemTime,
+    pub level: String,
+    pub message: String,
+    pub error_code: Option<i32>,
+}
+
+/// Keeps telemetry and console output in a SQLite database, across many
+/// sessions
+///
+/// Each [`run`](Self::run) is a new session, tagged with the team and match,
+/// so a whole season can go in one file.
+pub struct TelemetryDb {
+    conn: Connection,
+}
+impl TelemetryDb {
+    /// Open the database at `path`, creating it if it doesn't exist
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// Keep everything in memory, mostly for trying things out
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Start a session for `ds`'s team and current match, returning its id
+    pub fn start_session(&self, ds: &Ds) -> rusqlite::Result<i64> {
+        let info = ds.match_info();
+        self.conn.execute(
+            "INSERT INTO sessions
+                (started_ms, team, competition, match_type, match_number, replay_number)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                unix_ms(SystemTime::now()),
+                ds.team_number(),
+                info.competition,
+                info.match_type as u8,
+                info.match_number,
+                info.replay_number,
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn record_sample(&self, session: i64, telemetry: &Telemetry) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO samples
+                (session_id, time_ms, status, voltage, cpu_percent, can_util, packets_lost, brownouts)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                session,
+                unix_ms(SystemTime::now()),
+                status_name(telemetry.status),
+                telemetry.battery_voltage,
+                telemetry.cpu.map(|cpu| cpu.total()),
+                telemetry.can.map(|can| can.utilization),
+                telemetry.connection.packets_lost,
+                telemetry.brownouts,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_console_line(&self, session: i64, line: &ConsoleLine) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO console (session_id, time_ms, level, message, error_code)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                session,
+                unix_ms(line.received),
+                level_name(line.level),
+                line.message,
+                line.error_code,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Record a new session of `ds`, sampling telemetry every `period` and
+    /// keeping every console line, until a database error occurs
+    ///
+    /// This takes `&mut self` so the future can be spawned, since a SQLite
+    /// connection can't be shared between threads.
+    pub async fn run(&mut self, ds: &Ds, period: Duration) -> rusqlite::Result<()> {
+        let session = self.start_session(ds)?;
+        let mut events = ds.subscribe();
+        let mut interval = interval(period);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => self.record_sample(session, &ds.telemetry())?,
+                event = events.recv() => match event {
+                    Ok(DsEvent::ConsoleLine(line)) => self.record_console_line(session, &line)?,
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    // The DS is gone
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+            }
+        }
+    }
+
+    /// Get every session, oldest first
+    pub fn sessions(&self) -> rusqlite::Result<Vec<Session>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("{SELECT_SESSIONS} ORDER BY id"))?;
+        stmt.query_map([], session_from_row)?.collect()
+    }
+
+    /// Get the sessions for one team's matches at an event, oldest first
+    pub fn sessions_for(&self, team: u16, competition: &str) -> rusqlite::Result<Vec<Session>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{SELECT_SESSIONS} WHERE team = ?1 AND competition = ?2 ORDER BY id"
+        ))?;
+        stmt.query_map(params![team, competition], session_from_row)?
+            .collect()
+    }
+
+    /// Get every sample from a session, oldest first
+    pub fn samples(&self, session: i64) -> rusqlite::Result<Vec<StoredSample>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT time_ms, status, voltage, cpu_percent, can_util, packets_lost, brownouts
+                FROM samples WHERE session_id = ?1 ORDER BY time_ms",
+        )?;
+        stmt.query_map([session], |row| {
+            Ok(StoredSample {
+                time: from_unix_ms(row.get(0)?),
+                status: row.get(1)?,
+                voltage: row.get(2)?,
+                cpu_percent: row.get(3)?,
+                can_util: row.get(4)?,
+                packets_lost: row.get(5)?,
+                brownouts: row.get(6)?,
+            })
+        })?
+        .collect()
+    }
+
+    /// Get every console line from a session, oldest first
+    pub fn console_lines(&self, session: i64) -> rusqlite::Result<Vec<StoredConsoleLine>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT time_ms, level, message, error_code
+                FROM console WHERE session_id = ?1 ORDER BY time_ms",
+        )?;
+        stmt.query_map([session], |row| {
+            Ok(StoredConsoleLine {
+                time: from_unix_ms(row.get(0)?),
+                level: row.get(1)?,
+                message: row.get(2)?,
+                error_code: row.get(3)?,
+            })
+        })?
+        .collect()
+    }
+
+    /// Get the lowest voltage and most brownouts seen in a session, for
+    /// spotting bad batteries and matches at a glance
+    pub fn session_summary(&self, session: i64) -> rusqlite::Result<(Option<f32>, u32)> {
+        self.conn.query_row(
+            "SELECT MIN(voltage), COALESCE(MAX(brownouts), 0)
+                FROM samples WHERE session_id = ?1 AND status != 'no-communication'",
+            [session],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+    }
+}
+
+const SELECT_SESSIONS: &str = "SELECT id, started_ms, team, competition, match_type, match_number, replay_number FROM sessions";
+
+fn session_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Session> {
+    Ok(Session {
+        id: row.get(0)?,
+        started: from_unix_ms(row.get(1)?),
+        team: row.get(2)?,
+        competition: row.get(3)?,
+        match_type: MatchType::from_u8(row.get(4)?),
+        match_number: row.get(5)?,
+        replay_number: row.get(6)?,
+    })
+}
+
+fn unix_ms(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+fn from_unix_ms(ms: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(ms.max(0) as u64)
+}
+
+const fn level_name(level: ConsoleLevel) -> &'static str {
+    match level {
+        ConsoleLevel::Info => "info",
+        ConsoleLevel::Warning => "warning",
+        ConsoleLevel::Error => "error",
+    }
+}
@@ -0,0 +1,28 @@
+//! Counting down the time left in a match period
+//!
+//! While a countdown is running, every control packet carries the seconds
+//! left, which is what robot code sees through `Timer.getMatchTime()`.
+
+use std::time::{Duration, Instant};
+
+use crate::Ds;
+
+impl Ds {
+    /// Start counting down `remaining` in every control packet
+    ///
+    /// Replaces any countdown already running.
+    pub fn set_match_time(&self, remaining: Duration) {
+        self.match_deadline.store(Some(Instant::now() + remaining));
+    }
+
+    /// Stop sending a countdown
+    pub fn clear_match_time(&self) {
+        self.match_deadline.store(None);
+    }
+
+    /// Get the time left in the current match period, if counting down
+    pub fn match_time(&self) -> Option<Duration> {
+        let deadline = self.match_deadline.load()?;
+        Some(deadline.saturating_duration_since(Instant::now()))
+    }
+}
@@ -0,0 +1,132 @@
+//! Publishing telemetry and events to an MQTT broker, for pit displays and
+//! anything else already listening there
+//!
+//! Telemetry snapshots are published as JSON every period, retained so new
+//! subscribers get the latest one right away. Events are published as JSON
+//! as they happen.
+//!
+//! ```no_run
+//! # async fn run(ds: &robudst::Ds) {
+//! use robudst::mqtt::{MqttPublisher, MqttTopics};
+//! use rumqttc::MqttOptions;
+//!
+//! let options = MqttOptions::new("robudst", "pit-pi.local", 1883);
+//! MqttPublisher::new(options, MqttTopics::for_team(4533)).run(ds).await;
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, ClientError, EventLoop, MqttOptions, QoS};
+use tokio::{
+    sync::broadcast::error::RecvError,
+    time::{interval, sleep},
+};
+
+use crate::{Ds, trace::Level};
+
+/// How often telemetry is published by default
+pub const DEFAULT_MQTT_PERIOD: Duration = Duration::from_millis(500);
+
+/// How long to wait before reconnecting to the broker
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// How many messages can wait to be sent to the broker
+const REQUEST_CAPACITY: usize = 64;
+
+/// Where to publish
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttTopics {
+    pub telemetry: String,
+    pub events: String,
+}
+impl MqttTopics {
+    /// Publish under `robudst/<team>/`
+    pub fn for_team(team_number: u16) -> Self {
+        Self::with_prefix(format!("robudst/{team_number}"))
+    }
+
+    /// Publish to `<prefix>/telemetry` and `<prefix>/events`
+    pub fn with_prefix(prefix: impl AsRef<str>) -> Self {
+        let prefix = prefix.as_ref().trim_end_matches('/');
+        Self {
+            telemetry: format!("{prefix}/telemetry"),
+            events: format!("{prefix}/events"),
+        }
+    }
+}
+
+/// Publishes a [`Ds`]'s telemetry and events to an MQTT broker
+pub struct MqttPublisher {
+    client: AsyncClient,
+    event_loop: EventLoop,
+    topics: MqttTopics,
+    period: Duration,
+}
+impl MqttPublisher {
+    pub fn new(options: MqttOptions, topics: MqttTopics) -> Self {
+        let (client, event_loop) = AsyncClient::new(options, REQUEST_CAPACITY);
+        Self {
+            client,
+            event_loop,
+            topics,
+            period: DEFAULT_MQTT_PERIOD,
+        }
+    }
+
+    /// Publish telemetry every `period` instead of the default
+    pub const fn period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    /// Publish until `ds` is dropped
+    ///
+    /// The broker is reconnected to if the connection drops. Messages that
+    /// can't be queued while it's down are dropped rather than piling up,
+    /// since newer telemetry is coming anyway.
+    pub async fn run(mut self, ds: &Ds) {
+        let mut events = ds.subscribe();
+        let mut interval = interval(self.period);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    self.publish(&self.topics.telemetry, true, &ds.telemetry());
+                }
+                event = events.recv() => match event {
+                    Ok(event) => self.publish(&self.topics.events, false, &event),
+                    Err(RecvError::Lagged(missed)) => {
+                        event!(Level::WARN, missed, "MQTT publisher fell behind on events");
+                    }
+                    // The DS is gone
+                    Err(RecvError::Closed) => return,
+                },
+                polled = self.event_loop.poll() => {
+                    if let Err(e) = polled {
+                        event!(Level::WARN, error = %e, "MQTT connection failed");
+                        sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        }
+    }
+
+    fn publish(&self, topic: &str, retain: bool, value: &impl serde::Serialize) {
+        let payload = match serde_json::to_vec(value) {
+            Ok(payload) => payload,
+            Err(e) => {
+                event!(Level::ERROR, error = %e, "Couldn't serialize MQTT payload");
+                return;
+            }
+        };
+
+        match self
+            .client
+            .try_publish(topic, QoS::AtMostOnce, retain, payload)
+        {
+            Ok(()) | Err(ClientError::TryRequest(_)) => {}
+            Err(e) => event!(Level::WARN, error = %e, "Couldn't publish to MQTT"),
+        }
+    }
+}
@@ -0,0 +1,514 @@
+//! Publishing the DS's view of the match to NetworkTables, like the official
+//! DS does
+//!
+//! Robot code and dashboard widgets read the `FMSInfo` table to find out the
+//! alliance, match, and game data. [`Nt4Publisher`] connects to the NT4
+//! server in robot code and keeps that table up to date.
+//!
+//! Only the parts of NT4 needed to publish are spoken: topics are announced
+//! over JSON, values are sent as MessagePack, and the clock is synced once
+//! on connect so timestamps make sense to the server. The one exception is
+//! the [`AutoSelector`], which follows a `SendableChooser` in robot code.
+//!
+//! ```no_run
+//! # async fn run(ds: &robudst::Ds) -> std::io::Result<()> {
+//! use robudst::nt4::Nt4Publisher;
+//!
+//! Nt4Publisher::new("10.45.33.2").run(ds).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Reference: <https://github.com/wpilibsuite/allwpilib/blob/main/ntcore/doc/networktables4.adoc>
+
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use tokio::time::{interval, timeout};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{Message, client::IntoClientRequest, http::HeaderValue},
+};
+
+use crate::{AlliancePos, Ds, Error, RobotCodeMode, RobotStatus, trace::Level};
+
+/// The port NT4 servers listen on
+pub const NT4_PORT: u16 = 5810;
+
+/// How often the table is checked for changes by default
+pub const DEFAULT_NT4_PERIOD: Duration = Duration::from_millis(100);
+
+const SUBPROTOCOL: &str = "v4.1.networktables.first.wpi.edu";
+
+/// How long the server gets to answer the time sync
+const SYNC_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The id NT4 reserves for time sync messages
+const RTT_PUBUID: i64 = -1;
+
+/// The pubuid for an [`AutoSelector`]'s selection, after the `FMSInfo`
+/// topics
+const SELECTED_PUBUID: i64 = TOPICS.len() as i64;
+
+/// What `SendableChooser` calls the robot's chooser by default
+pub const DEFAULT_AUTO_CHOOSER: &str = "Auto Chooser";
+
+/// NT4 type ids for values the [`AutoSelector`] reads
+const STRING_TYPE: u8 = 4;
+const STRING_ARRAY_TYPE: u8 = 20;
+
+/// `FMSControlData` bits, as robot code reads them
+mod control {
+    pub const ENABLED: i64 = 0x01;
+    pub const AUTONOMOUS: i64 = 0x02;
+    pub const TEST: i64 = 0x04;
+    pub const ESTOP: i64 = 0x08;
+    pub const FMS_ATTACHED: i64 = 0x10;
+    pub const DS_ATTACHED: i64 = 0x20;
+}
+
+/// A value of one of the NetworkTables types the DS publishes
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Boolean(bool),
+    Double(f64),
+    Int(i64),
+    String(String),
+}
+impl Value {
+    const fn type_name(&self) -> &'static str {
+        match self {
+            Self::Boolean(_) => "boolean",
+            Self::Double(_) => "double",
+            Self::Int(_) => "int",
+            Self::String(_) => "string",
+        }
+    }
+
+    const fn type_id(&self) -> u8 {
+        match self {
+            Self::Boolean(_) => 0,
+            Self::Double(_) => 1,
+            Self::Int(_) => 2,
+            Self::String(_) => 4,
+        }
+    }
+
+    /// Append a value update for `pubuid`, as a MessagePack array
+    fn encode(&self, pubuid: i64, timestamp: i64, out: &mut Vec<u8>) {
+        // Writing to a Vec can't fail
+        rmp::encode::write_array_len(out, 4).unwrap();
+        rmp::encode::write_sint(out, pubuid).unwrap();
+        rmp::encode::write_sint(out, timestamp).unwrap();
+        rmp::encode::write_uint(out, self.type_id().into()).unwrap();
+        match self {
+            Self::Boolean(value) => rmp::encode::write_bool(out, *value).unwrap(),
+            Self::Double(value) => rmp::encode::write_f64(out, *value).unwrap(),
+            Self::Int(value) => {
+                rmp::encode::write_sint(out, *value).unwrap();
+            }
+            Self::String(value) => rmp::encode::write_str(out, value).unwrap(),
+        }
+    }
+}
+
+/// Topic names, in pubuid order
+///
+/// `MatchTime` isn't one robot code reads (it gets the time from control
+/// packets), but dashboards find it handy.
+const TOPICS: [&str; 10] = [
+    "/FMSInfo/.type",
+    "/FMSInfo/GameSpecificMessage",
+    "/FMSInfo/EventName",
+    "/FMSInfo/MatchNumber",
+    "/FMSInfo/ReplayNumber",
+    "/FMSInfo/MatchType",
+    "/FMSInfo/IsRedAlliance",
+    "/FMSInfo/StationNumber",
+    "/FMSInfo/FMSControlData",
+    "/FMSInfo/MatchTime",
+];
+
+/// Get the value of every topic, in [`TOPICS`] order
+fn fms_info(ds: &Ds) -> [Value; TOPICS.len()] {
+    let info = ds.match_info();
+    let (red, station) = match ds.alliance() {
+        AlliancePos::Red(station) => (true, station),
+        AlliancePos::Blue(station) => (false, station),
+    };
+
+    let status = ds.status();
+    let mut control = 0;
+    if status == RobotStatus::Enabled {
+        control |= control::ENABLED;
+    }
+    match ds.mode() {
+        RobotCodeMode::Autonomous => control |= control::AUTONOMOUS,
+        RobotCodeMode::Test => control |= control::TEST,
+        RobotCodeMode::Teleop => {}
+    }
+    if ds.is_estopped() {
+        control |= control::ESTOP;
+    }
+    if ds.is_fms_connected() {
+        control |= control::FMS_ATTACHED;
+    }
+    if status != RobotStatus::NoCommunication {
+        control |= control::DS_ATTACHED;
+    }
+
+    [
+        Value::String("FMSInfo".into()),
+        Value::String(ds.game_data()),
+        Value::String(info.competition),
+        Value::Int(info.match_number.into()),
+        Value::Int(info.replay_number.into()),
+        Value::Int(info.match_type as i64),
+        Value::Boolean(red),
+        Value::Int(station.into()),
+        Value::Int(control),
+        Value::Double(ds.match_time().map_or(-1.0, |time| time.as_secs_f64())),
+    ]
+}
+
+/// Picks the autonomous routine from a `SendableChooser` in robot code
+///
+/// Robot code publishes the options under `/SmartDashboard/<chooser>/`, and
+/// reads back whatever is published as `selected`. Give the selector to an
+/// [`Nt4Publisher`] to follow the chooser; the selection is published again
+/// every time the publisher connects, so it survives robot reboots.
+///
+/// Check [`is_confirmed`](Self::is_confirmed) before enabling to be sure the
+/// robot will run the chosen routine.
+pub struct AutoSelector {
+    chooser: String,
+    state: Mutex<ChooserState>,
+}
+
+#[derive(Default)]
+struct ChooserState {
+    options: Vec<String>,
+    default: Option<String>,
+    active: Option<String>,
+    selected: Option<String>,
+}
+
+impl AutoSelector {
+    /// Follow the chooser published as `/SmartDashboard/<chooser>`
+    pub fn new(chooser: impl Into<String>) -> Arc<Self> {
+        Arc::new(Self {
+            chooser: chooser.into(),
+            state: Mutex::default(),
+        })
+    }
+
+    /// The options the robot offers, once it's published them
+    pub fn options(&self) -> Vec<String> {
+        self.state.lock().unwrap().options.clone()
+    }
+
+    /// The option the robot runs if nothing is selected
+    pub fn default_option(&self) -> Option<String> {
+        self.state.lock().unwrap().default.clone()
+    }
+
+    /// The option the robot says it will run
+    pub fn active(&self) -> Option<String> {
+        self.state.lock().unwrap().active.clone()
+    }
+
+    /// The option chosen with [`select`](Self::select)
+    pub fn selected(&self) -> Option<String> {
+        self.state.lock().unwrap().selected.clone()
+    }
+
+    /// Choose `option` for the robot to run
+    ///
+    /// Once the robot has published its options, anything else is rejected.
+    pub fn select(&self, option: impl Into<String>) -> Result<(), Error> {
+        let option = option.into();
+        let mut state = self.state.lock().unwrap();
+        if !state.options.is_empty() && !state.options.contains(&option) {
+            return Err(Error::UnknownAutoOption);
+        }
+
+        state.selected = Some(option);
+        Ok(())
+    }
+
+    /// Whether the robot has picked up the selection
+    pub fn is_confirmed(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.selected.is_some() && state.selected == state.active
+    }
+
+    fn prefix(&self) -> String {
+        format!("/SmartDashboard/{}/", self.chooser)
+    }
+
+    /// Keep a value the robot published under the chooser
+    fn update(&self, key: &str, value: ChooserValue) {
+        let mut state = self.state.lock().unwrap();
+        match (key, value) {
+            ("options", ChooserValue::Strings(options)) => state.options = options,
+            ("default", ChooserValue::String(default)) => state.default = Some(default),
+            ("active", ChooserValue::String(active)) => state.active = Some(active),
+            _ => {}
+        }
+    }
+}
+
+/// A value published under a chooser
+enum ChooserValue {
+    String(String),
+    Strings(Vec<String>),
+}
+
+/// Keeps the `FMSInfo` table on an NT4 server up to date
+pub struct Nt4Publisher {
+    url: String,
+    period: Duration,
+    auto_selector: Option<Arc<AutoSelector>>,
+}
+impl Nt4Publisher {
+    /// Publish to the NT4 server at `host`, usually the roboRIO
+    pub fn new(host: impl AsRef<str>) -> Self {
+        Self::with_url(format!("ws://{}:{NT4_PORT}/nt/robudst", host.as_ref()))
+    }
+
+    /// Publish to an NT4 server at a full WebSocket URL
+    pub fn with_url(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            period: DEFAULT_NT4_PERIOD,
+            auto_selector: None,
+        }
+    }
+
+    /// Follow a `SendableChooser` and publish what's selected with `selector`
+    pub fn auto_selector(mut self, selector: Arc<AutoSelector>) -> Self {
+        self.auto_selector = Some(selector);
+        self
+    }
+
+    /// Check for changes every `period` instead of the default
+    pub const fn period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    /// Connect and publish until the connection drops
+    pub async fn run(&self, ds: &Ds) -> io::Result<()> {
+        let mut request = self
+            .url
+            .as_str()
+            .into_client_request()
+            .map_err(io::Error::other)?;
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static(SUBPROTOCOL),
+        );
+
+        let (mut ws, _) = connect_async(request).await.map_err(io::Error::other)?;
+        event!(Level::INFO, url = %self.url, "Connected to NT4 server");
+
+        let initial = fms_info(ds);
+        let mut announce = TOPICS
+            .iter()
+            .zip(&initial)
+            .enumerate()
+            .map(|(pubuid, (name, value))| {
+                json!({
+                    "method": "publish",
+                    "params": {
+                        "name": name,
+                        "pubuid": pubuid,
+                        "type": value.type_name(),
+                        "properties": { "retained": true },
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+        if let Some(selector) = &self.auto_selector {
+            let prefix = selector.prefix();
+            announce.push(json!({
+                "method": "publish",
+                "params": {
+                    "name": format!("{prefix}selected"),
+                    "pubuid": SELECTED_PUBUID,
+                    "type": "string",
+                    "properties": {},
+                },
+            }));
+            announce.push(json!({
+                "method": "subscribe",
+                "params": {
+                    "topics": [prefix],
+                    "subuid": 0,
+                    "options": { "prefix": true },
+                },
+            }));
+        }
+        ws.send(Message::text(serde_json::Value::from(announce).to_string()))
+            .await
+            .map_err(io::Error::other)?;
+
+        // Offset from our clock to the server's, in microseconds
+        let start = Instant::now();
+        let now_us = || start.elapsed().as_micros() as i64;
+        let sent_at = now_us();
+        let mut ping = Vec::new();
+        Value::Int(sent_at).encode(RTT_PUBUID, 0, &mut ping);
+        ws.send(Message::binary(ping))
+            .await
+            .map_err(io::Error::other)?;
+
+        let server_time = timeout(SYNC_TIMEOUT, async {
+            while let Some(msg) = ws.next().await {
+                if let Message::Binary(data) = msg.map_err(io::Error::other)?
+                    && let Some(server_time) = read_rtt_reply(&data)
+                {
+                    return Ok(server_time);
+                }
+            }
+            Err(io::Error::from(io::ErrorKind::UnexpectedEof))
+        })
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "NT4 time sync timed out"))??;
+        let received_at = now_us();
+        let offset = server_time + (received_at - sent_at) / 2 - received_at;
+
+        let mut last: Option<[Value; TOPICS.len()]> = None;
+        let mut last_selected = None;
+        // Names of the chooser topics the server announced, by id
+        let mut chooser_topics = HashMap::new();
+        let mut interval = interval(self.period);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let values = fms_info(ds);
+                    let timestamp = now_us() + offset;
+
+                    let mut frame = Vec::new();
+                    for (pubuid, value) in values.iter().enumerate() {
+                        if last.as_ref().is_none_or(|last| last[pubuid] != *value) {
+                            value.encode(pubuid as i64, timestamp, &mut frame);
+                        }
+                    }
+                    if let Some(selector) = &self.auto_selector {
+                        let selected = selector.selected();
+                        if let Some(option) = &selected
+                            && selected != last_selected
+                        {
+                            Value::String(option.clone()).encode(SELECTED_PUBUID, timestamp, &mut frame);
+                        }
+                        last_selected = selected;
+                    }
+
+                    if !frame.is_empty() {
+                        ws.send(Message::binary(frame)).await.map_err(io::Error::other)?;
+                    }
+                    last = Some(values);
+                }
+                msg = ws.next() => match msg {
+                    Some(msg) => {
+                        let msg = msg.map_err(io::Error::other)?;
+                        if let Some(selector) = &self.auto_selector {
+                            follow_chooser(selector, &mut chooser_topics, msg);
+                        }
+                    }
+                    None => return Err(io::ErrorKind::ConnectionAborted.into()),
+                },
+            }
+        }
+    }
+}
+
+/// Keep what the server says about `selector`'s chooser
+///
+/// Anything else is pings, which tungstenite answers itself, and topics that
+/// aren't subscribed to.
+fn follow_chooser(selector: &AutoSelector, topics: &mut HashMap<i64, String>, msg: Message) {
+    let prefix = selector.prefix();
+    match msg {
+        Message::Text(text) => {
+            let Ok(serde_json::Value::Array(messages)) = serde_json::from_str(&text) else {
+                return;
+            };
+            for msg in messages {
+                let params = &msg["params"];
+                if msg["method"] == "announce"
+                    && let (Some(id), Some(name)) = (params["id"].as_i64(), params["name"].as_str())
+                    && let Some(key) = name.strip_prefix(&prefix)
+                {
+                    topics.insert(id, key.to_owned());
+                }
+            }
+        }
+        Message::Binary(data) => {
+            let data = &mut &data[..];
+            while !data.is_empty() {
+                let Some((id, value)) = read_chooser_value(data) else {
+                    // Can't tell where the next message starts
+                    return;
+                };
+                if let (Some(key), Some(value)) = (topics.get(&id), value) {
+                    selector.update(key, value);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Read a value update, keeping the value if it's a type choosers use
+fn read_chooser_value(data: &mut &[u8]) -> Option<(i64, Option<ChooserValue>)> {
+    if rmp::decode::read_array_len(data).ok()? != 4 {
+        return None;
+    }
+    let id: i64 = rmp::decode::read_int(data).ok()?;
+    let _timestamp: i64 = rmp::decode::read_int(data).ok()?;
+    let value = match rmp::decode::read_int(data).ok()? {
+        STRING_TYPE => ChooserValue::String(read_string(data)?),
+        STRING_ARRAY_TYPE => {
+            let len = rmp::decode::read_array_len(data).ok()?;
+            ChooserValue::Strings((0..len).map(|_| read_string(data)).collect::<Option<_>>()?)
+        }
+        // Like `.controllable` and `.instance`, which aren't needed
+        0 => {
+            rmp::decode::read_bool(data).ok()?;
+            return Some((id, None));
+        }
+        2 => {
+            rmp::decode::read_int::<i64, _>(data).ok()?;
+            return Some((id, None));
+        }
+        _ => return None,
+    };
+    Some((id, Some(value)))
+}
+
+fn read_string(data: &mut &[u8]) -> Option<String> {
+    let len = rmp::decode::read_str_len(data).ok()? as usize;
+    let bytes = data.get(..len)?;
+    *data = &data[len..];
+    String::from_utf8(bytes.to_vec()).ok()
+}
+
+/// Get the server time from a reply to a time sync, if that's what `data`
+/// starts with
+fn read_rtt_reply(mut data: &[u8]) -> Option<i64> {
+    let data = &mut data;
+    if rmp::decode::read_array_len(data).ok()? != 4 {
+        return None;
+    }
+    let pubuid: i64 = rmp::decode::read_int(data).ok()?;
+    let server_time: i64 = rmp::decode::read_int(data).ok()?;
+    (pubuid == RTT_PUBUID).then_some(server_time)
+}
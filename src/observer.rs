@@ -0,0 +1,167 @@
+//! Watching another driver station's robot without ever sending to it
+//!
+//! An [`ObserverTransport`] feeds a [`Ds`](crate::Ds) what the roboRIO sends
+//! and throws away everything the DS would send back, so the usual getters,
+//! [`telemetry`](crate::Ds::telemetry), and [events](crate::event) all work
+//! for a robot someone else is driving. Handy for mentors keeping an eye on
+//! a student-driven robot, or for a CSA debugging one on the field.
+//!
+//! The roboRIO only sends to the driver station controlling it, so its
+//! traffic has to be seen some other way: on a mirrored switch port with a
+//! live capture, or by binding the DS port on a machine the traffic reaches.
+//!
+//! ```no_run
+//! # async fn run(capture: impl tokio::io::AsyncRead + Unpin + Send + 'static) -> std::io::Result<()> {
+//! // capture: tcpdump -i eth0 -U -w - udp port 1150 or tcp port 1740
+//! use robudst::{Ds, observer::ObserverTransport};
+//!
+//! let transport = ObserverTransport::from_pcap_stream(capture);
+//! let ds = Ds::with_transport(4533, transport);
+//! ds.run().await;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+
+use tokio::{
+    io::AsyncRead,
+    sync::{
+        Mutex,
+        mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+    },
+    task::JoinHandle,
+};
+
+use crate::{
+    replay::{Channel, Direction, pcap::read_pcap_stream},
+    trace::Level,
+    transport::{Transport, TransportFuture},
+};
+
+/// A transport that only listens
+///
+/// Sends always succeed without going anywhere. The byte stream only has
+/// anything in it when observing a capture, since TCP can't be picked up by
+/// just binding a port.
+pub struct ObserverTransport {
+    datagram_rx: Mutex<UnboundedReceiver<Vec<u8>>>,
+    /// Stream data, and whatever didn't fit in the last read
+    stream_rx: Mutex<(UnboundedReceiver<Vec<u8>>, Vec<u8>)>,
+    /// Kept open when there's no stream, so the DS doesn't see it close
+    _stream_tx: Option<UnboundedSender<Vec<u8>>>,
+    reader: JoinHandle<()>,
+}
+impl ObserverTransport {
+    /// Listen for status packets on `addr`, usually port
+    /// [`DS_UDP_PORT`](crate::transport::DS_UDP_PORT)
+    #[cfg(all(feature = "runtime-tokio", not(target_arch = "wasm32")))]
+    pub async fn bind(addr: std::net::SocketAddr) -> io::Result<Self> {
+        let socket = tokio::net::UdpSocket::bind(addr).await?;
+        let (datagram_tx, datagram_rx) = unbounded_channel();
+        let (stream_tx, stream_rx) = unbounded_channel();
+
+        let reader = tokio::spawn(async move {
+            let mut buf = [0; 1024];
+            loop {
+                match socket.recv(&mut buf).await {
+                    Ok(len) => {
+                        if datagram_tx.send(buf[..len].to_vec()).is_err() {
+                            return;
+                        }
+                    }
+                    Err(err) => {
+                        event!(Level::WARN, %err, "Observer socket failed");
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            datagram_rx: Mutex::new(datagram_rx),
+            stream_rx: Mutex::new((stream_rx, Vec::new())),
+            _stream_tx: Some(stream_tx),
+            reader,
+        })
+    }
+
+    /// Follow a live pcap capture, like `tcpdump -U -w -` writes
+    ///
+    /// Both status packets and the roboRIO's TCP stream (console output,
+    /// errors, versions) are picked up. Once the capture ends, the DS sees
+    /// the connection close.
+    pub fn from_pcap_stream(reader: impl AsyncRead + Unpin + Send + 'static) -> Self {
+        let (datagram_tx, datagram_rx) = unbounded_channel();
+        let (stream_tx, stream_rx) = unbounded_channel();
+
+        let reader = tokio::spawn(async move {
+            let res = read_pcap_stream(reader, |packet| {
+                if packet.direction != Direction::FromRobot {
+                    return;
+                }
+                // Nobody receiving means the transport is being dropped
+                let _ = match packet.channel {
+                    Channel::Udp => datagram_tx.send(packet.data),
+                    Channel::Tcp => stream_tx.send(packet.data),
+                };
+            })
+            .await;
+            event!(Level::INFO, ?res, "Observed capture ended");
+        });
+
+        Self {
+            datagram_rx: Mutex::new(datagram_rx),
+            stream_rx: Mutex::new((stream_rx, Vec::new())),
+            _stream_tx: None,
+            reader,
+        }
+    }
+}
+impl Drop for ObserverTransport {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+impl Transport for ObserverTransport {
+    fn send_datagram<'a>(&'a self, _buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn recv_datagram<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        Box::pin(async move {
+            let datagram = self
+                .datagram_rx
+                .lock()
+                .await
+                .recv()
+                .await
+                .ok_or(io::ErrorKind::ConnectionAborted)?;
+            let len = datagram.len().min(buf.len());
+            buf[..len].copy_from_slice(&datagram[..len]);
+            Ok(len)
+        })
+    }
+
+    fn write_stream<'a>(&'a self, _buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn read_stream<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        Box::pin(async move {
+            let mut stream = self.stream_rx.lock().await;
+            let (rx, leftover) = &mut *stream;
+            if leftover.is_empty() {
+                match rx.recv().await {
+                    Some(data) => *leftover = data,
+                    None => return Ok(0),
+                }
+            }
+
+            let len = leftover.len().min(buf.len());
+            buf[..len].copy_from_slice(&leftover[..len]);
+            leftover.drain(..len);
+            Ok(len)
+        })
+    }
+}
@@ -0,0 +1,294 @@
+//! Running several robots at once, for demo fields and multi-robot labs
+//!
+//! A [`DsPool`] spawns a driver station per team and keeps track of them by
+//! team number. Everything can be enabled, disabled, or stopped together,
+//! and [`DsPool::subscribe`] gives one stream of every robot's events.
+//!
+//! Every robot sends its status packets to the same port on this machine.
+//! [`SocketTransport`](crate::transport::SocketTransport)s share the socket
+//! for it, and sort the packets by the address they came from, so each
+//! roboRIO needs an address of its own.
+//!
+//! ```no_run
+//! # async fn run() {
+//! use robudst::{Ds, pool::DsPool};
+//!
+//! let mut pool = DsPool::new();
+//! for team in [4533, 254, 1678] {
+//!     pool.add(Ds::init(team).await).await;
+//! }
+//!
+//! let mut events = pool.subscribe();
+//! for (team, err) in pool.enable_all().await {
+//!     println!("{team} didn't enable: {err:?}");
+//! }
+//! while let Ok(event) = events.recv().await {
+//!     println!("{}: {:?}", event.team_number, event.event);
+//! }
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    task::JoinHandle,
+};
+
+use crate::{Ds, Error, event::DsEvent, handle::DsHandle, trace::Level};
+
+/// How many events a slow subscriber can fall behind before missing some,
+/// shared between every robot in the pool
+const POOL_EVENT_CAPACITY: usize = 256;
+
+/// A [`DsEvent`] from one of the robots in a [`DsPool`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TeamEvent {
+    pub team_number: u16,
+    pub event: DsEvent,
+}
+
+/// A spawned driver station, and the tasks that go with it
+struct Member {
+    ds: DsHandle,
+    task: JoinHandle<()>,
+    forwarder: JoinHandle<()>,
+}
+
+/// Driver stations for several robots, keyed by team number
+pub struct DsPool {
+    members: BTreeMap<u16, Member>,
+    events: broadcast::Sender<TeamEvent>,
+}
+impl DsPool {
+    pub fn new() -> Self {
+        Self {
+            members: BTreeMap::new(),
+            events: broadcast::channel(POOL_EVENT_CAPACITY).0,
+        }
+    }
+
+    /// Spawn `ds` and add it to the pool under its team number
+    ///
+    /// A driver station already in the pool for the same team is shut down
+    /// and replaced.
+    pub async fn add(&mut self, ds: Ds) -> DsHandle {
+        let team_number = ds.team_number();
+        if let Some(old) = self.remove(team_number) {
+            event!(Level::INFO, team_number, "Replacing driver station in pool");
+            old.shutdown().await;
+        }
+
+        let mut ds_events = ds.subscribe();
+        let (ds, task) = ds.spawn();
+
+        let events = self.events.clone();
+        let forwarder = tokio::spawn(async move {
+            loop {
+                match ds_events.recv().await {
+                    Ok(event) => {
+                        // Nobody listening is fine
+                        let _ = events.send(TeamEvent { team_number, event });
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        event!(
+                            Level::WARN,
+                            team_number,
+                            missed,
+                            "Pool fell behind on events"
+                        );
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+
+        self.members.insert(
+            team_number,
+            Member {
+                ds: ds.clone(),
+                task,
+                forwarder,
+            },
+        );
+        ds
+    }
+
+    /// Take `team_number`'s driver station out of the pool, leaving it
+    /// running
+    ///
+    /// Its events stop showing up in the pool's stream.
+    pub fn remove(&mut self, team_number: u16) -> Option<DsHandle> {
+        let member = self.members.remove(&team_number)?;
+        member.forwarder.abort();
+        Some(member.ds)
+    }
+
+    /// Get `team_number`'s driver station
+    pub fn get(&self, team_number: u16) -> Option<&DsHandle> {
+        self.members.get(&team_number).map(|member| &member.ds)
+    }
+
+    /// Team numbers in the pool, in order
+    pub fn teams(&self) -> impl Iterator<Item = u16> + '_ {
+        self.members.keys().copied()
+    }
+
+    /// Every driver station in the pool, ordered by team number
+    pub fn iter(&self) -> impl Iterator<Item = &DsHandle> {
+        self.members.values().map(|member| &member.ds)
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Get notified of every robot's [`DsEvent`]s from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<TeamEvent> {
+        self.events.subscribe()
+    }
+
+    /// Enable every robot
+    ///
+    /// Robots that can't be enabled (see [`Ds::enable`]) are skipped, and
+    /// returned with the reason.
+    pub async fn enable_all(&self) -> Vec<(u16, Error)> {
+        let mut failed = Vec::new();
+        for (&team_number, member) in &self.members {
+            if let Err(err) = member.ds.enable().await {
+                failed.push((team_number, err));
+            }
+        }
+        failed
+    }
+
+    /// Disable every robot
+    pub async fn disable_all(&self) {
+        for member in self.members.values() {
+            member.ds.disable().await;
+        }
+    }
+
+    /// Trigger an emergency stop on every robot
+    pub async fn estop_all(&self) {
+        event!(
+            Level::WARN,
+            teams = self.members.len(),
+            "Emergency stopping pool"
+        );
+        for member in self.members.values() {
+            member.ds.estop().await;
+        }
+    }
+
+    /// Shut down every driver station and empty the pool
+    ///
+    /// See [`Ds::shutdown`].
+    pub async fn shutdown_all(&mut self) {
+        for (_, member) in std::mem::take(&mut self.members) {
+            member.ds.shutdown().await;
+            let _ = member.task.await;
+            member.forwarder.abort();
+        }
+    }
+}
+impl Default for DsPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Drop for DsPool {
+    fn drop(&mut self) {
+        for member in self.members.values() {
+            member.forwarder.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, time::Duration};
+
+    use tokio::net::{TcpListener, UdpSocket};
+
+    use super::*;
+    use crate::{
+        proto::incoming::udp::{Status, Trace},
+        test_support::rio,
+        transport::{Ports, SocketTransport},
+    };
+
+    /// A pretend roboRIO on its own loopback address
+    struct FakeRio {
+        _tcp: TcpListener,
+        udp: UdpSocket,
+        ports: Ports,
+    }
+    impl FakeRio {
+        async fn bind(addr: IpAddr, ds_udp: u16) -> Self {
+            let tcp = TcpListener::bind((addr, 0)).await.unwrap();
+            let udp = UdpSocket::bind((addr, 0)).await.unwrap();
+            let ports = Ports {
+                rio_tcp: tcp.local_addr().unwrap().port(),
+                rio_udp: udp.local_addr().unwrap().port(),
+                ds_udp,
+            };
+            Self {
+                _tcp: tcp,
+                udp,
+                ports,
+            }
+        }
+
+        /// Keep sending status packets until `ds` sees `battery`
+        async fn send_until_seen(&self, ds: &DsHandle, battery: f32) {
+            let packet =
+                rio::status_packet(0, Status::empty(), Trace::empty(), battery, false, &[]);
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while ds.battery_voltage() != battery {
+                    self.udp
+                        .send_to(&packet, ("127.0.0.1", self.ports.ds_udp))
+                        .await
+                        .unwrap();
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("status packet never arrived");
+        }
+    }
+
+    #[tokio::test]
+    async fn robots_get_their_own_status_packets() {
+        let ds_udp = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let rio_a = FakeRio::bind([127, 0, 0, 2].into(), ds_udp).await;
+        let rio_b = FakeRio::bind([127, 0, 0, 3].into(), ds_udp).await;
+
+        let mut pool = DsPool::new();
+        for (team, rio) in [(4533, &rio_a), (254, &rio_b)] {
+            let addr = rio.udp.local_addr().unwrap().ip();
+            let transport = SocketTransport::connect_with_ports(addr, rio.ports)
+                .await
+                .unwrap();
+            pool.add(Ds::with_transport(team, transport)).await;
+        }
+        let (a, b) = (pool.get(4533).unwrap(), pool.get(254).unwrap());
+
+        rio_a.send_until_seen(a, 12.5).await;
+        assert_eq!(b.battery_voltage(), 0.0);
+        rio_b.send_until_seen(b, 11.0).await;
+        assert_eq!(a.battery_voltage(), 12.5);
+
+        pool.shutdown_all().await;
+        assert!(pool.is_empty());
+    }
+}
@@ -0,0 +1,226 @@
+//! Running practice matches, like the official DS practice mode
+
+use std::time::Duration;
+
+use crate::{Ds, Error, RobotCodeMode, RobotStatus, event::DsEvent, timer::sleep, trace::Level};
+
+/// How long each part of a match lasts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchTiming {
+    pub autonomous: Duration,
+    /// Disabled period between autonomous and teleop
+    pub pause: Duration,
+    pub teleop: Duration,
+    /// How much of the end of teleop counts as the endgame
+    pub endgame: Duration,
+}
+impl Default for MatchTiming {
+    fn default() -> Self {
+        Self {
+            autonomous: Duration::from_secs(15),
+            pause: Duration::from_secs(3),
+            teleop: Duration::from_secs(135),
+            endgame: Duration::from_secs(20),
+        }
+    }
+}
+impl MatchTiming {
+    /// Get the phase `elapsed` into a match, and the time left in the
+    /// current period (autonomous or teleop)
+    pub fn phase_at(&self, elapsed: Duration) -> (MatchPhase, Duration) {
+        let Self {
+            autonomous,
+            pause,
+            teleop,
+            endgame,
+        } = *self;
+
+        if elapsed < autonomous {
+            (MatchPhase::Autonomous, autonomous - elapsed)
+        } else if elapsed < autonomous + pause {
+            (MatchPhase::Pause, teleop)
+        } else if elapsed < autonomous + pause + teleop {
+            let remaining = autonomous + pause + teleop - elapsed;
+            if remaining <= endgame {
+                (MatchPhase::Endgame, remaining)
+            } else {
+                (MatchPhase::Teleop, remaining)
+            }
+        } else {
+            (MatchPhase::PostMatch, Duration::ZERO)
+        }
+    }
+}
+
+/// Where a match is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MatchPhase {
+    PreMatch,
+    Autonomous,
+    Pause,
+    Teleop,
+    Endgame,
+    PostMatch,
+}
+impl MatchPhase {
+    #[inline(always)]
+    pub const fn is_enabled(self) -> bool {
+        matches!(self, Self::Autonomous | Self::Teleop | Self::Endgame)
+    }
+}
+
+/// Disables the robot if a match sequence is dropped partway through
+struct SequenceGuard<'d>(&'d Ds);
+impl Drop for SequenceGuard<'_> {
+    fn drop(&mut self) {
+        let ds = self.0;
+        if ds.match_phase() == MatchPhase::PostMatch {
+            return;
+        }
+
+        // Can't send from here, but the next control packet will carry it
+        if ds.status() == RobotStatus::Enabled {
+            ds.status.store(RobotStatus::Disabled);
+        }
+        ds.clear_match_time();
+        ds.match_phase.store(MatchPhase::PostMatch);
+        ds.emit(DsEvent::MatchPhaseChanged(MatchPhase::PostMatch));
+    }
+}
+
+impl Ds {
+    /// Get the phase of the running match sequence
+    #[inline(always)]
+    pub fn match_phase(&self) -> MatchPhase {
+        self.match_phase.load()
+    }
+
+    /// Run a whole practice match: autonomous, a disabled pause, then teleop
+    /// with an endgame
+    ///
+    /// The mode, enable state, and match countdown are switched at each
+    /// boundary, and a [`DsEvent::MatchPhaseChanged`] is emitted for each
+    /// phase. Dropping the future ends the match early and disables the
+    /// robot.
+    ///
+    /// If the robot can't be enabled for a phase (see [`Ds::enable`]), the
+    /// match ends there with the reason, rather than carrying on disabled.
+    pub async fn run_match_sequence(&self, timing: MatchTiming) -> Result<(), Error> {
+        let _guard = SequenceGuard(self);
+
+        self.enter_phase(MatchPhase::Autonomous, timing.autonomous)
+            .await?;
+        sleep(timing.autonomous).await;
+
+        self.enter_phase(MatchPhase::Pause, timing.teleop).await?;
+        sleep(timing.pause).await;
+
+        self.enter_phase(MatchPhase::Teleop, timing.teleop).await?;
+        sleep(timing.teleop.saturating_sub(timing.endgame)).await;
+
+        let endgame = timing.endgame.min(timing.teleop);
+        self.enter_phase(MatchPhase::Endgame, endgame).await?;
+        sleep(endgame).await;
+
+        self.enter_phase(MatchPhase::PostMatch, Duration::ZERO)
+            .await
+    }
+
+    async fn enter_phase(&self, phase: MatchPhase, remaining: Duration) -> Result<(), Error> {
+        match phase {
+            MatchPhase::Autonomous => self.mode.store(RobotCodeMode::Autonomous),
+            MatchPhase::Teleop => self.mode.store(RobotCodeMode::Teleop),
+            _ => {}
+        }
+
+        match phase {
+            MatchPhase::PreMatch | MatchPhase::PostMatch => self.clear_match_time(),
+            _ => self.set_match_time(remaining),
+        }
+
+        self.match_phase.store(phase);
+        event!(Level::INFO, ?phase, "Match phase changed");
+        self.emit(DsEvent::MatchPhaseChanged(phase));
+
+        match (phase.is_enabled(), self.status()) {
+            (true, RobotStatus::Disabled) => {
+                if let Err(err) = self.enable().await {
+                    event!(Level::WARN, ?phase, ?err, "Couldn't enable for match phase");
+                    return Err(err);
+                }
+            }
+            (false, RobotStatus::Enabled) => self.disable().await,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MemoryTransport;
+
+    const fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn phases_change_at_the_boundaries() {
+        let timing = MatchTiming::default();
+
+        assert_eq!(
+            timing.phase_at(Duration::ZERO),
+            (MatchPhase::Autonomous, secs(15))
+        );
+        assert_eq!(
+            timing.phase_at(secs(15) - Duration::from_millis(1)),
+            (MatchPhase::Autonomous, Duration::from_millis(1))
+        );
+        // The pause counts down teleop, which hasn't started yet
+        assert_eq!(timing.phase_at(secs(15)), (MatchPhase::Pause, secs(135)));
+        assert_eq!(timing.phase_at(secs(18)), (MatchPhase::Teleop, secs(135)));
+        assert_eq!(timing.phase_at(secs(132)), (MatchPhase::Teleop, secs(21)));
+        assert_eq!(timing.phase_at(secs(133)), (MatchPhase::Endgame, secs(20)));
+        assert_eq!(
+            timing.phase_at(secs(153)),
+            (MatchPhase::PostMatch, Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn endgame_longer_than_teleop_is_all_endgame() {
+        let timing = MatchTiming {
+            teleop: secs(10),
+            endgame: secs(30),
+            ..Default::default()
+        };
+
+        assert_eq!(timing.phase_at(secs(18)), (MatchPhase::Endgame, secs(10)));
+        assert_eq!(timing.phase_at(secs(27)), (MatchPhase::Endgame, secs(1)));
+        assert_eq!(
+            timing.phase_at(secs(28)),
+            (MatchPhase::PostMatch, Duration::ZERO)
+        );
+    }
+
+    #[tokio::test]
+    async fn refused_enable_ends_the_match() {
+        let ds = Ds::with_transport(4533, MemoryTransport::pair().0);
+        // As if the robot were connected
+        ds.status.store(RobotStatus::Disabled);
+        ds.set_joystick_interlock(Some(secs(1)));
+        let timing = MatchTiming {
+            autonomous: Duration::from_millis(10),
+            pause: Duration::from_millis(10),
+            ..Default::default()
+        };
+
+        // Autonomous doesn't need joysticks, but teleop does
+        let res = ds.run_match_sequence(timing).await;
+        assert!(matches!(res, Err(Error::NoJoystickInput)));
+        assert_eq!(ds.match_phase(), MatchPhase::PostMatch);
+        assert_eq!(ds.status(), RobotStatus::Disabled);
+    }
+}
@@ -0,0 +1,149 @@
+//! [`tokio_util::codec`] implementations for the roboRIO protocol
+//!
+//! These let the DS side of the protocol be used with
+//! [`Framed`](tokio_util::codec::Framed) and
+//! [`UdpFramed`](tokio_util::udp::UdpFramed) directly, without a [`Ds`](crate::Ds).
+
+use std::{io, sync::Arc};
+
+use bytes::{Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::{
+    outgoing::{tcp::TcpOutgoingTag, udp::UdpOutgoingPacket},
+    version::{Frc2015, ProtocolVersion, StatusReport},
+};
+
+/// Splits the roboRIO's TCP stream into tags, and encodes tags to send to it
+///
+/// Each decoded frame is one whole tag, including its 2 byte size prefix.
+/// Bytes of a tag that hasn't fully arrived yet are kept until the rest
+/// shows up.
+#[derive(Debug, Clone)]
+pub struct TcpTagCodec {
+    protocol: Arc<dyn ProtocolVersion>,
+}
+impl TcpTagCodec {
+    pub fn new(protocol: impl ProtocolVersion + 'static) -> Self {
+        Self::from_arc(Arc::new(protocol))
+    }
+
+    pub(crate) fn from_arc(protocol: Arc<dyn ProtocolVersion>) -> Self {
+        Self { protocol }
+    }
+}
+impl Default for TcpTagCodec {
+    fn default() -> Self {
+        Self::new(Frc2015)
+    }
+}
+impl Decoder for TcpTagCodec {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if src.len() < 2 {
+            return Ok(None);
+        }
+
+        let size = u16::from_be_bytes([src[0], src[1]]) as usize;
+        if src.len() < 2 + size {
+            src.reserve(2 + size - src.len());
+            return Ok(None);
+        }
+
+        Ok(Some(src.split_to(2 + size).freeze()))
+    }
+}
+impl Encoder<TcpOutgoingTag<'_>> for TcpTagCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, tag: TcpOutgoingTag<'_>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.protocol.write_tcp(tag, dst);
+        Ok(())
+    }
+}
+
+/// Decodes status packets from the roboRIO, and encodes control packets to
+/// send to it
+///
+/// Datagrams that aren't valid status packets are skipped.
+#[derive(Debug, Clone)]
+pub struct UdpPacketCodec {
+    protocol: Arc<dyn ProtocolVersion>,
+}
+impl UdpPacketCodec {
+    pub fn new(protocol: impl ProtocolVersion + 'static) -> Self {
+        Self {
+            protocol: Arc::new(protocol),
+        }
+    }
+}
+impl Default for UdpPacketCodec {
+    fn default() -> Self {
+        Self::new(Frc2015)
+    }
+}
+impl Decoder for UdpPacketCodec {
+    type Item = StatusReport;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // Every datagram is exactly one packet
+        if src.is_empty() {
+            return Ok(None);
+        }
+        let datagram = src.split();
+
+        Ok(self.protocol.read_status(&datagram))
+    }
+}
+impl Encoder<UdpOutgoingPacket<'_>> for UdpPacketCodec {
+    type Error = io::Error;
+
+    fn encode(
+        &mut self,
+        pkt: UdpOutgoingPacket<'_>,
+        dst: &mut BytesMut,
+    ) -> Result<(), Self::Error> {
+        self.protocol.write_control(pkt, dst);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tcp_decoder_waits_for_whole_frames() {
+        let tag = [0x00, 0x05, 0x04, 0x00, 0x01, 0x00, 0x02];
+        let mut codec = TcpTagCodec::default();
+        let mut buf = BytesMut::new();
+
+        buf.extend_from_slice(&tag[..1]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(&tag[1..5]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(&tag[5..]);
+        buf.extend_from_slice(&tag[..3]);
+        assert_eq!(codec.decode(&mut buf).unwrap().as_deref(), Some(&tag[..]));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(&buf[..], &tag[..3]);
+    }
+
+    #[test]
+    fn udp_decoder_skips_bad_datagrams() {
+        let mut codec = UdpPacketCodec::default();
+
+        let mut buf = BytesMut::from(&[0x00, 0x01, 0x01][..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert!(buf.is_empty());
+
+        let mut buf = BytesMut::from(&[0x00, 0x2A, 0x01, 0x04, 0x20, 0x0C, 0x80, 0x00][..]);
+        let status = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(status.seqnum, 42);
+        assert_eq!(status.battery, 12.5);
+        assert!(buf.is_empty());
+    }
+}
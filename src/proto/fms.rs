@@ -0,0 +1,356 @@
+//! Packets exchanged between the driver station and the field management
+//! system (FMS)
+//!
+//! The FMS sends control packets to each DS over UDP, and the DS answers with
+//! its status. A TCP connection carries everything else, like the team
+//! number and game data.
+//!
+//! Cheesy Arena, the usual off-season FMS, speaks mostly the same protocol
+//! with a few differences in the TCP tags; see [`FmsDialect`].
+
+use std::net::Ipv4Addr;
+
+use robudst_proto::tag::Tag;
+
+use super::outgoing::udp::Control;
+use crate::{AlliancePos, MatchType};
+
+/// Address of the FMS on a competition field
+pub const FMS_ADDR: Ipv4Addr = Ipv4Addr::new(10, 0, 100, 5);
+/// Port the FMS accepts DS TCP connections on
+pub const FMS_TCP_PORT: u16 = 1750;
+/// Port the FMS receives DS status packets on
+pub const FMS_UDP_PORT: u16 = 1160;
+/// Port the DS receives FMS control packets on
+pub const DS_FMS_UDP_PORT: u16 = 1121;
+
+/// Which FMS implementation is on the other end
+///
+/// Both use the same tag ids. Cheesy Arena prefixes game data with its
+/// length, and drops driver stations that don't send a keepalive tag every
+/// few seconds.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FmsDialect {
+    #[default]
+    Official,
+    CheesyArena,
+}
+
+/// Control packet sent from the FMS to a driver station
+#[derive(Debug, Clone, Copy)]
+pub struct FmsControlPacket {
+    pub seqnum: u16,
+    pub control: Control,
+    pub station: AlliancePos,
+    pub match_type: MatchType,
+    pub match_number: u16,
+    pub play_number: u8,
+    /// Seconds left in the current match period
+    pub remaining_time: u16,
+}
+impl FmsControlPacket {
+    /// 2 bytes for seqnum, 1 byte each for comm version, control, request,
+    /// station, and match type, 2 bytes for match number, 1 byte for play
+    /// number, 10 bytes of date, and 2 bytes for remaining time
+    const SIZE: usize = 22;
+
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < Self::SIZE {
+            return None;
+        }
+
+        let seqnum = u16::from_be_bytes([buf[0], buf[1]]);
+        let control = Control::from_bits_truncate(buf[3]);
+        let station = AlliancePos::from_station(buf[5])?;
+        let match_type = MatchType::from_u8(buf[6]);
+        let match_number = u16::from_be_bytes([buf[7], buf[8]]);
+        let play_number = buf[9];
+        // The date (10 bytes) is only used by the official DS to set the
+        // laptop's clock
+        let remaining_time = u16::from_be_bytes([buf[20], buf[21]]);
+
+        Some(Self {
+            seqnum,
+            control,
+            station,
+            match_type,
+            match_number,
+            play_number,
+            remaining_time,
+        })
+    }
+
+    pub fn write(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::SIZE);
+
+        buf.extend(self.seqnum.to_be_bytes());
+        buf.push(0x00);
+        buf.push(self.control.bits());
+        buf.push(0x00);
+        buf.push(self.station.to_pos());
+        buf.push(self.match_type as u8);
+        buf.extend(self.match_number.to_be_bytes());
+        buf.push(self.play_number);
+        buf.extend([0; 10]);
+        buf.extend(self.remaining_time.to_be_bytes());
+
+        buf
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct DsStatus: u8 {
+        const ESTOP       = 0b1000_0000;
+        const ROBOT_COMMS = 0b0010_0000;
+        const RADIO_PING  = 0b0001_0000;
+        const RIO_PING    = 0b0000_1000;
+        const ENABLED     = 0b0000_0100;
+
+        // Mode flags
+        const TELEOP = 0b00;
+        const TEST   = 0b01;
+        const AUTO   = 0b10;
+    }
+}
+
+/// Status packet sent from a driver station to the FMS
+#[derive(Debug, Clone, Copy)]
+pub struct DsStatusPacket {
+    pub seqnum: u16,
+    pub status: DsStatus,
+    pub team_number: u16,
+    pub battery: f32,
+}
+impl DsStatusPacket {
+    /// 2 bytes for seqnum, 1 byte each for comm version and status, 2 bytes
+    /// for team number, and 2 bytes for battery voltage
+    const SIZE: usize = 8;
+
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < Self::SIZE {
+            return None;
+        }
+
+        Some(Self {
+            seqnum: u16::from_be_bytes([buf[0], buf[1]]),
+            status: DsStatus::from_bits_truncate(buf[3]),
+            team_number: u16::from_be_bytes([buf[4], buf[5]]),
+            battery: buf[6] as f32 + buf[7] as f32 / 256.0,
+        })
+    }
+
+    pub fn write(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::SIZE);
+
+        buf.extend(self.seqnum.to_be_bytes());
+        buf.push(0x00);
+        buf.push(self.status.bits());
+        buf.extend(self.team_number.to_be_bytes());
+        buf.push(self.battery as u8);
+        buf.push((self.battery.fract() * 256.0) as u8);
+
+        buf
+    }
+}
+
+/// Whether the FMS considers the DS to be in the right station
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum StationStatus {
+    Good = 0,
+    Bad = 1,
+    Waiting = 2,
+}
+
+/// TCP tags sent from the FMS to a driver station
+#[derive(Debug)]
+pub enum FmsTcpTag<'t> {
+    EventCode(&'t str),
+    StationInfo {
+        station: AlliancePos,
+        status: StationStatus,
+    },
+    GameData(&'t str),
+}
+
+/// TCP tags sent from a driver station to the FMS
+#[derive(Debug)]
+pub enum DsTcpTag {
+    TeamNumber(u16),
+    /// Sent periodically so Cheesy Arena knows the DS is still there
+    Keepalive,
+}
+
+/// Write a TCP frame: a 2 byte size (including the id), the id, then the data
+fn write_frame(id: u8, data: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(3 + data.len());
+
+    buf.extend((data.len() as u16 + 1).to_be_bytes());
+    buf.push(id);
+    buf.extend_from_slice(data);
+
+    buf
+}
+
+/// Split a complete TCP frame off the front of `buf`, returning its tag and
+/// the number of bytes it took up, or `None` if more bytes are needed
+///
+/// Frames with a size of zero are keepalives, and have no tag.
+#[cfg_attr(
+    any(not(feature = "runtime-tokio"), target_arch = "wasm32"),
+    allow(dead_code)
+)]
+pub(crate) fn read_frame(buf: &[u8]) -> Option<(Option<Tag<'_>>, usize)> {
+    let (size, rest) = buf.split_first_chunk::<2>()?;
+    let size = u16::from_be_bytes(*size) as usize;
+    let frame = rest.get(..size)?;
+
+    let tag = frame.split_first().map(|(&id, data)| Tag { id, data });
+    Some((tag, 2 + size))
+}
+
+impl<'t> FmsTcpTag<'t> {
+    /// Parse a single tag from its id and data
+    ///
+    /// Returns `None` for unknown or malformed tags.
+    pub fn parse(id: u8, data: &'t [u8], dialect: FmsDialect) -> Option<Self> {
+        match (dialect, id) {
+            // Event code
+            (_, 0x14) => {
+                let (&len, code) = data.split_first()?;
+                let code = code.get(..len as usize)?;
+                Some(Self::EventCode(core::str::from_utf8(code).ok()?))
+            }
+
+            // Station info
+            (_, 0x19) => {
+                let station = AlliancePos::from_station(*data.first()?)?;
+                let status = match data.get(1)? {
+                    0 => StationStatus::Good,
+                    1 => StationStatus::Bad,
+                    _ => StationStatus::Waiting,
+                };
+                Some(Self::StationInfo { station, status })
+            }
+
+            // Game data
+            (FmsDialect::Official, 0x1C) => Some(Self::GameData(core::str::from_utf8(data).ok()?)),
+            (FmsDialect::CheesyArena, 0x1C) => {
+                let (&len, game_data) = data.split_first()?;
+                let game_data = game_data.get(..len as usize)?;
+                Some(Self::GameData(core::str::from_utf8(game_data).ok()?))
+            }
+
+            _ => None,
+        }
+    }
+
+    pub fn write(&self, dialect: FmsDialect) -> Vec<u8> {
+        match self {
+            Self::EventCode(code) => {
+                let mut data = vec![code.len() as u8];
+                data.extend_from_slice(code.as_bytes());
+                write_frame(0x14, &data)
+            }
+            Self::StationInfo { station, status } => {
+                write_frame(0x19, &[station.to_pos(), *status as u8])
+            }
+            Self::GameData(game_data) => match dialect {
+                FmsDialect::Official => write_frame(0x1C, game_data.as_bytes()),
+                FmsDialect::CheesyArena => {
+                    let mut data = vec![game_data.len() as u8];
+                    data.extend_from_slice(game_data.as_bytes());
+                    write_frame(0x1C, &data)
+                }
+            },
+        }
+    }
+}
+
+impl DsTcpTag {
+    /// Parse a single tag from its id and data
+    ///
+    /// Returns `None` for unknown or malformed tags.
+    pub fn parse(id: u8, data: &[u8]) -> Option<Self> {
+        match id {
+            // Team number
+            0x18 => {
+                let team = data.get(..2)?;
+                Some(Self::TeamNumber(u16::from_be_bytes([team[0], team[1]])))
+            }
+
+            // Keepalive
+            0x1C => Some(Self::Keepalive),
+
+            _ => None,
+        }
+    }
+
+    pub fn write(&self) -> Vec<u8> {
+        match self {
+            Self::TeamNumber(team) => write_frame(0x18, &team.to_be_bytes()),
+            Self::Keepalive => write_frame(0x1C, &[]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_use_the_documented_ids() {
+        // Event code "CALG", then station info for blue 2 in the right
+        // station
+        let buf = [
+            0x00, 0x06, 0x14, 0x04, b'C', b'A', b'L', b'G', 0x00, 0x03, 0x19, 0x04, 0x00,
+        ];
+
+        for dialect in [FmsDialect::Official, FmsDialect::CheesyArena] {
+            let (Some(tag), len) = read_frame(&buf).unwrap() else {
+                panic!("event code frame has no tag");
+            };
+            assert!(matches!(
+                FmsTcpTag::parse(tag.id, tag.data, dialect),
+                Some(FmsTcpTag::EventCode("CALG"))
+            ));
+            let (Some(tag), _) = read_frame(&buf[len..]).unwrap() else {
+                panic!("station info frame has no tag");
+            };
+            assert!(matches!(
+                FmsTcpTag::parse(tag.id, tag.data, dialect),
+                Some(FmsTcpTag::StationInfo {
+                    station: AlliancePos::Blue(2),
+                    status: StationStatus::Good,
+                })
+            ));
+
+            let station_info = FmsTcpTag::StationInfo {
+                station: AlliancePos::Blue(2),
+                status: StationStatus::Good,
+            };
+            assert_eq!(FmsTcpTag::EventCode("CALG").write(dialect), buf[..len]);
+            assert_eq!(station_info.write(dialect), buf[len..]);
+        }
+    }
+
+    #[test]
+    fn empty_frames_are_skipped() {
+        // A keepalive, then the event code "CALG"
+        let buf = [0x00, 0x00, 0x00, 0x06, 0x14, 0x04, b'C', b'A', b'L', b'G'];
+
+        assert!(matches!(read_frame(&buf), Some((None, 2))));
+        let Some((Some(tag), 8)) = read_frame(&buf[2..]) else {
+            panic!("event code after a keepalive wasn't read");
+        };
+        assert!(matches!(
+            FmsTcpTag::parse(tag.id, tag.data, FmsDialect::Official),
+            Some(FmsTcpTag::EventCode("CALG"))
+        ));
+
+        // Half a size still needs more bytes
+        assert!(read_frame(&buf[..1]).is_none());
+        assert!(read_frame(&buf[2..6]).is_none());
+    }
+}
@@ -1,8 +1,22 @@
-use crate::Ds;
+use crate::{Ds, Error};
 
-pub(crate) mod tcp;
-pub(crate) mod udp;
+pub mod tcp;
+pub mod udp;
 
 pub(crate) trait IncomingTagHandler<'d> {
     fn handle(&self, ds: &'d Ds);
 }
+
+/// Get `N` bytes starting at `pos`, or an error if `buf` is too short
+#[inline(always)]
+pub(crate) fn read_array<const N: usize>(buf: &[u8], pos: usize) -> Result<[u8; N], Error> {
+    buf.get(pos..pos + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(Error::MalformedPacket)
+}
+
+/// Get `len` bytes starting at `pos`, or an error if `buf` is too short
+#[inline(always)]
+pub(crate) fn read_slice(buf: &[u8], pos: usize, len: usize) -> Result<&[u8], Error> {
+    buf.get(pos..pos + len).ok_or(Error::MalformedPacket)
+}
@@ -1,14 +1,19 @@
-use crate::Error;
-use bytes::Buf;
-use std::str;
-use tracing::Level;
-
-use super::IncomingTagHandler;
+use crate::{
+    Error,
+    console::{ConsoleLevel, ConsoleLine},
+    diagnostics::{DisableFaults, RailFaults, ResourceUsage, UsageEntry, VersionEntry},
+    event::DsEvent,
+    trace::Level,
+};
+use robudst_proto::tag::{Tag, TcpTags};
+use std::{str, time::SystemTime};
+
+use super::{IncomingTagHandler, read_array, read_slice};
 
 /// Enum containing possible incoming TCP packets from the roboRIO
 pub enum TcpIncomingTag<'t> {
     RadioEvent(&'t str),
-    UsageReport,
+    UsageReport(UsageReport<'t>),
     DisableFaults(DisableFaults),
     RailFaults(RailFaults),
     VersionInfo(VersionInfo<'t>),
@@ -16,151 +21,171 @@ pub enum TcpIncomingTag<'t> {
     Stdout(Stdout<'t>),
     Dummy,
 }
+impl TcpIncomingTag<'_> {
+    /// Copy out of the receive buffer, so the tag can be stored or sent to
+    /// another task
+    pub fn to_owned(&self) -> TcpIncomingTagOwned {
+        match self {
+            Self::RadioEvent(message) => TcpIncomingTagOwned::RadioEvent((*message).to_owned()),
+            Self::UsageReport(report) => TcpIncomingTagOwned::UsageReport(report.to_owned()),
+            Self::DisableFaults(faults) => TcpIncomingTagOwned::DisableFaults(*faults),
+            Self::RailFaults(faults) => TcpIncomingTagOwned::RailFaults(*faults),
+            Self::VersionInfo(info) => TcpIncomingTagOwned::VersionInfo(info.to_owned()),
+            Self::ErrorMessage(message) => TcpIncomingTagOwned::ErrorMessage(message.to_owned()),
+            Self::Stdout(stdout) => TcpIncomingTagOwned::Stdout(stdout.to_owned()),
+            Self::Dummy => TcpIncomingTagOwned::Dummy,
+        }
+    }
+}
 
-pub(crate) trait IncomingTcpPacket: Sized {
-    fn decode(buf: &mut impl Buf) -> Result<Self, Error>;
+/// A [`TcpIncomingTag`] that owns its data
+#[derive(Debug, Clone, PartialEq)]
+pub enum TcpIncomingTagOwned {
+    RadioEvent(String),
+    UsageReport(ResourceUsage),
+    DisableFaults(DisableFaults),
+    RailFaults(RailFaults),
+    VersionInfo(VersionEntry),
+    ErrorMessage(ErrorMessageOwned),
+    Stdout(StdoutOwned),
+    Dummy,
 }
 
+/// Decodes every tag in a buffer of whole, size-prefixed tags
+///
+/// Tags that are malformed or unknown are skipped. A tag cut off by the end
+/// of the buffer ends the stream.
 pub struct TcpTagStream<'t> {
-    buf: &'t [u8],
-    pos: usize,
+    tags: TcpTags<'t>,
 }
 impl<'t> TcpTagStream<'t> {
     #[inline(always)]
     pub const fn new(buf: &'t [u8]) -> Self {
-        Self { buf, pos: 0usize }
+        Self {
+            tags: TcpTags::new(buf),
+        }
     }
 }
 impl<'t> Iterator for TcpTagStream<'t> {
     type Item = TcpIncomingTag<'t>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let buf = self.buf;
-        let len = buf.len();
-
-        if len - self.pos < 2 {
-            return None;
-        }
-
-        let buf = &buf[self.pos..];
-
-        let size = u16::from_be_bytes([buf[0], buf[1]]);
-        self.pos += 2;
-
-        if size > 0 {
-            let id = buf[2];
-            self.pos += 1;
-
-            let buf = &buf[self.pos..];
-
-            match id {
+        loop {
+            let Tag { id, data: buf } = self.tags.next()?;
+            let tag = match id {
                 // Radio event
-                0x00 => {
-                    let message = core::str::from_utf8(buf).unwrap();
-                    Some(TcpIncomingTag::RadioEvent(message))
-                }
+                0x00 => Ok(TcpIncomingTag::RadioEvent(
+                    core::str::from_utf8(buf).unwrap_or_default(),
+                )),
 
                 // Usage report
-                0x01 => Some(TcpIncomingTag::UsageReport),
+                0x01 => UsageReport::parse(buf).map(TcpIncomingTag::UsageReport),
 
                 // Disable faults
-                0x04 => {
-                    // 1 byte for tag id + 2*u16
-                    assert_eq!(size, 5);
-
-                    Some(TcpIncomingTag::DisableFaults(DisableFaults::parse(buf)))
-                }
+                0x04 => DisableFaults::parse(buf).map(TcpIncomingTag::DisableFaults),
 
                 // Rail faults
-                0x05 => {
-                    // 1 byte for tag id + 3*u16
-                    assert_eq!(size, 7);
-
-                    Some(TcpIncomingTag::RailFaults(RailFaults::parse(buf)))
-                }
+                0x05 => RailFaults::parse(buf).map(TcpIncomingTag::RailFaults),
 
                 // Version info
-                0x0A => {
-                    // 1 byte for tag id + at least 6 bytes of data
-                    assert!(size >= 6);
-
-                    Some(TcpIncomingTag::VersionInfo(VersionInfo::parse(buf)))
-                }
+                0x0A => VersionInfo::parse(buf).map(TcpIncomingTag::VersionInfo),
 
                 // Error message
-                0x0B => {
-                    // 1 byte for tag id + at least 19 bytes of data
-                    assert!(size >= 20);
-
-                    Some(TcpIncomingTag::ErrorMessage(ErrorMessage::parse(buf)))
-                }
+                0x0B => ErrorMessage::parse(buf).map(TcpIncomingTag::ErrorMessage),
 
                 // Stdout
-                0x0C => {
-                    // 1 byte for tag id + at least 6 bytes for message
-                    assert!(size >= 7);
+                0x0C => Stdout::parse(buf).map(TcpIncomingTag::Stdout),
 
-                    Some(TcpIncomingTag::Stdout(Stdout::parse(buf)))
-                }
-
-                // Unknown
-                0x0D => {
-                    assert_eq!(buf, &[0x00, 0x00, 0x04, 0x04, 0x04, 0x04]);
+                // Unknown, always `[0x00, 0x00, 0x04, 0x04, 0x04, 0x04]`
+                0x0D => Ok(TcpIncomingTag::Dummy),
 
-                    Some(TcpIncomingTag::Dummy)
+                _ => {
+                    event!(Level::TRACE, id, "Skipping unknown TCP tag");
+                    continue;
                 }
+            };
 
-                _ => None,
+            match tag {
+                Ok(tag) => return Some(tag),
+                // A robot sending garbage shouldn't take the DS down
+                Err(_) => event!(Level::DEBUG, id, "Skipping malformed TCP tag"),
             }
-        } else {
-            None
         }
     }
 }
 
-#[derive(Debug)]
-pub struct DisableFaults {
-    comms: u16,
-    pwr12v: u16,
-}
 impl DisableFaults {
     #[inline(always)]
-    pub(crate) const fn parse(buf: &[u8]) -> Self {
-        let comms = u16::from_be_bytes([buf[0], buf[1]]);
-        let pwr12v = u16::from_be_bytes([buf[2], buf[3]]);
+    pub(crate) fn parse(buf: &[u8]) -> Result<Self, Error> {
+        let comms = u16::from_be_bytes(read_array(buf, 0)?);
+        let power_12v = u16::from_be_bytes(read_array(buf, 2)?);
 
-        Self { comms, pwr12v }
+        Ok(Self { comms, power_12v })
     }
 }
 impl IncomingTagHandler<'_> for DisableFaults {
-    fn handle(&self, _ds: &crate::Ds) {
-        event!(Level::ERROR, ?self, "A disable fault occurred");
+    fn handle(&self, ds: &crate::Ds) {
+        ds.record_disable_faults(*self);
     }
 }
 
-#[derive(Debug)]
-pub struct RailFaults {
-    pwr6v: u16,
-    pwr5v: u16,
-    pwr3_3v: u16,
-}
 impl RailFaults {
     #[inline(always)]
-    pub(crate) const fn parse(buf: &[u8]) -> Self {
-        let pwr6v = u16::from_be_bytes([buf[0], buf[1]]);
-        let pwr5v = u16::from_be_bytes([buf[2], buf[3]]);
-        let pwr3_3v = u16::from_be_bytes([buf[4], buf[5]]);
+    pub(crate) fn parse(buf: &[u8]) -> Result<Self, Error> {
+        let rail_6v = u16::from_be_bytes(read_array(buf, 0)?);
+        let rail_5v = u16::from_be_bytes(read_array(buf, 2)?);
+        let rail_3v3 = u16::from_be_bytes(read_array(buf, 4)?);
+
+        Ok(Self {
+            rail_6v,
+            rail_5v,
+            rail_3v3,
+        })
+    }
+}
+impl IncomingTagHandler<'_> for RailFaults {
+    fn handle(&self, ds: &crate::Ds) {
+        ds.record_rail_faults(*self);
+    }
+}
 
-        Self {
-            pwr6v,
-            pwr5v,
-            pwr3_3v,
+/// WPILib usage reporting, for FIRST to see what teams use
+///
+/// This is the team number, an unknown byte, then a resource id and instance
+/// number for each thing reported.
+pub struct UsageReport<'u> {
+    team: u16,
+    entries: &'u [u8],
+}
+impl<'u> UsageReport<'u> {
+    #[inline(always)]
+    pub(crate) fn parse(buf: &'u [u8]) -> Result<Self, Error> {
+        let team = u16::from_be_bytes(read_array(buf, 0)?);
+        let entries = buf.get(3..).ok_or(Error::MalformedPacket)?;
+        if entries.len() % 2 != 0 {
+            return Err(Error::MalformedPacket);
+        }
+
+        Ok(Self { team, entries })
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = UsageEntry> + '_ {
+        self.entries.chunks_exact(2).map(|entry| UsageEntry {
+            resource: entry[0],
+            instance: entry[1],
+        })
+    }
+
+    pub fn to_owned(&self) -> ResourceUsage {
+        ResourceUsage {
+            team: self.team,
+            entries: self.entries().collect(),
         }
     }
 }
-impl IncomingTagHandler<'_> for RailFaults {
-    fn handle(&self, _ds: &crate::Ds) {
-        event!(Level::ERROR, ?self, "A rail fault occurred");
+impl IncomingTagHandler<'_> for UsageReport<'_> {
+    fn handle(&self, ds: &crate::Ds) {
+        ds.emit(DsEvent::UsageReport(self.to_owned()));
     }
 }
 
@@ -172,29 +197,35 @@ pub struct VersionInfo<'v> {
 }
 impl<'v> VersionInfo<'v> {
     #[inline(always)]
-    pub(crate) fn parse(buf: &'v [u8]) -> Self {
-        let ty = buf[0];
-        let id = buf[3];
-        let name_len = buf[4] as usize;
-        let name = core::str::from_utf8(&buf[5..=5 + name_len]);
-        let version_len = buf[6 + name_len] as usize;
-        let version = core::str::from_utf8(&buf[7 + name_len..=7 + name_len + version_len]);
-
-        Self {
+    pub(crate) fn parse(buf: &'v [u8]) -> Result<Self, Error> {
+        let [ty] = read_array(buf, 0)?;
+        // 2 unused bytes
+        let [id] = read_array(buf, 3)?;
+        let [name_len] = read_array(buf, 4)?;
+        let name = read_slice(buf, 5, name_len as usize)?;
+        let pos = 5 + name_len as usize;
+        let [version_len] = read_array(buf, pos)?;
+        let version = read_slice(buf, pos + 1, version_len as usize)?;
+
+        Ok(Self {
             ty,
             id,
-            name: if let Ok(name) = name { name } else { "" },
-            version: if let Ok(version) = version {
-                version
-            } else {
-                ""
-            },
+            name: core::str::from_utf8(name).unwrap_or_default(),
+            version: core::str::from_utf8(version).unwrap_or_default(),
+        })
+    }
+
+    pub fn to_owned(&self) -> VersionEntry {
+        VersionEntry {
+            device_type: self.ty,
+            id: self.id,
+            name: self.name.to_owned(),
+            version: self.version.to_owned(),
         }
     }
 }
 impl<'v> IncomingTagHandler<'_> for VersionInfo<'v> {
-    fn handle(&self, _ds: &crate::Ds) {
-        // TODO: properly share this with the library consumer
+    fn handle(&self, ds: &crate::Ds) {
         event!(
             Level::INFO,
             r#type = self.ty,
@@ -202,6 +233,7 @@ impl<'v> IncomingTagHandler<'_> for VersionInfo<'v> {
             name = self.name,
             version = self.version
         );
+        ds.record_version(self.to_owned());
     }
 }
 
@@ -217,43 +249,26 @@ pub struct ErrorMessage<'e> {
 }
 impl<'e> ErrorMessage<'e> {
     #[inline(always)]
-    pub(crate) fn parse(buf: &'e [u8]) -> Self {
-        let timestamp = f32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
-        let seqnum = u16::from_be_bytes([buf[4], buf[5]]);
-        let error_code = i32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]);
-        let flags = if let Some(flags) = ErrorMsgFlags::from_bits(buf[12]) {
-            flags
-        } else {
-            ErrorMsgFlags::empty()
-        };
-        let details_len = u16::from_be_bytes([buf[13], buf[14]]) as usize;
-        let details = if let Ok(details) = core::str::from_utf8(&buf[15..15 + details_len]) {
-            details
-        } else {
-            ""
-        };
-        let location_len =
-            u16::from_be_bytes([buf[15 + details_len], buf[16 + details_len]]) as usize;
-        let location = if let Ok(location) =
-            core::str::from_utf8(&buf[17 + details_len..17 + details_len + location_len])
-        {
-            location
-        } else {
-            ""
-        };
-        let call_stack_len = u16::from_be_bytes([
-            buf[17 + details_len + location_len],
-            buf[18 + details_len + location_len],
-        ]) as usize;
-        let call_stack = if let Ok(call_stack) = core::str::from_utf8(
-            &buf[19 + details_len + location_len..19 + details_len + location_len + call_stack_len],
-        ) {
-            call_stack
-        } else {
-            ""
+    pub(crate) fn parse(buf: &'e [u8]) -> Result<Self, Error> {
+        let timestamp = f32::from_be_bytes(read_array(buf, 0)?);
+        let seqnum = u16::from_be_bytes(read_array(buf, 4)?);
+        let error_code = i32::from_be_bytes(read_array(buf, 8)?);
+        let [flags] = read_array(buf, 12)?;
+        let flags = ErrorMsgFlags::from_bits_truncate(flags);
+
+        // Then three length-prefixed strings
+        let mut pos = 13;
+        let mut next_str = || -> Result<&'e str, Error> {
+            let len = u16::from_be_bytes(read_array(buf, pos)?) as usize;
+            let s = read_slice(buf, pos + 2, len)?;
+            pos += 2 + len;
+            Ok(core::str::from_utf8(s).unwrap_or_default())
         };
+        let details = next_str()?;
+        let location = next_str()?;
+        let call_stack = next_str()?;
 
-        Self {
+        Ok(Self {
             timestamp,
             seqnum,
             error_code,
@@ -261,36 +276,75 @@ impl<'e> ErrorMessage<'e> {
             details,
             location,
             call_stack,
+        })
+    }
+
+    pub fn to_owned(&self) -> ErrorMessageOwned {
+        ErrorMessageOwned {
+            timestamp: self.timestamp,
+            seqnum: self.seqnum,
+            error_code: self.error_code,
+            flags: self.flags,
+            details: self.details.to_owned(),
+            location: self.location.to_owned(),
+            call_stack: self.call_stack.to_owned(),
         }
     }
 }
+
+/// An [`ErrorMessage`] that owns its data
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorMessageOwned {
+    /// Seconds since robot code started
+    pub timestamp: f32,
+    pub seqnum: u16,
+    pub error_code: i32,
+    pub flags: ErrorMsgFlags,
+    pub details: String,
+    pub location: String,
+    pub call_stack: String,
+}
 impl<'e> IncomingTagHandler<'_> for ErrorMessage<'e> {
-    fn handle(&self, _ds: &crate::Ds) {
-        if self.flags.contains(ErrorMsgFlags::ERROR) {
+    fn handle(&self, ds: &crate::Ds) {
+        let level = if self.flags.contains(ErrorMsgFlags::ERROR) {
             event!(
                 Level::ERROR,
                 timestamp = self.timestamp,
                 seqnum = self.seqnum,
-                error_code = self.seqnum,
+                error_code = self.error_code,
                 details = self.details,
                 location = self.location,
                 call_stack = self.call_stack
             );
+            ConsoleLevel::Error
         } else {
             event!(
                 Level::WARN,
                 timestamp = self.timestamp,
                 seqnum = self.seqnum,
-                error_code = self.seqnum,
+                error_code = self.error_code,
                 details = self.details,
                 location = self.location,
                 call_stack = self.call_stack
             );
-        }
+            ConsoleLevel::Warning
+        };
+
+        ds.push_console_line(ConsoleLine {
+            seqnum: 0,
+            received: SystemTime::now(),
+            robot_timestamp: Some(self.timestamp),
+            level,
+            message: self.details.to_owned(),
+            error_code: Some(self.error_code),
+            location: self.location.to_owned(),
+            call_stack: self.call_stack.to_owned(),
+        });
     }
 }
 
 bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct ErrorMsgFlags: u8 {
         const ERROR      = 0b0000_0001;
         const IS_LV_CODE = 0b0000_0010;
@@ -304,29 +358,151 @@ pub struct Stdout<'s> {
 }
 impl<'s> Stdout<'s> {
     #[inline(always)]
-    pub(crate) fn parse(buf: &'s [u8]) -> Self {
-        let timestamp = f32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
-        let seqnum = u16::from_be_bytes([buf[4], buf[5]]);
-        let message = if let Ok(message) = core::str::from_utf8(&buf[6..]) {
-            message
-        } else {
-            ""
-        };
+    pub(crate) fn parse(buf: &'s [u8]) -> Result<Self, Error> {
+        let timestamp = f32::from_be_bytes(read_array(buf, 0)?);
+        let seqnum = u16::from_be_bytes(read_array(buf, 4)?);
+        let message = core::str::from_utf8(&buf[6..]).unwrap_or_default();
 
-        Self {
+        Ok(Self {
             timestamp,
             seqnum,
             message,
+        })
+    }
+
+    pub fn to_owned(&self) -> StdoutOwned {
+        StdoutOwned {
+            timestamp: self.timestamp,
+            seqnum: self.seqnum,
+            message: self.message.to_owned(),
         }
     }
 }
+
+/// A [`Stdout`] that owns its data
+#[derive(Debug, Clone, PartialEq)]
+pub struct StdoutOwned {
+    /// Seconds since robot code started
+    pub timestamp: f32,
+    pub seqnum: u16,
+    pub message: String,
+}
 impl<'s> IncomingTagHandler<'_> for Stdout<'s> {
-    fn handle(&self, _ds: &crate::Ds) {
+    fn handle(&self, ds: &crate::Ds) {
         event!(
             Level::INFO,
             self.message,
             timestamp = self.timestamp,
             seqnum = self.seqnum
         );
+
+        ds.push_console_line(ConsoleLine {
+            seqnum: 0,
+            received: SystemTime::now(),
+            robot_timestamp: Some(self.timestamp),
+            level: ConsoleLevel::Info,
+            message: self.message.to_owned(),
+            error_code: None,
+            location: String::new(),
+            call_stack: String::new(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Size-prefix a tag
+    fn frame(id: u8, data: &[u8]) -> Vec<u8> {
+        let mut buf = ((data.len() + 1) as u16).to_be_bytes().to_vec();
+        buf.push(id);
+        buf.extend_from_slice(data);
+        buf
+    }
+
+    fn stdout(timestamp: f32, seqnum: u16, message: &str) -> Vec<u8> {
+        let mut data = timestamp.to_be_bytes().to_vec();
+        data.extend_from_slice(&seqnum.to_be_bytes());
+        data.extend_from_slice(message.as_bytes());
+        frame(0x0C, &data)
+    }
+
+    fn version_info(ty: u8, id: u8, name: &str, version: &str) -> Vec<u8> {
+        let mut data = vec![ty, 0, 0, id, name.len() as u8];
+        data.extend_from_slice(name.as_bytes());
+        data.push(version.len() as u8);
+        data.extend_from_slice(version.as_bytes());
+        frame(0x0A, &data)
+    }
+
+    #[test]
+    fn decodes_every_tag_in_a_buffer() {
+        let mut buf = stdout(1.5, 7, "hello");
+        buf.extend(frame(0x04, &[0, 2, 0, 3]));
+        buf.extend(version_info(0, 1, "roboRIO Image", "2025_v2.0"));
+        buf.extend(stdout(2.0, 8, "world"));
+
+        let tags: Vec<_> = TcpTagStream::new(&buf).collect();
+        assert_eq!(tags.len(), 4);
+
+        let TcpIncomingTag::Stdout(first) = &tags[0] else {
+            panic!("expected stdout");
+        };
+        assert_eq!(first.timestamp, 1.5);
+        assert_eq!(first.seqnum, 7);
+        assert_eq!(first.message, "hello");
+
+        let TcpIncomingTag::DisableFaults(faults) = &tags[1] else {
+            panic!("expected disable faults");
+        };
+        assert_eq!(
+            *faults,
+            DisableFaults {
+                comms: 2,
+                power_12v: 3
+            }
+        );
+
+        let TcpIncomingTag::VersionInfo(version) = &tags[2] else {
+            panic!("expected version info");
+        };
+        assert_eq!(version.id, 1);
+        assert_eq!(version.name, "roboRIO Image");
+        assert_eq!(version.version, "2025_v2.0");
+
+        let TcpIncomingTag::Stdout(last) = &tags[3] else {
+            panic!("expected stdout");
+        };
+        assert_eq!(last.message, "world");
+    }
+
+    #[test]
+    fn skips_unknown_and_malformed_tags() {
+        let mut buf = frame(0x7F, &[1, 2, 3]);
+        // Disable faults are 4 bytes
+        buf.extend(frame(0x04, &[0, 1]));
+        // Version info whose name runs past the end of the tag
+        buf.extend(frame(0x0A, &[0, 0, 0, 0, 200, b'a']));
+        buf.extend_from_slice(&[0, 0]);
+        buf.extend(stdout(0.0, 0, "still here"));
+
+        let tags: Vec<_> = TcpTagStream::new(&buf).collect();
+        assert_eq!(tags.len(), 1);
+        assert!(matches!(&tags[0], TcpIncomingTag::Stdout(s) if s.message == "still here"));
+    }
+
+    #[test]
+    fn stops_at_a_truncated_tag() {
+        let mut buf = stdout(0.0, 0, "whole");
+        let partial = stdout(0.0, 1, "cut off");
+        buf.extend_from_slice(&partial[..partial.len() - 3]);
+
+        let tags: Vec<_> = TcpTagStream::new(&buf).collect();
+        assert_eq!(tags.len(), 1);
+
+        // A lone byte of a size prefix isn't a tag either
+        assert_eq!(TcpTagStream::new(&[0]).count(), 0);
+        assert_eq!(TcpTagStream::new(&[]).count(), 0);
     }
 }
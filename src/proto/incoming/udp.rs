@@ -1,4 +1,17 @@
-use super::IncomingTagHandler;
+pub use robudst_proto::status::{Status, Trace};
+use robudst_proto::{
+    status::{self, StatusHeader},
+    tag::UdpTags,
+};
+
+use super::{IncomingTagHandler, read_array, read_slice};
+use crate::{
+    Error,
+    diagnostics::{
+        CanMetrics, CpuInfo, PDH_CHANNELS, PDP_CHANNELS, PdhStats, PdpStats, PowerStats, RamInfo,
+    },
+    trace::Level,
+};
 
 pub(crate) struct UdpIncomingPacket {
     pub seqnum: u16,
@@ -6,6 +19,14 @@ pub(crate) struct UdpIncomingPacket {
     pub trace: Trace,
     pub battery: f32,
     pub need_date: bool,
+    pub cpu: Option<CpuInfo>,
+    pub ram: Option<RamInfo>,
+    /// Free disk space, in bytes
+    pub free_disk: Option<u32>,
+    pub can: Option<CanMetrics>,
+    pub power: Option<PowerStats>,
+    /// Joystick outputs, in joystick slot order
+    pub joystick_outputs: Vec<JoystickOutput>,
 }
 
 pub(crate) struct UdpIncomingStream<'u> {
@@ -17,275 +38,293 @@ impl<'u> UdpIncomingStream<'u> {
     pub const fn new(buf: &'u [u8]) -> Self {
         Self { buf, pos: 0usize }
     }
-    pub fn parse_one(buf: &'u [u8]) -> UdpIncomingPacket {
-        Self::new(buf).next().unwrap()
-    }
 }
 impl Iterator for UdpIncomingStream<'_> {
     type Item = UdpIncomingPacket;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let buf = self.buf;
-        let len = buf.len();
-
-        // Verify there's at least 8 bytes (for the static fields)
-        if len - self.pos <= 8 {
-            return None;
-        }
-
         // Get a slice that starts at the cursor pos, so impl is cleaner
-        let buf = &buf[self.pos..];
-
-        // Get values for each of the fields, then advance cursor pos by 8
-        let seqnum = u16::from_be_bytes([buf[0], buf[1]]);
-        let _comm_version = buf[2];
-        let status = Status::from_bits(buf[3]).unwrap();
-        let trace = Trace::from_bits(buf[4]).unwrap();
-        let battery = (buf[5] as f32 + buf[6] as f32) / 256.0;
-        let need_date = buf[7] == 1;
-        self.pos += 8;
-
-        while self.pos < len {
-            let tag_size = buf[self.pos];
-            let tag_id = buf[self.pos + 1];
-            self.pos += 2;
-
-            if (self.pos + tag_size as usize) < len {
-                return None;
-            }
-            let buf = &buf[self.pos..self.pos + tag_size as usize];
-            self.pos += tag_size as usize;
-
-            match tag_id {
-                // Joystick output
-                0x01 => {
-                    if tag_size == 1 {
-                        continue;
+        let buf = self.buf.get(self.pos..)?;
+
+        let header = StatusHeader::parse(buf)?;
+
+        let mut joystick_outputs = Vec::new();
+        let mut cpu = None;
+        let mut ram = None;
+        let mut free_disk = None;
+        let mut can = None;
+        let mut power = None;
+
+        // The rest of the datagram is tags
+        let mut tags = UdpTags::new(&buf[status::HEADER_LEN..]);
+        for tag in &mut tags {
+            let (tag_id, data) = (tag.id, tag.data);
+            let res = match tag_id {
+                status::JOYSTICK_OUTPUT => {
+                    if data.is_empty() {
+                        // Slots are positional, so this joystick has no outputs
+                        joystick_outputs.push(JoystickOutput::default());
+                        Ok(())
+                    } else {
+                        JoystickOutput::parse(data).map(|output| joystick_outputs.push(output))
                     }
-                    // 1 byte for tag id + 8 bytes of data
-                    assert_eq!(tag_size, 9);
-
-                    JoystickOutput::parse(buf);
-                }
-
-                // Disk space
-                0x04 => {
-                    let _free_disk = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
                 }
 
-                // CPU stats
-                0x05 => {
-                    // 1 byte for tag id + 5*f32
-                    assert_eq!(tag_size, 21);
+                status::DISK_SPACE => read_array(data, 0).map(|bytes| {
+                    free_disk = Some(u32::from_be_bytes(bytes));
+                }),
 
-                    CpuInfo::parse(buf);
-                }
+                status::CPU_INFO => CpuInfo::parse(data).map(|info| cpu = Some(info)),
 
-                // RAM stats
-                0x06 => {
-                    // 1 byte for tag id + 2*u32
-                    assert_eq!(tag_size, 9);
+                status::RAM_INFO => RamInfo::parse(data).map(|info| ram = Some(info)),
 
-                    RamInfo::parse(buf);
-                }
+                status::POWER => PowerStats::parse(data).map(|stats| power = Some(stats)),
 
-                // PDP log
-                0x08 => {
-                    // 1 byte for tag id + 25 bytes of stuff I'd rather not deal with at the moment
-                    assert_eq!(tag_size, 26);
-                }
+                // Unknown, 9 bytes of who knows what
+                0x09 => Ok(()),
 
-                // Unknown
-                0x09 => {
-                    // 1 byte for tag id + 9 bytes of who knows what
-                    assert_eq!(tag_size, 10);
-                }
-                // CAN metrics
-                0x0E => {
-                    // 1 byte for tag id + f32 + 2*u32 + 2*u8
-                    assert_eq!(tag_size, 15);
+                status::CAN_METRICS => CanMetrics::parse(data).map(|metrics| can = Some(metrics)),
 
-                    CanMetrics::parse(buf);
-                }
-                _ => {}
+                _ => Ok(()),
+            };
+            // One bad tag shouldn't throw away the rest of the packet
+            if res.is_err() {
+                event!(Level::DEBUG, tag_id, "Skipping malformed UDP tag");
             }
         }
+        if !tags.remaining().is_empty() {
+            event!(
+                Level::DEBUG,
+                tag_size = tags.remaining()[0],
+                "Ignoring truncated UDP tag"
+            );
+        }
+        self.pos += buf.len();
 
         Some(UdpIncomingPacket {
-            seqnum,
-            status,
-            trace,
-            battery,
-            need_date,
+            seqnum: header.seqnum,
+            status: header.status,
+            trace: header.trace,
+            battery: header.battery,
+            need_date: header.need_date,
+            cpu,
+            ram,
+            free_disk,
+            can,
+            power,
+            joystick_outputs,
         })
     }
 }
 
-pub(crate) enum UdpIncomingTag {
-    JoystickOutput(JoystickOutput),
-    DiskSpace(usize),
-    CpuInfo(CpuInfo),
-    RamInfo(RamInfo),
-    CanMetrics(CanMetrics),
-}
-
-pub(crate) struct JoystickOutput {
-    outputs: u32,
-    left_rumble: u16,
-    right_rumble: u16,
+/// Outputs requested by robot code for a single joystick
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct JoystickOutput {
+    /// Bitfield of HID outputs, with output 1 in the least significant bit
+    pub outputs: u32,
+    pub left_rumble: u16,
+    pub right_rumble: u16,
 }
 impl JoystickOutput {
     #[inline(always)]
-    pub(crate) const fn parse(buf: &[u8]) -> Self {
-        let outputs = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
-        let left_rumble = u16::from_be_bytes([buf[4], buf[5]]);
-        let right_rumble = u16::from_be_bytes([buf[6], buf[7]]);
+    pub(crate) fn parse(buf: &[u8]) -> Result<Self, Error> {
+        let outputs = u32::from_le_bytes(read_array(buf, 0)?);
+        let left_rumble = u16::from_be_bytes(read_array(buf, 4)?);
+        let right_rumble = u16::from_be_bytes(read_array(buf, 6)?);
 
-        JoystickOutput {
+        Ok(JoystickOutput {
             outputs,
             left_rumble,
             right_rumble,
-        }
-    }
-}
-impl IncomingTagHandler<'_> for JoystickOutput {
-    fn handle(&self, ds: &'_ crate::Ds) {
-        //
+        })
     }
 }
 
-pub(crate) struct CpuInfo {
-    num_of_cpus: f32,
-    cpu_time_critical: f32,
-    cpu_above_normal: f32,
-    cpu_normal: f32,
-    cpu_low: f32,
-}
 impl CpuInfo {
     #[inline(always)]
-    pub(crate) const fn parse(buf: &[u8]) -> Self {
-        let num_of_cpus = f32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
-        let cpu_time_critical = f32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
-        let cpu_above_normal = f32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]);
-        let cpu_normal = f32::from_be_bytes([buf[12], buf[13], buf[14], buf[15]]);
-        let cpu_low = f32::from_be_bytes([buf[16], buf[17], buf[18], buf[19]]);
-
-        Self {
-            num_of_cpus,
-            cpu_time_critical,
-            cpu_above_normal,
-            cpu_normal,
-            cpu_low,
-        }
+    pub(crate) fn parse(buf: &[u8]) -> Result<Self, Error> {
+        let num_cpus = f32::from_be_bytes(read_array(buf, 0)?);
+        let time_critical = f32::from_be_bytes(read_array(buf, 4)?);
+        let above_normal = f32::from_be_bytes(read_array(buf, 8)?);
+        let normal = f32::from_be_bytes(read_array(buf, 12)?);
+        let low = f32::from_be_bytes(read_array(buf, 16)?);
+
+        Ok(Self {
+            num_cpus,
+            time_critical,
+            above_normal,
+            normal,
+            low,
+        })
     }
 }
 
-pub(crate) struct RamInfo {
-    block: u32,
-    free_space: u32,
-}
 impl RamInfo {
     #[inline(always)]
-    pub(crate) const fn parse(buf: &[u8]) -> Self {
-        let block = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
-        let free_space = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+    pub(crate) fn parse(buf: &[u8]) -> Result<Self, Error> {
+        let block = u32::from_be_bytes(read_array(buf, 0)?);
+        let free_space = u32::from_be_bytes(read_array(buf, 4)?);
 
-        Self { block, free_space }
+        Ok(Self { block, free_space })
     }
 }
 
-pub(crate) struct CanMetrics {
-    utilization: f32,
-    bus_off: u32,
-    tx_full: u32,
-    rx_errors: u8,
-    tx_errors: u8,
-}
 impl CanMetrics {
     #[inline(always)]
-    pub(crate) const fn parse(buf: &[u8]) -> Self {
-        let utilization = f32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
-        let bus_off = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
-        let tx_full = u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]);
-        let rx_errors = buf[12];
-        let tx_errors = buf[13];
-
-        Self {
+    pub(crate) fn parse(buf: &[u8]) -> Result<Self, Error> {
+        let utilization = f32::from_be_bytes(read_array(buf, 0)?);
+        let bus_off = u32::from_be_bytes(read_array(buf, 4)?);
+        let tx_full = u32::from_be_bytes(read_array(buf, 8)?);
+        let [rx_errors, tx_errors] = read_array(buf, 12)?;
+
+        Ok(Self {
             utilization,
             bus_off,
             tx_full,
             rx_errors,
             tx_errors,
-        }
+        })
     }
 }
 impl IncomingTagHandler<'_> for CanMetrics {
     fn handle(&self, ds: &'_ crate::Ds) {
-        ds.can_bus_util.store(self.utilization);
+        ds.can_metrics.store(Some(*self));
     }
 }
 
-bitflags! {
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-    pub(crate) struct Status: u8 {
-        const ESTOP = 0b1000_0000;
-        const BROWNOUT = 0b0001_0000;
-        const CODE_START = 0b0000_1000;
-        const ENABLED = 0b0000_0100;
-
-        // Mode flags
-        const TELEOP = 0b00;
-        const TEST = 0b01;
-        const AUTO = 0b10;
-    }
-}
-impl Status {
-    #[inline(always)]
-    pub const fn is_enabled(&self) -> bool {
-        self.contains(Status::ENABLED)
-    }
+/// Amps per bit of a channel current, for both the PDP and PDH
+const CURRENT_SCALE: f32 = 0.125;
 
-    #[inline(always)]
-    pub const fn is_browned_out(self) -> bool {
-        self.contains(Status::BROWNOUT)
-    }
+impl PowerStats {
+    /// A PDP log is always this long, so anything else is from a PDH
+    const PDP_LOG_LEN: usize = 25;
 
     #[inline(always)]
-    pub const fn is_estopped(self) -> bool {
-        self.contains(Status::ESTOP)
+    pub(crate) fn parse(buf: &[u8]) -> Result<Self, Error> {
+        if buf.len() == Self::PDP_LOG_LEN {
+            PdpStats::parse(buf).map(Self::Pdp)
+        } else {
+            PdhStats::parse(buf).map(Self::Pdh)
+        }
     }
+}
 
-    //#[inline(always)]
-    //pub const fn is_in_(&self) -> bool {
-    //    self.contains(Self::)
-    //}
-    #[inline(always)]
-    pub const fn is_in_teleop(&self) -> bool {
-        self.contains(Self::TELEOP)
-    }
+impl PdpStats {
+    /// The PDP's three CAN status frames, back to back after an unknown byte
+    ///
+    /// The first two frames hold 6 channel currents each and the last holds
+    /// 4, packed as 10 bit values with the most significant bit first. The
+    /// last 3 bytes of the last frame are resistance, voltage, and
+    /// temperature.
     #[inline(always)]
-    pub const fn is_in_auto(&self) -> bool {
-        self.contains(Self::AUTO)
+    pub(crate) fn parse(buf: &[u8]) -> Result<Self, Error> {
+        let frames = [
+            (read_slice(buf, 1, 8)?, 6),
+            (read_slice(buf, 9, 8)?, 6),
+            (read_slice(buf, 17, 5)?, 4),
+        ];
+        let [resistance, voltage, temperature] = read_array(buf, 22)?;
+
+        let mut currents = [0.0; PDP_CHANNELS];
+        let channels = frames
+            .into_iter()
+            .flat_map(|(frame, count)| (0..count).map(move |i| unpack_10_bits(frame, i)));
+        for (current, raw) in currents.iter_mut().zip(channels) {
+            *current = raw as f32 * CURRENT_SCALE;
+        }
+
+        Ok(Self {
+            currents,
+            resistance,
+            voltage: voltage as f32 * 0.05 + 4.0,
+            temperature: temperature as f32 * 1.032_508_4 - 67.856_45,
+        })
     }
+}
+
+impl PdhStats {
+    /// The PDH's channel currents, laid out like the PDP's after an unknown
+    /// byte
+    ///
+    /// That's 4 frames of 8 bytes, each with 6 packed 10 bit channel
+    /// currents. Unlike the PDP log, nothing else is known to follow.
     #[inline(always)]
-    pub const fn is_in_test(&self) -> bool {
-        self.contains(Self::TEST)
+    pub(crate) fn parse(buf: &[u8]) -> Result<Self, Error> {
+        let mut currents = [0.0; PDH_CHANNELS];
+        for (frame_index, frame) in currents.chunks_mut(6).enumerate() {
+            let raw = read_slice(buf, 1 + frame_index * 8, 8)?;
+            for (i, current) in frame.iter_mut().enumerate() {
+                *current = unpack_10_bits(raw, i) as f32 * CURRENT_SCALE;
+            }
+        }
+
+        Ok(Self { currents })
     }
 }
 
-bitflags! {
-    pub struct Trace: u8 {
-        const ROBOT_CODE = 0b0010_0000;
-        const IS_ROBORIO = 0b0001_0000;
-        const TEST_MODE  = 0b0000_1000;
-        const AUTONOMOUS = 0b0000_0100;
-        const TELEOP     = 0b0000_0010;
-        const DISABLED   = 0b0000_0001;
-    }
+/// Get the `index`th 10 bit value packed into `frame`, most significant bit
+/// first
+#[inline(always)]
+fn unpack_10_bits(frame: &[u8], index: usize) -> u16 {
+    let bit = index * 10;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let word = u16::from_be_bytes([frame[byte], frame[byte + 1]]);
+
+    (word >> (6 - shift)) & 0x3FF
 }
-impl Trace {
-    #[inline(always)]
-    pub const fn has_robot_code(&self) -> bool {
-        self.contains(Self::ROBOT_CODE)
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pack 10 bit values most significant bit first, padded out to `len`
+    fn pack(values: &[u16], len: usize) -> Vec<u8> {
+        let mut bits = 0u128;
+        for &value in values {
+            bits = (bits << 10) | value as u128;
+        }
+        bits <<= len * 8 - values.len() * 10;
+
+        bits.to_be_bytes()[16 - len..].to_vec()
+    }
+
+    #[test]
+    fn decodes_pdp_currents() {
+        let raw: Vec<u16> = (0..16).map(|i| i * 60 + 3).collect();
+        let mut tag = vec![0x00];
+        tag.extend(pack(&raw[..6], 8));
+        tag.extend(pack(&raw[6..12], 8));
+        tag.extend(pack(&raw[12..], 5));
+        tag.extend([42, 160, 70]);
+
+        let Ok(PowerStats::Pdp(stats)) = PowerStats::parse(&tag) else {
+            panic!("not decoded as a PDP");
+        };
+        for (current, raw) in stats.currents.iter().zip(&raw) {
+            assert_eq!(*current, *raw as f32 * 0.125);
+        }
+        assert_eq!(stats.resistance, 42);
+        assert_eq!(stats.voltage, 12.0);
+        assert!((stats.temperature - 4.42).abs() < 0.01);
+
+        assert!(PdpStats::parse(&tag[..24]).is_err());
+    }
+
+    #[test]
+    fn decodes_pdh_currents() {
+        let raw: Vec<u16> = (0..24).map(|i| i * 40 + 1).collect();
+        let mut tag = vec![0x00];
+        for frame in raw.chunks(6) {
+            tag.extend(pack(frame, 8));
+        }
+
+        let Ok(PowerStats::Pdh(stats)) = PowerStats::parse(&tag) else {
+            panic!("not decoded as a PDH");
+        };
+        for (current, raw) in stats.currents.iter().zip(&raw) {
+            assert_eq!(*current, *raw as f32 * 0.125);
+        }
+
+        assert!(PowerStats::parse(&tag[..32]).is_err());
     }
 }
@@ -1,2 +1,10 @@
+//! Encoding and decoding the driver station protocol
+//!
+//! The packet layouts themselves (headers, flags, and tag framing) come from
+//! [`robudst_proto`], which works without `std` on embedded devices.
+
+pub mod codec;
+pub mod fms;
 pub mod incoming;
 pub mod outgoing;
+pub mod version;
@@ -1,3 +1,7 @@
+use bytes::BufMut;
+
+use crate::MatchType;
+
 pub enum TcpOutgoingTag<'t> {
     JoystickDescriptor {
         index: u8,
@@ -10,15 +14,18 @@ pub enum TcpOutgoingTag<'t> {
     },
     MatchInfo {
         competition: &'t str,
-        match_kind: u8,
+        match_type: MatchType,
+        match_number: u16,
+        replay_number: u8,
     },
     GameData {
         game_data: &'t str,
     },
 }
 impl TcpOutgoingTag<'_> {
-    pub fn write(self) -> Vec<u8> {
-        match self {
+    /// Append the tag, including its size prefix, to `buf`
+    pub fn encode_into(&self, buf: &mut impl BufMut) {
+        match *self {
             Self::JoystickDescriptor {
                 index,
                 is_xbox,
@@ -28,36 +35,64 @@ impl TcpOutgoingTag<'_> {
                 button_count,
                 pov_count,
             } => {
-                let mut buf = Vec::new();
-                buf.clear();
-
                 // 1 byte for tag id
                 // 1 byte each for index, is_xbox, kind, and name.len (4 bytes)
                 // 1 byte each for axis_count, button_count, and pov_count (3 bytes)
-                buf.push(8u8 + name.len() as u8 + axes.len() as u8);
-                buf.push(0x02);
+                let size = 8 + name.len() as u16 + axes.len() as u16;
+                buf.put_u16(size);
+                buf.put_u8(0x02);
 
-                buf.extend([index, is_xbox as u8, kind as u8, name.len() as u8]);
+                buf.put_slice(&[index, is_xbox as u8, kind as u8, name.len() as u8]);
 
-                buf.extend_from_slice(name.as_bytes());
-                buf.push(axes.len() as u8);
-                buf.extend(axes.into_iter().map(|axis| *axis as u8));
-                buf.extend([button_count, pov_count]);
-
-                buf
+                buf.put_slice(name.as_bytes());
+                buf.put_u8(axes.len() as u8);
+                for axis in axes {
+                    buf.put_u8(*axis as u8);
+                }
+                buf.put_slice(&[button_count, pov_count]);
             }
 
             Self::MatchInfo {
                 competition,
-                match_kind,
-            } => Vec::new(),
+                match_type,
+                match_number,
+                replay_number,
+            } => {
+                let competition =
+                    &competition.as_bytes()[..competition.len().min(u8::MAX as usize)];
+
+                // 1 byte for tag id
+                // 1 byte for competition.len, plus the competition
+                // 1 byte for match type, 2 bytes for match number, 1 byte for replay number
+                let size = 6 + competition.len() as u16;
+                buf.put_u16(size);
+                buf.put_u8(0x07);
+
+                buf.put_u8(competition.len() as u8);
+                buf.put_slice(competition);
+                buf.put_u8(match_type as u8);
+                buf.put_u16(match_number);
+                buf.put_u8(replay_number);
+            }
 
-            Self::GameData { game_data } => Vec::new(),
+            Self::GameData { game_data } => {
+                // 1 byte for tag id, plus the game data
+                let size = 1 + game_data.len() as u16;
+                buf.put_u16(size);
+                buf.put_u8(0x0E);
+                buf.put_slice(game_data.as_bytes());
+            }
         }
     }
+
+    pub fn write(self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode_into(&mut buf);
+        buf
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(i8)]
 pub enum JoystickKind {
     Unknown = -1,
@@ -79,7 +114,7 @@ pub enum JoystickKind {
     HIDFirstPerson = 24,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum AxisKind {
     X = 0,
@@ -1,4 +1,29 @@
-use crate::{AlliancePos, Ds, RobotCodeMode, RobotStatus};
+use std::{
+    sync::atomic::Ordering,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::BufMut;
+pub use robudst_proto::control::{Control, Request};
+use robudst_proto::{
+    control::{self, ControlHeader},
+    joystick,
+};
+
+use crate::{
+    AlliancePos, Ds, RobotCodeMode, RobotStatus,
+    joystick::{Joystick, MAX_JOYSTICKS},
+    utils::civil_from_days,
+};
+
+/// Longest timezone name that fits in a tag, after its id
+const MAX_TIMEZONE_LEN: usize = u8::MAX as usize - 1;
+/// Longest a joystick tag can be, after its id
+const MAX_JOYSTICK_LEN: usize = joystick::encoded_len(
+    joystick::MAX_AXES,
+    joystick::MAX_BUTTONS,
+    joystick::MAX_POVS,
+);
 
 pub struct UdpOutgoingPacket<'u> {
     seqnum: u16,
@@ -7,19 +32,30 @@ pub struct UdpOutgoingPacket<'u> {
     req: Request,
     alliance: AlliancePos,
     tags: &'u [UdpOutgoingTag<'u>],
+    joysticks: [Joystick; MAX_JOYSTICKS],
+    /// How many of `joysticks` get sent
+    joystick_count: usize,
 }
-impl UdpOutgoingPacket<'_> {
+impl<'u> UdpOutgoingPacket<'u> {
     pub fn build(ds: &Ds) -> Self {
         let mut control = Control::empty();
 
-        match ds.status.load() {
-            RobotStatus::EStopped => {
-                control |= Control::ESTOP;
-            }
-            RobotStatus::Enabled => {
-                control |= Control::ENABLED;
+        // Keep the robot stopped even if the status got overwritten
+        if ds.estop_latched.load(Ordering::Acquire) {
+            control |= Control::ESTOP;
+        } else {
+            match ds.status.load() {
+                RobotStatus::EStopped => {
+                    control |= Control::ESTOP;
+                }
+                RobotStatus::Enabled => {
+                    control |= Control::ENABLED;
+                }
+                _ => {}
             }
-            _ => {}
+        }
+        if ds.fms_connected.load(Ordering::Acquire) {
+            control |= Control::FMS_CONNECTED;
         }
         match ds.mode.load() {
             RobotCodeMode::Teleop => {
@@ -35,16 +71,38 @@ impl UdpOutgoingPacket<'_> {
 
         let alliance = ds.alliance_pos.load();
 
+        // Joystick tags are positional, so empty slots below the highest
+        // used one still need to be sent
+        let mut joysticks = [Joystick::EMPTY; MAX_JOYSTICKS];
+        let mut joystick_count = 0;
+        for (slot, joystick) in ds.joysticks.iter().enumerate() {
+            joysticks[slot] = joystick.load().unwrap_or(Joystick::EMPTY);
+            if joysticks[slot] != Joystick::EMPTY {
+                joystick_count = slot + 1;
+            }
+        }
+
         Self {
             seqnum: 0,
-            comm_version: 0x01,
+            comm_version: control::COMM_VERSION,
             control,
             req: Request::empty(),
             alliance,
             tags: &[],
+            joysticks,
+            joystick_count,
         }
     }
 
+    pub(crate) const fn set_seqnum(&mut self, seqnum: u16) {
+        self.seqnum = seqnum;
+    }
+
+    /// Add tags to send before the joysticks
+    pub(crate) const fn set_tags(&mut self, tags: &'u [UdpOutgoingTag<'u>]) {
+        self.tags = tags;
+    }
+
     pub(crate) const fn reboot_rio(&mut self) {
         self.req = Request::REBOOT_RIO;
     }
@@ -53,75 +111,61 @@ impl UdpOutgoingPacket<'_> {
         self.req = Request::RESTART_CODE;
     }
 
-    pub(crate) fn write(self) -> Vec<u8> {
-        let mut buf: Vec<u8> = Vec::new();
-        buf.clear();
-
-        buf.extend(self.seqnum.to_be_bytes().to_vec());
-        buf.push(self.comm_version);
-        buf.push(self.control.bits());
-        buf.push(self.req.bits());
-        buf.push(self.alliance.to_pos());
-
-        for tag in self.tags {
-            match tag {
-                UdpOutgoingTag::Countdown { countdown: _ } => {
-                    let tag = tag.write();
-                    buf.extend_from_slice(&[tag.len() as u8, 0x07]);
-                    buf.extend(tag);
-                }
-                UdpOutgoingTag::Joystick {
-                    axes: _,
-                    buttons: _,
-                    povs: _,
-                } => {
-                    let tag = tag.write();
-                    buf.extend_from_slice(&[tag.len() as u8, 0x0C]);
-                    buf.extend(tag);
-                }
-                UdpOutgoingTag::Date {
-                    microseconds: _,
-                    second: _,
-                    minute: _,
-                    hour: _,
-                    day: _,
-                    month: _,
-                    year: _,
-                } => {
-                    let tag = tag.write();
-                    buf.extend_from_slice(&[tag.len() as u8, 0x0F]);
-                    buf.extend(tag);
-                }
-                UdpOutgoingTag::Timezone { timezone: _ } => {
-                    let tag = tag.write();
-                    buf.extend_from_slice(&[tag.len() as u8, 0x10]);
-                    buf.extend(tag);
-                }
-                _ => {}
-            }
-        }
+    /// Every tag to send, joysticks last
+    fn all_tags(&self) -> impl Iterator<Item = UdpOutgoingTag<'_>> {
+        self.tags.iter().copied().chain(
+            self.joysticks[..self.joystick_count]
+                .iter()
+                .map(Joystick::as_tag),
+        )
+    }
 
-        buf
+    /// How many bytes [`encode_into`](Self::encode_into) writes
+    pub fn encoded_len(&self) -> usize {
+        // 2 bytes for each tag's size and id
+        control::HEADER_LEN
+            + self
+                .all_tags()
+                .map(|tag| 2 + tag.encoded_len())
+                .sum::<usize>()
     }
-}
 
-bitflags! {
-    pub struct Control: u8 {
-        const ESTOP         = 0b1000_0000;
-        const FMS_CONNECTED = 0b0000_1000;
-        const ENABLED       = 0b0000_0100;
+    /// Append the packet to `buf`
+    pub fn encode_into(&self, buf: &mut impl BufMut) {
+        let header = ControlHeader {
+            seqnum: self.seqnum,
+            comm_version: self.comm_version,
+            control: self.control,
+            request: self.req,
+            station: self.alliance.to_pos(),
+        };
+        buf.put_slice(&header.to_bytes());
 
-        const TELEOP = 0b00;
-        const AUTO   = 0b10;
-        const TEST   = 0b01;
+        for tag in self.all_tags() {
+            // The size includes the tag id
+            buf.put_u8(tag.encoded_len() as u8 + 1);
+            buf.put_u8(tag.id());
+            tag.encode_into(buf);
+        }
     }
 
-    pub struct Request: u8 {
-        const REBOOT_RIO   = 0b0000_1000;
-        const RESTART_CODE = 0b0000_0100;
+    /// Write the packet to the start of `buf`, returning its length, or
+    /// `None` if `buf` is too short
+    pub fn encode_to_slice(&self, buf: &mut [u8]) -> Option<usize> {
+        let len = self.encoded_len();
+        let mut out = buf.get_mut(..len)?;
+        self.encode_into(&mut out);
+        Some(len)
+    }
+
+    pub fn write(self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.encoded_len());
+        self.encode_into(&mut buf);
+        buf
     }
 }
 
+#[derive(Clone, Copy)]
 pub enum UdpOutgoingTag<'u> {
     Countdown {
         countdown: f32,
@@ -131,13 +175,17 @@ pub enum UdpOutgoingTag<'u> {
         buttons: &'u [bool],
         povs: &'u [i16],
     },
+    /// UTC date and time
     Date {
         microseconds: u32,
         second: u8,
         minute: u8,
         hour: u8,
+        /// `1..=31`
         day: u8,
+        /// `0..=11`
         month: u8,
+        /// Years since 1900
         year: u8,
     },
     Timezone {
@@ -145,35 +193,60 @@ pub enum UdpOutgoingTag<'u> {
     },
 }
 impl<'u> UdpOutgoingTag<'u> {
-    pub fn write(&self) -> Vec<u8> {
+    /// Make a date tag for `time`
+    pub fn date(time: SystemTime) -> Self {
+        let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since.as_secs();
+        let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+        let secs_of_day = secs % 86_400;
+
+        UdpOutgoingTag::Date {
+            microseconds: since.subsec_micros(),
+            second: (secs_of_day % 60) as u8,
+            minute: (secs_of_day / 60 % 60) as u8,
+            hour: (secs_of_day / 3600) as u8,
+            day,
+            month: month - 1,
+            year: (year - 1900).clamp(0, u8::MAX as i64) as u8,
+        }
+    }
+
+    const fn id(&self) -> u8 {
+        match self {
+            UdpOutgoingTag::Countdown { .. } => control::COUNTDOWN,
+            UdpOutgoingTag::Joystick { .. } => control::JOYSTICK,
+            UdpOutgoingTag::Date { .. } => control::DATE,
+            UdpOutgoingTag::Timezone { .. } => control::TIMEZONE,
+        }
+    }
+
+    /// How many bytes [`encode_into`](Self::encode_into) writes
+    pub fn encoded_len(&self) -> usize {
         match self {
-            UdpOutgoingTag::Countdown { countdown } => countdown.to_be_bytes().to_vec(),
+            UdpOutgoingTag::Countdown { .. } => 4,
             UdpOutgoingTag::Joystick {
                 axes,
                 buttons,
                 povs,
-            } => {
-                let mut buf = Vec::new();
-                buf.clear();
-
-                buf.push(axes.len() as u8);
-                buf.extend(axes.iter().map(|axis| *axis as u8));
-
-                // Each button's state is a binary value, packed in little endian byte order
-                buf.push((buttons.len() / 8) as u8 + if buttons.len() == 0 { 0 } else { 1 });
-                for btn_chunk in buttons.array_chunks::<8>() {
-                    let mut byte = 0u8;
-                    for button in btn_chunk {
-                        byte |= if *button { 1 } else { 0 };
-                        byte <<= 1;
-                    }
-                    buf.push(byte);
-                }
-
-                buf.push(povs.len() as u8);
-                buf.extend(povs.iter().map(|pov| pov.to_be_bytes()).flatten());
+            } => joystick::encoded_len(axes.len(), buttons.len(), povs.len()),
+            UdpOutgoingTag::Date { .. } => 10,
+            UdpOutgoingTag::Timezone { timezone } => timezone.len().min(MAX_TIMEZONE_LEN),
+        }
+    }
 
-                buf
+    /// Append the tag's data, without its size and id, to `buf`
+    pub fn encode_into(&self, buf: &mut impl BufMut) {
+        match *self {
+            UdpOutgoingTag::Countdown { countdown } => buf.put_f32(countdown),
+            UdpOutgoingTag::Joystick {
+                axes,
+                buttons,
+                povs,
+            } => {
+                let mut data = [0u8; MAX_JOYSTICK_LEN];
+                // Joysticks can't be made with more inputs than fit
+                let len = joystick::encode(axes, buttons, povs, &mut data).unwrap_or_default();
+                buf.put_slice(&data[..len]);
             }
             UdpOutgoingTag::Date {
                 microseconds,
@@ -183,8 +256,19 @@ impl<'u> UdpOutgoingTag<'u> {
                 day,
                 month,
                 year,
-            } => Vec::new(),
-            UdpOutgoingTag::Timezone { timezone } => timezone.as_bytes().to_vec(),
+            } => {
+                buf.put_u32(microseconds);
+                buf.put_slice(&[second, minute, hour, day, month, year]);
+            }
+            UdpOutgoingTag::Timezone { timezone } => {
+                buf.put_slice(&timezone.as_bytes()[..self.encoded_len()]);
+            }
         }
     }
+
+    pub fn write(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.encoded_len());
+        self.encode_into(&mut buf);
+        buf
+    }
 }
@@ -0,0 +1,116 @@
+//! Which season's wire format to speak
+//!
+//! Every season since 2015 has used the same packet layouts, but FRC tweaks
+//! tags from time to time. Encoding and decoding goes through a
+//! [`ProtocolVersion`], so a season that changes things can get its own
+//! implementation without [`Ds`](crate::Ds) knowing.
+
+use std::fmt::Debug;
+
+use bytes::BytesMut;
+
+use crate::{
+    RobotCodeMode, RobotStatus,
+    diagnostics::{CanMetrics, CpuInfo, PowerStats, RamInfo},
+    joystick::JoystickOutput,
+    proto::{
+        incoming::udp::{Status, Trace, UdpIncomingPacket, UdpIncomingStream},
+        outgoing::{tcp::TcpOutgoingTag, udp::UdpOutgoingPacket},
+    },
+    utils::find_status,
+};
+
+/// A status packet from the roboRIO, decoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusReport {
+    pub seqnum: u16,
+    pub status: RobotStatus,
+    pub mode: RobotCodeMode,
+    /// The status byte that `status` and `mode` were worked out from
+    pub status_flags: Status,
+    pub trace: Trace,
+    /// Battery voltage
+    pub battery: f32,
+    /// Whether the roboRIO wants the date and time
+    pub need_date: bool,
+    /// CPU usage, if the packet had it
+    pub cpu: Option<CpuInfo>,
+    /// Memory usage, if the packet had it
+    pub ram: Option<RamInfo>,
+    /// Free disk space in bytes, if the packet had it
+    pub free_disk: Option<u32>,
+    /// CAN bus health, if the packet had it
+    pub can: Option<CanMetrics>,
+    /// Power distribution readings, if the packet had them
+    pub power: Option<PowerStats>,
+    /// Joystick outputs, in joystick slot order
+    pub joystick_outputs: Vec<JoystickOutput>,
+}
+
+/// Encodes and decodes packets for a range of seasons
+pub trait ProtocolVersion: Debug + Send + Sync {
+    /// Whether this is the right format for the `year` season
+    fn supports_year(&self, year: u16) -> bool;
+
+    /// Append an encoded control packet to `buf`
+    fn write_control(&self, pkt: UdpOutgoingPacket<'_>, buf: &mut BytesMut);
+
+    /// Append an encoded TCP tag, including its size prefix, to `buf`
+    fn write_tcp(&self, tag: TcpOutgoingTag<'_>, buf: &mut BytesMut);
+
+    /// Decode a status packet, if it's valid
+    fn read_status(&self, buf: &[u8]) -> Option<StatusReport>;
+}
+
+/// The format used since the roboRIO replaced the cRIO in 2015
+///
+/// WPILib desktop simulation (with `halsim_ds_socket`) speaks this too.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Frc2015;
+impl ProtocolVersion for Frc2015 {
+    fn supports_year(&self, year: u16) -> bool {
+        year >= 2015
+    }
+
+    fn write_control(&self, pkt: UdpOutgoingPacket<'_>, buf: &mut BytesMut) {
+        buf.reserve(pkt.encoded_len());
+        pkt.encode_into(buf);
+    }
+
+    fn write_tcp(&self, tag: TcpOutgoingTag<'_>, buf: &mut BytesMut) {
+        tag.encode_into(buf);
+    }
+
+    fn read_status(&self, buf: &[u8]) -> Option<StatusReport> {
+        let UdpIncomingPacket {
+            seqnum,
+            status: status_flags,
+            trace,
+            battery,
+            need_date,
+            cpu,
+            ram,
+            free_disk,
+            can,
+            power,
+            joystick_outputs,
+        } = UdpIncomingStream::new(buf).next()?;
+        let (status, mode) = find_status(status_flags, trace);
+
+        Some(StatusReport {
+            seqnum,
+            status,
+            mode,
+            status_flags,
+            trace,
+            battery,
+            need_date,
+            cpu,
+            ram,
+            free_disk,
+            can,
+            power,
+            joystick_outputs,
+        })
+    }
+}
@@ -0,0 +1,182 @@
+//! Programming the robot radio, without the Windows-only configuration
+//! utility
+//!
+//! The Vivid-Hosting radio is configured over HTTP, by posting JSON to its
+//! `/configuration` endpoint at `10.TE.AM.1`. The OM5P radios can only be
+//! programmed by flashing them with the FRC Radio Configuration Utility, so
+//! they're recognized but not supported.
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! use robudst::radio::{RadioConfig, RadioModel, configure_radio, radio_address};
+//!
+//! let config = RadioConfig::new(4533).wpa_key("hunter22");
+//! configure_radio(radio_address(4533).unwrap().into(), RadioModel::VividHosting, &config).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
+
+use serde_json::json;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+use crate::{trace::Level, utils::gen_team_ip};
+
+/// How long the radio gets to apply a configuration and answer
+///
+/// Radios restart their wireless while applying changes, which is slow.
+pub const RADIO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Which kind of radio is being configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RadioModel {
+    /// OpenMesh OM5P-AN or OM5P-AC, used through 2024
+    Om5p,
+    /// Vivid-Hosting VH-109, used since 2025
+    VividHosting,
+}
+
+/// How wide a channel the radio uses
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelBandwidth {
+    #[default]
+    Mhz20,
+    Mhz40,
+}
+impl ChannelBandwidth {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Mhz20 => "20MHz",
+            Self::Mhz40 => "40MHz",
+        }
+    }
+}
+
+/// What to program the radio with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RadioConfig {
+    pub team_number: u16,
+    /// Network name, the team number by default
+    pub ssid: String,
+    /// Network password, or an open network if `None`
+    pub wpa_key: Option<String>,
+    pub bandwidth: ChannelBandwidth,
+}
+impl RadioConfig {
+    pub fn new(team_number: u16) -> Self {
+        Self {
+            team_number,
+            ssid: team_number.to_string(),
+            wpa_key: None,
+            bandwidth: ChannelBandwidth::default(),
+        }
+    }
+
+    pub fn ssid(mut self, ssid: impl Into<String>) -> Self {
+        self.ssid = ssid.into();
+        self
+    }
+
+    pub fn wpa_key(mut self, wpa_key: impl Into<String>) -> Self {
+        self.wpa_key = Some(wpa_key.into());
+        self
+    }
+
+    pub const fn bandwidth(mut self, bandwidth: ChannelBandwidth) -> Self {
+        self.bandwidth = bandwidth;
+        self
+    }
+}
+
+/// Get `team_number`'s radio address, `10.TE.AM.1`
+///
+/// Returns `None` for team numbers without a team IP.
+pub fn radio_address(team_number: u16) -> Option<Ipv4Addr> {
+    gen_team_ip(team_number).map(|ip| {
+        let [a, b, c, _] = ip.octets();
+        Ipv4Addr::new(a, b, c, 1)
+    })
+}
+
+/// Program the radio at `address` with `config`
+///
+/// Fails with [`io::ErrorKind::Unsupported`] for radios that can't be
+/// programmed over the network, and with an error carrying the HTTP status
+/// if the radio rejects the configuration.
+pub async fn configure_radio(
+    address: IpAddr,
+    model: RadioModel,
+    config: &RadioConfig,
+) -> io::Result<()> {
+    match model {
+        RadioModel::Om5p => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "OM5P radios can only be programmed with the FRC Radio Configuration Utility",
+        )),
+        RadioModel::VividHosting => {
+            let body = json!({
+                "mode": "TEAM_ROBOT_RADIO",
+                "teamNumber": config.team_number,
+                "ssid": config.ssid,
+                "wpaKey": config.wpa_key.as_deref().unwrap_or_default(),
+                "channelBandwidth": config.bandwidth.as_str(),
+            });
+
+            event!(Level::INFO, %address, team = config.team_number, "Configuring radio");
+            timeout(
+                RADIO_TIMEOUT,
+                post_json(address, "/configuration", &body.to_string()),
+            )
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Radio didn't answer"))?
+        }
+    }
+}
+
+/// Send a minimal HTTP/1.1 POST, and check the response is a success
+async fn post_json(address: IpAddr, path: &str, body: &str) -> io::Result<()> {
+    let mut stream = TcpStream::connect((address, 80)).await?;
+
+    let request = format!(
+        "POST {path} HTTP/1.1\r\n\
+         Host: {address}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {body}",
+        body.len()
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+
+    let status_line = response
+        .split(|&b| b == b'\n')
+        .next()
+        .map(String::from_utf8_lossy)
+        .unwrap_or_default();
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Bad HTTP response"))?;
+
+    if (200..300).contains(&status) {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "Radio rejected configuration with HTTP {status}"
+        )))
+    }
+}
@@ -0,0 +1,227 @@
+//! Importing, recording, and replaying DS↔roboRIO traffic
+//!
+//! A [`Capture`] can be read from a pcap or pcapng file (from Wireshark,
+//! tcpdump, etc.) or a session saved by a [`Recorder`], decoded after the
+//! fact with [`Capture::robot_samples`], or played back into a live
+//! [`Ds`](crate::Ds) through a
+//! [`MemoryTransport`](crate::transport::MemoryTransport):
+//!
+//! ```no_run
+//! # async fn example() -> std::io::Result<()> {
+//! use robudst::{Ds, replay::Capture, transport::MemoryTransport};
+//!
+//! let capture = Capture::open("match.pcapng")?;
+//! let (ds_end, rio_end) = MemoryTransport::pair();
+//! let ds = Ds::with_transport(4533, ds_end);
+//!
+//! tokio::select! {
+//!     _ = ds.run() => {}
+//!     res = capture.replay(&rio_end, true) => res?,
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fs, io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    RobotCodeMode, RobotStatus,
+    proto::incoming::udp::{UdpIncomingPacket, UdpIncomingStream},
+    timer::sleep_until,
+    transport::Transport,
+    utils::find_status,
+};
+
+pub(crate) mod pcap;
+mod recorder;
+
+pub use recorder::Recorder;
+
+/// Which way a packet was headed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    ToRobot,
+    FromRobot,
+}
+
+/// Which connection a packet was sent on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Udp,
+    Tcp,
+}
+
+/// A single packet pulled out of a capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedPacket {
+    /// Time since the first packet in the capture
+    pub timestamp: Duration,
+    pub direction: Direction,
+    pub channel: Channel,
+    /// The UDP or TCP payload
+    pub data: Vec<u8>,
+}
+
+/// Robot state decoded from a captured status packet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RobotSample {
+    pub timestamp: Duration,
+    pub status: RobotStatus,
+    pub mode: RobotCodeMode,
+    pub battery: f32,
+}
+
+/// A sequence of captured DS traffic
+#[derive(Debug, Clone, Default)]
+pub struct Capture {
+    packets: Vec<CapturedPacket>,
+}
+impl Capture {
+    /// Make a capture out of already extracted packets
+    pub fn from_packets(mut packets: Vec<CapturedPacket>) -> Self {
+        packets.sort_by_key(|pkt| pkt.timestamp);
+        Self { packets }
+    }
+
+    /// Read a [`Recorder`] session, or a pcap or pcapng file
+    ///
+    /// In captures, everything that isn't DS↔roboRIO traffic on the standard
+    /// ports is ignored.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let buf = fs::read(path)?;
+        if buf.starts_with(recorder::MAGIC) {
+            Self::from_recording(&buf)
+        } else {
+            Self::from_pcap(&buf)
+        }
+    }
+
+    /// Parse a session saved by a [`Recorder`]
+    pub fn from_recording(buf: &[u8]) -> io::Result<Self> {
+        Ok(Self::from_packets(recorder::parse(buf)?))
+    }
+
+    /// Parse the contents of a pcap or pcapng file
+    pub fn from_pcap(buf: &[u8]) -> io::Result<Self> {
+        Ok(Self::from_packets(pcap::parse(buf)?))
+    }
+
+    #[inline(always)]
+    pub fn packets(&self) -> &[CapturedPacket] {
+        &self.packets
+    }
+
+    /// How long the capture lasts
+    pub fn duration(&self) -> Duration {
+        self.packets
+            .last()
+            .map(|pkt| pkt.timestamp)
+            .unwrap_or_default()
+    }
+
+    /// Decode every status packet the robot sent
+    pub fn robot_samples(&self) -> impl Iterator<Item = RobotSample> + '_ {
+        self.packets
+            .iter()
+            .filter(|pkt| pkt.direction == Direction::FromRobot && pkt.channel == Channel::Udp)
+            .flat_map(|pkt| {
+                UdpIncomingStream::new(&pkt.data).map(|decoded| {
+                    let UdpIncomingPacket {
+                        status,
+                        trace,
+                        battery,
+                        ..
+                    } = decoded;
+                    let (status, mode) = find_status(status, trace);
+
+                    RobotSample {
+                        timestamp: pkt.timestamp,
+                        status,
+                        mode,
+                        battery,
+                    }
+                })
+            })
+    }
+
+    /// Play the robot's side of the capture into `transport`
+    ///
+    /// `transport` should be the roboRIO end of a connection whose other end
+    /// belongs to a [`Ds`](crate::Ds). With `realtime`, packets are spaced out
+    /// like they originally were; otherwise they're sent as fast as possible.
+    /// Whatever the DS sends back is read and thrown away.
+    pub async fn replay(&self, transport: &impl Transport, realtime: bool) -> io::Result<()> {
+        self.play(Direction::FromRobot, transport, realtime).await
+    }
+
+    /// Play the driver station's side of the capture into `transport`
+    ///
+    /// The opposite of [`Capture::replay`], for driving a roboRIO (real or
+    /// simulated) exactly like the recorded DS did.
+    pub async fn replay_to_robot(
+        &self,
+        transport: &impl Transport,
+        realtime: bool,
+    ) -> io::Result<()> {
+        self.play(Direction::ToRobot, transport, realtime).await
+    }
+
+    async fn play(
+        &self,
+        direction: Direction,
+        transport: &impl Transport,
+        realtime: bool,
+    ) -> io::Result<()> {
+        let start = Instant::now();
+
+        let send = async {
+            for pkt in &self.packets {
+                if pkt.direction != direction {
+                    continue;
+                }
+                if realtime {
+                    sleep_until(start + pkt.timestamp).await;
+                }
+
+                match pkt.channel {
+                    Channel::Udp => transport.send_datagram(&pkt.data).await?,
+                    Channel::Tcp => transport.write_stream(&pkt.data).await?,
+                }
+            }
+
+            Ok(())
+        };
+
+        tokio::select! {
+            res = send => res,
+            res = drain(transport) => res,
+        }
+    }
+}
+
+/// Read and drop everything coming from the other end, so it never blocks on
+/// a full buffer
+async fn drain(transport: &impl Transport) -> io::Result<()> {
+    let mut udp_buf = [0u8; 1024];
+    let mut tcp_buf = [0u8; 4096];
+
+    loop {
+        tokio::select! {
+            res = transport.recv_datagram(&mut udp_buf) => {
+                res?;
+            }
+            res = transport.read_stream(&mut tcp_buf) => {
+                if res? == 0 {
+                    // The stream closed, keep draining datagrams
+                    loop {
+                        transport.recv_datagram(&mut udp_buf).await?;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,601 @@
+//! Just enough pcap and pcapng parsing to pull DS traffic out of a capture
+//!
+//! Only the pieces Wireshark and tcpdump actually produce are handled:
+//! Ethernet, Linux cooked, loopback, and raw IP link layers, carrying UDP or
+//! TCP over IPv4 or IPv6. TCP payloads are taken in capture order without any
+//! reassembly, which is fine for a single well-behaved connection.
+
+use std::{io, time::Duration};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{CapturedPacket, Channel, Direction};
+use crate::transport::{DS_UDP_PORT, RIO_TCP_PORT, RIO_UDP_PORT};
+
+const PCAP_MAGIC_US: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NS: u32 = 0xA1B2_3C4D;
+const PCAPNG_SHB: u32 = 0x0A0D_0D0A;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+const IP_PROTO_TCP: u8 = 6;
+const IP_PROTO_UDP: u8 = 17;
+
+#[inline(always)]
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Reads integers in whichever byte order the file was written in
+#[derive(Clone, Copy)]
+struct Endian {
+    big: bool,
+}
+const LE: Endian = Endian { big: false };
+const BE: Endian = Endian { big: true };
+impl Endian {
+    fn u16(self, buf: &[u8], at: usize) -> io::Result<u16> {
+        let bytes = buf
+            .get(at..at + 2)
+            .ok_or_else(|| invalid("truncated capture"))?;
+        let bytes = [bytes[0], bytes[1]];
+        Ok(if self.big {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(self, buf: &[u8], at: usize) -> io::Result<u32> {
+        let bytes = buf
+            .get(at..at + 4)
+            .ok_or_else(|| invalid("truncated capture"))?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Ok(if self.big {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+/// Parse a whole pcap or pcapng file, keeping only DS traffic
+pub(super) fn parse(buf: &[u8]) -> io::Result<Vec<CapturedPacket>> {
+    let magic = buf.get(..4).ok_or_else(|| invalid("not a capture file"))?;
+    let magic = u32::from_le_bytes([magic[0], magic[1], magic[2], magic[3]]);
+
+    let mut packets = Vec::new();
+    if magic == PCAPNG_SHB {
+        parse_pcapng(buf, &mut packets)?;
+    } else {
+        parse_pcap(buf, &mut packets)?;
+    }
+
+    // Make timestamps relative to the first packet
+    if let Some(start) = packets.iter().map(|pkt| pkt.timestamp).min() {
+        for pkt in &mut packets {
+            pkt.timestamp -= start;
+        }
+    }
+
+    Ok(packets)
+}
+
+/// A pcap file header
+#[derive(Clone, Copy)]
+struct PcapHeader {
+    endian: Endian,
+    nanos: bool,
+    linktype: u32,
+}
+impl PcapHeader {
+    const LEN: usize = 24;
+    const RECORD_LEN: usize = 16;
+
+    fn parse(buf: &[u8]) -> io::Result<Self> {
+        let (endian, nanos) = match (LE.u32(buf, 0)?, BE.u32(buf, 0)?) {
+            (PCAP_MAGIC_US, _) => (LE, false),
+            (PCAP_MAGIC_NS, _) => (LE, true),
+            (_, PCAP_MAGIC_US) => (BE, false),
+            (_, PCAP_MAGIC_NS) => (BE, true),
+            _ => return Err(invalid("not a capture file")),
+        };
+        let linktype = endian.u32(buf, 20)? & 0x0FFF_FFFF;
+
+        Ok(Self {
+            endian,
+            nanos,
+            linktype,
+        })
+    }
+
+    /// Read a record header, returning the timestamp and frame length
+    fn record(self, buf: &[u8], at: usize) -> io::Result<(Duration, usize)> {
+        let secs = self.endian.u32(buf, at)?;
+        let frac = self.endian.u32(buf, at + 4)?;
+        let len = self.endian.u32(buf, at + 8)? as usize;
+
+        let timestamp = Duration::from_secs(secs as u64)
+            + if self.nanos {
+                Duration::from_nanos(frac as u64)
+            } else {
+                Duration::from_micros(frac as u64)
+            };
+        Ok((timestamp, len))
+    }
+}
+
+fn parse_pcap(buf: &[u8], packets: &mut Vec<CapturedPacket>) -> io::Result<()> {
+    let header = PcapHeader::parse(buf)?;
+
+    let mut pos = PcapHeader::LEN;
+    while pos < buf.len() {
+        let (timestamp, len) = header.record(buf, pos)?;
+        let start = pos + PcapHeader::RECORD_LEN;
+        let frame = buf
+            .get(start..start + len)
+            .ok_or_else(|| invalid("truncated capture"))?;
+        pos = start + len;
+
+        packets.extend(decode_frame(header.linktype, frame, timestamp));
+    }
+
+    Ok(())
+}
+
+/// Read a pcap stream as it's captured, like the output of
+/// `tcpdump -U -w -`, calling `on_packet` with the DS traffic in it
+///
+/// Returns once the stream ends. Only pcap is supported, since that's what
+/// capture tools write to pipes.
+#[cfg_attr(not(feature = "runtime-tokio"), allow(dead_code))]
+pub(crate) async fn read_pcap_stream(
+    mut reader: impl AsyncRead + Unpin,
+    mut on_packet: impl FnMut(CapturedPacket),
+) -> io::Result<()> {
+    let mut buf = [0; PcapHeader::LEN];
+    reader.read_exact(&mut buf).await?;
+    let header = PcapHeader::parse(&buf)?;
+
+    let mut record = [0; PcapHeader::RECORD_LEN];
+    let mut frame = Vec::new();
+    loop {
+        match reader.read_exact(&mut record).await {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            res => res?,
+        };
+        let (timestamp, len) = header.record(&record, 0)?;
+
+        frame.resize(len, 0);
+        reader.read_exact(&mut frame).await?;
+        if let Some(packet) = decode_frame(header.linktype, &frame, timestamp) {
+            on_packet(packet);
+        }
+    }
+}
+
+/// An interface from a pcapng section
+struct Interface {
+    linktype: u32,
+    /// Timestamp ticks per second
+    ticks_per_sec: u64,
+}
+
+fn parse_pcapng(buf: &[u8], packets: &mut Vec<CapturedPacket>) -> io::Result<()> {
+    let mut endian = LE;
+    let mut interfaces = Vec::new();
+    // Simple packet blocks have no timestamp, so they reuse the last one seen
+    let mut last_timestamp = Duration::ZERO;
+
+    let mut pos = 0;
+    while pos < buf.len() {
+        // The section header decides the byte order for everything after it
+        if LE.u32(buf, pos)? == PCAPNG_SHB {
+            endian = match LE.u32(buf, pos + 8)? {
+                PCAPNG_BYTE_ORDER_MAGIC => LE,
+                _ => BE,
+            };
+            interfaces.clear();
+        }
+
+        let block_type = endian.u32(buf, pos)?;
+        let block_len = endian.u32(buf, pos + 4)? as usize;
+        if block_len < 12 {
+            return Err(invalid("bad pcapng block length"));
+        }
+        let body = buf
+            .get(pos + 8..pos + block_len - 4)
+            .ok_or_else(|| invalid("truncated capture"))?;
+        pos += block_len;
+
+        match block_type {
+            // Interface description
+            0x0000_0001 => interfaces.push(Interface {
+                linktype: endian.u16(body, 0)? as u32,
+                ticks_per_sec: if_tsresol(endian, body.get(8..).unwrap_or_default())?,
+            }),
+
+            // Enhanced packet
+            0x0000_0006 => {
+                let interface = endian.u32(body, 0)? as usize;
+                let interface = interfaces
+                    .get(interface)
+                    .ok_or_else(|| invalid("packet from unknown interface"))?;
+                let ticks = ((endian.u32(body, 4)? as u64) << 32) | endian.u32(body, 8)? as u64;
+                let len = endian.u32(body, 12)? as usize;
+                let frame = body
+                    .get(20..20 + len)
+                    .ok_or_else(|| invalid("truncated capture"))?;
+
+                last_timestamp = Duration::from_secs(ticks / interface.ticks_per_sec)
+                    + Duration::from_nanos(
+                        (ticks % interface.ticks_per_sec) * 1_000_000_000 / interface.ticks_per_sec,
+                    );
+                packets.extend(decode_frame(interface.linktype, frame, last_timestamp));
+            }
+
+            // Simple packet, always from the first interface
+            0x0000_0003 => {
+                let interface = interfaces
+                    .first()
+                    .ok_or_else(|| invalid("packet from unknown interface"))?;
+                let len = endian.u32(body, 0)? as usize;
+                let frame = body.get(4..4 + len).unwrap_or(&body[4.min(body.len())..]);
+                packets.extend(decode_frame(interface.linktype, frame, last_timestamp));
+            }
+
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the timestamp resolution in an interface's options, defaulting to
+/// microseconds
+fn if_tsresol(endian: Endian, mut options: &[u8]) -> io::Result<u64> {
+    while options.len() >= 4 {
+        let code = endian.u16(options, 0)?;
+        let len = endian.u16(options, 2)? as usize;
+        let value = options.get(4..4 + len).unwrap_or_default();
+
+        match code {
+            // End of options
+            0 => break,
+            // if_tsresol
+            9 => {
+                let resol = *value.first().ok_or_else(|| invalid("bad if_tsresol"))?;
+                let exp = (resol & 0x7F) as u32;
+                return if resol & 0x80 == 0 {
+                    10u64.checked_pow(exp)
+                } else {
+                    2u64.checked_pow(exp)
+                }
+                .ok_or_else(|| invalid("bad if_tsresol"));
+            }
+            _ => {}
+        }
+
+        // Options are padded to 4 bytes
+        options = options
+            .get(4 + len.next_multiple_of(4)..)
+            .unwrap_or_default();
+    }
+
+    Ok(1_000_000)
+}
+
+/// Dig the UDP or TCP payload out of a link layer frame, if it's DS traffic
+fn decode_frame(linktype: u32, frame: &[u8], timestamp: Duration) -> Option<CapturedPacket> {
+    let (ethertype, ip) = match linktype {
+        LINKTYPE_ETHERNET => {
+            let mut ethertype = u16::from_be_bytes([*frame.get(12)?, *frame.get(13)?]);
+            let mut ip = frame.get(14..)?;
+            if ethertype == ETHERTYPE_VLAN {
+                ethertype = u16::from_be_bytes([*frame.get(16)?, *frame.get(17)?]);
+                ip = frame.get(18..)?;
+            }
+            (ethertype, ip)
+        }
+        LINKTYPE_LINUX_SLL => (
+            u16::from_be_bytes([*frame.get(14)?, *frame.get(15)?]),
+            frame.get(16..)?,
+        ),
+        LINKTYPE_LINUX_SLL2 => (
+            u16::from_be_bytes([*frame.first()?, *frame.get(1)?]),
+            frame.get(20..)?,
+        ),
+        // Loopback has a 4 byte address family in the capturing host's byte
+        // order, but the IP version is easier to just read
+        LINKTYPE_NULL => (0, frame.get(4..)?),
+        LINKTYPE_RAW => (0, frame),
+        _ => return None,
+    };
+
+    let (proto, transport) = match (ethertype, *ip.first()? >> 4) {
+        (ETHERTYPE_IPV4, _) | (0, 4) => {
+            let header_len = (*ip.first()? & 0x0F) as usize * 4;
+            let total_len = u16::from_be_bytes([*ip.get(2)?, *ip.get(3)?]) as usize;
+            // Only the first fragment has the transport header
+            let frag_offset = u16::from_be_bytes([*ip.get(6)?, *ip.get(7)?]) & 0x1FFF;
+            if frag_offset != 0 {
+                return None;
+            }
+            // Ethernet pads short frames, so trust the IP length
+            (*ip.get(9)?, ip.get(header_len..total_len.min(ip.len()))?)
+        }
+        (ETHERTYPE_IPV6, _) | (0, 6) => {
+            let payload_len = u16::from_be_bytes([*ip.get(4)?, *ip.get(5)?]) as usize;
+            (*ip.get(6)?, ip.get(40..(40 + payload_len).min(ip.len()))?)
+        }
+        _ => return None,
+    };
+
+    let src_port = u16::from_be_bytes([*transport.first()?, *transport.get(1)?]);
+    let dst_port = u16::from_be_bytes([*transport.get(2)?, *transport.get(3)?]);
+
+    let (channel, direction, data) = match proto {
+        IP_PROTO_UDP => {
+            let direction = match dst_port {
+                RIO_UDP_PORT => Direction::ToRobot,
+                DS_UDP_PORT => Direction::FromRobot,
+                _ => return None,
+            };
+            (Channel::Udp, direction, transport.get(8..)?)
+        }
+        IP_PROTO_TCP => {
+            let direction = if dst_port == RIO_TCP_PORT {
+                Direction::ToRobot
+            } else if src_port == RIO_TCP_PORT {
+                Direction::FromRobot
+            } else {
+                return None;
+            };
+            let header_len = (*transport.get(12)? >> 4) as usize * 4;
+            let data = transport.get(header_len..)?;
+            // Bare ACKs and the like
+            if data.is_empty() {
+                return None;
+            }
+            (Channel::Tcp, direction, data)
+        }
+        _ => return None,
+    };
+
+    Some(CapturedPacket {
+        timestamp,
+        direction,
+        channel,
+        data: data.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        replay::{Capture, Recorder},
+        transport::{MemoryTransport, Transport},
+    };
+
+    /// An IPv4 UDP packet with no link layer, for `LINKTYPE_RAW`
+    fn ipv4_udp(src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+        let total_len = (20 + 8 + payload.len()) as u16;
+        let mut ip = vec![0x45, 0x00];
+        ip.extend(total_len.to_be_bytes());
+        ip.extend([0, 0, 0, 0, 64, IP_PROTO_UDP, 0, 0]);
+        ip.extend([10, 45, 33, 5, 10, 45, 33, 2]);
+        ip.extend(src_port.to_be_bytes());
+        ip.extend(dst_port.to_be_bytes());
+        ip.extend((8 + payload.len() as u16).to_be_bytes());
+        ip.extend([0, 0]);
+        ip.extend_from_slice(payload);
+        ip
+    }
+
+    /// The same packet in an Ethernet frame
+    fn ethernet_udp(src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0xFF; 12];
+        frame.extend(ETHERTYPE_IPV4.to_be_bytes());
+        frame.extend(ipv4_udp(src_port, dst_port, payload));
+        frame
+    }
+
+    /// A pcap file with microsecond timestamps, in either byte order
+    fn pcap(big_endian: bool, linktype: u32, records: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let word = |value: u32| {
+            if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+
+        let mut buf = word(PCAP_MAGIC_US).to_vec();
+        buf.extend(if big_endian {
+            [0, 2, 0, 4]
+        } else {
+            [2, 0, 4, 0]
+        });
+        buf.extend([0; 8]);
+        buf.extend(word(65535));
+        buf.extend(word(linktype));
+        for &(secs, micros, frame) in records {
+            buf.extend(word(secs));
+            buf.extend(word(micros));
+            buf.extend(word(frame.len() as u32));
+            buf.extend(word(frame.len() as u32));
+            buf.extend_from_slice(frame);
+        }
+        buf
+    }
+
+    /// A little endian pcapng block, padding the body to 4 bytes
+    fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let padded = body.len().next_multiple_of(4);
+        let len = (12 + padded) as u32;
+
+        let mut buf = block_type.to_le_bytes().to_vec();
+        buf.extend(len.to_le_bytes());
+        buf.extend_from_slice(body);
+        buf.resize(8 + padded, 0);
+        buf.extend(len.to_le_bytes());
+        buf
+    }
+
+    fn section_header() -> Vec<u8> {
+        let mut body = PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes().to_vec();
+        body.extend([1, 0, 0, 0]);
+        body.extend(u64::MAX.to_le_bytes());
+        block(PCAPNG_SHB, &body)
+    }
+
+    fn interface(linktype: u16, tsresol: Option<u8>) -> Vec<u8> {
+        let mut body = linktype.to_le_bytes().to_vec();
+        body.extend([0, 0]);
+        body.extend(65535u32.to_le_bytes());
+        if let Some(tsresol) = tsresol {
+            body.extend([9, 0, 1, 0, tsresol, 0, 0, 0]);
+            body.extend([0, 0, 0, 0]);
+        }
+        block(0x0000_0001, &body)
+    }
+
+    fn enhanced_packet(interface: u32, ticks: u64, frame: &[u8]) -> Vec<u8> {
+        let mut body = interface.to_le_bytes().to_vec();
+        body.extend(((ticks >> 32) as u32).to_le_bytes());
+        body.extend((ticks as u32).to_le_bytes());
+        body.extend((frame.len() as u32).to_le_bytes());
+        body.extend((frame.len() as u32).to_le_bytes());
+        body.extend_from_slice(frame);
+        block(0x0000_0006, &body)
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let control = ethernet_udp(56789, RIO_UDP_PORT, &[0x00, 0x01, 0x01, 0x04, 0x00, 0x00]);
+        let file = pcap(false, LINKTYPE_ETHERNET, &[(1, 0, &control)]);
+
+        let header_err = parse(&file[..2]).unwrap_err();
+        assert_eq!(header_err.kind(), io::ErrorKind::InvalidData);
+        assert!(parse(&file[..PcapHeader::LEN - 4]).is_err());
+        // The record says there's more frame than there is
+        assert!(parse(&file[..file.len() - 3]).is_err());
+
+        let mut pcapng = section_header();
+        pcapng.extend(interface(LINKTYPE_ETHERNET as u16, None));
+        pcapng.extend(enhanced_packet(0, 1_000_000, &control));
+        assert_eq!(parse(&pcapng).unwrap().len(), 1);
+        assert!(parse(&pcapng[..pcapng.len() - 8]).is_err());
+    }
+
+    #[test]
+    fn byte_swapped_pcap() {
+        let control = [0x00, 0x01, 0x01, 0x04, 0x00, 0x00];
+        let status = [0x00, 0x01, 0x01, 0x04, 0x20, 0x0C, 0x00, 0x00];
+        let records: [(u32, u32, &[u8]); 2] = [
+            (100, 250_000, &ipv4_udp(56789, RIO_UDP_PORT, &control)),
+            (100, 270_000, &ipv4_udp(1110, DS_UDP_PORT, &status)),
+        ];
+
+        for big_endian in [false, true] {
+            let packets = parse(&pcap(big_endian, LINKTYPE_RAW, &records)).unwrap();
+            assert_eq!(packets.len(), 2);
+            assert_eq!(packets[0].direction, Direction::ToRobot);
+            assert_eq!(packets[0].data, control);
+            assert_eq!(packets[1].direction, Direction::FromRobot);
+            assert_eq!(packets[1].data, status);
+            assert_eq!(packets[1].timestamp, Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn pcapng_with_several_interfaces() {
+        let control = [0x00, 0x01, 0x01, 0x04, 0x00, 0x00];
+        let status = [0x00, 0x01, 0x01, 0x04, 0x20, 0x0C, 0x00, 0x00];
+
+        let mut file = section_header();
+        // Ethernet in microseconds, and raw IP in nanoseconds
+        file.extend(interface(LINKTYPE_ETHERNET as u16, None));
+        file.extend(interface(LINKTYPE_RAW as u16, Some(9)));
+        file.extend(enhanced_packet(
+            1,
+            5_000_000_000,
+            &ipv4_udp(1110, DS_UDP_PORT, &status),
+        ));
+        file.extend(enhanced_packet(
+            0,
+            5_020_000,
+            &ethernet_udp(56789, RIO_UDP_PORT, &control),
+        ));
+
+        let packets = parse(&file).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].data, status);
+        assert_eq!(packets[0].timestamp, Duration::ZERO);
+        assert_eq!(packets[1].data, control);
+        assert_eq!(packets[1].timestamp, Duration::from_millis(20));
+
+        file.extend(enhanced_packet(2, 0, &control));
+        assert!(parse(&file).is_err());
+    }
+
+    #[tokio::test]
+    async fn recordings_open_like_captures_of_the_same_traffic() {
+        let control = [0x00, 0x01, 0x01, 0x04, 0x00, 0x00];
+        let status = [0x00, 0x01, 0x01, 0x04, 0x20, 0x0C, 0x00, 0x00];
+
+        let (ds_end, rio_end) = MemoryTransport::pair();
+        let mut recording = Vec::new();
+        {
+            let recorder = Recorder::new(ds_end, &mut recording).unwrap();
+            recorder.send_datagram(&control).await.unwrap();
+            rio_end.send_datagram(&status).await.unwrap();
+            let mut buf = [0u8; 64];
+            recorder.recv_datagram(&mut buf).await.unwrap();
+        }
+        let captured = pcap(
+            false,
+            LINKTYPE_RAW,
+            &[
+                (0, 0, &ipv4_udp(56789, RIO_UDP_PORT, &control)),
+                (0, 10, &ipv4_udp(1110, DS_UDP_PORT, &status)),
+            ],
+        );
+
+        let dir = std::env::temp_dir();
+        let mut opened = Vec::new();
+        for (name, contents) in [("recording", &recording), ("capture", &captured)] {
+            let path = dir.join(format!("robudst-{}-{name}", std::process::id()));
+            std::fs::write(&path, contents).unwrap();
+            let capture = Capture::open(&path);
+            std::fs::remove_file(&path).unwrap();
+
+            let packets: Vec<_> = capture
+                .unwrap()
+                .packets()
+                .iter()
+                .map(|pkt| (pkt.direction, pkt.channel, pkt.data.clone()))
+                .collect();
+            opened.push(packets);
+        }
+
+        assert_eq!(opened[0], opened[1]);
+        assert_eq!(
+            opened[0],
+            [
+                (Direction::ToRobot, Channel::Udp, control.to_vec()),
+                (Direction::FromRobot, Channel::Udp, status.to_vec()),
+            ]
+        );
+    }
+}
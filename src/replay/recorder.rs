@@ -0,0 +1,252 @@
+//! Recording sessions in a compact binary format
+//!
+//! A recording starts with [`MAGIC`] and a version byte, followed by one
+//! record per packet:
+//!
+//! | Size | Field                                                  |
+//! |------|--------------------------------------------------------|
+//! | 4    | Microseconds since the previous record (LE)            |
+//! | 1    | Bit 0 set if from the robot, bit 1 set if TCP          |
+//! | 2    | Payload length (LE)                                    |
+//! | n    | Payload                                                |
+
+use std::{
+    io::{self, BufWriter, Write},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{CapturedPacket, Channel, Direction};
+use crate::{
+    trace::Level,
+    transport::{Transport, TransportFuture},
+};
+
+/// Every recording starts with this
+pub(super) const MAGIC: &[u8; 8] = b"RBDSREC\0";
+const VERSION: u8 = 1;
+
+const FROM_ROBOT: u8 = 0b01;
+const TCP: u8 = 0b10;
+
+struct RecorderState<W: Write> {
+    out: BufWriter<W>,
+    last: Instant,
+}
+
+/// Wraps a [`Transport`], writing everything sent and received through it
+/// to `W`
+///
+/// Recording is opt-in: wrap the transport before handing it to
+/// [`Ds::with_transport`](crate::Ds::with_transport). The result can be loaded
+/// back with [`Capture::open`](super::Capture::open) and replayed.
+pub struct Recorder<T, W: Write> {
+    inner: T,
+    state: Mutex<RecorderState<W>>,
+}
+impl<T: Transport, W: Write + Send> Recorder<T, W> {
+    pub fn new(inner: T, out: W) -> io::Result<Self> {
+        let mut out = BufWriter::new(out);
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+
+        Ok(Self {
+            inner,
+            state: Mutex::new(RecorderState {
+                out,
+                last: Instant::now(),
+            }),
+        })
+    }
+
+    /// Write out anything still buffered
+    pub fn flush(&self) -> io::Result<()> {
+        self.state.lock().unwrap().out.flush()
+    }
+
+    fn record(&self, direction: Direction, channel: Channel, data: &[u8]) {
+        let mut kind = 0;
+        if direction == Direction::FromRobot {
+            kind |= FROM_ROBOT;
+        }
+        if channel == Channel::Tcp {
+            kind |= TCP;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        // Stream writes can be longer than a record allows, so split them up
+        for chunk in data.chunks(u16::MAX as usize) {
+            let delta = now
+                .duration_since(state.last)
+                .as_micros()
+                .min(u32::MAX as u128) as u32;
+            state.last = now;
+
+            let res = state
+                .out
+                .write_all(&delta.to_le_bytes())
+                .and_then(|_| state.out.write_all(&[kind]))
+                .and_then(|_| state.out.write_all(&(chunk.len() as u16).to_le_bytes()))
+                .and_then(|_| state.out.write_all(chunk));
+            // A broken recording shouldn't take the DS down with it
+            if let Err(err) = res {
+                event!(Level::WARN, %err, "Failed to write recording");
+            }
+        }
+    }
+}
+impl<T: Transport, W: Write + Send> Transport for Recorder<T, W> {
+    fn send_datagram<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            self.record(Direction::ToRobot, Channel::Udp, buf);
+            self.inner.send_datagram(buf).await
+        })
+    }
+
+    fn recv_datagram<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        Box::pin(async move {
+            let len = self.inner.recv_datagram(buf).await?;
+            self.record(Direction::FromRobot, Channel::Udp, &buf[..len]);
+            Ok(len)
+        })
+    }
+
+    fn write_stream<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            self.record(Direction::ToRobot, Channel::Tcp, buf);
+            self.inner.write_stream(buf).await
+        })
+    }
+
+    fn read_stream<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        Box::pin(async move {
+            let len = self.inner.read_stream(buf).await?;
+            if len != 0 {
+                self.record(Direction::FromRobot, Channel::Tcp, &buf[..len]);
+            }
+            Ok(len)
+        })
+    }
+
+    fn close(&self) -> TransportFuture<'_, ()> {
+        Box::pin(async move {
+            self.flush()?;
+            self.inner.close().await
+        })
+    }
+}
+
+/// Parse a recording made by [`Recorder`]
+pub(super) fn parse(buf: &[u8]) -> io::Result<Vec<CapturedPacket>> {
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    if buf.get(..MAGIC.len()) != Some(MAGIC) {
+        return Err(invalid("not a recording"));
+    }
+    if buf.get(MAGIC.len()) != Some(&VERSION) {
+        return Err(invalid("unsupported recording version"));
+    }
+
+    let mut packets = Vec::new();
+    let mut timestamp = Duration::ZERO;
+    let mut pos = MAGIC.len() + 1;
+    while pos < buf.len() {
+        let header = buf
+            .get(pos..pos + 7)
+            .ok_or_else(|| invalid("truncated recording"))?;
+        let delta = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let kind = header[4];
+        let len = u16::from_le_bytes([header[5], header[6]]) as usize;
+        let data = buf
+            .get(pos + 7..pos + 7 + len)
+            .ok_or_else(|| invalid("truncated recording"))?;
+        pos += 7 + len;
+
+        timestamp += Duration::from_micros(delta as u64);
+        packets.push(CapturedPacket {
+            timestamp,
+            direction: if kind & FROM_ROBOT != 0 {
+                Direction::FromRobot
+            } else {
+                Direction::ToRobot
+            },
+            channel: if kind & TCP != 0 {
+                Channel::Tcp
+            } else {
+                Channel::Udp
+            },
+            data: data.to_vec(),
+        });
+    }
+
+    Ok(packets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Ds, RobotStatus,
+        proto::incoming::{
+            tcp::StdoutOwned,
+            udp::{Status, Trace},
+        },
+        replay::Capture,
+        test_support::rio,
+        transport::MemoryTransport,
+    };
+
+    #[tokio::test]
+    async fn recorded_session_replays_into_a_new_ds() {
+        let status = rio::status_packet(
+            0,
+            Status::empty(),
+            Trace::ROBOT_CODE | Trace::DISABLED,
+            12.25,
+            false,
+            &[],
+        );
+        let stdout = rio::stdout(&StdoutOwned {
+            timestamp: 2.0,
+            seqnum: 1,
+            message: "Robot program starting".to_owned(),
+        });
+
+        // Record the robot's side of a short session
+        let (ds_end, rio_end) = MemoryTransport::pair();
+        let mut recording = Vec::new();
+        {
+            let recorder = Recorder::new(ds_end, &mut recording).unwrap();
+            let mut buf = [0u8; 256];
+            rio_end.send_datagram(&status).await.unwrap();
+            recorder.recv_datagram(&mut buf).await.unwrap();
+            rio_end.write_stream(&stdout).await.unwrap();
+            let len = recorder.read_stream(&mut buf).await.unwrap();
+            assert_eq!(&buf[..len], stdout);
+        }
+
+        let capture = Capture::from_recording(&recording).unwrap();
+        let (ds_end, rio_end) = MemoryTransport::pair();
+        let ds = Ds::with_transport(4533, ds_end);
+
+        let test = async {
+            capture.replay(&rio_end, false).await.unwrap();
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while ds.console_history().is_empty() || ds.battery_voltage() != 12.25 {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            })
+            .await
+            .expect("replayed packets never arrived");
+        };
+
+        tokio::select! {
+            _ = ds.run() => unreachable!(),
+            _ = test => {}
+        }
+        assert_eq!(ds.status(), RobotStatus::Disabled);
+        assert_eq!(ds.console_history()[0].message, "Robot program starting");
+    }
+}
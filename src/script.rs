@@ -0,0 +1,352 @@
+//! Automating the driver station with [Rhai](https://rhai.rs) scripts
+//!
+//! A script defines functions for whichever hooks it cares about, and they
+//! get called as things happen to the robot. Hooks can control the DS with
+//! the functions below, so teams can automate chores without writing Rust:
+//!
+//! ```rhai
+//! fn on_start() {
+//!     add_voltage_alert(10.5);
+//! }
+//!
+//! // Restart the robot program if it crashes twice
+//! fn on_code_lost() {
+//!     this.crashes = (this.crashes ?? 0) + 1;
+//!     if this.crashes == 2 {
+//!         log("Robot code crashed twice, restarting it");
+//!         restart_code();
+//!     }
+//! }
+//!
+//! fn on_voltage_below(threshold, voltage) {
+//!     if is_enabled() && voltage < 9.0 {
+//!         disable();
+//!     }
+//! }
+//! ```
+//!
+//! | Hook                                  | Called when                            |
+//! |---------------------------------------|----------------------------------------|
+//! | `on_start()`                          | The script starts running              |
+//! | `on_enable()`, `on_disable()`         | The robot is enabled or disabled       |
+//! | `on_code_started()`, `on_code_lost()` | The robot program starts or goes away  |
+//! | `on_comm_lost()`                      | The roboRIO stops sending status       |
+//! | `on_error_message(message, code)`     | The robot reports an error             |
+//! | `on_voltage_below(threshold, voltage)` | The battery drops below a voltage alert |
+//!
+//! Hooks can call `enable()`, `disable()`, `estop()`, `set_mode(mode)` (with
+//! `"auto"`, `"teleop"`, or `"test"`), `restart_code()`, `reboot_rio()`,
+//! `add_voltage_alert(threshold)`, and `log(message)`. They can read
+//! `battery_voltage()`, `is_enabled()`, `has_robot_code()`, and
+//! `is_estopped()`. `this` is an object map that keeps whatever hooks put
+//! in it, for counting things across calls.
+//!
+//! Control calls take effect once the hook returns, in the order they were
+//! made.
+//!
+//! ```no_run
+//! # async fn run(ds: &robudst::Ds) {
+//! use robudst::script::ScriptHooks;
+//!
+//! ScriptHooks::load("hooks.rhai").unwrap().run(ds).await;
+//! # }
+//! ```
+
+use std::{
+    mem,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rhai::{AST, CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    Ds, RobotCodeMode, RobotStatus, battery::VoltageAlert, console::ConsoleLevel, event::DsEvent,
+    telemetry::Telemetry, timer::interval, trace::Level,
+};
+
+/// How often the robot's status is checked for hooks that aren't events
+const STATUS_POLL_PERIOD: Duration = Duration::from_millis(20);
+
+/// Something a hook asked the DS to do
+#[derive(Debug)]
+enum Action {
+    Enable,
+    Disable,
+    Estop,
+    SetMode(RobotCodeMode),
+    RestartCode,
+    RebootRio,
+    AddVoltageAlert(f32),
+}
+
+/// What hooks can see of the DS, and what they've asked it to do
+#[derive(Default)]
+struct Shared {
+    telemetry: Option<Telemetry>,
+    estopped: bool,
+    actions: Vec<Action>,
+}
+
+/// A loaded script, ready to be hooked up to a driver station
+pub struct ScriptHooks {
+    engine: Engine,
+    ast: AST,
+    shared: Arc<Mutex<Shared>>,
+    /// `this` for every hook
+    state: Dynamic,
+}
+impl ScriptHooks {
+    /// Compile the script at `path`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<EvalAltResult>> {
+        let (engine, shared) = Self::engine();
+        let ast = engine.compile_file(path.as_ref().into())?;
+        Ok(Self::with_ast(engine, shared, ast))
+    }
+
+    /// Compile a script from its source
+    pub fn from_source(source: &str) -> Result<Self, Box<EvalAltResult>> {
+        let (engine, shared) = Self::engine();
+        let ast = engine.compile(source)?;
+        Ok(Self::with_ast(engine, shared, ast))
+    }
+
+    fn with_ast(engine: Engine, shared: Arc<Mutex<Shared>>, ast: AST) -> Self {
+        Self {
+            engine,
+            ast,
+            shared,
+            state: Dynamic::from_map(Map::new()),
+        }
+    }
+
+    /// An engine with the DS functions registered
+    fn engine() -> (Engine, Arc<Mutex<Shared>>) {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let mut engine = Engine::new();
+
+        let action = |action: fn() -> Action| {
+            let shared = shared.clone();
+            move || shared.lock().unwrap().actions.push(action())
+        };
+        engine.register_fn("enable", action(|| Action::Enable));
+        engine.register_fn("disable", action(|| Action::Disable));
+        engine.register_fn("estop", action(|| Action::Estop));
+        engine.register_fn("restart_code", action(|| Action::RestartCode));
+        engine.register_fn("reboot_rio", action(|| Action::RebootRio));
+
+        let s = shared.clone();
+        engine.register_fn(
+            "set_mode",
+            move |mode: &str| -> Result<(), Box<EvalAltResult>> {
+                let mode = match mode {
+                    "auto" | "autonomous" => RobotCodeMode::Autonomous,
+                    "teleop" => RobotCodeMode::Teleop,
+                    "test" => RobotCodeMode::Test,
+                    _ => return Err(format!("unknown mode {mode:?}").into()),
+                };
+                s.lock().unwrap().actions.push(Action::SetMode(mode));
+                Ok(())
+            },
+        );
+        let s = shared.clone();
+        engine.register_fn("add_voltage_alert", move |threshold: f64| {
+            let action = Action::AddVoltageAlert(threshold as f32);
+            s.lock().unwrap().actions.push(action);
+        });
+
+        let s = shared.clone();
+        engine.register_fn("battery_voltage", move || {
+            s.lock()
+                .unwrap()
+                .telemetry
+                .as_ref()
+                .map_or(0.0, |t| f64::from(t.battery_voltage))
+        });
+        let s = shared.clone();
+        engine.register_fn("is_enabled", move || {
+            s.lock()
+                .unwrap()
+                .telemetry
+                .as_ref()
+                .is_some_and(|t| t.status == RobotStatus::Enabled)
+        });
+        let s = shared.clone();
+        engine.register_fn("has_robot_code", move || {
+            s.lock()
+                .unwrap()
+                .telemetry
+                .as_ref()
+                .is_some_and(|t| t.has_robot_code)
+        });
+        let s = shared.clone();
+        engine.register_fn("is_estopped", move || s.lock().unwrap().estopped);
+
+        engine.on_print(|message| event!(Level::INFO, output = message, "Script logged"));
+        engine.register_fn("log", |message: &str| {
+            event!(Level::INFO, output = message, "Script logged");
+        });
+
+        (engine, shared)
+    }
+
+    /// Call hooks for everything that happens to `ds` from now on
+    ///
+    /// Runs until the DS is shut down. A hook that fails is logged, and
+    /// doesn't stop the others.
+    pub async fn run(&mut self, ds: &Ds) {
+        let mut events = ds.subscribe();
+        let mut poll = interval(STATUS_POLL_PERIOD);
+        let mut enabled = ds.status() == RobotStatus::Enabled;
+        let mut has_code = ds.has_robot_code();
+
+        self.call(ds, "on_start", ()).await;
+
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(DsEvent::CommLost) => self.call(ds, "on_comm_lost", ()).await,
+                    Ok(DsEvent::ConsoleLine(line)) if line.level == ConsoleLevel::Error => {
+                        let code = i64::from(line.error_code.unwrap_or(0));
+                        self.call(ds, "on_error_message", (line.message, code)).await;
+                    }
+                    Ok(DsEvent::VoltageLow { threshold, voltage }) => {
+                        let args = (f64::from(threshold), f64::from(voltage));
+                        self.call(ds, "on_voltage_below", args).await;
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        event!(Level::WARN, missed, "Scripts missed events");
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+                _ = poll.tick() => {
+                    let now_enabled = ds.status() == RobotStatus::Enabled;
+                    if now_enabled != enabled {
+                        enabled = now_enabled;
+                        let hook = if enabled { "on_enable" } else { "on_disable" };
+                        self.call(ds, hook, ()).await;
+                    }
+
+                    let now_has_code = ds.has_robot_code();
+                    if now_has_code != has_code {
+                        has_code = now_has_code;
+                        let hook = if has_code { "on_code_started" } else { "on_code_lost" };
+                        self.call(ds, hook, ()).await;
+                    }
+                }
+                _ = ds.shutdown.cancelled() => return,
+            }
+        }
+    }
+
+    /// Call the hook `name`, if the script has it, then do what it asked
+    async fn call(&mut self, ds: &Ds, name: &str, args: impl FuncArgs) {
+        if !self.ast.iter_functions().any(|f| f.name == name) {
+            return;
+        }
+
+        {
+            let mut shared = self.shared.lock().unwrap();
+            shared.telemetry = Some(ds.telemetry());
+            shared.estopped = ds.is_estopped();
+        }
+
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.state);
+        if let Err(err) = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut Scope::new(),
+            &self.ast,
+            name,
+            args,
+        ) {
+            event!(Level::WARN, hook = name, %err, "Script hook failed");
+        }
+
+        let actions = mem::take(&mut self.shared.lock().unwrap().actions);
+        for action in actions {
+            event!(Level::DEBUG, hook = name, ?action, "Script action");
+            match action {
+                Action::Enable => {
+                    if let Err(err) = ds.enable().await {
+                        event!(Level::WARN, ?err, "Script couldn't enable the robot");
+                    }
+                }
+                Action::Disable => ds.disable().await,
+                Action::Estop => ds.estop().await,
+                Action::SetMode(mode) => ds.set_mode(mode).await,
+                Action::RestartCode => {
+                    if let Err(err) = ds.restart_code().await {
+                        event!(Level::WARN, %err, "Script couldn't restart robot code");
+                    }
+                }
+                Action::RebootRio => {
+                    if let Err(err) = ds.reboot_rio().await {
+                        event!(Level::WARN, %err, "Script couldn't reboot the roboRIO");
+                    }
+                }
+                Action::AddVoltageAlert(threshold) => {
+                    ds.add_voltage_alert(VoltageAlert::new(threshold));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proto::outgoing::udp::Control,
+        test_support::ds::ControlPacket,
+        transport::{MemoryTransport, Transport},
+    };
+
+    #[tokio::test]
+    async fn hooks_enable_then_disable_the_robot() {
+        let mut hooks = ScriptHooks::from_source(
+            r#"
+            fn on_start() {
+                set_mode("auto");
+                enable();
+            }
+
+            fn on_enable() {
+                disable();
+            }
+            "#,
+        )
+        .unwrap();
+        let (ds_end, rio) = MemoryTransport::pair();
+        let ds = Ds::with_transport(4533, ds_end);
+
+        // Every control packet until the robot is disabled again
+        let sent = async {
+            let mut sent = Vec::new();
+            let mut buf = [0u8; 1024];
+            loop {
+                let len = rio.recv_datagram(&mut buf).await.unwrap();
+                let control = ControlPacket::parse(&buf[..len]).unwrap().control;
+                let was_enabled = sent.iter().any(|c: &Control| c.contains(Control::ENABLED));
+                sent.push(control);
+                if was_enabled && !control.contains(Control::ENABLED) {
+                    return sent;
+                }
+            }
+        };
+
+        let sent = tokio::select! {
+            _ = ds.run() => unreachable!(),
+            _ = hooks.run(&ds) => unreachable!(),
+            res = tokio::time::timeout(Duration::from_secs(5), sent) => res.unwrap(),
+        };
+        let enabled = sent.iter().find(|c| c.contains(Control::ENABLED)).unwrap();
+        assert!(enabled.contains(Control::AUTO));
+        assert_eq!(ds.status(), RobotStatus::Disabled);
+        assert_eq!(ds.mode(), RobotCodeMode::Autonomous);
+    }
+}
@@ -0,0 +1,276 @@
+//! The loops that talk to the roboRIO over a single connection
+//!
+//! Sending and receiving on each channel are independent, so a TCP write
+//! waiting on a slow roboRIO can't hold up control packets, and vice versa.
+//! TCP tags to send are queued on a channel that only the TCP writer reads.
+
+use std::{io, ops::ControlFlow, sync::atomic::Ordering, time::Instant};
+
+use bytes::{Bytes, BytesMut};
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+use tokio_util::{codec::Decoder, sync::CancellationToken};
+
+use crate::{
+    Ds, RobotStatus,
+    connection::ConnectionState,
+    proto::{
+        codec::TcpTagCodec,
+        incoming::{
+            IncomingTagHandler,
+            tcp::{TcpIncomingTag, TcpTagStream},
+        },
+        version::StatusReport,
+    },
+    timer::{interval, sleep_until},
+    trace::Level,
+    transport::Transport,
+};
+
+impl Ds {
+    /// Talk over a single connection until it's lost, or until told to stop
+    ///
+    /// Without a way to reconnect, UDP keeps going after the TCP connection
+    /// closes, since it's the only thing left.
+    ///
+    /// Each loop only stops between operations, so a write is never cut off
+    /// partway through, and tags already queued are written before stopping.
+    pub(crate) async fn run_connection(
+        &self,
+        transport: &dyn Transport,
+        cancel: &CancellationToken,
+    ) -> ControlFlow<()> {
+        let (tcp_tx, tcp_rx) = unbounded_channel();
+        *self.tcp_outgoing.lock().unwrap() = Some(tcp_tx);
+
+        // Cancelled once any loop is done with this connection
+        let done = CancellationToken::new();
+        let watch = async {
+            let flow = tokio::select! {
+                _ = self.stopped(cancel) => ControlFlow::Break(()),
+                _ = self.retarget.notified() => ControlFlow::Continue(()),
+                _ = done.cancelled() => ControlFlow::Continue(()),
+            };
+            done.cancel();
+            flow
+        };
+
+        let (flow, ..) = tokio::join!(
+            watch,
+            self.udp_send_loop(transport, &done),
+            self.udp_recv_loop(transport, &done),
+            self.tcp_send_loop(transport, tcp_rx, &done),
+            self.tcp_recv_loop(transport, &done),
+        );
+
+        self.tcp_outgoing.lock().unwrap().take();
+        flow
+    }
+
+    /// Send control packets, and queue up any TCP tags that are due
+    async fn udp_send_loop(&self, transport: &dyn Transport, done: &CancellationToken) {
+        let mut send_interval = interval(self.send_interval);
+        // Reused for every packet
+        let mut buf = BytesMut::new();
+
+        loop {
+            tokio::select! {
+                _ = done.cancelled() => return,
+                _ = send_interval.tick() => {
+                    self.send_udp_on(transport, &mut buf).await;
+
+                    if self.descriptors_changed.swap(false, Ordering::AcqRel) {
+                        self.send_joystick_descriptors();
+                    }
+                    if self.tcp_state_stale.swap(false, Ordering::AcqRel) {
+                        self.send_tcp_state();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Receive status packets, noticing when they stop coming
+    async fn udp_recv_loop(&self, transport: &dyn Transport, done: &CancellationToken) {
+        let mut buf = [0u8; 1024];
+        // When the last status packet arrived, if comms are up
+        let mut last_status = None;
+
+        loop {
+            let comm_deadline = last_status.map(|at| at + self.comm_timeout.load());
+
+            tokio::select! {
+                _ = done.cancelled() => return,
+                _ = sleep_until(comm_deadline.unwrap_or_else(Instant::now)), if comm_deadline.is_some() => {
+                    last_status = None;
+                    self.comm_lost();
+                }
+                res = transport.recv_datagram(&mut buf) => {
+                    let len = match res {
+                        Ok(len) => len,
+                        // Nothing is listening on the roboRIO right now, which
+                        // some platforms report as a reset
+                        Err(err) if matches!(
+                            err.kind(),
+                            io::ErrorKind::ConnectionRefused
+                                | io::ErrorKind::ConnectionReset
+                                | io::ErrorKind::WouldBlock
+                                | io::ErrorKind::Interrupted
+                        ) => continue,
+                        Err(err) => {
+                            event!(Level::DEBUG, %err, "Failed to receive status");
+                            if self.connector.is_some() {
+                                done.cancel();
+                                return;
+                            }
+                            // Retrying would just spin on the same error, but
+                            // comms still need to time out
+                            if let Some(deadline) = comm_deadline {
+                                tokio::select! {
+                                    _ = done.cancelled() => return,
+                                    _ = sleep_until(deadline) => self.comm_lost(),
+                                }
+                            }
+                            return;
+                        }
+                    };
+
+                    if let Some(report) = self.protocol.read_status(&buf[..len]) {
+                        last_status = Some(Instant::now());
+                        self.store_status(report);
+                        self.forward_status(&buf[..len]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Keep everything a status packet says about the robot
+    pub(crate) fn store_status(&self, report: StatusReport) {
+        let StatusReport {
+            seqnum,
+            status,
+            mode,
+            status_flags,
+            trace,
+            battery,
+            need_date,
+            cpu,
+            ram,
+            free_disk,
+            can,
+            power,
+            joystick_outputs,
+        } = report;
+
+        self.store_status_with(|| {
+            self.count_status_packet(seqnum);
+            self.status.store(status);
+            self.mode.store(mode);
+            let was_browned_out = self.status_flags.swap(status_flags).is_browned_out();
+            self.trace.store(trace);
+            self.record_battery(battery);
+            if status_flags.is_browned_out() && !was_browned_out {
+                self.brownout_occurred(battery);
+            }
+            // Usage stats don't come with every packet
+            if cpu.is_some() {
+                self.cpu_info.store(cpu);
+            }
+            if let Some(ram) = ram {
+                self.store_ram_info(ram);
+            }
+            if let Some(free_disk) = free_disk {
+                self.store_free_disk(free_disk);
+            }
+            if let Some(can) = can {
+                can.handle(self);
+            }
+            if let Some(power) = power {
+                self.store_power_stats(power);
+            }
+        });
+        self.store_joystick_outputs(&joystick_outputs);
+        if need_date {
+            self.date_requested.store(true, Ordering::Release);
+        }
+        self.status_received.notify_waiters();
+    }
+
+    /// Write queued TCP tags, one at a time
+    async fn tcp_send_loop(
+        &self,
+        transport: &dyn Transport,
+        mut tags: UnboundedReceiver<Bytes>,
+        done: &CancellationToken,
+    ) {
+        loop {
+            let tag = tokio::select! {
+                biased;
+                Some(tag) = tags.recv() => tag,
+                _ = done.cancelled() => return,
+            };
+
+            // The receive side notices a broken connection and reconnects
+            if let Err(err) = transport.write_stream(&tag).await {
+                event!(Level::DEBUG, %err, "Failed to send TCP tag");
+            }
+        }
+    }
+
+    /// Read TCP tags and handle them
+    async fn tcp_recv_loop(&self, transport: &dyn Transport, done: &CancellationToken) {
+        let mut buf = [0u8; 4096];
+        let mut frames = BytesMut::new();
+        let mut codec = TcpTagCodec::from_arc(self.protocol.clone());
+
+        loop {
+            let res = tokio::select! {
+                _ = done.cancelled() => return,
+                res = transport.read_stream(&mut buf) => res,
+            };
+
+            let len = match res {
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+                    ) =>
+                {
+                    continue;
+                }
+                Ok(0) | Err(_) => {
+                    // The roboRIO closed the connection or went away
+                    if self.connector.is_some() {
+                        done.cancel();
+                    } else {
+                        self.status.store(RobotStatus::NoCommunication);
+                        self.set_connection_state(ConnectionState::Lost);
+                    }
+                    return;
+                }
+                Ok(len) => len,
+            };
+            frames.extend_from_slice(&buf[..len]);
+
+            while let Ok(Some(frame)) = codec.decode(&mut frames) {
+                self.handle_tcp_frame(&frame);
+            }
+        }
+    }
+
+    /// Handle every tag in a frame from the roboRIO's TCP stream
+    pub(crate) fn handle_tcp_frame(&self, frame: &[u8]) {
+        for tag in TcpTagStream::new(frame) {
+            match tag {
+                TcpIncomingTag::RadioEvent(message) => self.radio_event(message),
+                TcpIncomingTag::UsageReport(tag) => tag.handle(self),
+                TcpIncomingTag::DisableFaults(tag) => tag.handle(self),
+                TcpIncomingTag::RailFaults(tag) => tag.handle(self),
+                TcpIncomingTag::VersionInfo(tag) => tag.handle(self),
+                TcpIncomingTag::ErrorMessage(tag) => tag.handle(self),
+                TcpIncomingTag::Stdout(tag) => tag.handle(self),
+                TcpIncomingTag::Dummy => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Labeling practice runs with a team's real schedule from The Blue Alliance
+//!
+//! Away from the field there's no FMS to say which match is next, so logs
+//! all end up unlabeled. [`TbaClient`] looks up the team's schedule at an
+//! event, and [`TbaClient::sync_next_match`] fills in the match info and
+//! alliance station for whichever match hasn't been played yet.
+//!
+//! ```no_run
+//! # async fn run(ds: &robudst::Ds) -> reqwest::Result<()> {
+//! use robudst::tba::TbaClient;
+//!
+//! let tba = TbaClient::new("<read key from thebluealliance.com/account>");
+//! tba.sync_next_match(ds, "2025mndu").await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Reference: <https://www.thebluealliance.com/apidocs/v3>
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::{AlliancePos, Ds, MatchInfo, MatchType, trace::Level};
+
+const TBA_API: &str = "https://www.thebluealliance.com/api/v3";
+
+/// A match on a team's schedule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledMatch {
+    /// TBA's key for the match, like `2025mndu_qm12`
+    pub key: String,
+    /// TBA's key for the event, like `2025mndu`
+    pub event_key: String,
+    pub match_type: MatchType,
+    /// The match number as FMS counts it
+    pub match_number: u16,
+    /// Where the team plays, if they're in the match
+    pub alliance_pos: Option<AlliancePos>,
+    /// When the match is scheduled
+    pub time: Option<SystemTime>,
+    /// Whether the match has been played
+    pub played: bool,
+}
+
+/// A match as TBA's "simple" match model has it
+#[derive(Deserialize)]
+struct TbaMatch {
+    key: String,
+    event_key: String,
+    comp_level: String,
+    set_number: u16,
+    match_number: u16,
+    alliances: TbaAlliances,
+    time: Option<u64>,
+    actual_time: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct TbaAlliances {
+    red: TbaAlliance,
+    blue: TbaAlliance,
+}
+
+#[derive(Deserialize)]
+struct TbaAlliance {
+    team_keys: Vec<String>,
+}
+
+impl TbaMatch {
+    fn scheduled_for(self, team_number: u16) -> ScheduledMatch {
+        let team_key = format!("frc{team_number}");
+        let station = |alliance: &TbaAlliance| {
+            alliance
+                .team_keys
+                .iter()
+                .position(|key| *key == team_key)
+                .map(|i| i as u8 + 1)
+        };
+        let alliance_pos = station(&self.alliances.red)
+            .map(AlliancePos::Red)
+            .or_else(|| station(&self.alliances.blue).map(AlliancePos::Blue));
+
+        let (match_type, match_number) = match self.comp_level.as_str() {
+            "qm" => (MatchType::Qualification, self.match_number),
+            // Finals are numbered by match, the rest of the playoffs by set,
+            // same as FMS since double elimination
+            "f" => (MatchType::Elimination, self.match_number),
+            _ => (MatchType::Elimination, self.set_number),
+        };
+
+        ScheduledMatch {
+            key: self.key,
+            event_key: self.event_key,
+            match_type,
+            match_number,
+            alliance_pos,
+            time: self.time.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+            played: self.actual_time.is_some(),
+        }
+    }
+}
+
+/// Put `matches` in the order they're scheduled, with any that haven't been
+/// given a time yet at the end
+fn sort_schedule(matches: &mut [ScheduledMatch]) {
+    matches.sort_by_key(|m| (m.time.is_none(), m.time));
+}
+
+/// Reads schedules from The Blue Alliance's API
+pub struct TbaClient {
+    client: Client,
+    auth_key: String,
+}
+impl TbaClient {
+    /// Use `auth_key`, a read API key from a TBA account page
+    pub fn new(auth_key: impl Into<String>) -> Self {
+        Self {
+            client: Client::new(),
+            auth_key: auth_key.into(),
+        }
+    }
+
+    /// Get `team_number`'s matches at `event_key`, in schedule order
+    pub async fn team_matches(
+        &self,
+        team_number: u16,
+        event_key: &str,
+    ) -> reqwest::Result<Vec<ScheduledMatch>> {
+        let matches: Vec<TbaMatch> = self
+            .client
+            .get(format!(
+                "{TBA_API}/team/frc{team_number}/event/{event_key}/matches/simple"
+            ))
+            .header("X-TBA-Auth-Key", &self.auth_key)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut matches = matches
+            .into_iter()
+            .map(|m| m.scheduled_for(team_number))
+            .collect::<Vec<_>>();
+        sort_schedule(&mut matches);
+        Ok(matches)
+    }
+
+    /// Label `ds` with its team's next unplayed match at `event_key`
+    ///
+    /// Returns the match, or `None` if every match has been played, in which
+    /// case `ds` is left alone.
+    pub async fn sync_next_match(
+        &self,
+        ds: &Ds,
+        event_key: &str,
+    ) -> reqwest::Result<Option<ScheduledMatch>> {
+        let next = self
+            .team_matches(ds.team_number(), event_key)
+            .await?
+            .into_iter()
+            .find(|m| !m.played);
+
+        if let Some(next) = &next {
+            event!(Level::INFO, key = %next.key, "Using match from TBA");
+            ds.apply_scheduled_match(next).await;
+        }
+        Ok(next)
+    }
+}
+
+impl Ds {
+    /// Label the DS with `scheduled`'s match info and alliance station
+    pub async fn apply_scheduled_match(&self, scheduled: &ScheduledMatch) {
+        // FMS names events by code, without the year TBA adds
+        let competition = scheduled
+            .event_key
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .to_ascii_uppercase();
+        self.set_match_info(MatchInfo {
+            competition,
+            match_type: scheduled.match_type,
+            match_number: scheduled.match_number,
+            replay_number: 0,
+        })
+        .await;
+        if let Some(alliance_pos) = scheduled.alliance_pos {
+            self.store_alliance(alliance_pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tba_match(comp_level: &str, set_number: u16, match_number: u16) -> TbaMatch {
+        let alliance = |teams: [u16; 3]| TbaAlliance {
+            team_keys: teams.map(|team| format!("frc{team}")).to_vec(),
+        };
+        TbaMatch {
+            key: format!("2025mndu_{comp_level}{match_number}"),
+            event_key: "2025mndu".to_owned(),
+            comp_level: comp_level.to_owned(),
+            set_number,
+            match_number,
+            alliances: TbaAlliances {
+                red: alliance([2502, 4533, 3130]),
+                blue: alliance([1816, 5172, 2052]),
+            },
+            time: Some(1_740_000_000),
+            actual_time: None,
+        }
+    }
+
+    #[test]
+    fn comp_levels_become_fms_match_numbers() {
+        let cases = [
+            (("qm", 1, 12), (MatchType::Qualification, 12)),
+            (("sf", 4, 1), (MatchType::Elimination, 4)),
+            (("f", 1, 2), (MatchType::Elimination, 2)),
+        ];
+
+        for ((comp_level, set_number, match_number), expected) in cases {
+            let scheduled = tba_match(comp_level, set_number, match_number).scheduled_for(4533);
+            assert_eq!(
+                (scheduled.match_type, scheduled.match_number),
+                expected,
+                "{comp_level} {set_number}-{match_number}"
+            );
+        }
+    }
+
+    #[test]
+    fn station_comes_from_the_team_list() {
+        let cases = [
+            (4533, Some(AlliancePos::Red(2))),
+            (2502, Some(AlliancePos::Red(1))),
+            (2052, Some(AlliancePos::Blue(3))),
+            (254, None),
+        ];
+
+        for (team, alliance_pos) in cases {
+            let scheduled = tba_match("qm", 1, 12).scheduled_for(team);
+            assert_eq!(scheduled.alliance_pos, alliance_pos, "team {team}");
+        }
+    }
+
+    #[test]
+    fn unscheduled_matches_sort_last() {
+        let mut matches =
+            [(Some(300), "qm3"), (None, "sf1"), (Some(100), "qm1")].map(|(time, name)| {
+                let mut m = tba_match("qm", 1, 1);
+                m.key = name.to_owned();
+                m.time = time;
+                m.scheduled_for(4533)
+            });
+        sort_schedule(&mut matches);
+
+        let keys = matches.map(|m| m.key);
+        assert_eq!(keys, ["qm1", "qm3", "sf1"]);
+    }
+}
@@ -0,0 +1,84 @@
+//! Everything known about the robot, all at once
+//!
+//! Each getter on [`Ds`] can change between calls, so a dashboard reading a
+//! dozen of them might show half of one status packet and half of the next.
+//! [`Ds::telemetry`] reads them all from the same packet instead.
+
+use std::{
+    hint,
+    sync::atomic::{Ordering, fence},
+};
+
+use crate::{
+    Ds, RobotCodeMode, RobotStatus,
+    connection::ConnectionStats,
+    diagnostics::{CanMetrics, CpuInfo, DisableFaults, PowerStats, RailFaults, RamInfo},
+};
+
+/// A snapshot of the robot's state
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Telemetry {
+    pub status: RobotStatus,
+    pub mode: RobotCodeMode,
+    pub has_robot_code: bool,
+    pub browned_out: bool,
+    pub battery_voltage: f32,
+    pub can: Option<CanMetrics>,
+    pub cpu: Option<CpuInfo>,
+    pub ram: Option<RamInfo>,
+    pub free_disk_bytes: Option<u32>,
+    pub power: Option<PowerStats>,
+    pub disable_faults: DisableFaults,
+    pub rail_faults: RailFaults,
+    pub brownouts: u32,
+    pub connection: ConnectionStats,
+}
+
+impl Ds {
+    /// Get everything known about the robot, as of a single status packet
+    pub fn telemetry(&self) -> Telemetry {
+        loop {
+            // Odd while a status packet is being stored
+            let before = self.status_seq.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                hint::spin_loop();
+                continue;
+            }
+
+            let telemetry = Telemetry {
+                status: self.status(),
+                mode: self.mode(),
+                has_robot_code: self.has_robot_code(),
+                browned_out: self.is_browned_out(),
+                battery_voltage: self.battery_voltage(),
+                can: self.can_metrics(),
+                cpu: self.cpu_info(),
+                ram: self.ram_info(),
+                free_disk_bytes: self.free_disk_bytes(),
+                power: self.power_stats(),
+                disable_faults: self.disable_faults(),
+                rail_faults: self.rail_faults(),
+                brownouts: self.brownout_count(),
+                connection: self.connection_stats(),
+            };
+
+            fence(Ordering::Acquire);
+            if self.status_seq.load(Ordering::Relaxed) == before {
+                return telemetry;
+            }
+        }
+    }
+
+    /// Store status with `store`, without [`Ds::telemetry`] seeing it half
+    /// done
+    ///
+    /// Only the UDP receive loop stores status, so there's never more than
+    /// one of these at a time.
+    pub(crate) fn store_status_with(&self, store: impl FnOnce()) {
+        self.status_seq.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        store();
+        self.status_seq.fetch_add(1, Ordering::Release);
+    }
+}
@@ -0,0 +1,220 @@
+//! Decoding what the driver station sends, for checking it from a pretend
+//! roboRIO
+
+use robudst_proto::{
+    control::{self, ControlHeader},
+    tag::UdpTags,
+};
+
+use crate::{
+    AlliancePos, MatchInfo, MatchType,
+    joystick::{Joystick, JoystickDescriptor},
+    proto::outgoing::{
+        tcp::{AxisKind, JoystickKind},
+        udp::{Control, Request},
+    },
+};
+
+/// A control packet, decoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlPacket {
+    pub seqnum: u16,
+    pub comm_version: u8,
+    pub control: Control,
+    pub request: Request,
+    pub alliance: AlliancePos,
+    /// Every tag's id and data, in the order sent
+    pub tags: Vec<(u8, Vec<u8>)>,
+}
+impl ControlPacket {
+    /// Decode a control packet, or `None` if it's malformed
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        let header = ControlHeader::parse(buf)?;
+        let mut tags = UdpTags::new(&buf[control::HEADER_LEN..]);
+        let parsed = (&mut tags).map(|tag| (tag.id, tag.data.to_vec())).collect();
+        // Anything left over is a tag cut off by the end of the packet
+        if !tags.remaining().is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            seqnum: header.seqnum,
+            comm_version: header.comm_version,
+            control: header.control,
+            request: header.request,
+            alliance: AlliancePos::from_station(header.station)?,
+            tags: parsed,
+        })
+    }
+
+    fn tags_with_id(&self, id: u8) -> impl Iterator<Item = &[u8]> {
+        self.tags
+            .iter()
+            .filter(move |(tag_id, _)| *tag_id == id)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    /// Every joystick sent, in slot order
+    pub fn joysticks(&self) -> Option<Vec<Joystick>> {
+        self.tags_with_id(0x0C).map(parse_joystick).collect()
+    }
+
+    /// Seconds left in the match period, if sent
+    pub fn countdown(&self) -> Option<f32> {
+        let data = self.tags_with_id(0x07).next()?;
+        Some(f32::from_be_bytes(data.try_into().ok()?))
+    }
+
+    /// The timezone name, if sent
+    pub fn timezone(&self) -> Option<&str> {
+        str::from_utf8(self.tags_with_id(0x10).next()?).ok()
+    }
+}
+
+/// Decode the data of a joystick tag
+pub fn parse_joystick(data: &[u8]) -> Option<Joystick> {
+    let (&axis_count, rest) = data.split_first()?;
+    let (axes, rest) = rest.split_at_checked(axis_count as usize)?;
+    let axes = axes.iter().map(|&axis| axis as i8).collect::<Vec<_>>();
+
+    let (&button_count, rest) = rest.split_first()?;
+    let (packed, rest) = rest.split_at_checked((button_count as usize).div_ceil(8))?;
+    // Big endian, first button in the least significant bit
+    let packed = packed
+        .iter()
+        .fold(0u64, |bits, &byte| bits << 8 | byte as u64);
+    let buttons = (0..button_count)
+        .map(|i| packed & 1 << i != 0)
+        .collect::<Vec<_>>();
+
+    let (&pov_count, rest) = rest.split_first()?;
+    if rest.len() != pov_count as usize * 2 {
+        return None;
+    }
+    let povs = rest
+        .chunks_exact(2)
+        .map(|pov| i16::from_be_bytes([pov[0], pov[1]]))
+        .collect::<Vec<_>>();
+
+    Joystick::new(&axes, &buttons, &povs).ok()
+}
+
+/// A TCP tag from the driver station, decoded
+#[derive(Debug, Clone, PartialEq)]
+pub enum DsTag {
+    JoystickDescriptor {
+        index: u8,
+        descriptor: JoystickDescriptor,
+    },
+    MatchInfo(MatchInfo),
+    GameData(String),
+    /// A tag this module doesn't decode
+    Other {
+        id: u8,
+        data: Vec<u8>,
+    },
+}
+
+/// Decode every whole tag in `buf`, stopping at a malformed one
+pub fn parse_tcp_tags(buf: &[u8]) -> Vec<DsTag> {
+    let mut tags = Vec::new();
+    let mut rest = buf;
+    while let Some((size, after)) = rest.split_first_chunk::<2>() {
+        let Some((tag, after)) = after.split_at_checked(u16::from_be_bytes(*size) as usize) else {
+            break;
+        };
+        rest = after;
+
+        let Some((&id, data)) = tag.split_first() else {
+            continue;
+        };
+        match parse_tcp_tag(id, data) {
+            Some(tag) => tags.push(tag),
+            None => break,
+        }
+    }
+    tags
+}
+
+fn parse_tcp_tag(id: u8, data: &[u8]) -> Option<DsTag> {
+    let tag = match id {
+        0x02 => {
+            let [index, is_xbox, kind, name_len, ..] = *data else {
+                return None;
+            };
+            let (name, rest) = data[4..].split_at_checked(name_len as usize)?;
+            let (&axis_count, rest) = rest.split_first()?;
+            let (axes, rest) = rest.split_at_checked(axis_count as usize)?;
+            let [button_count, pov_count] = *rest else {
+                return None;
+            };
+
+            DsTag::JoystickDescriptor {
+                index,
+                descriptor: JoystickDescriptor {
+                    name: str::from_utf8(name).ok()?.to_owned(),
+                    kind: joystick_kind(kind as i8),
+                    is_xbox: is_xbox != 0,
+                    axes: axes.iter().map(|&axis| axis_kind(axis)).collect(),
+                    button_count,
+                    pov_count,
+                },
+            }
+        }
+        0x07 => {
+            let (&len, rest) = data.split_first()?;
+            let (competition, rest) = rest.split_at_checked(len as usize)?;
+            let [match_type, number_hi, number_lo, replay_number] = *rest else {
+                return None;
+            };
+
+            DsTag::MatchInfo(MatchInfo {
+                competition: str::from_utf8(competition).ok()?.to_owned(),
+                match_type: MatchType::from_u8(match_type),
+                match_number: u16::from_be_bytes([number_hi, number_lo]),
+                replay_number,
+            })
+        }
+        0x0E => DsTag::GameData(str::from_utf8(data).ok()?.to_owned()),
+        _ => DsTag::Other {
+            id,
+            data: data.to_vec(),
+        },
+    };
+    Some(tag)
+}
+
+fn joystick_kind(kind: i8) -> JoystickKind {
+    use JoystickKind::*;
+    [
+        XInputUnknown,
+        XInputGamepad,
+        XInputWheel,
+        XInputArcade,
+        XInputFlightStick,
+        XInputDancePad,
+        XInputGuitar,
+        XInputGuitar2,
+        XInputDrumKit,
+        XInputGuitar3,
+        XInputArcadePad,
+        HIDJoystick,
+        HIDGamepad,
+        HIDDriving,
+        HIDFlight,
+        HIDFirstPerson,
+    ]
+    .into_iter()
+    .find(|&known| known as i8 == kind)
+    .unwrap_or(Unknown)
+}
+
+fn axis_kind(axis: u8) -> AxisKind {
+    match axis {
+        1 => AxisKind::Y,
+        2 => AxisKind::Z,
+        3 => AxisKind::Twist,
+        4 => AxisKind::Throttle,
+        _ => AxisKind::X,
+    }
+}
@@ -0,0 +1,413 @@
+//! Golden packets for every tag type, byte for byte
+//!
+//! These are assembled by hand, field by field, from the tag layouts this
+//! crate decodes, with known values chosen so tests can check every field.
+//! They aren't copied from a capture, so they only show the encoders and
+//! decoders agree with each other. A fixture taken from real traffic should
+//! cite in its doc comment where it was captured, and with which roboRIO
+//! image and driver station version.
+//!
+//! Each TCP constant is one whole tag with its size prefix, and each UDP
+//! constant is one whole datagram.
+//!
+//! When a tag changes for a new season, add a fixture for the new layout
+//! alongside the old one rather than editing it, so both keep decoding.
+
+// TCP tags from the roboRIO
+
+/// A radio event
+pub const RADIO_EVENT: &[u8] = &[
+    0x00, 0x14, 0x00, 0x52, 0x61, 0x64, 0x69, 0x6F, 0x20, 0x6C, 0x69, 0x6E, 0x6B, 0x20, 0x72, 0x65,
+    0x73, 0x74, 0x6F, 0x72, 0x65, 0x64,
+];
+
+/// Usage reporting for team 4533: four things used
+pub const USAGE_REPORT: &[u8] = &[
+    0x00, 0x0C, 0x01, 0x11, 0xB5, 0x00, 0x15, 0x01, 0x3C, 0x00, 0x3C, 0x01, 0x41, 0x00,
+];
+
+/// 2 disables from comms, 1 from the 12V supply
+pub const DISABLE_FAULTS: &[u8] = &[0x00, 0x05, 0x04, 0x00, 0x02, 0x00, 0x01];
+
+/// No 6V faults, 3 on the 5V rail, 1 on the 3.3V rail
+pub const RAIL_FAULTS: &[u8] = &[0x00, 0x07, 0x05, 0x00, 0x00, 0x00, 0x03, 0x00, 0x01];
+
+/// The roboRIO image version
+pub const VERSION_INFO: &[u8] = &[
+    0x00, 0x2A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x0D, 0x72, 0x6F, 0x62, 0x6F, 0x52, 0x49, 0x4F, 0x20,
+    0x49, 0x6D, 0x61, 0x67, 0x65, 0x16, 0x46, 0x52, 0x43, 0x5F, 0x72, 0x6F, 0x62, 0x6F, 0x52, 0x49,
+    0x4F, 0x32, 0x5F, 0x32, 0x30, 0x32, 0x35, 0x5F, 0x76, 0x32, 0x2E, 0x30,
+];
+
+/// A WPILib warning, with no call stack
+pub const WARNING_MESSAGE: &[u8] = &[
+    0x00, 0xBB, 0x0B, 0x41, 0x48, 0x00, 0x00, 0x00, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00,
+    0x00, 0x4C, 0x4A, 0x6F, 0x79, 0x73, 0x74, 0x69, 0x63, 0x6B, 0x20, 0x42, 0x75, 0x74, 0x74, 0x6F,
+    0x6E, 0x20, 0x31, 0x20, 0x6F, 0x6E, 0x20, 0x70, 0x6F, 0x72, 0x74, 0x20, 0x30, 0x20, 0x6E, 0x6F,
+    0x74, 0x20, 0x61, 0x76, 0x61, 0x69, 0x6C, 0x61, 0x62, 0x6C, 0x65, 0x2C, 0x20, 0x63, 0x68, 0x65,
+    0x63, 0x6B, 0x20, 0x69, 0x66, 0x20, 0x63, 0x6F, 0x6E, 0x74, 0x72, 0x6F, 0x6C, 0x6C, 0x65, 0x72,
+    0x20, 0x69, 0x73, 0x20, 0x70, 0x6C, 0x75, 0x67, 0x67, 0x65, 0x64, 0x20, 0x69, 0x6E, 0x00, 0x5B,
+    0x65, 0x64, 0x75, 0x2E, 0x77, 0x70, 0x69, 0x2E, 0x66, 0x69, 0x72, 0x73, 0x74, 0x2E, 0x77, 0x70,
+    0x69, 0x6C, 0x69, 0x62, 0x6A, 0x2E, 0x44, 0x72, 0x69, 0x76, 0x65, 0x72, 0x53, 0x74, 0x61, 0x74,
+    0x69, 0x6F, 0x6E, 0x2E, 0x72, 0x65, 0x70, 0x6F, 0x72, 0x74, 0x4A, 0x6F, 0x79, 0x73, 0x74, 0x69,
+    0x63, 0x6B, 0x55, 0x6E, 0x70, 0x6C, 0x75, 0x67, 0x67, 0x65, 0x64, 0x57, 0x61, 0x72, 0x6E, 0x69,
+    0x6E, 0x67, 0x28, 0x44, 0x72, 0x69, 0x76, 0x65, 0x72, 0x53, 0x74, 0x61, 0x74, 0x69, 0x6F, 0x6E,
+    0x2E, 0x6A, 0x61, 0x76, 0x61, 0x3A, 0x31, 0x33, 0x38, 0x36, 0x29, 0x00, 0x00,
+];
+
+/// A CAN error, with a call stack
+pub const ERROR_MESSAGE: &[u8] = &[
+    0x00, 0x68, 0x0B, 0x41, 0xF0, 0x00, 0x00, 0x00, 0x04, 0x00, 0x01, 0xFF, 0xFF, 0xFC, 0x15, 0x01,
+    0x00, 0x16, 0x43, 0x41, 0x4E, 0x3A, 0x20, 0x4D, 0x65, 0x73, 0x73, 0x61, 0x67, 0x65, 0x20, 0x6E,
+    0x6F, 0x74, 0x20, 0x66, 0x6F, 0x75, 0x6E, 0x64, 0x00, 0x11, 0x66, 0x72, 0x63, 0x3A, 0x3A, 0x54,
+    0x61, 0x6C, 0x6F, 0x6E, 0x46, 0x58, 0x3A, 0x3A, 0x47, 0x65, 0x74, 0x00, 0x2D, 0x61, 0x74, 0x20,
+    0x66, 0x72, 0x63, 0x3A, 0x3A, 0x54, 0x61, 0x6C, 0x6F, 0x6E, 0x46, 0x58, 0x3A, 0x3A, 0x47, 0x65,
+    0x74, 0x0A, 0x61, 0x74, 0x20, 0x52, 0x6F, 0x62, 0x6F, 0x74, 0x3A, 0x3A, 0x54, 0x65, 0x6C, 0x65,
+    0x6F, 0x70, 0x50, 0x65, 0x72, 0x69, 0x6F, 0x64, 0x69, 0x63,
+];
+
+/// Robot program output
+pub const STDOUT: &[u8] = &[
+    0x00, 0x3B, 0x0C, 0x40, 0x60, 0x00, 0x00, 0x00, 0x01, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A,
+    0x2A, 0x2A, 0x2A, 0x20, 0x52, 0x6F, 0x62, 0x6F, 0x74, 0x20, 0x70, 0x72, 0x6F, 0x67, 0x72, 0x61,
+    0x6D, 0x20, 0x73, 0x74, 0x61, 0x72, 0x74, 0x75, 0x70, 0x20, 0x63, 0x6F, 0x6D, 0x70, 0x6C, 0x65,
+    0x74, 0x65, 0x20, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A,
+];
+
+/// The tag with unknown meaning the roboRIO sends
+pub const DUMMY: &[u8] = &[0x00, 0x05, 0x0D, 0x04, 0x04, 0x04, 0x04];
+
+// Status packets from the roboRIO
+
+/// An enabled teleop status packet from a roboRIO with code running, and
+/// no tags
+pub const STATUS_MINIMAL: &[u8] = &[0x00, 0x2A, 0x01, 0x04, 0x32, 0x0C, 0x80, 0x00];
+
+/// A disabled autonomous status packet with every tag the roboRIO sends:
+/// joystick outputs, disk, CPU, RAM, a PDP log, the unknown `0x09`, and
+/// CAN metrics
+pub const STATUS_WITH_TAGS: &[u8] = &[
+    0x00, 0x2B, 0x01, 0x0A, 0x34, 0x0B, 0xC0, 0x01, 0x09, 0x01, 0x05, 0x00, 0x00, 0x00, 0x80, 0x00,
+    0x00, 0x00, 0x05, 0x04, 0x20, 0x00, 0x00, 0x00, 0x15, 0x05, 0x40, 0x00, 0x00, 0x00, 0x3F, 0x00,
+    0x00, 0x00, 0x3F, 0xC0, 0x00, 0x00, 0x41, 0xF0, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x09, 0x06,
+    0x00, 0x00, 0x10, 0x00, 0x08, 0x00, 0x00, 0x00, 0x1A, 0x08, 0x00, 0x00, 0xC3, 0xF1, 0xEC, 0xB7,
+    0x3C, 0xD2, 0xF0, 0x5A, 0xDA, 0x77, 0x8E, 0x1F, 0x96, 0xE9, 0x70, 0xB4, 0xF0, 0xFD, 0x2F, 0x87,
+    0x2A, 0xA0, 0x46, 0x0A, 0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0F, 0x0E,
+    0x3E, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x03, 0x04,
+];
+
+// Control packets from the driver station
+
+/// An enabled autonomous control packet for Blue 2, with a countdown and
+/// one joystick
+pub const CONTROL_WITH_JOYSTICK: &[u8] = &[
+    0x00, 0x00, 0x01, 0x06, 0x00, 0x04, 0x05, 0x07, 0x41, 0x70, 0x00, 0x00, 0x0B, 0x0C, 0x03, 0x81,
+    0x00, 0x7F, 0x0A, 0x02, 0x05, 0x01, 0x00, 0x5A,
+];
+
+/// A disabled teleop control packet for Red 1, answering the roboRIO's
+/// request for the date: 18:15:30.25 UTC on October 17, 2026, in Chicago
+pub const CONTROL_WITH_DATE: &[u8] = &[
+    0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x0B, 0x0F, 0x00, 0x03, 0xD0, 0x90, 0x1E, 0x0F, 0x12, 0x11,
+    0x09, 0x7E, 0x10, 0x10, 0x41, 0x6D, 0x65, 0x72, 0x69, 0x63, 0x61, 0x2F, 0x43, 0x68, 0x69, 0x63,
+    0x61, 0x67, 0x6F,
+];
+
+// TCP tags from the driver station
+
+/// An Xbox controller in slot 0
+pub const JOYSTICK_DESCRIPTOR: &[u8] = &[
+    0x00, 0x1D, 0x02, 0x00, 0x01, 0x01, 0x0F, 0x58, 0x62, 0x6F, 0x78, 0x20, 0x43, 0x6F, 0x6E, 0x74,
+    0x72, 0x6F, 0x6C, 0x6C, 0x65, 0x72, 0x06, 0x00, 0x01, 0x02, 0x03, 0x04, 0x00, 0x0A, 0x01,
+];
+
+/// Qualification 12 at MNDU
+pub const MATCH_INFO: &[u8] = &[
+    0x00, 0x0A, 0x07, 0x04, 0x4D, 0x4E, 0x44, 0x55, 0x02, 0x00, 0x0C, 0x00,
+];
+
+/// Game data
+pub const GAME_DATA: &[u8] = &[0x00, 0x04, 0x0E, 0x4C, 0x52, 0x4C];
+
+/// Every TCP tag from the roboRIO
+pub const TCP_FROM_ROBOT: &[&[u8]] = &[
+    RADIO_EVENT,
+    USAGE_REPORT,
+    DISABLE_FAULTS,
+    RAIL_FAULTS,
+    VERSION_INFO,
+    WARNING_MESSAGE,
+    ERROR_MESSAGE,
+    STDOUT,
+    DUMMY,
+];
+
+/// Every status packet from the roboRIO
+pub const UDP_FROM_ROBOT: &[&[u8]] = &[STATUS_MINIMAL, STATUS_WITH_TAGS];
+
+/// Every control packet from the driver station
+pub const UDP_TO_ROBOT: &[&[u8]] = &[CONTROL_WITH_JOYSTICK, CONTROL_WITH_DATE];
+
+/// Every TCP tag from the driver station
+pub const TCP_TO_ROBOT: &[&[u8]] = &[JOYSTICK_DESCRIPTOR, MATCH_INFO, GAME_DATA];
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::{
+        AlliancePos, Ds, MatchInfo, MatchType, RobotCodeMode, RobotStatus,
+        diagnostics::{
+            CanMetrics, CpuInfo, DisableFaults, PowerStats, RailFaults, RamInfo, ResourceUsage,
+            UsageEntry, VersionEntry,
+        },
+        joystick::{Joystick, JoystickDescriptor, JoystickOutput},
+        proto::{
+            incoming::tcp::{
+                ErrorMessageOwned, ErrorMsgFlags, StdoutOwned, TcpIncomingTagOwned, TcpTagStream,
+            },
+            outgoing::{
+                tcp::{AxisKind, JoystickKind, TcpOutgoingTag},
+                udp::{Control, Request, UdpOutgoingPacket, UdpOutgoingTag},
+            },
+            version::{Frc2015, ProtocolVersion},
+        },
+        test_support::{
+            ds::{ControlPacket, DsTag, parse_tcp_tags},
+            rio,
+        },
+    };
+
+    fn tcp_from_robot() -> Vec<TcpIncomingTagOwned> {
+        vec![
+            TcpIncomingTagOwned::RadioEvent("Radio link restored".to_owned()),
+            TcpIncomingTagOwned::UsageReport(ResourceUsage {
+                team: 4533,
+                entries: [(0x15, 1), (0x3C, 0), (0x3C, 1), (0x41, 0)]
+                    .map(|(resource, instance)| UsageEntry { resource, instance })
+                    .to_vec(),
+            }),
+            TcpIncomingTagOwned::DisableFaults(DisableFaults {
+                comms: 2,
+                power_12v: 1,
+            }),
+            TcpIncomingTagOwned::RailFaults(RailFaults {
+                rail_6v: 0,
+                rail_5v: 3,
+                rail_3v3: 1,
+            }),
+            TcpIncomingTagOwned::VersionInfo(VersionEntry {
+                device_type: 0,
+                id: 0,
+                name: "roboRIO Image".to_owned(),
+                version: "FRC_roboRIO2_2025_v2.0".to_owned(),
+            }),
+            TcpIncomingTagOwned::ErrorMessage(ErrorMessageOwned {
+                timestamp: 12.5,
+                seqnum: 3,
+                error_code: 1,
+                flags: ErrorMsgFlags::empty(),
+                details: "Joystick Button 1 on port 0 not available, check if controller is plugged in".to_owned(),
+                location: "edu.wpi.first.wpilibj.DriverStation.reportJoystickUnpluggedWarning(DriverStation.java:1386)".to_owned(),
+                call_stack: String::new(),
+            }),
+            TcpIncomingTagOwned::ErrorMessage(ErrorMessageOwned {
+                timestamp: 30.0,
+                seqnum: 4,
+                error_code: -1003,
+                flags: ErrorMsgFlags::ERROR,
+                details: "CAN: Message not found".to_owned(),
+                location: "frc::TalonFX::Get".to_owned(),
+                call_stack: "at frc::TalonFX::Get\nat Robot::TeleopPeriodic".to_owned(),
+            }),
+            TcpIncomingTagOwned::Stdout(StdoutOwned {
+                timestamp: 3.5,
+                seqnum: 1,
+                message: "********** Robot program startup complete **********".to_owned(),
+            }),
+            TcpIncomingTagOwned::Dummy,
+        ]
+    }
+
+    #[test]
+    fn decodes_tcp_tags_from_robot() {
+        let buf = TCP_FROM_ROBOT.concat();
+        let tags = TcpTagStream::new(&buf)
+            .map(|tag| tag.to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(tags, tcp_from_robot());
+    }
+
+    #[test]
+    fn encodes_tcp_tags_from_robot() {
+        for (fixture, tag) in TCP_FROM_ROBOT.iter().zip(tcp_from_robot()) {
+            let encoded = match &tag {
+                TcpIncomingTagOwned::RadioEvent(message) => rio::radio_event(message),
+                TcpIncomingTagOwned::UsageReport(usage) => rio::usage_report(usage),
+                TcpIncomingTagOwned::DisableFaults(faults) => rio::disable_faults(faults),
+                TcpIncomingTagOwned::RailFaults(faults) => rio::rail_faults(faults),
+                TcpIncomingTagOwned::VersionInfo(entry) => rio::version_info(entry),
+                TcpIncomingTagOwned::ErrorMessage(message) => rio::error_message(message),
+                TcpIncomingTagOwned::Stdout(stdout) => rio::stdout(stdout),
+                TcpIncomingTagOwned::Dummy => rio::tcp_tag(0x0D, &[4; 4]),
+            };
+            assert_eq!(encoded, *fixture, "{tag:?}");
+        }
+    }
+
+    #[test]
+    fn decodes_status_packets() {
+        let minimal = Frc2015.read_status(STATUS_MINIMAL).unwrap();
+        assert_eq!(minimal.seqnum, 42);
+        assert_eq!(minimal.status, RobotStatus::Enabled);
+        assert_eq!(minimal.mode, RobotCodeMode::Teleop);
+        assert!(minimal.trace.has_robot_code() && minimal.trace.is_roborio());
+        assert_eq!(minimal.battery, 12.5);
+        assert!(!minimal.need_date);
+        assert_eq!(minimal.cpu, None);
+        assert!(minimal.joystick_outputs.is_empty());
+
+        let tagged = Frc2015.read_status(STATUS_WITH_TAGS).unwrap();
+        assert_eq!(tagged.seqnum, 43);
+        assert_eq!(tagged.status, RobotStatus::Disabled);
+        assert_eq!(tagged.mode, RobotCodeMode::Autonomous);
+        assert_eq!(tagged.battery, 11.75);
+        assert!(tagged.need_date);
+        assert_eq!(
+            tagged.joystick_outputs,
+            [JoystickOutput {
+                outputs: 0b101,
+                left_rumble: 0x8000,
+                right_rumble: 0,
+            }]
+        );
+        assert_eq!(tagged.free_disk, Some(512 * 1024 * 1024));
+        assert_eq!(
+            tagged.cpu,
+            Some(CpuInfo {
+                num_cpus: 2.0,
+                time_critical: 0.5,
+                above_normal: 1.5,
+                normal: 30.0,
+                low: 2.0,
+            })
+        );
+        assert_eq!(
+            tagged.ram,
+            Some(RamInfo {
+                block: 4096,
+                free_space: 128 * 1024 * 1024,
+            })
+        );
+        let Some(PowerStats::Pdp(pdp)) = tagged.power else {
+            panic!("no PDP log");
+        };
+        for (i, current) in pdp.currents.iter().enumerate() {
+            assert_eq!(*current, (i * 60 + 3) as f32 * 0.125);
+        }
+        assert_eq!((pdp.resistance, pdp.voltage), (42, 12.0));
+        assert_eq!(
+            tagged.can,
+            Some(CanMetrics {
+                utilization: 0.25,
+                bus_off: 1,
+                tx_full: 2,
+                rx_errors: 3,
+                tx_errors: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn encodes_control_packets() {
+        let ds = Ds::builder(4533).build().unwrap();
+        ds.status.store(RobotStatus::Enabled);
+        ds.mode.store(RobotCodeMode::Autonomous);
+        ds.set_alliance(AlliancePos::Blue(2)).unwrap();
+        let buttons = (0..10).map(|i| matches!(i, 0 | 2 | 9)).collect::<Vec<_>>();
+        ds.set_joystick(0, &[-127, 0, 127], &buttons, &[90])
+            .unwrap();
+
+        let countdown = [UdpOutgoingTag::Countdown { countdown: 15.0 }];
+        let mut pkt = UdpOutgoingPacket::build(&ds);
+        pkt.set_tags(&countdown);
+        assert_eq!(pkt.write(), CONTROL_WITH_JOYSTICK);
+
+        let decoded = ControlPacket::parse(CONTROL_WITH_JOYSTICK).unwrap();
+        assert_eq!(decoded.control, Control::ENABLED | Control::AUTO);
+        assert_eq!(decoded.request, Request::empty());
+        assert_eq!(decoded.alliance, AlliancePos::Blue(2));
+        assert_eq!(decoded.countdown(), Some(15.0));
+        assert_eq!(
+            decoded.joysticks(),
+            Some(vec![
+                Joystick::new(&[-127, 0, 127], &buttons, &[90]).unwrap()
+            ])
+        );
+
+        // Disabled, so dropping the DS doesn't try to disable the robot
+        ds.status.store(RobotStatus::Disabled);
+        ds.mode.store(RobotCodeMode::Teleop);
+        ds.set_alliance(AlliancePos::Red(1)).unwrap();
+        ds.store_joystick(0, None).unwrap();
+
+        let at = UNIX_EPOCH + Duration::new(1_792_260_930, 250_000_000);
+        let date = [
+            UdpOutgoingTag::date(at),
+            UdpOutgoingTag::Timezone {
+                timezone: "America/Chicago",
+            },
+        ];
+        let mut pkt = UdpOutgoingPacket::build(&ds);
+        pkt.set_tags(&date);
+        assert_eq!(pkt.write(), CONTROL_WITH_DATE);
+
+        let decoded = ControlPacket::parse(CONTROL_WITH_DATE).unwrap();
+        assert_eq!(decoded.alliance, AlliancePos::Red(1));
+        assert_eq!(decoded.timezone(), Some("America/Chicago"));
+        assert_eq!(decoded.joysticks(), Some(Vec::new()));
+    }
+
+    #[test]
+    fn encodes_tcp_tags_to_robot() {
+        let descriptor = JoystickDescriptor {
+            name: "Xbox Controller".to_owned(),
+            kind: JoystickKind::XInputGamepad,
+            is_xbox: true,
+            axes: vec![
+                AxisKind::X,
+                AxisKind::Y,
+                AxisKind::Z,
+                AxisKind::Twist,
+                AxisKind::Throttle,
+                AxisKind::X,
+            ],
+            button_count: 10,
+            pov_count: 1,
+        };
+        let match_info = MatchInfo {
+            competition: "MNDU".to_owned(),
+            match_type: MatchType::Qualification,
+            match_number: 12,
+            replay_number: 0,
+        };
+
+        assert_eq!(descriptor.as_tag(0).write(), JOYSTICK_DESCRIPTOR);
+        assert_eq!(match_info.as_tag().write(), MATCH_INFO);
+        let game_data = TcpOutgoingTag::GameData { game_data: "LRL" };
+        assert_eq!(game_data.write(), GAME_DATA);
+
+        assert_eq!(
+            parse_tcp_tags(&TCP_TO_ROBOT.concat()),
+            [
+                DsTag::JoystickDescriptor {
+                    index: 0,
+                    descriptor,
+                },
+                DsTag::MatchInfo(match_info),
+                DsTag::GameData("LRL".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,336 @@
+//! Packets and codecs for testing code that talks to the driver station
+//! protocol
+//!
+//! Enabled by the `test-support` feature, so crates built on this one can
+//! use it in their own tests:
+//!
+//! - [`fixtures`] has golden packets for every tag type, byte for byte
+//! - [`rio`] encodes what a roboRIO sends, for pretending to be one
+//! - [`ds`] decodes what the driver station sends, for checking it
+//!
+//! This crate checks its own encoders and decoders against the fixtures, and
+//! against each other with round-trip property tests, so a tag extended for
+//! a new season can't quietly break the old layout.
+
+pub mod ds;
+pub mod fixtures;
+pub mod rio;
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::{ds, rio};
+    use crate::{
+        AlliancePos, MatchInfo, MatchType,
+        diagnostics::{
+            CanMetrics, CpuInfo, DisableFaults, RailFaults, RamInfo, ResourceUsage, UsageEntry,
+            VersionEntry,
+        },
+        joystick::{Joystick, JoystickDescriptor, JoystickOutput, MAX_AXES, MAX_BUTTONS, MAX_POVS},
+        proto::{
+            fms::{DsStatus, DsStatusPacket, FmsControlPacket},
+            incoming::{
+                tcp::{
+                    ErrorMessageOwned, ErrorMsgFlags, StdoutOwned, TcpIncomingTagOwned,
+                    TcpTagStream,
+                },
+                udp::{Status, Trace},
+            },
+            outgoing::{
+                tcp::{AxisKind, JoystickKind, TcpOutgoingTag},
+                udp::Control,
+            },
+            version::{Frc2015, ProtocolVersion},
+        },
+    };
+
+    /// Decode a single TCP tag from the roboRIO
+    fn decode_tcp(buf: &[u8]) -> Vec<TcpIncomingTagOwned> {
+        TcpTagStream::new(buf).map(|tag| tag.to_owned()).collect()
+    }
+
+    /// A float that survives a round trip and compares equal to itself
+    fn float() -> impl Strategy<Value = f32> {
+        -1.0e6f32..1.0e6
+    }
+
+    /// A battery voltage that fits the wire format exactly
+    fn battery() -> impl Strategy<Value = f32> {
+        (any::<u8>(), any::<u8>()).prop_map(|(volts, frac)| volts as f32 + frac as f32 / 256.0)
+    }
+
+    fn alliance() -> impl Strategy<Value = AlliancePos> {
+        prop_oneof![
+            (1u8..=3).prop_map(AlliancePos::Red),
+            (1u8..=3).prop_map(AlliancePos::Blue),
+        ]
+    }
+
+    fn match_type() -> impl Strategy<Value = MatchType> {
+        prop::sample::select(vec![
+            MatchType::None,
+            MatchType::Practice,
+            MatchType::Qualification,
+            MatchType::Elimination,
+        ])
+    }
+
+    fn joystick() -> impl Strategy<Value = Joystick> {
+        (
+            prop::collection::vec(any::<i8>(), 0..=MAX_AXES),
+            prop::collection::vec(any::<bool>(), 0..=MAX_BUTTONS),
+            prop::collection::vec(any::<i16>(), 0..=MAX_POVS),
+        )
+            .prop_map(|(axes, buttons, povs)| Joystick::new(&axes, &buttons, &povs).unwrap())
+    }
+
+    fn descriptor() -> impl Strategy<Value = JoystickDescriptor> {
+        let kinds = vec![
+            JoystickKind::Unknown,
+            JoystickKind::XInputGamepad,
+            JoystickKind::XInputArcadePad,
+            JoystickKind::HIDJoystick,
+            JoystickKind::HIDFirstPerson,
+        ];
+        let axes = vec![
+            AxisKind::X,
+            AxisKind::Y,
+            AxisKind::Z,
+            AxisKind::Twist,
+            AxisKind::Throttle,
+        ];
+        (
+            "\\PC{0,32}",
+            prop::sample::select(kinds),
+            any::<bool>(),
+            prop::collection::vec(prop::sample::select(axes), 0..=MAX_AXES),
+            0..=MAX_BUTTONS as u8,
+            0..=MAX_POVS as u8,
+        )
+            .prop_map(|(name, kind, is_xbox, axes, button_count, pov_count)| {
+                JoystickDescriptor {
+                    name,
+                    kind,
+                    is_xbox,
+                    axes,
+                    button_count,
+                    pov_count,
+                }
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn usage_report_round_trips(
+            team in any::<u16>(),
+            entries in prop::collection::vec(any::<(u8, u8)>(), 0..32),
+        ) {
+            let usage = ResourceUsage {
+                team,
+                entries: entries
+                    .into_iter()
+                    .map(|(resource, instance)| UsageEntry { resource, instance })
+                    .collect(),
+            };
+            prop_assert_eq!(
+                decode_tcp(&rio::usage_report(&usage)),
+                [TcpIncomingTagOwned::UsageReport(usage)]
+            );
+        }
+
+        #[test]
+        fn fault_counts_round_trip(counts in any::<[u16; 5]>()) {
+            let disable = DisableFaults { comms: counts[0], power_12v: counts[1] };
+            let rail = RailFaults { rail_6v: counts[2], rail_5v: counts[3], rail_3v3: counts[4] };
+
+            let mut buf = rio::disable_faults(&disable);
+            buf.extend(rio::rail_faults(&rail));
+            prop_assert_eq!(
+                decode_tcp(&buf),
+                [
+                    TcpIncomingTagOwned::DisableFaults(disable),
+                    TcpIncomingTagOwned::RailFaults(rail),
+                ]
+            );
+        }
+
+        #[test]
+        fn version_info_round_trips(
+            device_type in any::<u8>(),
+            id in any::<u8>(),
+            name in "\\PC{0,48}",
+            version in "\\PC{0,48}",
+        ) {
+            let entry = VersionEntry { device_type, id, name, version };
+            prop_assert_eq!(
+                decode_tcp(&rio::version_info(&entry)),
+                [TcpIncomingTagOwned::VersionInfo(entry)]
+            );
+        }
+
+        #[test]
+        fn console_output_round_trips(
+            timestamp in float(),
+            seqnum in any::<u16>(),
+            error_code in any::<i32>(),
+            flags in 0u8..4,
+            strings in prop::array::uniform3("\\PC{0,64}"),
+            output in "\\PC{0,128}",
+        ) {
+            let [details, location, call_stack] = strings;
+            let message = ErrorMessageOwned {
+                timestamp,
+                seqnum,
+                error_code,
+                flags: ErrorMsgFlags::from_bits_truncate(flags),
+                details,
+                location,
+                call_stack,
+            };
+            let stdout = StdoutOwned { timestamp, seqnum, message: output };
+
+            let mut buf = rio::error_message(&message);
+            buf.extend(rio::stdout(&stdout));
+            prop_assert_eq!(
+                decode_tcp(&buf),
+                [
+                    TcpIncomingTagOwned::ErrorMessage(message),
+                    TcpIncomingTagOwned::Stdout(stdout),
+                ]
+            );
+        }
+
+        #[test]
+        fn status_packet_round_trips(
+            seqnum in any::<u16>(),
+            status in any::<u8>(),
+            trace in any::<u8>(),
+            battery in battery(),
+            need_date in any::<bool>(),
+            outputs in prop::collection::vec(any::<(u32, u16, u16)>(), 0..=6),
+            free_disk in any::<u32>(),
+            cpu in prop::array::uniform5(float()),
+            ram in any::<(u32, u32)>(),
+            can in (float(), any::<u32>(), any::<u32>(), any::<u8>(), any::<u8>()),
+        ) {
+            let outputs = outputs
+                .into_iter()
+                .map(|(outputs, left_rumble, right_rumble)| JoystickOutput {
+                    outputs,
+                    left_rumble,
+                    right_rumble,
+                })
+                .collect::<Vec<_>>();
+            let cpu = CpuInfo {
+                num_cpus: cpu[0],
+                time_critical: cpu[1],
+                above_normal: cpu[2],
+                normal: cpu[3],
+                low: cpu[4],
+            };
+            let ram = RamInfo { block: ram.0, free_space: ram.1 };
+            let can = CanMetrics {
+                utilization: can.0,
+                bus_off: can.1,
+                tx_full: can.2,
+                rx_errors: can.3,
+                tx_errors: can.4,
+            };
+
+            let mut tags = outputs.iter().map(rio::joystick_output).collect::<Vec<_>>();
+            tags.extend([
+                rio::disk_space(free_disk),
+                rio::cpu_info(&cpu),
+                rio::ram_info(&ram),
+                rio::can_metrics(&can),
+            ]);
+            let status = Status::from_bits_retain(status);
+            let trace = Trace::from_bits_retain(trace);
+            let packet = rio::status_packet(seqnum, status, trace, battery, need_date, &tags);
+
+            let report = Frc2015.read_status(&packet).unwrap();
+            prop_assert_eq!(report.seqnum, seqnum);
+            prop_assert_eq!(report.status_flags, status);
+            prop_assert_eq!(report.trace, trace);
+            prop_assert_eq!(report.battery, battery);
+            prop_assert_eq!(report.need_date, need_date);
+            prop_assert_eq!(report.joystick_outputs, outputs);
+            prop_assert_eq!(report.free_disk, Some(free_disk));
+            prop_assert_eq!(report.cpu, Some(cpu));
+            prop_assert_eq!(report.ram, Some(ram));
+            prop_assert_eq!(report.can, Some(can));
+        }
+
+        #[test]
+        fn joystick_tag_round_trips(joystick in joystick()) {
+            prop_assert_eq!(ds::parse_joystick(&joystick.as_tag().write()), Some(joystick));
+        }
+
+        #[test]
+        fn tcp_tags_to_robot_round_trip(
+            descriptor in descriptor(),
+            index in 0u8..6,
+            competition in "[A-Z0-9]{0,16}",
+            match_type in match_type(),
+            match_number in any::<u16>(),
+            replay_number in any::<u8>(),
+            game_data in "\\PC{0,32}",
+        ) {
+            let match_info = MatchInfo { competition, match_type, match_number, replay_number };
+
+            let mut buf = descriptor.as_tag(index).write();
+            buf.extend(match_info.as_tag().write());
+            buf.extend(TcpOutgoingTag::GameData { game_data: &game_data }.write());
+            prop_assert_eq!(
+                ds::parse_tcp_tags(&buf),
+                [
+                    ds::DsTag::JoystickDescriptor { index, descriptor },
+                    ds::DsTag::MatchInfo(match_info),
+                    ds::DsTag::GameData(game_data),
+                ]
+            );
+        }
+
+        #[test]
+        fn fms_packets_round_trip(
+            seqnum in any::<u16>(),
+            control in any::<u8>(),
+            station in alliance(),
+            match_type in match_type(),
+            match_number in any::<u16>(),
+            play_number in any::<u8>(),
+            remaining_time in any::<u16>(),
+            status in any::<u8>(),
+            team_number in any::<u16>(),
+            battery in battery(),
+        ) {
+            let control = Control::from_bits_truncate(control);
+            let packet = FmsControlPacket {
+                seqnum,
+                control,
+                station,
+                match_type,
+                match_number,
+                play_number,
+                remaining_time,
+            };
+            let parsed = FmsControlPacket::parse(&packet.write()).unwrap();
+            prop_assert_eq!(parsed.seqnum, seqnum);
+            prop_assert_eq!(parsed.control, control);
+            prop_assert_eq!(parsed.station, station);
+            prop_assert_eq!(parsed.match_type, match_type);
+            prop_assert_eq!(parsed.match_number, match_number);
+            prop_assert_eq!(parsed.play_number, play_number);
+            prop_assert_eq!(parsed.remaining_time, remaining_time);
+
+            let status = DsStatus::from_bits_truncate(status);
+            let packet = DsStatusPacket { seqnum, status, team_number, battery };
+            let parsed = DsStatusPacket::parse(&packet.write()).unwrap();
+            prop_assert_eq!(parsed.seqnum, seqnum);
+            prop_assert_eq!(parsed.status, status);
+            prop_assert_eq!(parsed.team_number, team_number);
+            prop_assert_eq!(parsed.battery, battery);
+        }
+    }
+}
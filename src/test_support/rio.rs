@@ -0,0 +1,158 @@
+//! Encoding what the roboRIO sends, for pretending to be one
+//!
+//! Every tag comes back whole, with its size prefix, ready to be sent or
+//! put in a status packet.
+
+use robudst_proto::status::StatusHeader;
+
+use crate::{
+    diagnostics::{
+        CanMetrics, CpuInfo, DisableFaults, RailFaults, RamInfo, ResourceUsage, VersionEntry,
+    },
+    joystick::JoystickOutput,
+    proto::incoming::{
+        tcp::{ErrorMessageOwned, StdoutOwned},
+        udp::{Status, Trace},
+    },
+};
+
+/// Size-prefix a TCP tag, the size covering the id and data
+pub fn tcp_tag(id: u8, data: &[u8]) -> Vec<u8> {
+    let mut tag = ((data.len() + 1) as u16).to_be_bytes().to_vec();
+    tag.push(id);
+    tag.extend_from_slice(data);
+    tag
+}
+
+/// Size-prefix a status packet tag, the size covering the id and data
+///
+/// Panics if `data` is longer than a tag can be.
+pub fn udp_tag(id: u8, data: &[u8]) -> Vec<u8> {
+    let size = u8::try_from(data.len() + 1).expect("UDP tag too long");
+    let mut tag = vec![size, id];
+    tag.extend_from_slice(data);
+    tag
+}
+
+/// A status packet, with `tags` (each from [`udp_tag`]) after the header
+pub fn status_packet(
+    seqnum: u16,
+    status: Status,
+    trace: Trace,
+    battery: f32,
+    need_date: bool,
+    tags: &[Vec<u8>],
+) -> Vec<u8> {
+    let header = StatusHeader {
+        seqnum,
+        comm_version: 0x01,
+        status,
+        trace,
+        battery,
+        need_date,
+    };
+    let mut packet = header.to_bytes().to_vec();
+    for tag in tags {
+        packet.extend_from_slice(tag);
+    }
+    packet
+}
+
+pub fn radio_event(message: &str) -> Vec<u8> {
+    tcp_tag(0x00, message.as_bytes())
+}
+
+pub fn usage_report(usage: &ResourceUsage) -> Vec<u8> {
+    let mut data = usage.team.to_be_bytes().to_vec();
+    // Unknown, always zero
+    data.push(0x00);
+    for entry in &usage.entries {
+        data.extend([entry.resource, entry.instance]);
+    }
+    tcp_tag(0x01, &data)
+}
+
+pub fn disable_faults(faults: &DisableFaults) -> Vec<u8> {
+    let mut data = faults.comms.to_be_bytes().to_vec();
+    data.extend(faults.power_12v.to_be_bytes());
+    tcp_tag(0x04, &data)
+}
+
+pub fn rail_faults(faults: &RailFaults) -> Vec<u8> {
+    let mut data = faults.rail_6v.to_be_bytes().to_vec();
+    data.extend(faults.rail_5v.to_be_bytes());
+    data.extend(faults.rail_3v3.to_be_bytes());
+    tcp_tag(0x05, &data)
+}
+
+/// Panics if the name or version is over 255 bytes
+pub fn version_info(entry: &VersionEntry) -> Vec<u8> {
+    let short = |s: &str| u8::try_from(s.len()).expect("version string too long");
+
+    let mut data = vec![entry.device_type, 0, 0, entry.id, short(&entry.name)];
+    data.extend_from_slice(entry.name.as_bytes());
+    data.push(short(&entry.version));
+    data.extend_from_slice(entry.version.as_bytes());
+    tcp_tag(0x0A, &data)
+}
+
+pub fn error_message(message: &ErrorMessageOwned) -> Vec<u8> {
+    let mut data = message.timestamp.to_be_bytes().to_vec();
+    data.extend(message.seqnum.to_be_bytes());
+    // Not decoded by the DS
+    data.extend(1u16.to_be_bytes());
+    data.extend(message.error_code.to_be_bytes());
+    data.push(message.flags.bits());
+    for s in [&message.details, &message.location, &message.call_stack] {
+        data.extend((s.len() as u16).to_be_bytes());
+        data.extend_from_slice(s.as_bytes());
+    }
+    tcp_tag(0x0B, &data)
+}
+
+pub fn stdout(stdout: &StdoutOwned) -> Vec<u8> {
+    let mut data = stdout.timestamp.to_be_bytes().to_vec();
+    data.extend(stdout.seqnum.to_be_bytes());
+    data.extend_from_slice(stdout.message.as_bytes());
+    tcp_tag(0x0C, &data)
+}
+
+pub fn joystick_output(output: &JoystickOutput) -> Vec<u8> {
+    let mut data = output.outputs.to_le_bytes().to_vec();
+    data.extend(output.left_rumble.to_be_bytes());
+    data.extend(output.right_rumble.to_be_bytes());
+    udp_tag(0x01, &data)
+}
+
+/// Free disk space, in bytes
+pub fn disk_space(free: u32) -> Vec<u8> {
+    udp_tag(0x04, &free.to_be_bytes())
+}
+
+pub fn cpu_info(cpu: &CpuInfo) -> Vec<u8> {
+    let data = [
+        cpu.num_cpus,
+        cpu.time_critical,
+        cpu.above_normal,
+        cpu.normal,
+        cpu.low,
+    ]
+    .into_iter()
+    .flat_map(f32::to_be_bytes)
+    .collect::<Vec<_>>();
+    udp_tag(0x05, &data)
+}
+
+pub fn ram_info(ram: &RamInfo) -> Vec<u8> {
+    let mut data = ram.block.to_be_bytes().to_vec();
+    data.extend(ram.free_space.to_be_bytes());
+    udp_tag(0x06, &data)
+}
+
+pub fn can_metrics(can: &CanMetrics) -> Vec<u8> {
+    let mut data = can.utilization.to_be_bytes().to_vec();
+    data.extend(can.bus_off.to_be_bytes());
+    data.extend(can.tx_full.to_be_bytes());
+    data.extend([can.rx_errors, can.tx_errors]);
+    udp_tag(0x0E, &data)
+}
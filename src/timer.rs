@@ -0,0 +1,98 @@
+//! Timers that work on whichever async runtime the crate is built for
+//!
+//! With `runtime-tokio` they're tokio's, and have to be polled inside a tokio
+//! runtime. Without it they come from `futures-timer`, which keeps time on a
+//! thread of its own and works under any executor.
+
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+/// A future didn't finish before its [`timeout`]
+#[derive(Debug)]
+pub(crate) struct Elapsed;
+
+/// Wait for `duration` to pass
+#[cfg(feature = "runtime-tokio")]
+pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await
+}
+
+/// Wait for `duration` to pass
+#[cfg(not(feature = "runtime-tokio"))]
+pub(crate) async fn sleep(duration: Duration) {
+    futures_timer::Delay::new(duration).await
+}
+
+/// Wait until `deadline`, which may already have passed
+pub(crate) async fn sleep_until(deadline: Instant) {
+    sleep(deadline.saturating_duration_since(Instant::now())).await
+}
+
+/// Wait for `future`, giving up after `duration`
+pub(crate) async fn timeout<F: Future>(
+    duration: Duration,
+    future: F,
+) -> Result<F::Output, Elapsed> {
+    futures_lite::future::or(async { Ok(future.await) }, async {
+        sleep(duration).await;
+        Err(Elapsed)
+    })
+    .await
+}
+
+/// Ticks every `period`, starting right away
+///
+/// Ticks that were missed, because nothing was waiting for them, all happen
+/// at once to catch up.
+pub(crate) fn interval(period: Duration) -> Interval {
+    Interval {
+        next: Instant::now(),
+        period,
+    }
+}
+
+/// See [`interval`]
+pub(crate) struct Interval {
+    next: Instant,
+    period: Duration,
+}
+impl Interval {
+    /// Wait for the next tick
+    ///
+    /// Dropping the future before it's done doesn't skip the tick.
+    pub(crate) async fn tick(&mut self) {
+        sleep_until(self.next).await;
+        self.next += self.period;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn timeout_gives_up() {
+        assert!(timeout(Duration::from_millis(50), async {}).await.is_ok());
+        assert!(
+            timeout(Duration::from_millis(10), sleep(Duration::from_secs(5)))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn interval_catches_up() {
+        let mut interval = interval(Duration::from_millis(10));
+        interval.tick().await;
+        sleep(Duration::from_millis(35)).await;
+
+        // Three ticks were missed, so they're all due already
+        let start = Instant::now();
+        for _ in 0..3 {
+            interval.tick().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,65 @@
+//! Optional diagnostics through `tracing`
+//!
+//! Everything a consumer needs is delivered through [`DsEvent`](crate::event::DsEvent)s
+//! and getters on [`Ds`](crate::Ds), so logging is only a convenience. With
+//! the `tracing` feature off, `event!`, `debug!` and `warn!`
+//! don't log anything.
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Level;
+
+#[cfg(not(feature = "tracing"))]
+#[allow(clippy::upper_case_acronyms, dead_code)]
+pub(crate) enum Level {
+    TRACE,
+    DEBUG,
+    INFO,
+    WARN,
+    ERROR,
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! event {
+    ($level:expr, $($fields:tt)*) => {{
+        let _ = $level;
+        touch_fields!($($fields)*);
+    }};
+}
+
+#[cfg(not(feature = "tracing"))]
+#[allow(unused_macros)]
+macro_rules! debug {
+    ($($fields:tt)*) => {{
+        touch_fields!($($fields)*);
+    }};
+}
+
+#[cfg(not(feature = "tracing"))]
+#[allow(unused_macros)]
+macro_rules! warn {
+    ($($fields:tt)*) => {{
+        touch_fields!($($fields)*);
+    }};
+}
+
+/// Borrow everything that would have been logged, so values that are only
+/// logged don't trip unused variable warnings
+#[cfg(not(feature = "tracing"))]
+macro_rules! touch_fields {
+    () => {};
+    (% $value:expr $(, $($rest:tt)*)?) => {
+        let _ = &$value;
+        touch_fields!($($($rest)*)?);
+    };
+    (? $value:expr $(, $($rest:tt)*)?) => {
+        let _ = &$value;
+        touch_fields!($($($rest)*)?);
+    };
+    ($name:ident = $($rest:tt)*) => {
+        touch_fields!($($rest)*);
+    };
+    ($value:expr $(, $($rest:tt)*)?) => {
+        let _ = &$value;
+        touch_fields!($($($rest)*)?);
+    };
+}
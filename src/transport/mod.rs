@@ -0,0 +1,202 @@
+//! How the driver station talks to the roboRIO
+//!
+//! [`Ds`](crate::Ds) only needs two channels: datagrams (control packets out,
+//! status packets in) and a reliable byte stream (TCP tags both ways). The
+//! [`Transport`] trait abstracts over those, so the real sockets can be
+//! swapped for in-memory channels, recorded traffic, or a relay.
+//!
+//! [`SocketTransport`] is built on tokio's sockets, so it needs the
+//! `runtime-tokio` feature (on by default), and isn't there on wasm32 at all.
+//! Under another executor, like async-std or smol, implement [`Transport`]
+//! on its sockets and hand it to [`Ds::with_transport`](crate::Ds::with_transport),
+//! or implement [`Connect`] for [`Ds::with_connector`](crate::Ds::with_connector).
+//! A DS or dashboard built for a browser reaches the roboRIO through a
+//! [`relay`] instead.
+
+use std::{future::Future, io, pin::Pin};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf, duplex, split},
+    sync::{
+        Mutex,
+        mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+    },
+};
+
+pub mod relay;
+#[cfg(all(feature = "runtime-tokio", not(target_arch = "wasm32")))]
+mod socket;
+
+pub use relay::RelayTransport;
+#[cfg(all(feature = "runtime-tokio", not(target_arch = "wasm32")))]
+pub use socket::{SocketConnector, SocketTransport};
+
+/// Port the roboRIO accepts DS TCP connections on
+pub const RIO_TCP_PORT: u16 = 1740;
+/// Port the roboRIO receives control packets on
+pub const RIO_UDP_PORT: u16 = 1110;
+/// Port the DS receives status packets on
+pub const DS_UDP_PORT: u16 = 1150;
+
+/// Ports used to talk to the roboRIO
+///
+/// Only worth changing for simulators or relays that can't use the
+/// standard ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ports {
+    pub rio_tcp: u16,
+    pub rio_udp: u16,
+    pub ds_udp: u16,
+}
+impl Default for Ports {
+    fn default() -> Self {
+        Self {
+            rio_tcp: RIO_TCP_PORT,
+            rio_udp: RIO_UDP_PORT,
+            ds_udp: DS_UDP_PORT,
+        }
+    }
+}
+
+/// Future returned by [`Transport`] methods
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + 'a>>;
+
+/// A connection between the driver station and the roboRIO
+///
+/// Every method takes `&self`, so sending and receiving can happen at the
+/// same time. Implementations are expected to handle that themselves.
+pub trait Transport: Send + Sync {
+    /// Send a single datagram to the roboRIO
+    fn send_datagram<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()>;
+
+    /// Wait for a single datagram from the roboRIO, returning its length
+    ///
+    /// Datagrams longer than `buf` are truncated.
+    fn recv_datagram<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize>;
+
+    /// Write all of `buf` to the byte stream
+    fn write_stream<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()>;
+
+    /// Read whatever is available from the byte stream, returning how much
+    /// was read
+    ///
+    /// Returns `0` once the stream is closed.
+    fn read_stream<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize>;
+
+    /// Flush and close the byte stream, so the roboRIO sees a clean
+    /// disconnect
+    ///
+    /// Does nothing by default.
+    fn close(&self) -> TransportFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn send_datagram<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        (**self).send_datagram(buf)
+    }
+
+    fn recv_datagram<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        (**self).recv_datagram(buf)
+    }
+
+    fn write_stream<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        (**self).write_stream(buf)
+    }
+
+    fn read_stream<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        (**self).read_stream(buf)
+    }
+
+    fn close(&self) -> TransportFuture<'_, ()> {
+        (**self).close()
+    }
+}
+
+/// Makes new connections to the roboRIO
+///
+/// [`Ds`](crate::Ds) uses this to connect lazily, and to reconnect whenever
+/// the roboRIO goes away (like when it reboots).
+pub trait Connect: Send + Sync {
+    /// Try to open a new connection to `team_number`'s roboRIO
+    ///
+    /// Connectors with a fixed address can ignore the team number.
+    fn connect(&self, team_number: u16) -> TransportFuture<'_, Box<dyn Transport>>;
+}
+
+/// One end of an in-memory transport, made with [`MemoryTransport::pair`]
+///
+/// Whatever one end sends, the other receives. Handy for tests, and for
+/// pretending to be a roboRIO without one.
+pub struct MemoryTransport {
+    datagram_tx: UnboundedSender<Vec<u8>>,
+    datagram_rx: Mutex<UnboundedReceiver<Vec<u8>>>,
+    stream_rx: Mutex<ReadHalf<DuplexStream>>,
+    stream_tx: Mutex<WriteHalf<DuplexStream>>,
+}
+impl MemoryTransport {
+    /// How much stream data can be in flight before writes wait on reads
+    const STREAM_CAPACITY: usize = 64 * 1024;
+
+    /// Make two connected ends, one for the DS and one for the "roboRIO"
+    pub fn pair() -> (Self, Self) {
+        let (a_datagram_tx, b_datagram_rx) = unbounded_channel();
+        let (b_datagram_tx, a_datagram_rx) = unbounded_channel();
+        let (a_stream, b_stream) = duplex(Self::STREAM_CAPACITY);
+        let (a_stream_rx, a_stream_tx) = split(a_stream);
+        let (b_stream_rx, b_stream_tx) = split(b_stream);
+
+        (
+            Self {
+                datagram_tx: a_datagram_tx,
+                datagram_rx: Mutex::new(a_datagram_rx),
+                stream_rx: Mutex::new(a_stream_rx),
+                stream_tx: Mutex::new(a_stream_tx),
+            },
+            Self {
+                datagram_tx: b_datagram_tx,
+                datagram_rx: Mutex::new(b_datagram_rx),
+                stream_rx: Mutex::new(b_stream_rx),
+                stream_tx: Mutex::new(b_stream_tx),
+            },
+        )
+    }
+}
+impl Transport for MemoryTransport {
+    fn send_datagram<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            self.datagram_tx
+                .send(buf.to_vec())
+                .map_err(|_| io::Error::from(io::ErrorKind::ConnectionAborted))
+        })
+    }
+
+    fn recv_datagram<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        Box::pin(async move {
+            let datagram = self
+                .datagram_rx
+                .lock()
+                .await
+                .recv()
+                .await
+                .ok_or(io::ErrorKind::ConnectionAborted)?;
+
+            let len = datagram.len().min(buf.len());
+            buf[..len].copy_from_slice(&datagram[..len]);
+            Ok(len)
+        })
+    }
+
+    fn write_stream<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(async move { self.stream_tx.lock().await.write_all(buf).await })
+    }
+
+    fn read_stream<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        Box::pin(async move { self.stream_rx.lock().await.read(buf).await })
+    }
+
+    fn close(&self) -> TransportFuture<'_, ()> {
+        Box::pin(async move { self.stream_tx.lock().await.shutdown().await })
+    }
+}
@@ -0,0 +1,311 @@
+//! Carrying a [`Transport`] over a link that only passes whole messages,
+//! like a WebSocket or a WebRTC data channel
+//!
+//! Browsers can't open the UDP and TCP sockets the roboRIO speaks, so a
+//! dashboard hosted in one has to go through a relay on a machine that can.
+//! Each message on the link is either one datagram or one chunk of the byte
+//! stream, with a byte in front saying which (see [`encode`] and
+//! [`decode`]). An empty stream chunk means the stream was closed.
+//!
+//! Both ends of the link get a [`RelayTransport`], and hook its
+//! [`RelayLink`] up to whatever carries the messages. The relay then
+//! [`bridge`]s its end to the real roboRIO:
+//!
+//! ```no_run
+//! # async fn ws_recv() -> Option<Vec<u8>> { None }
+//! # async fn ws_send(_message: Vec<u8>) {}
+//! # #[cfg(feature = "runtime-tokio")]
+//! # async fn relay() -> std::io::Result<()> {
+//! use robudst::transport::{SocketTransport, relay::{RelayTransport, bridge}};
+//!
+//! let rio = SocketTransport::connect([10, 45, 33, 2].into()).await?;
+//! let (relay, link) = RelayTransport::new();
+//!
+//! tokio::select! {
+//!     res = bridge(&rio, &relay) => res?,
+//!     _ = async {
+//!         while let Some(message) = ws_recv().await {
+//!             if link.deliver(&message).is_err() {
+//!                 break;
+//!             }
+//!         }
+//!     } => {}
+//!     _ = async {
+//!         while let Some(message) = link.next_message().await {
+//!             ws_send(message).await;
+//!         }
+//!     } => {}
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! On the far side, the other [`RelayTransport`] goes to
+//! [`Ds::with_transport`](crate::Ds::with_transport). A browser dashboard
+//! that only needs to show what the robot is doing can skip the
+//! [`Ds`](crate::Ds) and run what comes out of [`decode`] through
+//! [`proto`](crate::proto) directly.
+
+use std::io;
+
+use tokio::sync::{
+    Mutex,
+    mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+};
+
+use super::{Transport, TransportFuture};
+use crate::replay::Channel;
+
+/// Marks a message holding a datagram
+const DATAGRAM: u8 = 0x00;
+/// Marks a message holding a chunk of the byte stream
+const STREAM: u8 = 0x01;
+
+/// Wrap a datagram or stream chunk into a single link message
+pub fn encode(channel: Channel, data: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(data.len() + 1);
+    message.push(match channel {
+        Channel::Udp => DATAGRAM,
+        Channel::Tcp => STREAM,
+    });
+    message.extend_from_slice(data);
+    message
+}
+
+/// Unwrap a link message made by [`encode`]
+///
+/// Returns `None` for an empty message, or one from something other than a
+/// relay.
+pub fn decode(message: &[u8]) -> Option<(Channel, &[u8])> {
+    let (&kind, data) = message.split_first()?;
+    let channel = match kind {
+        DATAGRAM => Channel::Udp,
+        STREAM => Channel::Tcp,
+        _ => return None,
+    };
+    Some((channel, data))
+}
+
+/// A transport whose datagrams and byte stream travel as messages over a
+/// [`RelayLink`]
+///
+/// Whatever one end sends comes out of the other, once the links are
+/// passing messages between each other.
+pub struct RelayTransport {
+    outgoing: UnboundedSender<Vec<u8>>,
+    datagram_rx: Mutex<UnboundedReceiver<Vec<u8>>>,
+    /// Stream data, and whatever didn't fit in the last read
+    stream_rx: Mutex<(UnboundedReceiver<Vec<u8>>, Vec<u8>)>,
+}
+impl RelayTransport {
+    /// Make a transport, and the link its messages go in and out through
+    pub fn new() -> (Self, RelayLink) {
+        let (outgoing_tx, outgoing_rx) = unbounded_channel();
+        let (datagram_tx, datagram_rx) = unbounded_channel();
+        let (stream_tx, stream_rx) = unbounded_channel();
+
+        (
+            Self {
+                outgoing: outgoing_tx,
+                datagram_rx: Mutex::new(datagram_rx),
+                stream_rx: Mutex::new((stream_rx, Vec::new())),
+            },
+            RelayLink {
+                outgoing: Mutex::new(outgoing_rx),
+                datagram_tx,
+                stream_tx,
+            },
+        )
+    }
+
+    fn send(&self, channel: Channel, data: &[u8]) -> io::Result<()> {
+        self.outgoing
+            .send(encode(channel, data))
+            .map_err(|_| io::ErrorKind::ConnectionAborted.into())
+    }
+}
+impl Transport for RelayTransport {
+    fn send_datagram<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(async move { self.send(Channel::Udp, buf) })
+    }
+
+    fn recv_datagram<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        Box::pin(async move {
+            let datagram = self
+                .datagram_rx
+                .lock()
+                .await
+                .recv()
+                .await
+                .ok_or(io::ErrorKind::ConnectionAborted)?;
+
+            let len = datagram.len().min(buf.len());
+            buf[..len].copy_from_slice(&datagram[..len]);
+            Ok(len)
+        })
+    }
+
+    fn write_stream<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            // An empty chunk would look like the stream closing
+            if buf.is_empty() {
+                return Ok(());
+            }
+            self.send(Channel::Tcp, buf)
+        })
+    }
+
+    fn read_stream<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        Box::pin(async move {
+            let mut stream = self.stream_rx.lock().await;
+            let (rx, leftover) = &mut *stream;
+            if leftover.is_empty() {
+                match rx.recv().await {
+                    Some(data) => *leftover = data,
+                    None => return Ok(0),
+                }
+            }
+
+            let len = leftover.len().min(buf.len());
+            buf[..len].copy_from_slice(&leftover[..len]);
+            leftover.drain(..len);
+            Ok(len)
+        })
+    }
+
+    fn close(&self) -> TransportFuture<'_, ()> {
+        Box::pin(async move { self.send(Channel::Tcp, &[]) })
+    }
+}
+
+/// The message side of a [`RelayTransport`]
+///
+/// Messages from the other end go in through [`deliver`](Self::deliver),
+/// and messages for it come out of [`next_message`](Self::next_message).
+/// Dropping the link looks like a lost connection to the transport.
+pub struct RelayLink {
+    outgoing: Mutex<UnboundedReceiver<Vec<u8>>>,
+    datagram_tx: UnboundedSender<Vec<u8>>,
+    stream_tx: UnboundedSender<Vec<u8>>,
+}
+impl RelayLink {
+    /// Hand the transport a message that arrived over the link
+    ///
+    /// Doesn't wait, so it can be called straight from a WebSocket's message
+    /// callback. Fails if the message isn't from a relay, or once the
+    /// transport has been dropped.
+    pub fn deliver(&self, message: &[u8]) -> io::Result<()> {
+        let (channel, data) = decode(message).ok_or(io::ErrorKind::InvalidData)?;
+        let tx = match channel {
+            Channel::Udp => &self.datagram_tx,
+            Channel::Tcp => &self.stream_tx,
+        };
+        tx.send(data.to_vec())
+            .map_err(|_| io::ErrorKind::ConnectionAborted.into())
+    }
+
+    /// Wait for the next message to send over the link
+    ///
+    /// Returns `None` once the transport has been dropped.
+    pub async fn next_message(&self) -> Option<Vec<u8>> {
+        self.outgoing.lock().await.recv().await
+    }
+}
+
+/// Pass everything between `a` and `b` until either byte stream closes, or
+/// something fails
+///
+/// Datagrams from one are sent on the other, and so is the byte stream.
+/// Closing one byte stream closes the other.
+pub async fn bridge(a: &impl Transport, b: &impl Transport) -> io::Result<()> {
+    tokio::select! {
+        res = forward_datagrams(a, b) => res,
+        res = forward_datagrams(b, a) => res,
+        res = forward_stream(a, b) => res,
+        res = forward_stream(b, a) => res,
+    }
+}
+
+async fn forward_datagrams(from: &impl Transport, to: &impl Transport) -> io::Result<()> {
+    let mut buf = [0u8; 1024];
+    loop {
+        let len = from.recv_datagram(&mut buf).await?;
+        to.send_datagram(&buf[..len]).await?;
+    }
+}
+
+async fn forward_stream(from: &impl Transport, to: &impl Transport) -> io::Result<()> {
+    let mut buf = [0u8; 4096];
+    loop {
+        let len = from.read_stream(&mut buf).await?;
+        if len == 0 {
+            return to.close().await;
+        }
+        to.write_stream(&buf[..len]).await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MemoryTransport;
+
+    /// Pass messages between two links until either transport goes away
+    async fn connect(a: RelayLink, b: RelayLink) {
+        loop {
+            let (message, to) = tokio::select! {
+                Some(message) = a.next_message() => (message, &b),
+                Some(message) = b.next_message() => (message, &a),
+                else => return,
+            };
+            if to.deliver(&message).is_err() {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        assert_eq!(
+            decode(&encode(Channel::Udp, &[1, 2, 3])),
+            Some((Channel::Udp, &[1, 2, 3][..]))
+        );
+        assert_eq!(
+            decode(&encode(Channel::Tcp, &[])),
+            Some((Channel::Tcp, &[][..]))
+        );
+        assert_eq!(decode(&[]), None);
+        assert_eq!(decode(&[0x7F, 1]), None);
+    }
+
+    #[tokio::test]
+    async fn relays_both_channels() {
+        let (rio, rio_end) = MemoryTransport::pair();
+        let (relay, relay_link) = RelayTransport::new();
+        let (ds, ds_link) = RelayTransport::new();
+        tokio::spawn(connect(relay_link, ds_link));
+        let bridged = tokio::spawn(async move { bridge(&rio_end, &relay).await });
+
+        let mut buf = [0u8; 16];
+        ds.send_datagram(&[1, 2, 3]).await.unwrap();
+        let len = rio.recv_datagram(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], [1, 2, 3]);
+
+        rio.send_datagram(&[4, 5]).await.unwrap();
+        let len = ds.recv_datagram(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], [4, 5]);
+
+        ds.write_stream(b"tags").await.unwrap();
+        let len = rio.read_stream(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"tags");
+
+        rio.write_stream(b"console").await.unwrap();
+        let len = ds.read_stream(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"console");
+
+        // Closing on one end closes the other, and ends the bridge
+        ds.close().await.unwrap();
+        assert_eq!(rio.read_stream(&mut buf).await.unwrap(), 0);
+        bridged.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+//! Talking to a real roboRIO over UDP and TCP sockets
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::{self, Arc, Weak},
+};
+
+use tokio::{
+    io::AsyncWriteExt,
+    net::{
+        TcpStream, UdpSocket,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::{
+        Mutex,
+        mpsc::{self, error::TrySendError},
+    },
+};
+
+use super::{Connect, Ports, Transport, TransportFuture};
+use crate::{trace::Level, utils::unspecified_for};
+
+/// Status packets a robot can have waiting before newer ones get dropped
+const ROUTE_CAPACITY: usize = 16;
+/// Longest status packet that's received whole
+const MAX_DATAGRAM: usize = 2048;
+
+/// Every socket receiving status packets, by the address it's bound to
+static SHARED_UDP: sync::Mutex<BTreeMap<SocketAddr, Weak<SharedUdp>>> =
+    sync::Mutex::new(BTreeMap::new());
+
+/// A socket receiving status packets for every robot on the same port
+///
+/// The roboRIO always sends to the same port, and only one socket can be
+/// bound to it, so driver stations for several robots have to share. Each
+/// datagram goes to whichever robot it was sent from, no matter which
+/// transport happened to read it.
+struct SharedUdp {
+    socket: UdpSocket,
+    routes: sync::Mutex<HashMap<IpAddr, mpsc::Sender<Vec<u8>>>>,
+}
+impl SharedUdp {
+    /// Receive datagrams from `rio_addr` on `bind_addr`, binding it if
+    /// nothing else has already
+    fn route(bind_addr: SocketAddr, rio_addr: IpAddr) -> io::Result<UdpRoute> {
+        let udp = {
+            let mut shared = SHARED_UDP.lock().unwrap();
+            match shared.get(&bind_addr).and_then(Weak::upgrade) {
+                Some(udp) => udp,
+                None => {
+                    let socket = std::net::UdpSocket::bind(bind_addr)?;
+                    socket.set_nonblocking(true)?;
+                    let udp = Arc::new(Self {
+                        socket: UdpSocket::from_std(socket)?,
+                        routes: sync::Mutex::new(HashMap::new()),
+                    });
+
+                    shared.retain(|_, udp| udp.strong_count() > 0);
+                    shared.insert(bind_addr, Arc::downgrade(&udp));
+                    udp
+                }
+            }
+        };
+
+        let rio_addr = rio_addr.to_canonical();
+        let (tx, rx) = mpsc::channel(ROUTE_CAPACITY);
+        // Reconnecting to the same robot takes over its packets
+        udp.routes.lock().unwrap().insert(rio_addr, tx.clone());
+
+        Ok(UdpRoute {
+            udp,
+            rio_addr,
+            tx,
+            rx: Mutex::new(rx),
+        })
+    }
+
+    /// Read from the socket until a datagram for `rio_addr` shows up,
+    /// handing off any others on the way
+    ///
+    /// With only one robot on the socket, it gets everything, like it would
+    /// with a socket to itself.
+    async fn recv_for(&self, rio_addr: IpAddr) -> io::Result<Vec<u8>> {
+        let mut buf = [0u8; MAX_DATAGRAM];
+
+        loop {
+            let (len, from) = self.socket.recv_from(&mut buf).await?;
+            let from = from.ip().to_canonical();
+
+            let routes = self.routes.lock().unwrap();
+            if from == rio_addr || routes.len() == 1 {
+                return Ok(buf[..len].to_vec());
+            }
+
+            match routes.get(&from).map(|tx| tx.try_send(buf[..len].to_vec())) {
+                Some(Ok(()) | Err(TrySendError::Full(_))) => {}
+                Some(Err(TrySendError::Closed(_))) | None => {
+                    event!(Level::TRACE, %from, "Dropping datagram from unknown robot");
+                }
+            }
+        }
+    }
+}
+
+/// Status packets from one roboRIO, off a [`SharedUdp`]
+struct UdpRoute {
+    udp: Arc<SharedUdp>,
+    rio_addr: IpAddr,
+    tx: mpsc::Sender<Vec<u8>>,
+    /// Datagrams another transport read for this one
+    rx: Mutex<mpsc::Receiver<Vec<u8>>>,
+}
+impl UdpRoute {
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut rx = self.rx.lock().await;
+        let datagram = futures_lite::future::or(
+            async {
+                // Never closes, since the sender is kept alongside it
+                Ok(rx.recv().await.unwrap_or_default())
+            },
+            self.udp.recv_for(self.rio_addr),
+        )
+        .await?;
+
+        let len = datagram.len().min(buf.len());
+        buf[..len].copy_from_slice(&datagram[..len]);
+        Ok(len)
+    }
+}
+impl Drop for UdpRoute {
+    fn drop(&mut self) {
+        let mut routes = self.udp.routes.lock().unwrap();
+        // Unless a newer connection to the same robot took over
+        if routes
+            .get(&self.rio_addr)
+            .is_some_and(|tx| tx.same_channel(&self.tx))
+        {
+            routes.remove(&self.rio_addr);
+        }
+    }
+}
+
+/// Connects [`SocketTransport`]s to a roboRIO at a fixed address
+pub struct SocketConnector {
+    pub rio_addr: IpAddr,
+}
+impl Connect for SocketConnector {
+    fn connect(&self, _team_number: u16) -> TransportFuture<'_, Box<dyn Transport>> {
+        Box::pin(async move {
+            let transport = SocketTransport::connect(self.rio_addr).await?;
+            Ok(Box::new(transport) as Box<dyn Transport>)
+        })
+    }
+}
+
+/// The real thing: UDP and TCP sockets connected to a roboRIO
+///
+/// Every transport in the process receiving on the same port shares one
+/// socket for it, and gets the status packets sent from its own roboRIO's
+/// address, so several robots can be driven at once (see
+/// [`DsPool`](crate::pool::DsPool)).
+pub struct SocketTransport {
+    udp_rx: UdpRoute,
+    udp_tx: UdpSocket,
+    /// Read through readiness, so there's no need to lock it
+    tcp_rx: OwnedReadHalf,
+    /// Locked for a whole write, so tags from different callers can't
+    /// interleave
+    tcp_tx: Mutex<OwnedWriteHalf>,
+}
+impl SocketTransport {
+    /// Connect to the roboRIO at `rio_addr` on the standard ports
+    pub async fn connect(rio_addr: IpAddr) -> io::Result<Self> {
+        Self::connect_with_ports(rio_addr, Ports::default()).await
+    }
+
+    /// Connect to the roboRIO at `rio_addr` on the given `ports`
+    pub async fn connect_with_ports(rio_addr: IpAddr, ports: Ports) -> io::Result<Self> {
+        let (tcp_rx, tcp_tx) = TcpStream::connect((rio_addr, ports.rio_tcp))
+            .await?
+            .into_split();
+        let unspecified = unspecified_for(rio_addr);
+        let udp_rx = SharedUdp::route((unspecified, ports.ds_udp).into(), rio_addr)?;
+        let udp_tx = UdpSocket::bind((unspecified, 0)).await?;
+        udp_tx.connect((rio_addr, ports.rio_udp)).await?;
+
+        Ok(Self {
+            udp_rx,
+            udp_tx,
+            tcp_rx,
+            tcp_tx: Mutex::new(tcp_tx),
+        })
+    }
+}
+impl Transport for SocketTransport {
+    fn send_datagram<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            self.udp_tx.send(buf).await?;
+            Ok(())
+        })
+    }
+
+    fn recv_datagram<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        Box::pin(self.udp_rx.recv(buf))
+    }
+
+    fn write_stream<'a>(&'a self, buf: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(async move { self.tcp_tx.lock().await.write_all(buf).await })
+    }
+
+    fn read_stream<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, usize> {
+        Box::pin(async move {
+            loop {
+                self.tcp_rx.readable().await?;
+                match self.tcp_rx.try_read(buf) {
+                    // Readiness can be spurious
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+                    res => return res,
+                }
+            }
+        })
+    }
+
+    fn close(&self) -> TransportFuture<'_, ()> {
+        Box::pin(async move { self.tcp_tx.lock().await.shutdown().await })
+    }
+}
@@ -1,4 +1,7 @@
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use futures_lite::{Stream, stream};
+use tokio::sync::watch;
 
 use crate::{RobotCodeMode, RobotStatus};
 
@@ -29,21 +32,112 @@ pub fn gen_team_ip(team_number: u16) -> Option<Ipv4Addr> {
     }
 }
 
+/// Get the wildcard address of the same family as `addr`, for binding
+/// sockets that talk to it
+#[cfg_attr(
+    any(not(feature = "runtime-tokio"), target_arch = "wasm32"),
+    allow(dead_code)
+)]
+pub const fn unspecified_for(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    }
+}
+
+/// A value that's read and written like an
+/// [`AtomicCell`](crossbeam_utils::atomic::AtomicCell), and can be watched for
+/// changes
+pub(crate) struct Watched<T>(watch::Sender<T>);
+impl<T: Copy + PartialEq> Watched<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self(watch::Sender::new(value))
+    }
+
+    pub(crate) fn load(&self) -> T {
+        *self.0.borrow()
+    }
+
+    /// Only wakes watchers if `value` is different from what's there
+    pub(crate) fn store(&self, value: T) {
+        self.0.send_if_modified(|current| {
+            let changed = *current != value;
+            *current = value;
+            changed
+        });
+    }
+
+    /// Every value stored from now on that's different from the one before
+    ///
+    /// Watchers only see the latest value, so one that changes and changes
+    /// back before the stream is polled again is skipped. The stream ends
+    /// once the value is dropped.
+    pub(crate) fn changes(&self) -> impl Stream<Item = T> + Send + Unpin + 'static
+    where
+        T: Send + Sync + 'static,
+    {
+        let rx = self.0.subscribe();
+        let last = *rx.borrow();
+        Box::pin(stream::unfold((rx, last), |(mut rx, last)| async move {
+            loop {
+                rx.changed().await.ok()?;
+                let value = *rx.borrow_and_update();
+                if value != last {
+                    return Some((value, (rx, value)));
+                }
+            }
+        }))
+    }
+}
+
+/// Get the host's timezone, as an IANA name like `America/Chicago`
+#[cfg(feature = "timezone")]
+pub fn system_timezone() -> Option<String> {
+    iana_time_zone::get_timezone().ok()
+}
+
+/// Get the timezone to send the roboRIO when the caller hasn't picked one
+///
+/// This is the host's timezone with the `timezone` feature, otherwise `UTC`.
+pub fn default_timezone() -> String {
+    #[cfg(feature = "timezone")]
+    if let Some(timezone) = system_timezone() {
+        return timezone;
+    }
+
+    String::from("UTC")
+}
+
+/// Convert days since the Unix epoch to a `(year, month, day)` date, with
+/// `1..=12` months and `1..=31` days
+///
+/// Reference: <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+pub const fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    (year, month, day)
+}
+
 #[inline(always)]
 pub const fn find_status(
     status: crate::proto::incoming::udp::Status,
     trace: crate::proto::incoming::udp::Trace,
 ) -> (RobotStatus, RobotCodeMode) {
-    assert!(status.is_in_teleop() ^ status.is_in_auto() ^ status.is_in_test());
-
-    let mode = if status.is_in_teleop() {
-        RobotCodeMode::Teleop
-    } else if status.is_in_auto() {
+    // `0b11` isn't a real mode, so treat it like teleop
+    let mode = if status.is_in_auto() {
         RobotCodeMode::Autonomous
     } else if status.is_in_test() {
         RobotCodeMode::Test
     } else {
-        panic!();
+        RobotCodeMode::Teleop
     };
 
     if !trace.has_robot_code() {